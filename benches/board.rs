@@ -0,0 +1,62 @@
+use std::hint::black_box;
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use egui::{Rect, pos2};
+use whiteboard::{WhiteboardApp, WhiteboardBuilder, render_to_image};
+
+// strokes on the boards, each with `POINTS` points
+const STROKES: [usize; 3] = [100, 1_000, 10_000];
+const POINTS: usize = 50;
+// side of the square the strokes are spread over, in board units
+const BOARD_SIZE: f32 = 4000.0;
+
+fn board(strokes: usize) -> WhiteboardApp {
+    WhiteboardBuilder::new()
+        .scribbles(strokes, POINTS, BOARD_SIZE)
+        .build()
+}
+
+// Pressing the eraser where nothing is: every segment of every stroke is
+// still checked, and the board stays the same between iterations
+fn eraser(c: &mut Criterion) {
+    let mut group = c.benchmark_group("eraser");
+    for strokes in STROKES {
+        let mut app = board(strokes);
+        group.bench_function(BenchmarkId::from_parameter(strokes), |b| {
+            b.iter(|| app.erase_at(black_box(pos2(-100.0, -100.0)), 5.0));
+        });
+    }
+    group.finish();
+}
+
+// Dragging a selection over a quarter of the board
+fn selection(c: &mut Criterion) {
+    let mut group = c.benchmark_group("selection");
+    let rect = Rect::from_min_max(
+        pos2(0.0, 0.0),
+        pos2(BOARD_SIZE / 2.0, BOARD_SIZE / 2.0),
+    );
+    for strokes in STROKES {
+        let mut app = board(strokes);
+        group.bench_function(BenchmarkId::from_parameter(strokes), |b| {
+            b.iter(|| app.select_in_rect(black_box(rect)));
+        });
+    }
+    group.finish();
+}
+
+// Drawing every line of the board into a frame of about 1000x1000 pixels
+fn render(c: &mut Criterion) {
+    let mut group = c.benchmark_group("render");
+    group.sample_size(10);
+    for strokes in STROKES {
+        let state = board(strokes).export_state();
+        group.bench_function(BenchmarkId::from_parameter(strokes), |b| {
+            b.iter(|| render_to_image(black_box(&state), 0.25));
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, eraser, selection, render);
+criterion_main!(benches);
@@ -0,0 +1,121 @@
+use std::collections::HashSet;
+
+use eframe::egui;
+use egui::{Rect, Vec2, vec2};
+
+use crate::{Line, lines_bounding_box};
+
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Alignment {
+    Left,
+    CenterX,
+    Right,
+    Top,
+    CenterY,
+    Bottom,
+}
+pub(crate) const ALIGNMENTS: [(Alignment, &str); 6] = [
+    (Alignment::Left, "Left"),
+    (Alignment::CenterX, "Center"),
+    (Alignment::Right, "Right"),
+    (Alignment::Top, "Top"),
+    (Alignment::CenterY, "Middle"),
+    (Alignment::Bottom, "Bottom"),
+];
+
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Distribution {
+    Horizontal,
+    Vertical,
+}
+
+fn line_bboxes(
+    lines: &[Line],
+    selected: &HashSet<usize>,
+) -> Vec<(usize, Rect)> {
+    let mut bboxes: Vec<(usize, Rect)> = selected
+        .iter()
+        .filter_map(|&i| {
+            let bbox = lines_bounding_box(std::slice::from_ref(lines.get(i)?));
+            bbox.is_finite().then_some((i, bbox))
+        })
+        .collect();
+    bboxes.sort_unstable_by_key(|&(i, _)| i);
+    bboxes
+}
+
+// How far each selected line has to move so its bounding box lines up with
+// the matching edge or center of the whole selection
+pub(crate) fn align(
+    lines: &[Line],
+    selected: &HashSet<usize>,
+    alignment: Alignment,
+) -> Vec<(usize, Vec2)> {
+    let bboxes = line_bboxes(lines, selected);
+    let Some(selection) =
+        bboxes.iter().map(|&(_, bbox)| bbox).reduce(Rect::union)
+    else {
+        return Vec::new();
+    };
+    bboxes
+        .into_iter()
+        .map(|(i, bbox)| {
+            let offset = match alignment {
+                Alignment::Left => vec2(selection.min.x - bbox.min.x, 0.0),
+                Alignment::CenterX => {
+                    vec2(selection.center().x - bbox.center().x, 0.0)
+                }
+                Alignment::Right => vec2(selection.max.x - bbox.max.x, 0.0),
+                Alignment::Top => vec2(0.0, selection.min.y - bbox.min.y),
+                Alignment::CenterY => {
+                    vec2(0.0, selection.center().y - bbox.center().y)
+                }
+                Alignment::Bottom => vec2(0.0, selection.max.y - bbox.max.y),
+            };
+            (i, offset)
+        })
+        .collect()
+}
+
+// Spaces the selected lines so the gaps between neighbouring bounding boxes
+// are equal. The first and last line along the axis stay where they are.
+pub(crate) fn distribute(
+    lines: &[Line],
+    selected: &HashSet<usize>,
+    distribution: Distribution,
+) -> Vec<(usize, Vec2)> {
+    let mut bboxes = line_bboxes(lines, selected);
+    if bboxes.len() < 3 {
+        return Vec::new();
+    }
+    // (min, size) of each bbox along the axis
+    let axis = |bbox: &Rect| match distribution {
+        Distribution::Horizontal => (bbox.min.x, bbox.width()),
+        Distribution::Vertical => (bbox.min.y, bbox.height()),
+    };
+    bboxes.sort_by(|a, b| {
+        let (a_min, a_size) = axis(&a.1);
+        let (b_min, b_size) = axis(&b.1);
+        (a_min + a_size / 2.0).total_cmp(&(b_min + b_size / 2.0))
+    });
+    let (first_min, _) = axis(&bboxes[0].1);
+    let (last_min, last_size) = axis(&bboxes[bboxes.len() - 1].1);
+    let total_size: f32 = bboxes.iter().map(|(_, bbox)| axis(bbox).1).sum();
+    let gap = (last_min + last_size - first_min - total_size)
+        / (bboxes.len() - 1) as f32;
+
+    let mut position = first_min;
+    bboxes
+        .iter()
+        .map(|(i, bbox)| {
+            let (min, size) = axis(bbox);
+            let shift = position - min;
+            position += size + gap;
+            let offset = match distribution {
+                Distribution::Horizontal => vec2(shift, 0.0),
+                Distribution::Vertical => vec2(0.0, shift),
+            };
+            (*i, offset)
+        })
+        .collect()
+}
@@ -0,0 +1,166 @@
+use std::{io, path::Path};
+
+use crate::format::EXTENSIONS;
+
+pub(crate) const SUPPORTED: bool = cfg!(any(windows, target_os = "linux"));
+
+// Makes this executable the handler for whiteboard files of the current
+// user. Returns a description of what was changed.
+pub(crate) fn register() -> io::Result<String> {
+    let exe = std::env::current_exe()?;
+    register_for(&exe)
+}
+
+#[cfg(windows)]
+fn register_for(exe: &Path) -> io::Result<String> {
+    const PROG_ID: &str = "SimpleWhiteboard.Document";
+    let classes = r"HKCU\Software\Classes";
+    let exe = exe.display();
+    let mut entries = vec![
+        (format!(r"{classes}\{PROG_ID}"), "Whiteboard".to_owned()),
+        (
+            format!(r"{classes}\{PROG_ID}\DefaultIcon"),
+            format!("\"{exe}\",0"),
+        ),
+        (
+            format!(r"{classes}\{PROG_ID}\shell\open\command"),
+            format!("\"{exe}\" \"%1\""),
+        ),
+    ];
+    for extension in EXTENSIONS {
+        entries.push((format!(r"{classes}\.{extension}"), PROG_ID.to_owned()));
+    }
+    for (key, value) in &entries {
+        let status = std::process::Command::new("reg")
+            .args(["add", key.as_str(), "/ve", "/d", value.as_str(), "/f"])
+            .status()?;
+        if !status.success() {
+            return Err(io::Error::other(format!("reg add {key} failed")));
+        }
+    }
+    Ok(format!(
+        "Registered {} in {classes}. Explorer may need a moment to pick up \
+         the change.",
+        extension_list()
+    ))
+}
+
+#[cfg(target_os = "linux")]
+fn register_for(exe: &Path) -> io::Result<String> {
+    use std::{fs, process::Command};
+
+    const MIME_TYPE: &str = "application/x-simple-whiteboard";
+    const DESKTOP_FILE: &str = "simple-whiteboard.desktop";
+
+    let data_dir = directories::BaseDirs::new()
+        .ok_or_else(|| io::Error::other("no home directory available"))?
+        .data_dir()
+        .to_path_buf();
+
+    let globs: String = EXTENSIONS
+        .iter()
+        .map(|extension| format!("    <glob pattern=\"*.{extension}\"/>\n"))
+        .collect();
+    let mime_dir = data_dir.join("mime");
+    fs::create_dir_all(mime_dir.join("packages"))?;
+    fs::write(
+        mime_dir.join("packages").join("simple-whiteboard.xml"),
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<mime-info xmlns="http://www.freedesktop.org/standards/shared-mime-info">
+  <mime-type type="{MIME_TYPE}">
+    <comment>Whiteboard</comment>
+{globs}  </mime-type>
+</mime-info>
+"#
+        ),
+    )?;
+
+    let icon_dir = data_dir.join("icons/hicolor/512x512/apps");
+    fs::create_dir_all(&icon_dir)?;
+    fs::write(
+        icon_dir.join("simple-whiteboard.png"),
+        include_bytes!("../assets/icon.png"),
+    )?;
+
+    let applications_dir = data_dir.join("applications");
+    fs::create_dir_all(&applications_dir)?;
+    fs::write(
+        applications_dir.join(DESKTOP_FILE),
+        format!(
+            "[Desktop Entry]
+Type=Application
+Name=Simple Whiteboard
+Exec={} %f
+Icon=simple-whiteboard
+MimeType={MIME_TYPE};
+Categories=Graphics;
+Terminal=false
+",
+            desktop_exec_path(exe)
+        ),
+    )?;
+
+    // refresh the caches and make us the default; desktops without these
+    // tools still find the files above on their next scan
+    for (program, args) in [
+        ("update-mime-database", vec![mime_dir.as_os_str()]),
+        (
+            "update-desktop-database",
+            vec![applications_dir.as_os_str()],
+        ),
+        (
+            "xdg-mime",
+            vec![
+                "default".as_ref(),
+                DESKTOP_FILE.as_ref(),
+                MIME_TYPE.as_ref(),
+            ],
+        ),
+    ] {
+        if let Err(e) = Command::new(program).args(args).status() {
+            eprintln!("failed to run {program}: {e}");
+        }
+    }
+    Ok(format!(
+        "Registered {} for {MIME_TYPE} in {}.",
+        extension_list(),
+        data_dir.display()
+    ))
+}
+
+// Quotes the path for the `Exec` key: inside double quotes `"`, `` ` ``, `$`
+// and `\` are escaped with a backslash, backslashes are doubled once more
+// because the key is a string value, and `%` is doubled so it isn't taken
+// for a field code
+#[cfg(target_os = "linux")]
+fn desktop_exec_path(exe: &Path) -> String {
+    let mut quoted = String::from("\"");
+    for c in exe.to_string_lossy().chars() {
+        match c {
+            '"' | '`' | '$' => quoted.push_str(&format!("\\\\{c}")),
+            '\\' => quoted.push_str("\\\\\\\\"),
+            '%' => quoted.push_str("%%"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+fn register_for(_exe: &Path) -> io::Result<String> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "registering file types isn't supported on this platform",
+    ))
+}
+
+#[cfg_attr(not(any(windows, target_os = "linux")), allow(dead_code))]
+fn extension_list() -> String {
+    EXTENSIONS
+        .iter()
+        .map(|extension| format!(".{extension}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,102 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+// Writes `bytes` to a temporary file next to `path` and renames it over
+// `path`, so a crash or a full disk mid-write leaves the old file intact
+pub(crate) fn write(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let tmp_path = temp_path(path);
+    let result = write_temp(&tmp_path, path, bytes).and_then(|()| {
+        fs::rename(&tmp_path, path).or_else(|e| {
+            // some filesystems (network shares, FAT on some platforms) can't
+            // rename over an existing file; the temp file is complete at
+            // this point, so copying it is the next best thing
+            eprintln!(
+                "renaming {} failed ({e}), copying instead",
+                tmp_path.display()
+            );
+            fs::copy(&tmp_path, path).map(|_| ())
+        })
+    });
+    // only left behind if something failed
+    let _ = fs::remove_file(&tmp_path);
+    result?;
+    sync_parent_dir(path);
+    Ok(())
+}
+
+// `dir/board.wb` -> `dir/.board.wb.tmp`
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+fn write_temp(tmp_path: &Path, path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut file = fs::File::create(tmp_path)?;
+    // keep the permissions of the file being replaced
+    if let Ok(metadata) = fs::metadata(path) {
+        file.set_permissions(metadata.permissions())?;
+    }
+    file.write_all(bytes)?;
+    file.sync_all()
+}
+
+// Makes the rename itself durable. Not possible (or needed) on Windows.
+fn sync_parent_dir(path: &Path) {
+    #[cfg(unix)]
+    if let Some(dir) = path.parent()
+        && let Ok(dir) = fs::File::open(if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        })
+    {
+        let _ = dir.sync_all();
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_the_file_without_leaving_the_temp_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("board.wb");
+        fs::write(&path, b"old").unwrap();
+        write(&path, b"new").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert!(!temp_path(&path).exists());
+    }
+
+    #[test]
+    fn a_failed_write_leaves_the_file_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("board.wb");
+        fs::write(&path, b"old").unwrap();
+        // the temp file can't be created where a directory is in the way
+        fs::create_dir(temp_path(&path)).unwrap();
+        assert!(write(&path, b"new").is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"old");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_the_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("board.wb");
+        fs::write(&path, b"old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        write(&path, b"new").unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
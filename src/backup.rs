@@ -0,0 +1,54 @@
+use std::{
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+pub(crate) struct Backup {
+    pub path: PathBuf,
+    pub modified: SystemTime,
+}
+
+// `board.wb` -> `board.wb.bak` for index 0, `board.wb.bak.<index>` after
+fn backup_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = OsString::from(path.file_name().unwrap_or_default());
+    name.push(".bak");
+    if index > 0 {
+        name.push(format!(".{index}"));
+    }
+    path.with_file_name(name)
+}
+
+// Shifts the existing backups of `path` one slot back and copies the current
+// file into the first one, keeping at most `count` backups. Nothing happens
+// if `path` doesn't exist yet.
+pub(crate) fn rotate(path: &Path, count: usize) -> io::Result<()> {
+    if count == 0 || !path.exists() {
+        return Ok(());
+    }
+    for index in (1..count).rev() {
+        let from = backup_path(path, index - 1);
+        if from.exists() {
+            fs::rename(&from, backup_path(path, index))?;
+        }
+    }
+    fs::copy(path, backup_path(path, 0))?;
+    Ok(())
+}
+
+// The backups of `path` that exist, newest first
+pub(crate) fn list(path: &Path) -> Vec<Backup> {
+    let mut backups = Vec::new();
+    for index in 0.. {
+        let backup = backup_path(path, index);
+        let Ok(metadata) = fs::metadata(&backup) else {
+            break;
+        };
+        backups.push(Backup {
+            path: backup,
+            modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+        });
+    }
+    backups
+}
@@ -0,0 +1,354 @@
+use eframe::egui;
+use egui::{ImageSource, Painter, Pos2, Stroke};
+
+use crate::{
+    Line, constrain_to_axis, draw_crosshair, is_finite_pos, new_line_id,
+    now_ms,
+    ruler::Ruler,
+    stroke,
+    tools::{CanvasTool, Tool, ToolContext, ToolSettings},
+};
+
+// how close (in screen px) the start or end of a stroke has to be to an
+// existing stroke end to snap onto it
+const ENDPOINT_SNAP_RADIUS: f32 = 8.0;
+// the thinnest a pressure sensitive stroke gets, relative to the brush width
+const MIN_PRESSURE: f32 = 0.1;
+// drawing speed (canvas px per second) at which a speed sensitive stroke is
+// half as wide as the brush
+const HALF_WIDTH_SPEED: f32 = 1500.0;
+// how far (in screen px) the pointer has to get from the last point of a
+// stroke before it adds another, so jitter doesn't bloat strokes. Slow
+// drawing still adds a point every time it has moved this far.
+const MIN_POINT_DISTANCE: f32 = 1.0;
+
+// Draws strokes with the current color, width and style. While the ruler
+// is being placed, presses place its anchors instead. The highlighter is a
+// brush too, with settings of its own.
+pub(crate) struct Brush {
+    // `Tool::BRUSH` or `Tool::HIGHLIGHTER`
+    kind: Tool,
+    // the stroke being drawn
+    points: Vec<Pos2>,
+    widths: Vec<f32>,
+    // last stylus pressure reported during the current stroke
+    pressure: Option<f32>,
+    last_point_time: f64,
+    last_speed_factor: f32,
+}
+impl Brush {
+    pub fn new(kind: Tool) -> Self {
+        Self {
+            kind,
+            points: Vec::new(),
+            widths: Vec::new(),
+            pressure: None,
+            last_point_time: 0.0,
+            last_speed_factor: 1.0,
+        }
+    }
+    // Where the brush puts a point for the pointer at `pos`: along an axis
+    // from the stroke's start while Shift is held, or on the ruler
+    fn position(&self, cx: &ToolContext<'_>, pos: Pos2) -> Pos2 {
+        if cx.ctx.input(|i| i.modifiers.shift)
+            && *cx.ruler == Ruler::Off
+            && let Some(&start) = self.points.first()
+        {
+            constrain_to_axis(start, pos)
+        } else {
+            cx.ruler.project(pos)
+        }
+    }
+    // Where a stroke starting or ending at `pos` should go. The start of
+    // the stroke being drawn counts too, so a shape can be closed in one
+    // stroke.
+    fn snap_endpoint(&self, cx: &ToolContext<'_>, pos: Pos2) -> Pos2 {
+        if !cx.snap_to_endpoints {
+            return pos;
+        }
+        let radius = ENDPOINT_SNAP_RADIUS / cx.camera.zoom;
+        // only once the stroke has left its start, or every short stroke
+        // would collapse onto it
+        let own_start = self.points.first().filter(|start| {
+            start.distance(pos) <= radius
+                && self
+                    .points
+                    .iter()
+                    .any(|p| p.distance(**start) > radius * 2.0)
+        });
+        [
+            nearest_endpoint(&cx.document.lines, pos, radius),
+            own_start.copied(),
+        ]
+        .into_iter()
+        .flatten()
+        .min_by(|a, b| a.distance(pos).total_cmp(&b.distance(pos)))
+        .unwrap_or(pos)
+    }
+    fn add_point(&mut self, cx: &ToolContext<'_>, pos: Pos2) {
+        let pos = self.position(cx, pos);
+        self.update_pressure(cx.ctx);
+        let min_distance = MIN_POINT_DISTANCE / cx.camera.zoom;
+        if !is_finite_pos(pos)
+            || self
+                .points
+                .last()
+                .is_some_and(|last| last.distance(pos) < min_distance)
+        {
+            return;
+        }
+        let pressure =
+            self.pressure.map_or(1.0, |p| p.clamp(MIN_PRESSURE, 1.0));
+        let width = cx.width * pressure * self.speed_factor(cx, pos);
+        let pos = if self.points.is_empty() {
+            self.snap_endpoint(cx, pos)
+        } else {
+            pos
+        };
+        self.points.push(pos);
+        self.widths.push(width);
+    }
+    // Width multiplier for the next point: 1 when drawing slowly, shrinking
+    // as the pointer moves faster. Changes are smoothed so mouse jitter
+    // doesn't show up as blobs.
+    fn speed_factor(&mut self, cx: &ToolContext<'_>, pos: Pos2) -> f32 {
+        let now = cx.ctx.input(|i| i.time);
+        let elapsed = (now - self.last_point_time) as f32;
+        self.last_point_time = now;
+        if !cx.speed_sensitive_width {
+            return 1.0;
+        }
+        let Some(&last) = self.points.last() else {
+            self.last_speed_factor = 1.0;
+            return 1.0;
+        };
+        if elapsed > 0.0 {
+            let speed = last.distance(pos) / elapsed;
+            let target = 1.0 / (1.0 + speed / HALF_WIDTH_SPEED);
+            self.last_speed_factor = (self.last_speed_factor * 0.7
+                + target * 0.3)
+                .clamp(MIN_PRESSURE, 1.0);
+        }
+        self.last_speed_factor
+    }
+    // Remembers the stylus pressure of this frame, if the device reports one
+    fn update_pressure(&mut self, ctx: &egui::Context) {
+        let force = ctx.input(|i| {
+            i.events.iter().rev().find_map(|event| match event {
+                egui::Event::Touch { force, .. } => *force,
+                _ => None,
+            })
+        });
+        if force.is_some() {
+            self.pressure = force;
+        }
+    }
+    // Adds the stroke drawn so far to the board
+    fn finish(&mut self, cx: &mut ToolContext<'_>) {
+        if self.points.len() >= 2
+            && let Some(last) = self.points.pop()
+        {
+            let last = self.snap_endpoint(cx, last);
+            self.points.push(last);
+        }
+        // never store NaN/inf points, they break hit-testing and saving
+        let (points, mut widths): (Vec<Pos2>, Vec<f32>) = self
+            .points
+            .drain(..)
+            .zip(self.widths.drain(..))
+            .filter(|(p, _)| is_finite_pos(*p))
+            .unzip();
+        self.pressure = None;
+        if points.is_empty() {
+            return;
+        }
+        // without pressure input every point has the brush width
+        if widths.iter().all(|&w| w == cx.width) {
+            widths.clear();
+        }
+        let mut line = Line {
+            points,
+            color: cx.color,
+            width: cx.width,
+            widths,
+            style: cx.style,
+            created_ms: now_ms(),
+            fill: None,
+            fill_only: false,
+            picture: None,
+            locked: false,
+            id: new_line_id(),
+        };
+        if line.is_closed() {
+            line.fill = cx.fill;
+        }
+        cx.recent_colors.push(line.color);
+        cx.document.add_line(line);
+    }
+}
+impl CanvasTool for Brush {
+    fn kind(&self) -> Tool {
+        self.kind
+    }
+    fn icon(&self) -> ImageSource<'static> {
+        if self.kind == Tool::HIGHLIGHTER {
+            egui::include_image!("../assets/tools/highlighter.png")
+        } else {
+            egui::include_image!("../assets/tools/brush.png")
+        }
+    }
+    fn tooltip(&self) -> &'static str {
+        if self.kind == Tool::HIGHLIGHTER {
+            "Highlighter"
+        } else {
+            "Brush"
+        }
+    }
+    fn default_settings(&self) -> Option<ToolSettings> {
+        Some(if self.kind == Tool::HIGHLIGHTER {
+            ToolSettings {
+                width: 12.0,
+                opacity: Some(0.4),
+            }
+        } else {
+            ToolSettings::default()
+        })
+    }
+    fn on_pointer_down(&mut self, cx: &mut ToolContext<'_>, pos: Pos2) {
+        if cx.ruler.is_placing() {
+            cx.ruler.place_anchor(pos);
+            return;
+        }
+        self.cancel();
+        self.add_point(cx, pos);
+    }
+    fn on_pointer_drag(&mut self, cx: &mut ToolContext<'_>, pos: Pos2) {
+        if !cx.ruler.is_placing() {
+            self.add_point(cx, pos);
+        }
+    }
+    // 放開拖曳時儲存線條
+    fn on_pointer_up(&mut self, cx: &mut ToolContext<'_>, _pos: Pos2) {
+        if !self.points.is_empty() {
+            self.finish(cx);
+        }
+    }
+    fn paint_overlay(
+        &mut self,
+        cx: &ToolContext<'_>,
+        painter: &Painter,
+        pointer: Option<Pos2>,
+    ) {
+        cx.ruler.draw(painter, &cx.camera, cx.canvas_rect);
+        let visuals = &cx.ctx.style().visuals;
+        // mark the stroke end the next stroke would start or end on
+        if !cx.ruler.is_placing()
+            && let Some(pos) = pointer
+        {
+            let snapped = self.snap_endpoint(cx, pos);
+            if snapped != pos {
+                painter.circle_stroke(
+                    cx.camera.to_screen(snapped),
+                    ENDPOINT_SNAP_RADIUS,
+                    Stroke::new(1.5_f32, visuals.selection.bg_fill),
+                );
+            }
+        }
+        if cx.show_crosshair
+            && let Some(pos) = pointer
+        {
+            draw_crosshair(
+                painter,
+                cx.canvas_rect,
+                cx.camera.to_screen(self.position(cx, pos)),
+                visuals.text_color().gamma_multiply(0.25),
+            );
+        }
+        // 繪製正在畫的線條
+        if self.points.len() >= 2 {
+            let points: Vec<Pos2> = self
+                .points
+                .iter()
+                .map(|&p| cx.camera.to_screen(p))
+                .collect();
+            let widths: Vec<f32> =
+                self.widths.iter().map(|w| w * cx.camera.zoom).collect();
+            painter.extend(stroke::shapes(
+                &points,
+                &widths,
+                cx.width * cx.camera.zoom,
+                cx.color,
+                cx.style,
+            ));
+        }
+    }
+    fn is_busy(&self) -> bool {
+        !self.points.is_empty()
+    }
+    fn cancel(&mut self) {
+        self.points.clear();
+        self.widths.clear();
+    }
+}
+
+// The first or last point of a stroke closest to `pos`, if any is within
+// `radius`
+fn nearest_endpoint(lines: &[Line], pos: Pos2, radius: f32) -> Option<Pos2> {
+    lines
+        .iter()
+        .filter(|line| line.picture.is_none())
+        .flat_map(|line| [line.points.first(), line.points.last()])
+        .flatten()
+        .map(|&p| (p, p.distance(pos)))
+        .filter(|&(_, distance)| distance <= radius)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(p, _)| p)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use egui::{Color32, Rect, pos2};
+
+    use super::*;
+    use crate::{
+        camera::Camera, colors::RecentColors, document::Document,
+        preferences::Preferences, stroke::LineStyle,
+    };
+
+    #[test]
+    fn strokes_skip_non_finite_points() {
+        let ctx = egui::Context::default();
+        let mut document = Document::default();
+        let mut selection = HashSet::new();
+        let mut ruler = Ruler::Off;
+        let mut recent_colors = RecentColors::default();
+        let preferences = Preferences::default();
+        let mut cx = ToolContext {
+            ctx: &ctx,
+            document: &mut document,
+            selection: &mut selection,
+            camera: Camera::default(),
+            canvas_rect: Rect::EVERYTHING,
+            color: Color32::BLACK,
+            width: 2.0,
+            style: LineStyle::Solid,
+            fill: None,
+            snap_to_endpoints: false,
+            speed_sensitive_width: false,
+            show_crosshair: false,
+            ruler: &mut ruler,
+            recent_colors: &mut recent_colors,
+            preferences: &preferences,
+        };
+        let mut brush = Brush::new(Tool::BRUSH);
+        brush.on_pointer_down(&mut cx, pos2(0.0, 0.0));
+        brush.on_pointer_drag(&mut cx, pos2(f32::NAN, 5.0));
+        brush.on_pointer_drag(&mut cx, pos2(10.0, 0.0));
+        brush.on_pointer_drag(&mut cx, pos2(20.0, f32::INFINITY));
+        brush.on_pointer_up(&mut cx, pos2(20.0, 0.0));
+        assert_eq!(document.lines.len(), 1);
+        assert_eq!(document.lines[0].points, [pos2(0.0, 0.0), pos2(10.0, 0.0)]);
+    }
+}
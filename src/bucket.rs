@@ -0,0 +1,404 @@
+use std::collections::HashMap;
+
+use eframe::egui;
+use egui::{Pos2, Rect, pos2};
+
+use crate::{
+    Line, distance_point_to_segment, render,
+    stroke::{LineStyle, MAX_FILL_VERTICES},
+};
+
+// longest side of the raster the region is searched on
+const MAX_RASTER_SIZE: f32 = 1024.0;
+// thinner strokes could leave gaps between their anti-aliased pixels
+const MIN_WALL_WIDTH: f32 = 1.5;
+// opacity from which a pixel belongs to a stroke
+const WALL_ALPHA: u8 = 96;
+// how many pixels the fill reaches under the surrounding strokes, so no
+// background shows along their edges
+const TUCK_UNDER: usize = 2;
+// the outline may be this many pixels off the raster, more if it would
+// have too many vertices otherwise
+const TOLERANCE: f32 = 0.6;
+// enclosed areas smaller than this, in square pixels, aren't cut out
+const MIN_HOLE_AREA: f32 = 4.0;
+
+// The area around `pos` enclosed by strokes, as a closed outline (without
+// the repeated first point) in board coordinates, or `None` if `pos` isn't
+// enclosed. Strokes are rasterized at up to `scale` pixels per unit to find
+// it, so gaps smaller than a pixel at that scale count as closed. Enclosed
+// areas within the region, like the inside of a circle drawn in it, are cut
+// out through zero-width bridges so it stays a single outline.
+pub(crate) fn enclosed_region(
+    lines: &[Line],
+    pos: Pos2,
+    scale: f32,
+) -> Option<Vec<Pos2>> {
+    // earlier fills and pictures don't bound anything, and dashes close up
+    let strokes: Vec<&Line> = lines
+        .iter()
+        .filter(|l| !l.fill_only && l.picture.is_none())
+        .collect();
+    let mut content = Rect::NOTHING;
+    for line in &strokes {
+        for &p in &line.points {
+            content.extend_with(p);
+        }
+    }
+    if !content.contains(pos) {
+        return None;
+    }
+    let max_width = strokes
+        .iter()
+        .flat_map(|line| line.widths.iter().chain([&line.width]))
+        .fold(MIN_WALL_WIDTH, |max, &w| max.max(w));
+    let scale = scale
+        .min(MAX_RASTER_SIZE / (content.width() + max_width))
+        .min(MAX_RASTER_SIZE / (content.height() + max_width));
+    if !scale.is_finite() || scale <= 0.0 {
+        return None;
+    }
+    // a margin of free pixels so anything unenclosed reaches the border
+    let region = content.expand(max_width / 2.0 + 4.0 / scale);
+    let min_width = MIN_WALL_WIDTH / scale;
+    let walls: Vec<Line> = strokes
+        .iter()
+        .map(|&line| Line {
+            width: line.width.max(min_width),
+            widths: line.widths.iter().map(|w| w.max(min_width)).collect(),
+            style: LineStyle::Solid,
+            fill: None,
+            ..line.clone()
+        })
+        .collect();
+    let pixmap = render::render_strokes(&walls, region, scale)?;
+    let grid = Grid {
+        width: pixmap.width() as usize,
+        height: pixmap.height() as usize,
+    };
+    let wall: Vec<bool> = pixmap
+        .pixels()
+        .iter()
+        .map(|p| p.alpha() >= WALL_ALPHA)
+        .collect();
+
+    let start = (pos - region.min) * scale;
+    let start = grid.index(start.x as usize, start.y as usize)?;
+    if wall[start] {
+        return None;
+    }
+    let inside = grid.flood([start], |i| !wall[i]);
+    if grid.border().any(|i| inside[i]) {
+        return None;
+    }
+    // everything reachable from the border without crossing the region is
+    // outside; strokes within the region are filled over (they're drawn on
+    // top anyway), enclosed areas within it are not
+    let outside =
+        grid.flood(grid.border().filter(|&i| !inside[i]), |i| !inside[i]);
+    let mut filled =
+        grid.flood([start], |i| !outside[i] && (inside[i] || wall[i]));
+    // and the enclosed areas the other way, under the strokes around them
+    let mut holes: Vec<bool> = (0..filled.len())
+        .map(|i| !filled[i] && !outside[i])
+        .collect();
+    for _ in 0..TUCK_UNDER {
+        filled = grid.grow(&filled, |i| wall[i]);
+        holes = grid.grow(&holes, |i| wall[i]);
+    }
+    for (filled, hole) in filled.iter_mut().zip(holes) {
+        *filled &= !hole;
+    }
+
+    let mut outlines: Vec<Vec<Pos2>> = grid.outlines(&filled);
+    // traced with the inside on the left: the outer outline runs
+    // counterclockwise on screen and has the smallest signed area
+    let outer = (0..outlines.len()).min_by(|&a, &b| {
+        signed_area(&outlines[a]).total_cmp(&signed_area(&outlines[b]))
+    })?;
+    let outer = outlines.swap_remove(outer);
+    outlines.retain(|hole| signed_area(hole) >= MIN_HOLE_AREA);
+
+    let mut tolerance = TOLERANCE;
+    let polygon = loop {
+        let mut polygon = simplify(&outer, tolerance);
+        let holes: Vec<Vec<Pos2>> = outlines
+            .iter()
+            .map(|hole| simplify(hole, tolerance))
+            .filter(|hole| hole.len() >= 3)
+            .collect();
+        bridge_holes(&mut polygon, holes);
+        // room for the repeated first point that closes the line
+        if polygon.len() < MAX_FILL_VERTICES || polygon.len() <= 3 {
+            break polygon;
+        }
+        tolerance *= 1.5;
+    };
+    if polygon.len() < 3 {
+        return None;
+    }
+    Some(
+        polygon
+            .into_iter()
+            .map(|p| region.min + p.to_vec2() / scale)
+            .collect(),
+    )
+}
+
+// Even-odd test, so areas cut out through bridges are outside
+pub(crate) fn contains(outline: &[Pos2], p: Pos2) -> bool {
+    let mut inside = false;
+    for (a, b) in outline.iter().zip(outline.iter().cycle().skip(1)) {
+        if (a.y > p.y) != (b.y > p.y)
+            && p.x < a.x + (p.y - a.y) / (b.y - a.y) * (b.x - a.x)
+        {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+struct Grid {
+    width: usize,
+    height: usize,
+}
+impl Grid {
+    fn index(&self, x: usize, y: usize) -> Option<usize> {
+        (x < self.width && y < self.height).then_some(y * self.width + x)
+    }
+    fn neighbors(&self, i: usize) -> impl Iterator<Item = usize> {
+        let (x, y) = (i % self.width, i / self.width);
+        [
+            (x > 0).then(|| i - 1),
+            (x + 1 < self.width).then(|| i + 1),
+            (y > 0).then(|| i - self.width),
+            (y + 1 < self.height).then(|| i + self.width),
+        ]
+        .into_iter()
+        .flatten()
+    }
+    fn border(&self) -> impl Iterator<Item = usize> {
+        let (w, h) = (self.width, self.height);
+        (0..w)
+            .flat_map(move |x| [x, (h - 1) * w + x])
+            .chain((0..h).flat_map(move |y| [y * w, y * w + w - 1]))
+    }
+    // The pixels 4-connected to `seeds` through pixels that are `open`
+    fn flood(
+        &self,
+        seeds: impl IntoIterator<Item = usize>,
+        open: impl Fn(usize) -> bool,
+    ) -> Vec<bool> {
+        let mut reached = vec![false; self.width * self.height];
+        let mut stack: Vec<usize> = Vec::new();
+        for seed in seeds {
+            if open(seed) && !reached[seed] {
+                reached[seed] = true;
+                stack.push(seed);
+            }
+        }
+        while let Some(i) = stack.pop() {
+            for n in self.neighbors(i) {
+                if !reached[n] && open(n) {
+                    reached[n] = true;
+                    stack.push(n);
+                }
+            }
+        }
+        reached
+    }
+    // `mask` plus the `open` pixels next to it
+    fn grow(&self, mask: &[bool], open: impl Fn(usize) -> bool) -> Vec<bool> {
+        let mut grown = mask.to_vec();
+        for i in 0..mask.len() {
+            if !mask[i] && open(i) && self.neighbors(i).any(|n| mask[n]) {
+                grown[i] = true;
+            }
+        }
+        grown
+    }
+    // The boundaries of `mask` along pixel edges, with the masked pixels on
+    // the left, as corner points in pixel coordinates
+    fn outlines(&self, mask: &[bool]) -> Vec<Vec<Pos2>> {
+        let masked = |x: i32, y: i32| {
+            x >= 0
+                && y >= 0
+                && self.index(x as usize, y as usize).is_some_and(|i| mask[i])
+        };
+        let mut edges: HashMap<(i32, i32), Vec<(i32, i32)>> = HashMap::new();
+        let mut add = |from: (i32, i32), to: (i32, i32)| {
+            edges.entry(from).or_default().push(to);
+        };
+        for y in 0..self.height as i32 {
+            for x in 0..self.width as i32 {
+                if !masked(x, y) {
+                    continue;
+                }
+                if !masked(x, y - 1) {
+                    add((x + 1, y), (x, y));
+                }
+                if !masked(x - 1, y) {
+                    add((x, y), (x, y + 1));
+                }
+                if !masked(x, y + 1) {
+                    add((x, y + 1), (x + 1, y + 1));
+                }
+                if !masked(x + 1, y) {
+                    add((x + 1, y + 1), (x + 1, y));
+                }
+            }
+        }
+        let mut outlines = Vec::new();
+        while let Some(&start) = edges.keys().next() {
+            let mut outline = vec![start];
+            let mut vertex = start;
+            let mut heading: Option<(i32, i32)> = None;
+            while let Some(outgoing) = edges.get_mut(&vertex) {
+                // where two masked pixels touch diagonally, turn left to
+                // stay around the same pixel, keeping them apart
+                let index = heading
+                    .and_then(|(dx, dy)| {
+                        [(dy, -dx), (dx, dy), (-dy, dx)].iter().find_map(
+                            |&step| {
+                                outgoing.iter().position(|&(x, y)| {
+                                    (x - vertex.0, y - vertex.1) == step
+                                })
+                            },
+                        )
+                    })
+                    .unwrap_or(0);
+                let next = outgoing.swap_remove(index);
+                if outgoing.is_empty() {
+                    edges.remove(&vertex);
+                }
+                let step = (next.0 - vertex.0, next.1 - vertex.1);
+                // only keep corners
+                if heading == Some(step) {
+                    outline.pop();
+                }
+                heading = Some(step);
+                vertex = next;
+                if vertex == start {
+                    break;
+                }
+                outline.push(vertex);
+            }
+            outlines.push(
+                outline
+                    .into_iter()
+                    .map(|(x, y)| pos2(x as f32, y as f32))
+                    .collect(),
+            );
+        }
+        outlines
+    }
+}
+
+// Positive for clockwise outlines on screen (y pointing down)
+fn signed_area(outline: &[Pos2]) -> f32 {
+    outline
+        .iter()
+        .zip(outline.iter().cycle().skip(1))
+        .map(|(a, b)| a.x * b.y - b.x * a.y)
+        .sum::<f32>()
+        / 2.0
+}
+
+// Douglas-Peucker on a closed outline, split at the point farthest from
+// the first one
+fn simplify(outline: &[Pos2], tolerance: f32) -> Vec<Pos2> {
+    let Some(far) = (0..outline.len()).max_by(|&a, &b| {
+        outline[0]
+            .distance_sq(outline[a])
+            .total_cmp(&outline[0].distance_sq(outline[b]))
+    }) else {
+        return Vec::new();
+    };
+    let mut keep = vec![false; outline.len() + 1];
+    keep[0] = true;
+    keep[far] = true;
+    let closed: Vec<Pos2> =
+        outline.iter().chain(outline.first()).copied().collect();
+    let mut ranges = vec![(0, far), (far, outline.len())];
+    while let Some((from, to)) = ranges.pop() {
+        let farthest = (from + 1..to)
+            .map(|i| {
+                let d = distance_point_to_segment(
+                    closed[i],
+                    closed[from],
+                    closed[to],
+                );
+                (i, d)
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((i, d)) = farthest
+            && d > tolerance
+        {
+            keep[i] = true;
+            ranges.push((from, i));
+            ranges.push((i, to));
+        }
+    }
+    (0..outline.len())
+        .filter(|&i| keep[i])
+        .map(|i| outline[i])
+        .collect()
+}
+
+// Joins each hole (running the other way round) to the outline with a
+// zero-width cut from its leftmost point to the nearest outline vertex the
+// cut doesn't cross anything on the way to
+fn bridge_holes(outline: &mut Vec<Pos2>, mut holes: Vec<Vec<Pos2>>) {
+    let leftmost = |hole: &[Pos2]| {
+        (0..hole.len())
+            .min_by(|&a, &b| hole[a].x.total_cmp(&hole[b].x))
+            .unwrap_or(0)
+    };
+    holes.sort_by(|a, b| a[leftmost(a)].x.total_cmp(&b[leftmost(b)].x));
+    for h in 0..holes.len() {
+        let hole = &holes[h];
+        let start = leftmost(hole);
+        let from = hole[start];
+        let crosses = |to: Pos2| {
+            edges(outline)
+                .chain(holes[h..].iter().flat_map(|hole| edges(hole)))
+                .any(|(a, b)| segments_cross(from, to, a, b))
+        };
+        let mut candidates: Vec<usize> = (0..outline.len())
+            // earlier cuts end twice in the same spot; which of the two
+            // to join would need more care
+            .filter(|&i| {
+                outline.iter().filter(|&&p| p == outline[i]).count() == 1
+            })
+            .collect();
+        candidates.sort_by(|&a, &b| {
+            from.distance_sq(outline[a])
+                .total_cmp(&from.distance_sq(outline[b]))
+        });
+        let Some(joint) =
+            candidates.into_iter().find(|&i| !crosses(outline[i]))
+        else {
+            continue;
+        };
+        let mut joined: Vec<Pos2> = outline[..=joint].to_vec();
+        joined.extend(hole[start..].iter().chain(&hole[..=start]));
+        joined.extend(&outline[joint..]);
+        *outline = joined;
+    }
+}
+
+fn edges(outline: &[Pos2]) -> impl Iterator<Item = (Pos2, Pos2)> {
+    outline
+        .iter()
+        .copied()
+        .zip(outline.iter().copied().cycle().skip(1))
+}
+
+// Whether the segments cross at a point inside both; touching ends don't
+// count
+fn segments_cross(a: Pos2, b: Pos2, c: Pos2, d: Pos2) -> bool {
+    let side = |from: Pos2, to: Pos2, p: Pos2| {
+        (to - from).x * (p - from).y - (to - from).y * (p - from).x
+    };
+    side(a, b, c) * side(a, b, d) < 0.0 && side(c, d, a) * side(c, d, b) < 0.0
+}
@@ -0,0 +1,98 @@
+use egui::{Color32, Pos2, pos2, vec2};
+
+use crate::{
+    Line, MAX_STROKE_WIDTH, MIN_STROKE_WIDTH, WhiteboardApp, is_finite_pos,
+    new_line_id, stroke::LineStyle,
+};
+
+// Sets up a board with known content, without a window or the user's
+// settings, e.g. for tests and scripts:
+//
+//     let app = WhiteboardBuilder::new()
+//         .palette(vec![Color32::BLACK, Color32::RED])
+//         .line([pos2(0.0, 0.0), pos2(50.0, 20.0)], Color32::RED, 3.0)
+//         .build();
+//     assert_eq!(app.line_count(), 1);
+#[derive(Default)]
+pub struct WhiteboardBuilder {
+    lines: Vec<Line>,
+    palette: Vec<Color32>,
+}
+impl WhiteboardBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    // Adds a solid stroke through `points`. Non-finite points are left out
+    // and the width is kept within what the brush allows; a stroke without
+    // points isn't added.
+    pub fn line(
+        mut self,
+        points: impl IntoIterator<Item = Pos2>,
+        color: Color32,
+        width: f32,
+    ) -> Self {
+        let points: Vec<Pos2> =
+            points.into_iter().filter(|p| is_finite_pos(*p)).collect();
+        if points.is_empty() {
+            return self;
+        }
+        let width = if width.is_finite() {
+            width.clamp(MIN_STROKE_WIDTH, MAX_STROKE_WIDTH)
+        } else {
+            MIN_STROKE_WIDTH
+        };
+        self.lines.push(Line {
+            points,
+            color,
+            width,
+            widths: Vec::new(),
+            style: LineStyle::Solid,
+            created_ms: 0,
+            fill: None,
+            fill_only: false,
+            picture: None,
+            locked: false,
+            id: new_line_id(),
+        });
+        self
+    }
+    // Adds `count` wandering strokes of `points` points each, spread over a
+    // square of `size` units. The same arguments always give the same
+    // strokes, so benchmarks and tests can compare boards of any size.
+    pub fn scribbles(mut self, count: usize, points: usize, size: f32) -> Self {
+        // xorshift, plenty random for strokes
+        let mut state: u32 = 0x9e37_79b9;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as f32 / u32::MAX as f32
+        };
+        for _ in 0..count {
+            let mut pos = pos2(next() * size, next() * size);
+            let stroke: Vec<Pos2> = (0..points)
+                .map(|_| {
+                    pos += vec2(next() - 0.5, next() - 0.5) * 20.0;
+                    pos
+                })
+                .collect();
+            self = self.line(stroke, Color32::WHITE, 3.0);
+        }
+        self
+    }
+    // The colors to pick from, the default palette if empty
+    pub fn palette(mut self, colors: Vec<Color32>) -> Self {
+        self.palette = colors;
+        self
+    }
+    // The board holds the lines as if they had been opened from a file:
+    // nothing to undo and no unsaved changes
+    pub fn build(self) -> WhiteboardApp {
+        let mut app = WhiteboardApp::default();
+        if !self.palette.is_empty() {
+            app.document.palette = self.palette.into();
+        }
+        app.document.lines = self.lines;
+        app
+    }
+}
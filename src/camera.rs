@@ -0,0 +1,65 @@
+use eframe::egui;
+use egui::{Pos2, Rect, Vec2};
+
+pub(crate) const MIN_ZOOM: f32 = 0.1;
+pub(crate) const MAX_ZOOM: f32 = 8.0;
+// screen pixels left around the content by `fit`
+const FIT_MARGIN: f32 = 40.0;
+
+// Maps canvas (world) coordinates, which lines are stored in, to screen
+// coordinates: screen = world * zoom + pan. The default camera is the
+// identity, so boards saved before the view could move still line up.
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) struct Camera {
+    pub pan: Vec2,
+    pub zoom: f32,
+}
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            pan: Vec2::ZERO,
+            zoom: 1.0,
+        }
+    }
+}
+impl Camera {
+    pub fn to_screen(self, pos: Pos2) -> Pos2 {
+        (pos.to_vec2() * self.zoom + self.pan).to_pos2()
+    }
+    pub fn to_world(self, pos: Pos2) -> Pos2 {
+        ((pos.to_vec2() - self.pan) / self.zoom).to_pos2()
+    }
+    pub fn rect_to_screen(&self, rect: Rect) -> Rect {
+        Rect::from_min_max(self.to_screen(rect.min), self.to_screen(rect.max))
+    }
+    pub fn rect_to_world(&self, rect: Rect) -> Rect {
+        Rect::from_min_max(self.to_world(rect.min), self.to_world(rect.max))
+    }
+    // Zooms by `factor` while keeping the canvas point under `screen_pos`
+    // in place
+    pub fn zoom_around(&mut self, screen_pos: Pos2, factor: f32) {
+        self.zoom_to(screen_pos, self.zoom * factor);
+    }
+    pub fn zoom_to(&mut self, screen_pos: Pos2, zoom: f32) {
+        let anchor = self.to_world(screen_pos);
+        self.zoom = zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+        self.pan = screen_pos.to_vec2() - anchor.to_vec2() * self.zoom;
+    }
+    // Zooms and pans so `content` fills `viewport` with a small margin.
+    // Without content the view goes back to 1:1.
+    pub fn fit(&mut self, content: Rect, viewport: Rect) {
+        if !content.is_finite() || content.is_negative() {
+            *self = Self::default();
+            return;
+        }
+        let available = (viewport.size() - Vec2::splat(2.0 * FIT_MARGIN))
+            .max(Vec2::splat(1.0));
+        // a single dot or a straight line has no extent along one axis
+        let size = content.size().max(Vec2::splat(1.0));
+        self.zoom = (available.x / size.x)
+            .min(available.y / size.y)
+            .clamp(MIN_ZOOM, MAX_ZOOM);
+        self.pan = viewport.center().to_vec2()
+            - content.center().to_vec2() * self.zoom;
+    }
+}
@@ -0,0 +1,502 @@
+use std::{
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use eframe::egui;
+use egui::Color32;
+
+use crate::{
+    Line, atomic, colors, crypto,
+    diff::BoardDiff,
+    document::Document,
+    format::{self, Encoding},
+    points,
+    render::{self, ImageSettings},
+    state::WhiteboardState,
+    svg::{self, SvgSettings},
+};
+
+const RENDER_USAGE: &str = "\
+usage: whiteboard render <input> -o <output.png|output.jpg> [options]
+
+options:
+  -o, --output <path>     the image to write
+  --scale <factor>        pixels per board unit, 1 by default
+  --background <color>    dark (the default), light, transparent or #rrggbb
+  --page <number>         the page to render, the one shown when saving by
+                          default
+  --all-pages             every page side by side";
+const CONVERT_USAGE: &str = "\
+usage: whiteboard convert <input> -o <output.svg> [options]
+
+options:
+  -o, --output <path>     the SVG file to write
+  --fit                   crop the view to the strokes instead of also
+                          taking in the board origin
+  --margin <units>        room around the strokes, 10 by default
+  --background <color>    dark (the default), light, transparent or #rrggbb
+  --page <number>         the page to convert, the one shown when saving by
+                          default
+  --all-pages             every page side by side";
+const APPLY_USAGE: &str = "\
+usage: whiteboard apply <operations.json> -o <output> [options]
+
+Applies a JSON array of operations, such as
+{\"op\": \"draw\", \"points\": [[0, 0], [10, 20]], \"color\": \"#ff0000\"},
+{\"op\": \"erase_at\", \"x\": 10, \"y\": 20}, {\"op\": \"clear\"} and
+{\"op\": \"undo\"}, to the page shown when the board was saved.
+
+options:
+  -o, --out <path>        the whiteboard file to write
+  --in <path>             the whiteboard file to start from, an empty board
+                          by default
+  --page <number>         the page to apply the operations to";
+const POINTS_USAGE: &str = "\
+usage: whiteboard points <input> -o <output.csv|output.json> [options]
+
+Writes the points of every stroke, one row each: stroke, point, x, y,
+color and width. Strokes are numbered in drawing order.
+
+options:
+  -o, --output <path>     the CSV or JSON file to write
+  --normalize             coordinates from 0 to 1 across the longer side of
+                          the strokes' bounding box
+  --page <number>         the page to export, the one shown when saving by
+                          default";
+const DIFF_USAGE: &str = "\
+usage: whiteboard diff <old> <new> [options]
+
+Compares the strokes of two boards page by page and reports the ones added,
+removed and modified. Files don't store stroke ids, so a stroke counts as
+modified when one with the same creation time changed. Exits with 0 if the
+boards are identical, 1 if they differ and 2 on errors.
+
+options:
+  --summary               a line per page that differs, the default
+  --json                  the differences as JSON, with the strokes'
+                          indexes on their page
+  -o, --output <path>     also draw the pages that differ into a PNG, with
+                          removed strokes in red and added ones in green";
+// like the export window's scale field
+const MAX_SCALE: f32 = 8.0;
+// exit statuses: failures while rendering, and bad arguments
+const FAILURE: i32 = 1;
+const USAGE_ERROR: i32 = 2;
+// diff exits like diff(1) does: 1 when the boards differ, 2 on any error
+const DIFFERENT: i32 = 1;
+const DIFF_FAILURE: i32 = 2;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Command {
+    // rasterize to PNG or JPEG
+    Render,
+    // write SVG
+    Convert,
+    // edit a board with an operation log
+    Apply,
+    // write the points of the strokes as CSV or JSON
+    Points,
+    // compare two boards
+    Diff,
+}
+impl Command {
+    fn name(self) -> &'static str {
+        match self {
+            Command::Render => "render",
+            Command::Convert => "convert",
+            Command::Apply => "apply",
+            Command::Points => "points",
+            Command::Diff => "diff",
+        }
+    }
+    // whether the command draws the board, with a background and maybe
+    // several pages
+    fn draws(self) -> bool {
+        matches!(self, Command::Render | Command::Convert)
+    }
+    fn usage(self) -> &'static str {
+        match self {
+            Command::Render => RENDER_USAGE,
+            Command::Convert => CONVERT_USAGE,
+            Command::Apply => APPLY_USAGE,
+            Command::Points => POINTS_USAGE,
+            Command::Diff => DIFF_USAGE,
+        }
+    }
+}
+
+enum Pages {
+    Saved,
+    One(usize),
+    All,
+}
+
+struct CommandArgs {
+    input: PathBuf,
+    output: PathBuf,
+    background: Option<Color32>,
+    pages: Pages,
+    // render only
+    scale: f32,
+    // convert only
+    svg: SvgSettings,
+    // apply only, the board the operations start from
+    board: Option<PathBuf>,
+    // points only
+    normalize: bool,
+}
+
+// Runs the command in `args` (without the program name) and returns its exit
+// status, or `None` if the arguments don't name a command and the app should
+// start as usual
+pub(crate) fn run(args: &[OsString]) -> Option<i32> {
+    let command = match args.first()?.to_str()? {
+        "render" => Command::Render,
+        "convert" => Command::Convert,
+        "apply" => Command::Apply,
+        "points" => Command::Points,
+        "diff" => Command::Diff,
+        _ => return None,
+    };
+    let name = command.name();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", command.usage());
+        return Some(0);
+    }
+    // two inputs and no required output, unlike the other commands
+    if command == Command::Diff {
+        return Some(diff_files(&args[1..]));
+    }
+    let args = match parse_args(command, &args[1..]) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("whiteboard {name}: {e}\n\n{}", command.usage());
+            return Some(USAGE_ERROR);
+        }
+    };
+    let result = match command {
+        Command::Render => render_file(&args),
+        Command::Convert => convert_file(&args),
+        Command::Apply => apply_file(&args),
+        Command::Points => export_points(&args),
+        Command::Diff => unreachable!("diff parses its own arguments"),
+    };
+    Some(match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("whiteboard {name}: {e}");
+            FAILURE
+        }
+    })
+}
+
+fn parse_args(
+    command: Command,
+    args: &[OsString],
+) -> Result<CommandArgs, String> {
+    let mut input = None;
+    let mut output = None;
+    let mut background = Some(render::BACKGROUND);
+    let mut pages = Pages::Saved;
+    let mut board = None;
+    let mut normalize = false;
+    let mut scale = 1.0;
+    let mut svg = SvgSettings {
+        fit: false,
+        ..Default::default()
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .ok_or_else(|| format!("{name} needs a value"))
+                .map(|value| value.to_string_lossy().into_owned())
+        };
+        match arg.to_str() {
+            Some("-o" | "--output" | "--out") => {
+                output = Some(PathBuf::from(value("--output")?));
+            }
+            Some("--background") if command.draws() => {
+                background = parse_background(&value("--background")?)?;
+            }
+            Some("--page") => {
+                let page = value("--page")?
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|page| *page > 0)
+                    .ok_or("--page must be a page number, starting at 1")?;
+                pages = Pages::One(page - 1);
+            }
+            Some("--all-pages") if command.draws() => {
+                pages = Pages::All;
+            }
+            Some("--scale") if command == Command::Render => {
+                scale = value("--scale")?
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|scale| *scale > 0.0 && *scale <= MAX_SCALE)
+                    .ok_or_else(|| {
+                        format!(
+                            "--scale must be a number above 0 and up to \
+                             {MAX_SCALE}"
+                        )
+                    })?;
+            }
+            Some("--fit") if command == Command::Convert => svg.fit = true,
+            Some("--margin") if command == Command::Convert => {
+                svg.margin = value("--margin")?
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|margin| margin.is_finite() && *margin >= 0.0)
+                    .ok_or("--margin must be a number of at least 0")?;
+            }
+            Some("--in") if command == Command::Apply => {
+                board = Some(PathBuf::from(value("--in")?));
+            }
+            Some("--normalize") if command == Command::Points => {
+                normalize = true;
+            }
+            Some(option) if option.starts_with('-') => {
+                return Err(format!("unknown option {option}"));
+            }
+            _ if input.is_none() => input = Some(PathBuf::from(arg)),
+            _ => {
+                return Err(format!(
+                    "unexpected argument {}",
+                    arg.to_string_lossy()
+                ));
+            }
+        }
+    }
+    let input = input.ok_or("no input file given")?;
+    let output = output.ok_or("no output file given, use -o")?;
+    if background.is_none() && render::is_jpeg(&output) {
+        return Err("JPEG images can't have a transparent background".into());
+    }
+    svg.background = background;
+    Ok(CommandArgs {
+        input,
+        output,
+        background,
+        pages,
+        scale,
+        svg,
+        board,
+        normalize,
+    })
+}
+
+// `None` is a transparent background
+fn parse_background(text: &str) -> Result<Option<Color32>, String> {
+    match text {
+        "transparent" => Ok(None),
+        "dark" => Ok(Some(egui::Visuals::dark().panel_fill)),
+        "light" => Ok(Some(egui::Visuals::light().panel_fill)),
+        _ => colors::parse_hex(text).map(Some).ok_or_else(|| {
+            format!(
+                "unknown background {text}, expected dark, light, \
+                 transparent or #rrggbb"
+            )
+        }),
+    }
+}
+
+fn render_file(args: &CommandArgs) -> Result<(), String> {
+    let state = load(&args.input)?;
+    let lines = page_lines(&state, &args.pages)?;
+    let settings = ImageSettings {
+        scale: args.scale,
+        all_pages: matches!(args.pages, Pages::All),
+        ..Default::default()
+    };
+    render::export_image(&lines, &settings, args.background, &args.output)
+        .map_err(|e| format!("failed to write {}: {e}", args.output.display()))
+}
+
+fn convert_file(args: &CommandArgs) -> Result<(), String> {
+    let state = load(&args.input)?;
+    let lines = page_lines(&state, &args.pages)?;
+    let count = svg::export(&lines, &args.svg, &args.output).map_err(|e| {
+        format!("failed to write {}: {e}", args.output.display())
+    })?;
+    println!("Converted {count} strokes to {}", args.output.display());
+    Ok(())
+}
+
+fn apply_file(args: &CommandArgs) -> Result<(), String> {
+    let json = fs::read_to_string(&args.input)
+        .map_err(|e| format!("failed to read {}: {e}", args.input.display()))?;
+    // a board given to start from keeps its properties and brush settings
+    let mut state = args.board.as_deref().map(load).transpose()?;
+    let mut document =
+        state.as_ref().map(Document::from_state).unwrap_or_default();
+    let shown = document.current_page;
+    if let Pages::One(page) = args.pages
+        && page != shown
+        && !document.go_to_page(page)
+    {
+        return Err(format!(
+            "page {} doesn't exist, the board has {}",
+            page + 1,
+            document.page_count()
+        ));
+    }
+    let count = document
+        .apply_ops(&json)
+        .map_err(|e| format!("{}: {e}", args.input.display()))?;
+    // the board still opens on the page it was saved on
+    document.go_to_page(shown);
+    match &mut state {
+        Some(state) => {
+            state.set_pages(&document);
+            format::encode(Encoding::from_extension(&args.output), state, false)
+                .and_then(|bytes| atomic::write(&args.output, &bytes))
+        }
+        None => document.save_to(&args.output),
+    }
+    .map_err(|e| format!("failed to write {}: {e}", args.output.display()))?;
+    println!("Applied {count} operations to {}", args.output.display());
+    Ok(())
+}
+
+fn export_points(args: &CommandArgs) -> Result<(), String> {
+    let state = load(&args.input)?;
+    let lines = page_lines(&state, &args.pages)?;
+    let strokes: Vec<(usize, &Line)> = lines.iter().enumerate().collect();
+    let count = points::export(&strokes, args.normalize, &args.output)
+        .map_err(|e| {
+            format!("failed to write {}: {e}", args.output.display())
+        })?;
+    println!("Exported {count} points to {}", args.output.display());
+    Ok(())
+}
+
+// Reads a whiteboard file the way the app opens it, minus the dialogs: a
+// file that only loads partially is an error here
+pub(crate) fn load(path: &Path) -> Result<WhiteboardState, String> {
+    let bytes = fs::read(path)
+        .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+    if crypto::is_encrypted(&bytes) {
+        return Err(format!(
+            "{} is encrypted with a passphrase, open it in the app instead",
+            path.display()
+        ));
+    }
+    format::decode(&bytes)
+        .map_err(|e| format!("failed to load {}: {e}", path.display()))
+}
+
+fn page_lines(
+    state: &WhiteboardState,
+    pages: &Pages,
+) -> Result<Vec<Line>, String> {
+    let mut document = Document::from_state(state);
+    if let Pages::One(page) = *pages
+        && page != document.current_page
+        && !document.go_to_page(page)
+    {
+        return Err(format!(
+            "page {} doesn't exist, the board has {}",
+            page + 1,
+            document.page_count()
+        ));
+    }
+    Ok(document.export_lines(matches!(pages, Pages::All)))
+}
+
+struct DiffArgs {
+    old: PathBuf,
+    new: PathBuf,
+    json: bool,
+    // the visual diff to draw
+    image: Option<PathBuf>,
+}
+
+fn parse_diff_args(args: &[OsString]) -> Result<DiffArgs, String> {
+    let mut inputs = Vec::new();
+    let mut json = false;
+    let mut image = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.to_str() {
+            Some("--summary") => json = false,
+            Some("--json") => json = true,
+            Some("-o" | "--output") => {
+                let path = args.next().ok_or("--output needs a value")?;
+                image = Some(PathBuf::from(path));
+            }
+            Some(option) if option.starts_with('-') => {
+                return Err(format!("unknown option {option}"));
+            }
+            _ if inputs.len() < 2 => inputs.push(PathBuf::from(arg)),
+            _ => {
+                return Err(format!(
+                    "unexpected argument {}",
+                    arg.to_string_lossy()
+                ));
+            }
+        }
+    }
+    let [old, new] = <[PathBuf; 2]>::try_from(inputs)
+        .map_err(|_| "diff needs two whiteboard files")?;
+    Ok(DiffArgs {
+        old,
+        new,
+        json,
+        image,
+    })
+}
+
+// Returns the exit status
+fn diff_files(args: &[OsString]) -> i32 {
+    let args = match parse_diff_args(args) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("whiteboard diff: {e}\n\n{DIFF_USAGE}");
+            return USAGE_ERROR;
+        }
+    };
+    match diff(&args) {
+        Ok(true) => 0,
+        Ok(false) => DIFFERENT,
+        Err(e) => {
+            eprintln!("whiteboard diff: {e}");
+            DIFF_FAILURE
+        }
+    }
+}
+
+// Prints the differences, returning whether there are none
+fn diff(args: &DiffArgs) -> Result<bool, String> {
+    let load = |path: &Path| {
+        Document::load_from(path)
+            .map_err(|e| format!("failed to load {}: {e}", path.display()))
+    };
+    let old = load(&args.old)?;
+    let new = load(&args.new)?;
+    let diff = BoardDiff::new(&old, &new);
+    if args.json {
+        let json =
+            serde_json::to_string_pretty(&diff).map_err(|e| e.to_string())?;
+        println!("{json}");
+    } else {
+        println!("{}", diff.summary());
+    }
+    // identical boards have nothing to draw
+    if let Some(image) = &args.image
+        && !diff.is_identical()
+    {
+        let pages = diff.image_pages(&old, &new);
+        let lines = render::side_by_side(pages.iter().map(Vec::as_slice));
+        render::export_image(
+            &lines,
+            &ImageSettings::default(),
+            Some(render::BACKGROUND),
+            image,
+        )
+        .map_err(|e| format!("failed to write {}: {e}", image.display()))?;
+    }
+    Ok(diff.is_identical())
+}
@@ -0,0 +1,438 @@
+use std::{
+    io,
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        Arc, Mutex, MutexGuard, PoisonError,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+    },
+    thread,
+    time::Duration,
+};
+
+use chacha20poly1305::aead::{OsRng, rand_core::RngCore};
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use tungstenite::{
+    WebSocket,
+    client::IntoClientRequest,
+    handshake::server::{ErrorResponse, Request, Response},
+    http::{StatusCode, Uri},
+};
+
+use crate::state::LineState;
+
+pub(crate) const DEFAULT_PORT: u16 = 9053;
+// how long the connection threads wait for the other end before looking
+// for something to send
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+// Guests send the key the host was started with in this header of the
+// WebSocket handshake. Without the right one the host turns them away,
+// so only people the key was given to can join.
+const KEY_HEADER: &str = "x-whiteboard-key";
+
+// What the people sharing a board send each other, as JSON text messages.
+// Lines are told apart by their id since their positions differ from board
+// to board; when two people change the same line, the last change to
+// arrive wins.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum Message {
+    // every page of the host's board, sent to each guest that joins
+    Board {
+        pages: Vec<Vec<(u64, LineState)>>,
+    },
+    // a new line, or a newer version of one
+    Put {
+        page: usize,
+        id: u64,
+        line: LineState,
+    },
+    Remove {
+        page: usize,
+        ids: Vec<u64>,
+    },
+}
+
+pub(crate) enum Event {
+    // a guest joined, the host sends them the board
+    Joined(usize),
+    Left,
+    Received(Message),
+    // the connection to the host is gone
+    Disconnected(String),
+}
+
+pub(crate) enum Role {
+    Host { port: u16, key: String },
+    Guest { address: String },
+}
+
+// The open connections: a host with its guests, or a guest with the host.
+// They run on their own threads until the session is dropped.
+pub(crate) struct Session {
+    role: Role,
+    events: Receiver<Event>,
+    connections: Arc<Connections>,
+}
+
+// Shared with the connection threads
+struct Connections {
+    // one queue of outgoing text per open connection
+    peers: Mutex<Vec<(usize, Sender<String>)>>,
+    events: Sender<Event>,
+    stop: AtomicBool,
+    ctx: egui::Context,
+}
+impl Connections {
+    fn new(events: Sender<Event>, ctx: &egui::Context) -> Arc<Self> {
+        Arc::new(Self {
+            peers: Mutex::new(Vec::new()),
+            events,
+            stop: AtomicBool::new(false),
+            ctx: ctx.clone(),
+        })
+    }
+    fn event(&self, event: Event) {
+        let _ = self.events.send(event);
+        self.ctx.request_repaint();
+    }
+    // A thread that panicked while holding the lock can't have left the
+    // list half changed, so the others carry on with it
+    fn peers(&self) -> MutexGuard<'_, Vec<(usize, Sender<String>)>> {
+        self.peers.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+    fn add_peer(&self, id: usize) -> Receiver<String> {
+        let (sender, receiver) = mpsc::channel();
+        self.peers().push((id, sender));
+        receiver
+    }
+    fn remove_peer(&self, id: usize) {
+        self.peers().retain(|(peer, _)| *peer != id);
+    }
+    // Queues `text` for every connection except `except`
+    fn broadcast(&self, text: &str, except: Option<usize>) {
+        for (peer, sender) in self.peers().iter() {
+            if Some(*peer) != except {
+                let _ = sender.send(text.to_owned());
+            }
+        }
+    }
+}
+
+impl Session {
+    // Listens for guests on `port` of every network interface, any free
+    // port if it's 0. Guests need the key from `key()` to join.
+    pub fn host(port: u16, ctx: &egui::Context) -> io::Result<Self> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        listener.set_nonblocking(true)?;
+        let port = listener.local_addr()?.port();
+        let key = format!("{:016x}", OsRng.next_u64());
+        let (events, receiver) = mpsc::channel();
+        let connections = Connections::new(events, ctx);
+        let shared = Arc::clone(&connections);
+        let guest_key = key.clone();
+        thread::spawn(move || accept_guests(&listener, &guest_key, &shared));
+        Ok(Self {
+            role: Role::Host { port, key },
+            events: receiver,
+            connections,
+        })
+    }
+    // Connects to a host at `address`, like `192.168.1.20:9053` or
+    // `ws://192.168.1.20:9053`, with the key it gave out. Blocks until the
+    // host answers or the connection times out.
+    pub fn join(
+        address: &str,
+        key: &str,
+        ctx: &egui::Context,
+    ) -> Result<Self, String> {
+        let url = if address.contains("://") {
+            address.to_owned()
+        } else {
+            format!("ws://{address}")
+        };
+        let uri: Uri = url
+            .parse()
+            .map_err(|_| format!("{address} is not a valid address"))?;
+        let host = uri.host().ok_or("the address has no host name")?;
+        let port = uri.port_u16().unwrap_or(DEFAULT_PORT);
+        let socket_address = (host, port)
+            .to_socket_addrs()
+            .map_err(|e| format!("{host}: {e}"))?
+            .next()
+            .ok_or_else(|| format!("{host} has no address"))?;
+        let stream =
+            TcpStream::connect_timeout(&socket_address, CONNECT_TIMEOUT)
+                .map_err(|e| e.to_string())?;
+        stream
+            .set_read_timeout(Some(CONNECT_TIMEOUT))
+            .map_err(|e| e.to_string())?;
+        let mut request =
+            url.into_client_request().map_err(|e| e.to_string())?;
+        request.headers_mut().insert(
+            KEY_HEADER,
+            key.trim()
+                .parse()
+                .map_err(|_| "the key can only hold letters and digits")?,
+        );
+        let (socket, _) =
+            tungstenite::client(request, stream).map_err(|e| match e {
+                tungstenite::HandshakeError::Failure(
+                    tungstenite::Error::Http(response),
+                ) if response.status() == StatusCode::FORBIDDEN => {
+                    "the host didn't accept the key".to_owned()
+                }
+                e => e.to_string(),
+            })?;
+        socket
+            .get_ref()
+            .set_read_timeout(Some(POLL_INTERVAL))
+            .map_err(|e| e.to_string())?;
+        let (events, receiver) = mpsc::channel();
+        let connections = Connections::new(events, ctx);
+        let shared = Arc::clone(&connections);
+        // before returning, so nothing sent right away gets lost
+        let outgoing = connections.add_peer(0);
+        thread::spawn(move || {
+            if let Err(reason) = exchange(socket, 0, &outgoing, &shared, false)
+            {
+                shared.remove_peer(0);
+                shared.event(Event::Disconnected(reason));
+            }
+        });
+        Ok(Self {
+            role: Role::Guest {
+                address: address.to_owned(),
+            },
+            events: receiver,
+            connections,
+        })
+    }
+    // The events since the last call
+    pub fn events(&self) -> impl Iterator<Item = Event> + '_ {
+        self.events.try_iter()
+    }
+    // Sends to everyone on the other end
+    pub fn send(&self, message: &Message) {
+        if let Ok(text) = serde_json::to_string(message) {
+            self.connections.broadcast(&text, None);
+        }
+    }
+    pub fn send_to(&self, peer: usize, message: &Message) {
+        let Ok(text) = serde_json::to_string(message) else {
+            return;
+        };
+        let peers = self.connections.peers();
+        if let Some((_, sender)) = peers.iter().find(|(id, _)| *id == peer) {
+            let _ = sender.send(text);
+        }
+    }
+    // The port a session being hosted listens on, 0 for a guest
+    pub fn port(&self) -> u16 {
+        match &self.role {
+            Role::Host { port, .. } => *port,
+            Role::Guest { .. } => 0,
+        }
+    }
+    // The key guests need, for a session being hosted
+    pub fn key(&self) -> Option<&str> {
+        match &self.role {
+            Role::Host { key, .. } => Some(key),
+            Role::Guest { .. } => None,
+        }
+    }
+    // "Hosting on port 9053, 2 guests" or "Connected to 192.168.1.20"
+    pub fn describe(&self) -> String {
+        match &self.role {
+            Role::Host { port, .. } => {
+                let guests = self.connections.peers().len();
+                let plural = if guests == 1 { "" } else { "s" };
+                format!("Hosting on port {port}, {guests} guest{plural}")
+            }
+            Role::Guest { address } => format!("Connected to {address}"),
+        }
+    }
+}
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.connections.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+fn accept_guests(
+    listener: &TcpListener,
+    key: &str,
+    connections: &Arc<Connections>,
+) {
+    let mut next_id = 0;
+    while !connections.stop.load(Ordering::Relaxed) {
+        let stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(POLL_INTERVAL * 5);
+                continue;
+            }
+            Err(e) => {
+                eprintln!("failed to accept a guest: {e}");
+                continue;
+            }
+        };
+        let id = next_id;
+        next_id += 1;
+        let connections = Arc::clone(connections);
+        let key = key.to_owned();
+        thread::spawn(move || {
+            if let Err(e) = serve_guest(stream, id, &key, &connections) {
+                eprintln!("guest {id} disconnected: {e}");
+            }
+        });
+    }
+}
+
+fn serve_guest(
+    stream: TcpStream,
+    id: usize,
+    key: &str,
+    connections: &Connections,
+) -> Result<(), String> {
+    // accepted streams inherit non-blocking mode on some platforms
+    stream.set_nonblocking(false).map_err(|e| e.to_string())?;
+    stream
+        .set_read_timeout(Some(CONNECT_TIMEOUT))
+        .map_err(|e| e.to_string())?;
+    // tungstenite decides the shape of the refusal
+    #[allow(clippy::result_large_err)]
+    let check_key = |request: &Request, response: Response| {
+        let sent = request.headers().get(KEY_HEADER);
+        if sent.is_some_and(|sent| sent.as_bytes() == key.as_bytes()) {
+            Ok(response)
+        } else {
+            let mut refusal = ErrorResponse::new(Some("wrong key".to_owned()));
+            *refusal.status_mut() = StatusCode::FORBIDDEN;
+            Err(refusal)
+        }
+    };
+    let socket = tungstenite::accept_hdr(stream, check_key)
+        .map_err(|e| e.to_string())?;
+    socket
+        .get_ref()
+        .set_read_timeout(Some(POLL_INTERVAL))
+        .map_err(|e| e.to_string())?;
+    let outgoing = connections.add_peer(id);
+    connections.event(Event::Joined(id));
+    let result = exchange(socket, id, &outgoing, connections, true);
+    // guests turned away above never joined, so they don't leave either
+    connections.remove_peer(id);
+    connections.event(Event::Left);
+    result
+}
+
+// Passes messages both ways until the connection closes or the session
+// stops. The host relays what a guest sends to the other guests, except
+// whole boards: only the host sends those.
+fn exchange(
+    mut socket: WebSocket<TcpStream>,
+    id: usize,
+    outgoing: &Receiver<String>,
+    connections: &Connections,
+    relay: bool,
+) -> Result<(), String> {
+    loop {
+        if connections.stop.load(Ordering::Relaxed) {
+            let _ = socket.close(None);
+            let _ = socket.flush();
+            return Ok(());
+        }
+        match socket.read() {
+            Ok(tungstenite::Message::Text(text)) => {
+                match serde_json::from_str(&text) {
+                    Ok(Message::Board { .. }) if relay => {
+                        eprintln!("ignoring a board sent by guest {id}");
+                    }
+                    Ok(message) => {
+                        if relay {
+                            connections.broadcast(&text, Some(id));
+                        }
+                        connections.event(Event::Received(message));
+                    }
+                    Err(e) => eprintln!("ignoring an unreadable message: {e}"),
+                }
+            }
+            Ok(tungstenite::Message::Close(_)) => {
+                return Err("the connection was closed".to_owned());
+            }
+            // pings are answered by tungstenite itself
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(e))
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) => {}
+            Err(
+                tungstenite::Error::ConnectionClosed
+                | tungstenite::Error::AlreadyClosed,
+            ) => {
+                return Err("the connection was closed".to_owned());
+            }
+            Err(e) => return Err(e.to_string()),
+        }
+        for text in outgoing.try_iter() {
+            socket
+                .send(tungstenite::Message::text(text))
+                .map_err(|e| e.to_string())?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    // Waits for the next event, failing the test after a few seconds
+    fn next_event(session: &Session) -> Event {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            if let Some(event) = session.events().next() {
+                return event;
+            }
+            assert!(Instant::now() < deadline, "no event arrived");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn guests_need_the_key() {
+        let ctx = egui::Context::default();
+        let host = Session::host(0, &ctx).unwrap();
+        let address = format!("127.0.0.1:{}", host.port());
+        assert!(Session::join(&address, "wrong", &ctx).is_err());
+        let key = host.key().unwrap();
+        assert!(Session::join(&address, key, &ctx).is_ok());
+        assert!(matches!(next_event(&host), Event::Joined(_)));
+    }
+
+    #[test]
+    fn boards_from_guests_are_dropped() {
+        let ctx = egui::Context::default();
+        let host = Session::host(0, &ctx).unwrap();
+        let address = format!("127.0.0.1:{}", host.port());
+        let guest = Session::join(&address, host.key().unwrap(), &ctx).unwrap();
+        assert!(matches!(next_event(&host), Event::Joined(_)));
+        guest.send(&Message::Board { pages: Vec::new() });
+        guest.send(&Message::Remove {
+            page: 0,
+            ids: vec![7],
+        });
+        // messages arrive in order, so the board would have come first
+        match next_event(&host) {
+            Event::Received(Message::Remove { ids, .. }) => {
+                assert_eq!(ids, [7]);
+            }
+            _ => panic!("expected the guest's removal"),
+        }
+    }
+}
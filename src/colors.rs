@@ -2,11 +2,13 @@ use eframe::{
     emath::vec2,
     epaint::{Stroke, StrokeKind},
 };
-use egui::Color32;
+use egui::{Color32, WidgetInfo, WidgetType};
 
 pub struct ColorPalette {
     pub colors: Vec<Color32>,
     active_color_index: usize,
+    // contents of the hex color field while it's being edited
+    hex_input: String,
 }
 impl Default for ColorPalette {
     fn default() -> Self {
@@ -19,21 +21,28 @@ impl Default for ColorPalette {
                 Color32::BLUE,
             ],
             active_color_index: 0,
+            hex_input: String::new(),
         }
     }
 }
 impl ColorPalette {
-    pub fn draw(&mut self, ui: &mut egui::Ui) {
+    // Returns whether the palette colors were edited
+    pub fn draw(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = false;
         {
             ui.label("Color selection");
             ui.horizontal(|ui| {
                 // draw color palette
                 for i in 0..self.colors.len() {
                     let is_selected = i == self.active_color_index;
+                    let label = swatch_label(i, self.colors[i], is_selected);
 
                     let frame = if is_selected {
                         egui::Frame::new()
-                            .stroke(Stroke::new(2.0, ui.visuals().text_color()))
+                            .stroke(Stroke::new(
+                                2.0_f32,
+                                ui.visuals().text_color(),
+                            ))
                             .inner_margin(2.0)
                             .corner_radius(4.0)
                     } else {
@@ -42,7 +51,16 @@ impl ColorPalette {
 
                     frame.show(ui, |ui| {
                         if is_selected {
-                            ui.color_edit_button_srgba(&mut self.colors[i]);
+                            let response =
+                                ui.color_edit_button_srgba(&mut self.colors[i]);
+                            response.widget_info(|| {
+                                WidgetInfo::labeled(
+                                    WidgetType::ColorButton,
+                                    true,
+                                    &label,
+                                )
+                            });
+                            changed |= response.changed();
                         } else {
                             let size = vec2(
                                 ui.spacing().interact_size.y,
@@ -53,6 +71,14 @@ impl ColorPalette {
                                 egui::Sense::click(),
                             );
 
+                            response.widget_info(|| {
+                                WidgetInfo::labeled(
+                                    WidgetType::Button,
+                                    true,
+                                    &label,
+                                )
+                            });
+
                             if ui.is_rect_visible(rect) {
                                 let rounding = 2.0;
                                 ui.painter().rect_filled(
@@ -60,17 +86,24 @@ impl ColorPalette {
                                     rounding,
                                     self.colors[i],
                                 );
-                                ui.painter().rect_stroke(
-                                    rect,
-                                    rounding,
+                                // reached with Tab, picked with Enter or
+                                // Space
+                                let stroke = if response.has_focus() {
+                                    ui.visuals().selection.stroke
+                                } else {
                                     Stroke::new(
-                                        1.0,
+                                        1.0_f32,
                                         ui.visuals()
                                             .widgets
                                             .inactive
                                             .bg_stroke
                                             .color,
-                                    ),
+                                    )
+                                };
+                                ui.painter().rect_stroke(
+                                    rect,
+                                    rounding,
+                                    stroke,
                                     StrokeKind::Outside,
                                 );
                             }
@@ -90,6 +123,9 @@ impl ColorPalette {
                     add_btn_size + vec2(8.0, 8.0),
                     egui::Sense::click(),
                 );
+                response.widget_info(|| {
+                    WidgetInfo::labeled(WidgetType::Button, true, "Add color")
+                });
                 let btn_rect = rect.shrink(4.0);
                 if ui.is_rect_visible(btn_rect) {
                     let visuals = ui.style().interact(&response);
@@ -106,7 +142,7 @@ impl ColorPalette {
                         StrokeKind::Outside,
                     );
                     // draw the plus sign
-                    let stroke = Stroke::new(2.0, visuals.fg_stroke.color);
+                    let stroke = Stroke::new(2.0_f32, visuals.fg_stroke.color);
                     let center = btn_rect.center();
                     let radius = btn_rect.width() / 4.0;
                     ui.painter().line_segment(
@@ -127,15 +163,69 @@ impl ColorPalette {
                 if response.clicked() {
                     self.colors.push(self.get_current_color());
                     self.active_color_index = self.colors.len() - 1;
+                    changed = true;
                 }
             });
+            changed |= self.draw_hex_input(ui);
+        }
+        changed
+    }
+    // A text field showing the active color as hex; entering `#RRGGBB` or
+    // `#RRGGBBAA` replaces it. Anything else is ignored.
+    fn draw_hex_input(&mut self, ui: &mut egui::Ui) -> bool {
+        let current = to_hex(self.get_current_color());
+        let response = ui.horizontal(|ui| {
+            ui.label("Hex");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.hex_input)
+                    .hint_text(&current)
+                    .desired_width(90.0)
+                    .font(egui::TextStyle::Monospace),
+            )
+        });
+        let response = response.inner;
+        if !response.lost_focus() {
+            if !response.has_focus() {
+                self.hex_input.clear();
+            }
+            return false;
+        }
+        let entered = ui.input(|i| i.key_pressed(egui::Key::Enter));
+        let color = parse_hex(&self.hex_input);
+        self.hex_input.clear();
+        match color {
+            Some(color) if entered => {
+                self.colors[self.active_color_index] = color;
+                true
+            }
+            _ => false,
         }
     }
+    pub fn get_active_color_index(&self) -> usize {
+        self.active_color_index
+    }
     pub fn set_active_color_index(&mut self, active_color_index: usize) {
         if active_color_index < self.colors.len() {
             self.active_color_index = active_color_index;
         }
     }
+    pub fn add_color(&mut self, color: Color32) {
+        if !self.colors.contains(&color) {
+            self.colors.push(color);
+        }
+    }
+    // Makes `color` the current color, selecting it if it's in the palette
+    // and otherwise putting it in the active slot. Returns whether the
+    // palette was edited.
+    pub fn select_color(&mut self, color: Color32) -> bool {
+        if let Some(i) = self.colors.iter().position(|&c| c == color) {
+            self.active_color_index = i;
+            false
+        } else {
+            self.colors[self.active_color_index] = color;
+            true
+        }
+    }
     pub fn get_current_color(&self) -> Color32 {
         self.colors[self.active_color_index]
     }
@@ -148,6 +238,139 @@ impl From<Vec<Color32>> for ColorPalette {
         Self {
             colors,
             active_color_index: 0,
+            hex_input: String::new(),
+        }
+    }
+}
+
+pub(crate) fn to_hex(color: Color32) -> String {
+    let [r, g, b, a] = color.to_srgba_unmultiplied();
+    if a == 255 {
+        format!("#{r:02x}{g:02x}{b:02x}")
+    } else {
+        format!("#{r:02x}{g:02x}{b:02x}{a:02x}")
+    }
+}
+
+// `#RRGGBB` or `#RRGGBBAA`, the `#` is optional
+pub(crate) fn parse_hex(text: &str) -> Option<Color32> {
+    let hex = text.trim();
+    let hex = hex.strip_prefix('#').unwrap_or(hex);
+    if !matches!(hex.len(), 6 | 8)
+        || !hex.chars().all(|c| c.is_ascii_hexdigit())
+    {
+        return None;
+    }
+    Color32::from_hex(&format!("#{hex}")).ok()
+}
+
+// What screen readers call a palette swatch, like "Color 3: red"
+fn swatch_label(index: usize, color: Color32, selected: bool) -> String {
+    let label = format!("Color {}: {}", index + 1, color_name(color));
+    if selected {
+        format!("{label}, selected")
+    } else {
+        label
+    }
+}
+
+// A rough name for `color`, for screen readers
+pub(crate) fn color_name(color: Color32) -> &'static str {
+    let [r, g, b, a] = color.to_srgba_unmultiplied().map(f32::from);
+    if a == 0.0 {
+        return "transparent";
+    }
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let value = max / 255.0;
+    let saturation = if max > 0.0 { (max - min) / max } else { 0.0 };
+    if value < 0.15 {
+        return "black";
+    }
+    if saturation < 0.2 {
+        return match value {
+            v if v < 0.45 => "dark gray",
+            v if v < 0.85 => "gray",
+            _ => "white",
+        };
+    }
+    let hue = if max == r {
+        60.0 * ((g - b) / (max - min)).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / (max - min) + 2.0)
+    } else {
+        60.0 * ((r - g) / (max - min) + 4.0)
+    };
+    match hue {
+        h if h < 15.0 => "red",
+        h if h < 45.0 && value < 0.65 => "brown",
+        h if h < 45.0 => "orange",
+        h if h < 70.0 => "yellow",
+        h if h < 165.0 => "green",
+        h if h < 195.0 => "cyan",
+        h if h < 260.0 => "blue",
+        h if h < 315.0 => "purple",
+        h if h < 345.0 => "pink",
+        _ => "red",
+    }
+}
+
+const MAX_RECENT_COLORS: usize = 8;
+
+// The last few distinct colors drawn with, newest first, independent of the
+// palette so colors picked and replaced in a palette slot aren't lost
+#[derive(Default)]
+pub struct RecentColors {
+    pub colors: Vec<Color32>,
+}
+impl RecentColors {
+    pub fn push(&mut self, color: Color32) {
+        self.colors.retain(|&c| c != color);
+        self.colors.insert(0, color);
+        self.colors.truncate(MAX_RECENT_COLORS);
+    }
+    // Returns the color that was clicked
+    pub fn draw(&self, ui: &mut egui::Ui) -> Option<Color32> {
+        if self.colors.is_empty() {
+            return None;
         }
+        let mut clicked = None;
+        ui.label("Recent colors");
+        ui.horizontal(|ui| {
+            let size = vec2(
+                ui.spacing().interact_size.y * 0.75,
+                ui.spacing().interact_size.y * 0.75,
+            );
+            for &color in &self.colors {
+                let (rect, response) =
+                    ui.allocate_exact_size(size, egui::Sense::click());
+                response.widget_info(|| {
+                    WidgetInfo::labeled(
+                        WidgetType::Button,
+                        true,
+                        format!("Recent color: {}", color_name(color)),
+                    )
+                });
+                ui.painter().rect_filled(rect, 2.0, color);
+                let stroke = if response.has_focus() {
+                    ui.visuals().selection.stroke
+                } else {
+                    Stroke::new(
+                        1.0_f32,
+                        ui.visuals().widgets.inactive.bg_stroke.color,
+                    )
+                };
+                ui.painter().rect_stroke(
+                    rect,
+                    2.0,
+                    stroke,
+                    StrokeKind::Outside,
+                );
+                if response.on_hover_text(to_hex(color)).clicked() {
+                    clicked = Some(color);
+                }
+            }
+        });
+        clicked
     }
 }
@@ -0,0 +1,250 @@
+use std::{fmt, io};
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::{
+    AeadCore, KeyInit, XChaCha20Poly1305, XNonce,
+    aead::{Aead, OsRng, Payload, rand_core::RngCore},
+};
+
+// Encrypted files start with this magic and a format version, followed by
+// the Argon2id parameters (memory in KiB, iterations and parallelism as
+// little-endian u32), the salt and the nonce. The rest is the
+// XChaCha20-Poly1305 ciphertext of an ordinary whiteboard file, with the
+// header as associated data so it can't be altered either.
+const MAGIC: &[u8; 4] = b"WBEN";
+const VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const HEADER_LEN: usize = MAGIC.len() + 1 + 12 + SALT_LEN + NONCE_LEN;
+// files asking for more than this would stall or exhaust memory just to try
+// a passphrase
+const MAX_MEMORY_KIB: u32 = 1024 * 1024;
+const MAX_ITERATIONS: u32 = 64;
+
+// A key derived from a passphrase. The passphrase itself isn't kept, and
+// saving again reuses the key so it isn't derived on every save.
+#[derive(Clone)]
+pub(crate) struct FileKey {
+    params: Params,
+    salt: [u8; SALT_LEN],
+    key: [u8; 32],
+}
+
+pub(crate) enum DecryptError {
+    WrongPassphrase,
+    Invalid(String),
+}
+impl fmt::Display for DecryptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecryptError::WrongPassphrase => write!(
+                f,
+                "the passphrase is wrong, or the file was changed or damaged"
+            ),
+            DecryptError::Invalid(reason) => {
+                write!(f, "damaged encrypted file: {reason}")
+            }
+        }
+    }
+}
+
+pub(crate) fn is_encrypted(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+impl FileKey {
+    // A key for a newly encrypted file, with a fresh salt
+    pub fn derive(passphrase: &str) -> io::Result<Self> {
+        let mut salt = [0; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        Self::derive_with(passphrase, Params::default(), salt)
+            .map_err(|e| io::Error::other(e.to_string()))
+    }
+    fn derive_with(
+        passphrase: &str,
+        params: Params,
+        salt: [u8; SALT_LEN],
+    ) -> argon2::Result<Self> {
+        let mut key = [0; 32];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params.clone())
+            .hash_password_into(passphrase.as_bytes(), &salt, &mut key)?;
+        Ok(Self { params, salt, key })
+    }
+    // Whether `bytes` were encrypted with this key, as far as the header
+    // tells
+    pub fn matches(&self, bytes: &[u8]) -> bool {
+        Header::parse(bytes).is_ok_and(|header| {
+            header.salt == self.salt && header.params == self.params
+        })
+    }
+    pub fn encrypt(&self, plaintext: &[u8]) -> io::Result<Vec<u8>> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let mut bytes = Header {
+            params: self.params.clone(),
+            salt: self.salt,
+            nonce: nonce.into(),
+        }
+        .to_bytes();
+        let ciphertext = self
+            .cipher()
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: plaintext,
+                    aad: &bytes,
+                },
+            )
+            .map_err(|_| io::Error::other("encryption failed"))?;
+        bytes.extend_from_slice(&ciphertext);
+        Ok(bytes)
+    }
+    pub fn decrypt(&self, bytes: &[u8]) -> Result<Vec<u8>, DecryptError> {
+        let header = Header::parse(bytes).map_err(DecryptError::Invalid)?;
+        self.cipher()
+            .decrypt(
+                XNonce::from_slice(&header.nonce),
+                Payload {
+                    msg: &bytes[HEADER_LEN..],
+                    aad: &bytes[..HEADER_LEN],
+                },
+            )
+            // a wrong key and altered content look the same
+            .map_err(|_| DecryptError::WrongPassphrase)
+    }
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new(&self.key.into())
+    }
+}
+
+// Decrypts `bytes` with a key derived from `passphrase`, returning the
+// content and the key for saving the file again
+pub(crate) fn decrypt(
+    bytes: &[u8],
+    passphrase: &str,
+) -> Result<(Vec<u8>, FileKey), DecryptError> {
+    let header = Header::parse(bytes).map_err(DecryptError::Invalid)?;
+    let key = FileKey::derive_with(passphrase, header.params, header.salt)
+        .map_err(|e| DecryptError::Invalid(e.to_string()))?;
+    let plaintext = key.decrypt(bytes)?;
+    Ok((plaintext, key))
+}
+
+struct Header {
+    params: Params,
+    salt: [u8; SALT_LEN],
+    nonce: [u8; NONCE_LEN],
+}
+impl Header {
+    fn parse(bytes: &[u8]) -> Result<Self, String> {
+        if !is_encrypted(bytes) || bytes.len() < HEADER_LEN {
+            return Err("the header is incomplete".to_owned());
+        }
+        let version = bytes[MAGIC.len()];
+        if version != VERSION {
+            return Err(format!("unsupported encryption version {version}"));
+        }
+        let mut rest = &bytes[MAGIC.len() + 1..HEADER_LEN];
+        let mut next_u32 = || {
+            let (value, tail) = rest.split_at(4);
+            rest = tail;
+            u32::from_le_bytes(value.try_into().expect("split at 4 bytes"))
+        };
+        let (memory, iterations, parallelism) =
+            (next_u32(), next_u32(), next_u32());
+        if memory > MAX_MEMORY_KIB || iterations > MAX_ITERATIONS {
+            return Err("the key derivation settings are too costly".to_owned());
+        }
+        let params = Params::new(memory, iterations, parallelism, None)
+            .map_err(|e| e.to_string())?;
+        let (salt, nonce) = rest.split_at(SALT_LEN);
+        Ok(Self {
+            params,
+            salt: salt.try_into().expect("header length is checked"),
+            nonce: nonce.try_into().expect("header length is checked"),
+        })
+    }
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        for value in [
+            self.params.m_cost(),
+            self.params.t_cost(),
+            self.params.p_cost(),
+        ] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend_from_slice(&self.salt);
+        bytes.extend_from_slice(&self.nonce);
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTENT: &[u8] = br#"{"version": 3, "pages": []}"#;
+
+    // Cheap settings, real files use `Params::default()`
+    fn key(passphrase: &str, salt: u8) -> FileKey {
+        let params = Params::new(8, 1, 1, None).unwrap();
+        FileKey::derive_with(passphrase, params, [salt; SALT_LEN]).unwrap()
+    }
+
+    #[test]
+    fn content_decrypts_with_its_passphrase() {
+        let bytes = key("correct horse", 1).encrypt(CONTENT).unwrap();
+        assert!(is_encrypted(&bytes));
+        let Ok((plaintext, key)) = decrypt(&bytes, "correct horse") else {
+            panic!("the right passphrase was refused");
+        };
+        assert_eq!(plaintext, CONTENT);
+        // the key is kept for saving again
+        assert!(key.matches(&key.encrypt(CONTENT).unwrap()));
+        assert!(matches!(
+            decrypt(&bytes, "wrong horse"),
+            Err(DecryptError::WrongPassphrase)
+        ));
+    }
+
+    #[test]
+    fn changes_to_any_byte_are_detected() {
+        let key = key("correct horse", 1);
+        let bytes = key.encrypt(CONTENT).unwrap();
+        // the salt, part of the header, and the ciphertext
+        for i in [MAGIC.len() + 13, HEADER_LEN + 2, bytes.len() - 1] {
+            let mut changed = bytes.clone();
+            changed[i] ^= 1;
+            assert!(key.decrypt(&changed).is_err(), "byte {i}");
+        }
+        assert!(matches!(
+            key.decrypt(&bytes[..HEADER_LEN - 1]),
+            Err(DecryptError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn costly_key_derivation_is_refused() {
+        let bytes = key("correct horse", 1).encrypt(CONTENT).unwrap();
+        // memory and iterations, each would take far too long to derive
+        for (offset, value) in [(0, MAX_MEMORY_KIB + 1), (4, u32::MAX)] {
+            let mut costly = bytes.clone();
+            let start = MAGIC.len() + 1 + offset;
+            costly[start..start + 4].copy_from_slice(&value.to_le_bytes());
+            assert!(matches!(
+                decrypt(&costly, "correct horse"),
+                Err(DecryptError::Invalid(reason)) if reason.contains("costly")
+            ));
+        }
+    }
+
+    #[test]
+    fn keys_match_only_their_own_files() {
+        let mine = key("correct horse", 1);
+        assert!(mine.matches(&mine.encrypt(CONTENT).unwrap()));
+        // the same passphrase with another salt derives another key
+        let other = key("correct horse", 2);
+        assert!(!mine.matches(&other.encrypt(CONTENT).unwrap()));
+        assert!(!mine.matches(CONTENT));
+    }
+}
@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+
+use eframe::egui;
+use egui::Color32;
+use serde::Serialize;
+
+use crate::{Line, document::Document, state::LineState};
+
+// how unchanged strokes show in the visual diff, behind the changes
+const UNCHANGED_OPACITY: f32 = 0.25;
+
+// How the strokes of one page differ between two boards, by their index
+// on the page
+#[derive(Serialize)]
+pub(crate) struct PageDiff {
+    // counting from 1, like the page switcher
+    pub page: usize,
+    // indexes into the old page
+    pub removed: Vec<usize>,
+    // indexes into the new page
+    pub added: Vec<usize>,
+    // old and new index of strokes that changed
+    pub modified: Vec<(usize, usize)>,
+}
+impl PageDiff {
+    fn is_empty(&self) -> bool {
+        self.removed.is_empty()
+            && self.added.is_empty()
+            && self.modified.is_empty()
+    }
+}
+
+// The pages that differ between two boards
+#[derive(Serialize)]
+pub(crate) struct BoardDiff {
+    pub added: usize,
+    pub removed: usize,
+    pub modified: usize,
+    pub pages: Vec<PageDiff>,
+}
+impl BoardDiff {
+    pub fn new(old: &Document, new: &Document) -> Self {
+        let pages: Vec<PageDiff> = (0..old.page_count().max(new.page_count()))
+            .map(|page| PageDiff {
+                page: page + 1,
+                ..diff_page(page_lines(old, page), page_lines(new, page))
+            })
+            .filter(|page| !page.is_empty())
+            .collect();
+        Self {
+            added: pages.iter().map(|page| page.added.len()).sum(),
+            removed: pages.iter().map(|page| page.removed.len()).sum(),
+            modified: pages.iter().map(|page| page.modified.len()).sum(),
+            pages,
+        }
+    }
+    pub fn is_identical(&self) -> bool {
+        self.pages.is_empty()
+    }
+    // One line per page that differs and a total
+    pub fn summary(&self) -> String {
+        if self.is_identical() {
+            return "The boards are identical".to_owned();
+        }
+        let mut summary = String::new();
+        for page in &self.pages {
+            summary += &format!(
+                "page {}: {} added, {} removed, {} modified\n",
+                page.page,
+                page.added.len(),
+                page.removed.len(),
+                page.modified.len()
+            );
+        }
+        summary += &format!(
+            "{} strokes added, {} removed, {} modified",
+            self.added, self.removed, self.modified
+        );
+        summary
+    }
+    // The pages of the new board with removed strokes and the old version
+    // of modified ones in red, added strokes and the new version of
+    // modified ones in green, and everything else faded. Pages that are
+    // the same are left out.
+    pub fn image_pages(
+        &self,
+        old: &Document,
+        new: &Document,
+    ) -> Vec<Vec<Line>> {
+        self.pages
+            .iter()
+            .map(|diff| {
+                let old_lines = page_lines(old, diff.page - 1);
+                let new_lines = page_lines(new, diff.page - 1);
+                let changed: Vec<usize> = diff
+                    .added
+                    .iter()
+                    .copied()
+                    .chain(diff.modified.iter().map(|&(_, new)| new))
+                    .collect();
+                let unchanged = new_lines
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| !changed.contains(i))
+                    .map(|(_, line)| faded(line));
+                let removed = diff
+                    .removed
+                    .iter()
+                    .copied()
+                    .chain(diff.modified.iter().map(|&(old, _)| old))
+                    .map(|i| tinted(&old_lines[i], Color32::RED));
+                let added = changed
+                    .iter()
+                    .map(|&i| tinted(&new_lines[i], Color32::GREEN));
+                unchanged.chain(removed).chain(added).collect()
+            })
+            .collect()
+    }
+}
+
+// Strokes that are the same in every saved detail match first. Files don't
+// keep stroke ids, so of the rest, an old and a new stroke created at the
+// same millisecond are taken to be one stroke that was changed: moving,
+// resizing or recoloring a stroke keeps its creation time.
+fn diff_page(old: &[Line], new: &[Line]) -> PageDiff {
+    let mut unmatched_old: HashMap<String, Vec<usize>> = HashMap::new();
+    for (i, line) in old.iter().enumerate().rev() {
+        unmatched_old.entry(content_key(line)).or_default().push(i);
+    }
+    let mut added = Vec::new();
+    for (i, line) in new.iter().enumerate() {
+        let same = unmatched_old
+            .get_mut(&content_key(line))
+            .and_then(|indexes| indexes.pop());
+        if same.is_none() {
+            added.push(i);
+        }
+    }
+    let mut removed: Vec<usize> =
+        unmatched_old.into_values().flatten().collect();
+    removed.sort_unstable();
+
+    // only creation times that single out one stroke on each side
+    let by_created = |indexes: &[usize], lines: &[Line]| {
+        let mut created: HashMap<u64, Vec<usize>> = HashMap::new();
+        for &i in indexes {
+            if lines[i].created_ms != 0 {
+                created.entry(lines[i].created_ms).or_default().push(i);
+            }
+        }
+        created
+    };
+    let old_created = by_created(&removed, old);
+    let new_created = by_created(&added, new);
+    let mut modified: Vec<(usize, usize)> = old_created
+        .iter()
+        .filter_map(|(created, old_indexes)| {
+            match (old_indexes.as_slice(), new_created.get(created)?.as_slice())
+            {
+                (&[old], &[new]) => Some((old, new)),
+                _ => None,
+            }
+        })
+        .collect();
+    modified.sort_unstable();
+    removed.retain(|i| !modified.iter().any(|&(old, _)| old == *i));
+    added.retain(|i| !modified.iter().any(|&(_, new)| new == *i));
+    PageDiff {
+        page: 0,
+        removed,
+        added,
+        modified,
+    }
+}
+
+// A page only one of the boards has is all added or all removed
+fn page_lines(document: &Document, page: usize) -> &[Line] {
+    if page < document.page_count() {
+        document.page_lines(page)
+    } else {
+        &[]
+    }
+}
+
+// Everything that gets saved about a stroke
+fn content_key(line: &Line) -> String {
+    serde_json::to_string(&LineState::from(line))
+        .expect("strokes always serialize")
+}
+
+// Bucket fills are all color, other fills stay lighter than their stroke
+fn tinted(line: &Line, color: Color32) -> Line {
+    let mut line = line.clone();
+    line.color = color;
+    line.fill = line.fill.map(|_| {
+        if line.fill_only {
+            color
+        } else {
+            color.gamma_multiply(UNCHANGED_OPACITY)
+        }
+    });
+    line
+}
+fn faded(line: &Line) -> Line {
+    let mut line = line.clone();
+    line.color = line.color.gamma_multiply(UNCHANGED_OPACITY);
+    line.fill = line.fill.map(|fill| fill.gamma_multiply(UNCHANGED_OPACITY));
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use egui::pos2;
+
+    use super::*;
+    use crate::{new_line_id, stroke::LineStyle};
+
+    // A short stroke starting at `x`, drawn at `created_ms`
+    fn line(x: f32, created_ms: u64) -> Line {
+        Line {
+            points: vec![pos2(x, 0.0), pos2(x + 10.0, 10.0)],
+            color: Color32::BLACK,
+            width: 2.0,
+            widths: Vec::new(),
+            style: LineStyle::Solid,
+            created_ms,
+            fill: None,
+            fill_only: false,
+            picture: None,
+            locked: false,
+            id: new_line_id(),
+        }
+    }
+
+    fn document(pages: Vec<Vec<Line>>) -> Document {
+        let mut pages = pages.into_iter();
+        let mut document = Document {
+            lines: pages.next().unwrap_or_default(),
+            ..Document::default()
+        };
+        for lines in pages {
+            document.add_page(lines);
+        }
+        document
+    }
+
+    #[test]
+    fn strokes_with_the_same_content_match() {
+        let old = [line(0.0, 1), line(20.0, 2)];
+        // reordered, ids differ but aren't saved
+        let new = [line(20.0, 2), line(0.0, 1), line(40.0, 0)];
+        let diff = diff_page(&old, &new);
+        assert_eq!(diff.added, [2]);
+        assert!(diff.removed.is_empty() && diff.modified.is_empty());
+    }
+
+    #[test]
+    fn changed_strokes_match_by_creation_time() {
+        let old = [line(0.0, 1), line(20.0, 0)];
+        let new = [line(5.0, 1), line(25.0, 0)];
+        let diff = diff_page(&old, &new);
+        assert_eq!(diff.modified, [(0, 0)]);
+        // without a creation time there's nothing to tell them apart by
+        assert_eq!((diff.removed, diff.added), (vec![1], vec![1]));
+    }
+
+    #[test]
+    fn shared_creation_times_pair_nothing() {
+        let old = [line(0.0, 1), line(20.0, 1)];
+        let new = [line(5.0, 1)];
+        let diff = diff_page(&old, &new);
+        assert!(diff.modified.is_empty());
+        assert_eq!((diff.removed, diff.added), (vec![0, 1], vec![0]));
+    }
+
+    #[test]
+    fn summaries_count_per_page_and_in_total() {
+        let old = document(vec![vec![line(0.0, 1)], vec![line(0.0, 2)]]);
+        let diff = BoardDiff::new(&old, &old);
+        assert_eq!(diff.summary(), "The boards are identical");
+        let new = document(vec![
+            vec![line(5.0, 1), line(20.0, 3)],
+            vec![],
+            vec![line(0.0, 4)],
+        ]);
+        let diff = BoardDiff::new(&old, &new);
+        assert_eq!(
+            diff.summary(),
+            "page 1: 1 added, 0 removed, 1 modified\n\
+             page 2: 0 added, 1 removed, 0 modified\n\
+             page 3: 1 added, 0 removed, 0 modified\n\
+             2 strokes added, 1 removed, 1 modified"
+        );
+    }
+}
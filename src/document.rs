@@ -0,0 +1,864 @@
+use std::{collections::HashSet, fs, io, mem, ops::Range, path::Path};
+
+use eframe::egui;
+use egui::{Pos2, Rect, Vec2};
+
+use crate::{
+    Line, atomic, bucket,
+    collab::Message,
+    colors::ColorPalette,
+    crypto, distance_point_to_segment,
+    format::{self, Encoding},
+    new_line_id,
+    ops::{self, Op, OpsError},
+    render,
+    state::{LoadError, PageState, WhiteboardState},
+    stroke,
+    undo::{UndoAction, UndoStack},
+};
+
+// how many times each corner is cut when smoothing a stroke
+const SMOOTHING_ITERATIONS: usize = 2;
+
+// A page that isn't shown. The shown page lives in `lines` and
+// `undo_stack`, so each page keeps its own undo history.
+#[derive(Default)]
+pub(crate) struct Page {
+    pub lines: Vec<Line>,
+    pub undo_stack: UndoStack,
+}
+
+// The content of a board, its palette and its undo history, without any UI
+// state, so edits can be made and checked without a window. The app turns
+// input into calls on this.
+pub(crate) struct Document {
+    // lines of the shown page, in drawing order
+    pub lines: Vec<Line>,
+    pub undo_stack: UndoStack,
+    // every page of the document; the entry of `current_page` stays empty
+    // while it's shown
+    pub pages: Vec<Page>,
+    pub current_page: usize,
+    pub palette: ColorPalette,
+    // unsaved changes since the last save or open
+    pub dirty: bool,
+    // changes to send to the others while the board is shared, `None` when
+    // it isn't. Changing the order of lines and adding or removing pages
+    // aren't shared.
+    pub outbox: Option<Vec<Message>>,
+}
+impl Default for Document {
+    fn default() -> Self {
+        Self {
+            lines: Vec::new(),
+            undo_stack: UndoStack::default(),
+            pages: vec![Page::default()],
+            current_page: 0,
+            // 預設提供五種不同的顏色選項
+            palette: ColorPalette::default(),
+            dirty: false,
+            outbox: None,
+        }
+    }
+}
+impl Document {
+    // The pages of a loaded file, showing the page that was shown when it
+    // was saved
+    pub fn from_state(state: &WhiteboardState) -> Self {
+        let mut pages: Vec<Page> = state
+            .pages
+            .iter()
+            .map(|page| Page {
+                lines: page.to_lines(),
+                undo_stack: UndoStack::default(),
+            })
+            .collect();
+        if pages.is_empty() {
+            pages.push(Page::default());
+        }
+        let mut palette: ColorPalette = if state.palette.is_empty() {
+            ColorPalette::default()
+        } else {
+            state
+                .palette
+                .iter()
+                .map(|&color| color.into())
+                .collect::<Vec<_>>()
+                .into()
+        };
+        if let Some(index) = state.active_color_index {
+            let last = palette.get_palette_vec().len().saturating_sub(1);
+            palette.set_active_color_index(index.min(last));
+        }
+        let mut document = Self {
+            pages,
+            palette,
+            ..Self::default()
+        };
+        document.show_page(state.current_page.unwrap_or(0));
+        document
+    }
+    // The document in the whiteboard file at `path`. Unlike opening a file
+    // in the app it doesn't ask anything: damaged and encrypted files fail.
+    pub fn load_from(path: &Path) -> Result<Self, LoadError> {
+        let bytes = fs::read(path).map_err(LoadError::Io)?;
+        if crypto::is_encrypted(&bytes) {
+            return Err(LoadError::Encrypted);
+        }
+        Ok(Self::from_state(&format::decode(&bytes)?))
+    }
+    // Writes every page and the palette to `path`, in the encoding its
+    // extension stands for, and marks them as saved
+    pub fn save_to(&mut self, path: &Path) -> io::Result<()> {
+        let state = WhiteboardState::from_document(self, true, true);
+        let bytes =
+            format::encode(Encoding::from_extension(path), &state, false)?;
+        atomic::write(path, &bytes)?;
+        self.dirty = false;
+        Ok(())
+    }
+    // Every page for saving, rounded to 0.1px if `quantize` is set
+    pub fn to_pages(&self, quantize: bool) -> Vec<PageState> {
+        (0..self.page_count())
+            .map(|page| PageState::from_lines(self.page_lines(page), quantize))
+            .collect()
+    }
+
+    pub fn add_line(&mut self, line: Line) {
+        self.lines.push(line.clone());
+        self.undo_stack.add_draw(line);
+        self.dirty = true;
+        self.share(self.lines.len() - 1..self.lines.len());
+    }
+    // Appends `lines` as one undoable action, returning their indices. They
+    // count as new lines, even if they are copies.
+    pub fn append(&mut self, mut lines: Vec<Line>) -> Range<usize> {
+        let start = self.lines.len();
+        for line in &mut lines {
+            line.id = new_line_id();
+        }
+        self.undo_stack.add_import(lines.len());
+        self.lines.extend(lines);
+        self.dirty = true;
+        self.share(start..self.lines.len());
+        start..self.lines.len()
+    }
+    // Removes every line of the shown page. It can't be undone, and the
+    // page's undo history, which refers to positions in `lines`, goes too.
+    pub fn clear(&mut self) {
+        let cleared = mem::take(&mut self.lines);
+        self.undo_stack = UndoStack::default();
+        self.share_removal(cleared.iter().map(|line| line.id).collect());
+        self.dirty = true;
+    }
+    // Removes the lines at `indices`; returns whether any were removed
+    pub fn erase_lines(&mut self, indices: &HashSet<usize>) -> bool {
+        let mut indices: Vec<usize> = indices
+            .iter()
+            .copied()
+            .filter(|&i| i < self.lines.len())
+            .collect();
+        indices.sort_unstable_by(|a, b| b.cmp(a));
+        let erased = indices
+            .into_iter()
+            .map(|i| (i, self.lines.remove(i)))
+            .collect();
+        self.record_erase(erased)
+    }
+    // Removes every stroke passing within `radius` of `pos`. Fills and
+    // pictures are only erased from inside and only when no stroke was in
+    // reach, so erasing a stroke doesn't take the fill beneath it along.
+    // Locked lines stay. Returns whether anything was removed.
+    pub fn erase_at(&mut self, pos: Pos2, radius: f32) -> bool {
+        let (kept, mut erased): (Vec<_>, Vec<_>) =
+            self.lines.drain(..).enumerate().partition(|(_, line)| {
+                line.locked
+                    || line.fill_only
+                    || line.picture.is_some()
+                    || line.points.windows(2).all(|segment| {
+                        distance_point_to_segment(pos, segment[0], segment[1])
+                            >= radius
+                    })
+            });
+        self.lines = kept.into_iter().map(|(_, line)| line).collect();
+        erased.reverse();
+        if erased.is_empty()
+            && let Some(i) = self.lines.iter().rposition(|line| {
+                !line.locked
+                    && ((line.fill_only && bucket::contains(&line.points, pos))
+                        || line
+                            .picture_area()
+                            .is_some_and(|(_, rect)| rect.contains(pos)))
+            })
+        {
+            erased.push((i, self.lines.remove(i)));
+        }
+        self.record_erase(erased)
+    }
+    // `erased` holds the removed lines with their index, highest first
+    fn record_erase(&mut self, erased: Vec<(usize, Line)>) -> bool {
+        if erased.is_empty() {
+            return false;
+        }
+        self.share_removal(erased.iter().map(|(_, line)| line.id).collect());
+        self.undo_stack.extend_erase(erased);
+        self.dirty = true;
+        true
+    }
+    // The unlocked lines whose bounding box touches `rect`
+    pub fn select_in_rect(&self, rect: Rect) -> HashSet<usize> {
+        self.lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| !line.locked)
+            .filter(|(_, line)| {
+                let mut bbox = Rect::NOTHING;
+                line.points.iter().for_each(|&p| bbox.extend_with(p));
+                rect.intersects(bbox)
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+    // Moves the lines at `indices` by `delta` while they're dragged around;
+    // not undoable on its own
+    pub fn move_selection(&mut self, indices: &HashSet<usize>, delta: Vec2) {
+        if delta == Vec2::ZERO {
+            return;
+        }
+        for &i in indices {
+            if let Some(line) = self.lines.get_mut(i) {
+                line.points.iter_mut().for_each(|p| *p += delta);
+            }
+        }
+        self.dirty = true;
+        self.share(indices.iter().copied());
+    }
+    // Moves lines by the given offsets as one undoable action
+    pub fn translate_lines(&mut self, offsets: Vec<(usize, Vec2)>) {
+        let mut original = Vec::new();
+        for (i, offset) in offsets {
+            if offset == Vec2::ZERO {
+                continue;
+            }
+            if let Some(line) = self.lines.get_mut(i) {
+                original.push((i, line.points.clone()));
+                for p in &mut line.points {
+                    *p += offset;
+                }
+            }
+        }
+        if !original.is_empty() {
+            self.share(original.iter().map(|(i, _)| *i));
+            self.undo_stack.add_transform(original);
+            self.dirty = true;
+        }
+    }
+    // Sets the width of the lines at `indices` as one undoable action
+    pub fn set_width(&mut self, indices: &HashSet<usize>, width: f32) {
+        let mut indices: Vec<usize> = indices.iter().copied().collect();
+        indices.sort_unstable();
+        let mut original = Vec::new();
+        for i in indices {
+            let Some(line) = self.lines.get_mut(i) else {
+                continue;
+            };
+            if line.width == width {
+                continue;
+            }
+            original.push((i, line.width, line.widths.clone()));
+            // pressure sensitive strokes keep their shape
+            let scale = width / line.width;
+            line.widths.iter_mut().for_each(|w| *w *= scale);
+            line.width = width;
+        }
+        if !original.is_empty() {
+            self.share(original.iter().map(|(i, _, _)| *i));
+            self.undo_stack.add_width(original);
+            self.dirty = true;
+        }
+    }
+    // Rounds off the corners of the strokes at `indices` as one undoable
+    // action. Fills and pictures keep their shape.
+    pub fn smooth(&mut self, indices: &HashSet<usize>) {
+        let mut indices: Vec<usize> = indices.iter().copied().collect();
+        indices.sort_unstable();
+        let mut original = Vec::new();
+        for i in indices {
+            let Some(line) = self.lines.get_mut(i) else {
+                continue;
+            };
+            if line.fill_only || line.picture.is_some() || line.points.len() < 3
+            {
+                continue;
+            }
+            let (points, widths) = stroke::smooth(
+                &line.points,
+                &line.widths,
+                SMOOTHING_ITERATIONS,
+            );
+            original.push((
+                i,
+                mem::replace(&mut line.points, points),
+                mem::replace(&mut line.widths, widths),
+            ));
+        }
+        if !original.is_empty() {
+            self.share(original.iter().map(|(i, _, _)| *i));
+            self.undo_stack.add_reshape(original);
+            self.dirty = true;
+        }
+    }
+    // Locks or unlocks the lines at `indices` as one undoable action
+    pub fn set_locked(
+        &mut self,
+        indices: impl IntoIterator<Item = usize>,
+        locked: bool,
+    ) {
+        let mut changed = Vec::new();
+        for i in indices {
+            if let Some(line) = self.lines.get_mut(i)
+                && line.locked != locked
+            {
+                line.locked = locked;
+                changed.push(i);
+            }
+        }
+        if !changed.is_empty() {
+            self.share(changed.iter().copied());
+            self.undo_stack.add_lock(changed);
+            self.dirty = true;
+        }
+    }
+    // Puts the lines in a new drawing order, `order` listing the old index
+    // of each new position
+    pub fn reorder(&mut self, order: Vec<usize>) {
+        let mut lines: Vec<Option<Line>> =
+            self.lines.drain(..).map(Some).collect();
+        self.lines =
+            order.iter().filter_map(|&old| lines[old].take()).collect();
+        self.undo_stack.add_reorder(order);
+        self.dirty = true;
+    }
+    // Applies a JSON operation log to the shown page, see `ops`. Each
+    // operation is undone on its own, like the edit it stands for. Returns
+    // the number of operations.
+    pub fn apply_ops(&mut self, json: &str) -> Result<usize, OpsError> {
+        let ops = ops::parse(json)?;
+        let count = ops.len();
+        for op in ops {
+            match op {
+                Op::Draw(line) => self.add_line(line),
+                Op::EraseAt { pos, radius } => {
+                    self.erase_at(pos, radius);
+                }
+                Op::Clear => {
+                    self.erase_lines(&(0..self.lines.len()).collect());
+                }
+                Op::Undo => {
+                    self.undo();
+                }
+            }
+        }
+        Ok(count)
+    }
+    // Reverts the last change on the shown page; returns whether there was
+    // one
+    pub fn undo(&mut self) -> bool {
+        let Some(action) = self.undo_stack.pop() else {
+            return false;
+        };
+        self.dirty = true;
+        match action {
+            UndoAction::Erase(i, line) => {
+                let i = i.min(self.lines.len());
+                self.lines.insert(i, line);
+                self.share([i]);
+            }
+            UndoAction::Draw(_line) => {
+                let removed = self.lines.pop();
+                self.share_removal(
+                    removed.map(|line| line.id).into_iter().collect(),
+                );
+            }
+            UndoAction::Import(count) => {
+                let start = self.lines.len().saturating_sub(count);
+                let removed = self.lines.split_off(start);
+                self.share_removal(
+                    removed.iter().map(|line| line.id).collect(),
+                );
+            }
+            // an order for a different number of lines would lose some
+            UndoAction::Reorder(order) if order.len() == self.lines.len() => {
+                let mut lines: Vec<Option<Line>> =
+                    self.lines.drain(..).map(Some).collect();
+                let mut restored = vec![None; lines.len()];
+                for (new, old) in order.into_iter().enumerate() {
+                    if let Some(slot) = restored.get_mut(old) {
+                        *slot = lines[new].take();
+                    }
+                }
+                self.lines = restored.into_iter().flatten().collect();
+            }
+            UndoAction::Reorder(_) => {}
+            UndoAction::Width(original) => {
+                let changed: Vec<usize> =
+                    original.iter().map(|(i, _, _)| *i).collect();
+                for (i, width, widths) in original {
+                    if let Some(line) = self.lines.get_mut(i) {
+                        line.width = width;
+                        line.widths = widths;
+                    }
+                }
+                self.share(changed);
+            }
+            UndoAction::Reshape(original) => {
+                let changed: Vec<usize> =
+                    original.iter().map(|(i, _, _)| *i).collect();
+                for (i, points, widths) in original {
+                    if let Some(line) = self.lines.get_mut(i) {
+                        line.points = points;
+                        line.widths = widths;
+                    }
+                }
+                self.share(changed);
+            }
+            UndoAction::Lock(changed) => {
+                for &i in &changed {
+                    if let Some(line) = self.lines.get_mut(i) {
+                        line.locked = !line.locked;
+                    }
+                }
+                self.share(changed);
+            }
+            UndoAction::Transform(original) => {
+                let changed: Vec<usize> =
+                    original.iter().map(|(i, _)| *i).collect();
+                for (i, points) in original {
+                    if let Some(line) = self.lines.get_mut(i) {
+                        line.points = points;
+                    }
+                }
+                self.share(changed);
+            }
+        }
+        true
+    }
+
+    // Queues the lines at `indices` on the shown page for the others, for
+    // lines that are new or were changed in place
+    pub fn share(&mut self, indices: impl IntoIterator<Item = usize>) {
+        let Some(outbox) = &mut self.outbox else {
+            return;
+        };
+        for i in indices {
+            if let Some(line) = self.lines.get(i) {
+                outbox.push(Message::Put {
+                    page: self.current_page,
+                    id: line.id,
+                    line: line.into(),
+                });
+            }
+        }
+    }
+    fn share_removal(&mut self, ids: Vec<u64>) {
+        if let Some(outbox) = &mut self.outbox
+            && !ids.is_empty()
+        {
+            outbox.push(Message::Remove {
+                page: self.current_page,
+                ids,
+            });
+        }
+    }
+    // Every page for a guest joining the shared board
+    pub fn to_board(&self) -> Message {
+        let pages = (0..self.page_count())
+            .map(|page| {
+                self.page_lines(page)
+                    .iter()
+                    .map(|line| (line.id, line.into()))
+                    .collect()
+            })
+            .collect();
+        Message::Board { pages }
+    }
+    // Applies a change from someone sharing the board, without undo and
+    // without sending it back. Lines coming or going break the undo
+    // history of that page, which refers to positions in `lines`, so it's
+    // dropped. Returns whether lines of the shown page moved to other
+    // positions.
+    pub fn apply(&mut self, message: Message) -> bool {
+        self.dirty = true;
+        match message {
+            Message::Board { pages } => {
+                self.pages = pages
+                    .into_iter()
+                    .map(|lines| Page {
+                        lines: lines
+                            .iter()
+                            .map(|(id, line)| Line {
+                                id: *id,
+                                ..line.into()
+                            })
+                            .collect(),
+                        undo_stack: UndoStack::default(),
+                    })
+                    .collect();
+                if self.pages.is_empty() {
+                    self.pages.push(Page::default());
+                }
+                self.show_page(self.current_page);
+                true
+            }
+            Message::Put { page, id, line } => {
+                let line = Line {
+                    id,
+                    ..(&line).into()
+                };
+                let Some((lines, undo_stack)) = self.page_mut(page) else {
+                    return false;
+                };
+                if let Some(existing) =
+                    lines.iter_mut().find(|existing| existing.id == id)
+                {
+                    *existing = line;
+                    return false;
+                }
+                lines.push(line);
+                *undo_stack = UndoStack::default();
+                false
+            }
+            Message::Remove { page, ids } => {
+                let Some((lines, undo_stack)) = self.page_mut(page) else {
+                    return false;
+                };
+                let count = lines.len();
+                lines.retain(|line| !ids.contains(&line.id));
+                if lines.len() == count {
+                    return false;
+                }
+                *undo_stack = UndoStack::default();
+                page == self.current_page
+            }
+        }
+    }
+    fn page_mut(
+        &mut self,
+        page: usize,
+    ) -> Option<(&mut Vec<Line>, &mut UndoStack)> {
+        if page == self.current_page {
+            Some((&mut self.lines, &mut self.undo_stack))
+        } else {
+            self.pages
+                .get_mut(page)
+                .map(|page| (&mut page.lines, &mut page.undo_stack))
+        }
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+    pub fn page_lines(&self, page: usize) -> &[Line] {
+        if page == self.current_page {
+            &self.lines
+        } else {
+            &self.pages[page].lines
+        }
+    }
+    // Shows another page; returns false for the shown page and out of range
+    // pages
+    pub fn go_to_page(&mut self, page: usize) -> bool {
+        if page == self.current_page || page >= self.pages.len() {
+            return false;
+        }
+        self.pages[self.current_page] = Page {
+            lines: mem::take(&mut self.lines),
+            undo_stack: mem::take(&mut self.undo_stack),
+        };
+        self.show_page(page);
+        true
+    }
+    // Moves the content of `page` into `lines` and `undo_stack`
+    fn show_page(&mut self, page: usize) {
+        let page = page.min(self.pages.len() - 1);
+        let shown = mem::take(&mut self.pages[page]);
+        self.lines = shown.lines;
+        self.undo_stack = shown.undo_stack;
+        self.current_page = page;
+    }
+    // Inserts a page with `lines` after the current one and shows it
+    pub fn add_page(&mut self, lines: Vec<Line>) {
+        self.pages.insert(
+            self.current_page + 1,
+            Page {
+                lines,
+                undo_stack: UndoStack::default(),
+            },
+        );
+        self.go_to_page(self.current_page + 1);
+        self.dirty = true;
+    }
+    // Deletes the shown page unless it's the only one; returns whether it
+    // was deleted
+    pub fn delete_page(&mut self) -> bool {
+        if self.pages.len() <= 1 {
+            return false;
+        }
+        self.pages.remove(self.current_page);
+        self.show_page(self.current_page);
+        self.dirty = true;
+        true
+    }
+    // The lines to export: the shown page, or every page side by side from
+    // left to right
+    pub fn export_lines(&self, all_pages: bool) -> Vec<Line> {
+        if !all_pages {
+            return self.lines.clone();
+        }
+        render::side_by_side(
+            (0..self.page_count()).map(|page| self.page_lines(page)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use egui::{Color32, pos2};
+
+    use super::*;
+    use crate::{
+        MIN_STROKE_WIDTH,
+        stroke::LineStyle,
+        zorder::{self, ZOrder},
+    };
+
+    fn line(points: &[(f32, f32)]) -> Line {
+        Line {
+            points: points.iter().map(|&(x, y)| pos2(x, y)).collect(),
+            color: Color32::BLACK,
+            width: 2.0,
+            widths: Vec::new(),
+            style: LineStyle::Solid,
+            created_ms: 0,
+            fill: None,
+            fill_only: false,
+            picture: None,
+            locked: false,
+            id: new_line_id(),
+        }
+    }
+
+    #[test]
+    fn eraser_removes_strokes_within_its_radius() {
+        let (mut document, original) = three_lines();
+        assert!(!document.erase_at(pos2(5.0, 4.0), 3.0));
+        assert_eq!(ids(&document), original);
+        assert!(document.erase_at(pos2(5.0, 8.0), 3.0));
+        assert_eq!(ids(&document), [original[0], original[2]]);
+        assert!(document.dirty);
+    }
+
+    #[test]
+    fn eraser_skips_locked_lines() {
+        let (mut document, original) = three_lines();
+        document.set_locked([0], true);
+        assert!(!document.erase_at(pos2(5.0, 0.0), 2.0));
+        assert_eq!(ids(&document), original);
+    }
+
+    #[test]
+    fn eraser_takes_a_fill_only_from_inside() {
+        let mut document = Document::default();
+        let square = [(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)];
+        document.add_line(Line {
+            fill_only: true,
+            fill: Some(Color32::RED),
+            ..line(&square)
+        });
+        assert!(!document.erase_at(pos2(20.0, 20.0), 2.0));
+        assert!(document.erase_at(pos2(5.0, 5.0), 2.0));
+        assert!(document.lines.is_empty());
+    }
+
+    #[test]
+    fn undo_draw_and_import() {
+        let (mut document, original) = three_lines();
+        let imported =
+            document.append(vec![line(&[(0.0, 50.0), (10.0, 50.0)]); 2]);
+        assert_eq!(imported, 3..5);
+        assert!(document.undo());
+        assert_eq!(ids(&document), original);
+        assert!(document.undo());
+        assert_eq!(ids(&document), original[..2]);
+        assert!(document.undo());
+        assert!(document.undo());
+        assert!(!document.undo());
+        assert!(document.lines.is_empty());
+    }
+
+    #[test]
+    fn undo_translate() {
+        let (mut document, _) = three_lines();
+        document.translate_lines(vec![(1, Vec2::new(3.0, 4.0))]);
+        assert_eq!(document.lines[1].points[0], pos2(3.0, 14.0));
+        assert!(document.undo());
+        assert_eq!(document.lines[1].points[0], pos2(0.0, 10.0));
+    }
+
+    #[test]
+    fn select_in_rect_picks_lines_whose_bounds_touch_it() {
+        let (mut document, _) = three_lines();
+        let rect = Rect::from_min_max(pos2(-5.0, 8.0), pos2(1.0, 12.0));
+        assert_eq!(document.select_in_rect(rect), HashSet::from([1]));
+        let rect = Rect::from_min_max(pos2(15.0, -1.0), pos2(30.0, 30.0));
+        assert_eq!(document.select_in_rect(rect), HashSet::from([0, 1, 2]));
+        let rect = Rect::from_min_max(pos2(50.0, 50.0), pos2(60.0, 60.0));
+        assert!(document.select_in_rect(rect).is_empty());
+        document.set_locked([1], true);
+        let rect = Rect::from_min_max(pos2(-5.0, -5.0), pos2(30.0, 30.0));
+        assert_eq!(document.select_in_rect(rect), HashSet::from([0, 2]));
+    }
+
+    #[test]
+    fn move_selection_moves_only_the_selection() {
+        let (mut document, _) = three_lines();
+        document.move_selection(&HashSet::from([0, 2]), Vec2::new(1.0, 2.0));
+        let starts: Vec<Pos2> =
+            document.lines.iter().map(|line| line.points[0]).collect();
+        assert_eq!(starts, [pos2(1.0, 2.0), pos2(0.0, 10.0), pos2(1.0, 22.0)]);
+    }
+
+    #[test]
+    fn save_and_load_keep_pages_and_palette() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut document, _) = three_lines();
+        document.palette = vec![Color32::BLACK, Color32::GOLD].into();
+        document.palette.set_active_color_index(1);
+        document.add_page(vec![line(&[(1.0, 1.0), (2.0, 2.0)])]);
+        for name in ["board.wb", "board.wbz", "board.wbb"] {
+            let path = dir.path().join(name);
+            document.dirty = true;
+            document.save_to(&path).unwrap();
+            assert!(!document.dirty);
+            let loaded = Document::load_from(&path).unwrap();
+            assert_eq!(loaded.page_count(), 2);
+            assert_eq!(loaded.current_page, 1);
+            assert_eq!(loaded.page_lines(0).len(), 3);
+            assert_eq!(loaded.lines[0].points, document.lines[0].points);
+            assert_eq!(
+                loaded.palette.get_palette_vec(),
+                [Color32::BLACK, Color32::GOLD]
+            );
+            assert_eq!(loaded.palette.get_active_color_index(), 1);
+        }
+        assert!(matches!(
+            Document::load_from(&dir.path().join("missing.wb")),
+            Err(LoadError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn saving_leaves_out_non_finite_points() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("board.wb");
+        let mut document = Document::default();
+        // as if a bug got them past the checks while drawing
+        let mut stroke = line(&[(0.0, 0.0), (f32::NAN, 1.0), (10.0, 0.0)]);
+        stroke.width = f32::INFINITY;
+        stroke.widths = vec![1.0, 2.0, f32::NAN];
+        document.lines.push(stroke);
+        document.lines.push(line(&[(f32::INFINITY, 0.0)]));
+        document.save_to(&path).unwrap();
+        let loaded = Document::load_from(&path).unwrap();
+        assert_eq!(loaded.lines.len(), 1);
+        let stroke = &loaded.lines[0];
+        assert_eq!(stroke.points, [pos2(0.0, 0.0), pos2(10.0, 0.0)]);
+        assert_eq!(stroke.width, MIN_STROKE_WIDTH);
+        assert_eq!(stroke.widths, [1.0, MIN_STROKE_WIDTH]);
+    }
+
+    #[test]
+    fn undo_after_clearing_a_reordered_page() {
+        let mut document = Document::default();
+        document.add_line(line(&[(0.0, 0.0), (10.0, 0.0)]));
+        document.add_line(line(&[(0.0, 10.0), (10.0, 10.0)]));
+        let order = zorder::reorder(2, &HashSet::from([0]), ZOrder::Forward);
+        document.reorder(order);
+        document.clear();
+        assert!(!document.undo());
+        assert!(document.lines.is_empty());
+    }
+
+    // three lines across the board at y = 0, 10 and 20
+    fn three_lines() -> (Document, Vec<u64>) {
+        let mut document = Document::default();
+        for y in [0.0, 10.0, 20.0] {
+            document.add_line(line(&[(0.0, y), (10.0, y), (20.0, y + 5.0)]));
+        }
+        let ids = document.lines.iter().map(|line| line.id).collect();
+        (document, ids)
+    }
+    fn ids(document: &Document) -> Vec<u64> {
+        document.lines.iter().map(|line| line.id).collect()
+    }
+
+    #[test]
+    fn undo_erase_puts_lines_back_in_place() {
+        let (mut document, original) = three_lines();
+        assert!(document.erase_lines(&HashSet::from([0, 2])));
+        assert_eq!(ids(&document), [original[1]]);
+        assert!(document.undo());
+        assert!(document.undo());
+        assert_eq!(ids(&document), original);
+
+        // the eraser going through the first and last line
+        let (mut document, original) = three_lines();
+        document.set_locked([1], true);
+        assert!(document.erase_at(pos2(5.0, 5.0), 100.0));
+        assert_eq!(ids(&document), [original[1]]);
+        assert!(document.undo());
+        assert!(document.undo());
+        assert_eq!(ids(&document), original);
+    }
+
+    #[test]
+    fn undo_edits_made_before_an_erase() {
+        let (mut document, _) = three_lines();
+        let before: Vec<Vec<Pos2>> = document
+            .lines
+            .iter()
+            .map(|line| line.points.clone())
+            .collect();
+        document.translate_lines(vec![
+            (0, Vec2::new(5.0, 0.0)),
+            (1, Vec2::new(-5.0, 0.0)),
+        ]);
+        document.set_width(&HashSet::from([0]), 8.0);
+        document.smooth(&HashSet::from([0]));
+        document.set_locked([2], true);
+        let order = zorder::reorder(3, &HashSet::from([0]), ZOrder::Forward);
+        document.reorder(order);
+        document.erase_lines(&HashSet::from([0]));
+        // the erase and the five edits before it
+        for _ in 0..6 {
+            assert!(document.undo());
+        }
+        let after: Vec<Vec<Pos2>> = document
+            .lines
+            .iter()
+            .map(|line| line.points.clone())
+            .collect();
+        assert_eq!(after, before);
+        assert!(document.lines.iter().all(|line| line.width == 2.0));
+        assert!(document.lines.iter().all(|line| !line.locked));
+    }
+
+    #[test]
+    fn undo_reorder_for_other_lines() {
+        let mut document = Document::default();
+        document.add_line(line(&[(0.0, 0.0), (10.0, 0.0)]));
+        document.undo_stack.add_reorder(vec![1, 0]);
+        assert!(document.undo());
+        assert_eq!(document.lines.len(), 1);
+    }
+}
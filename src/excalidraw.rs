@@ -0,0 +1,417 @@
+use std::{collections::BTreeMap, f32::consts::TAU};
+
+use egui::{Color32, Pos2, Rect, Vec2, pos2, vec2};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{Line, is_finite_pos, new_line_id, now_ms, stroke::LineStyle};
+
+const ELLIPSE_SEGMENTS: usize = 64;
+const ARROW_HEAD_LENGTH: f32 = 15.0;
+
+#[derive(Deserialize)]
+struct Scene {
+    elements: Vec<Element>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Element {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    x: f32,
+    #[serde(default)]
+    y: f32,
+    #[serde(default)]
+    width: f32,
+    #[serde(default)]
+    height: f32,
+    #[serde(default)]
+    angle: f32,
+    #[serde(default)]
+    stroke_color: String,
+    #[serde(default)]
+    background_color: String,
+    #[serde(default = "default_stroke_width")]
+    stroke_width: f32,
+    #[serde(default)]
+    stroke_style: String,
+    #[serde(default)]
+    points: Vec<[f32; 2]>,
+    #[serde(default)]
+    is_deleted: bool,
+    // last edit time in ms since the epoch
+    #[serde(default)]
+    updated: u64,
+}
+fn default_stroke_width() -> f32 {
+    2.0
+}
+
+pub(crate) struct ImportedScene {
+    pub lines: Vec<Line>,
+    pub imported: usize,
+    // element type -> number of elements that could not be converted
+    pub skipped: BTreeMap<String, usize>,
+}
+
+pub(crate) fn import(json: &str) -> serde_json::Result<ImportedScene> {
+    let scene: Scene = serde_json::from_str(json)?;
+    let mut result = ImportedScene {
+        lines: Vec::new(),
+        imported: 0,
+        skipped: BTreeMap::new(),
+    };
+    for element in scene.elements.iter().filter(|e| !e.is_deleted) {
+        let polylines = match element.kind.as_str() {
+            "freedraw" | "line" => vec![element.relative_points()],
+            "arrow" => {
+                let points = element.relative_points();
+                let mut polylines = arrow_heads(&points);
+                polylines.insert(0, points);
+                polylines
+            }
+            "rectangle" => vec![element.rectangle_points()],
+            "diamond" => vec![element.diamond_points()],
+            "ellipse" => vec![element.ellipse_points()],
+            other => {
+                *result.skipped.entry(other.to_owned()).or_default() += 1;
+                continue;
+            }
+        };
+        let color =
+            Color32::from_hex(&element.stroke_color).unwrap_or(Color32::WHITE);
+        let center = pos2(element.width / 2.0, element.height / 2.0).to_vec2();
+        let (sin, cos) = element.angle.sin_cos();
+        let origin = vec2(element.x, element.y);
+        for points in polylines {
+            let points: Vec<Pos2> = points
+                .into_iter()
+                .map(|p| {
+                    // rotate around the element center, then move it into
+                    // scene coordinates
+                    let d = p.to_vec2() - center;
+                    let rotated =
+                        vec2(d.x * cos - d.y * sin, d.x * sin + d.y * cos);
+                    (rotated + center + origin).to_pos2()
+                })
+                .filter(|&p| is_finite_pos(p))
+                .collect();
+            if points.len() >= 2 {
+                let mut line = Line {
+                    points,
+                    color,
+                    width: element.stroke_width,
+                    widths: Vec::new(),
+                    style: match element.stroke_style.as_str() {
+                        "dashed" => LineStyle::Dashed,
+                        "dotted" => LineStyle::Dotted,
+                        _ => LineStyle::Solid,
+                    },
+                    created_ms: element.updated,
+                    // "transparent" and other non-hex values don't parse
+                    fill: Color32::from_hex(&element.background_color).ok(),
+                    fill_only: false,
+                    picture: None,
+                    locked: false,
+                    id: new_line_id(),
+                };
+                if !line.is_closed() {
+                    line.fill = None;
+                }
+                result.lines.push(line);
+            }
+        }
+        result.imported += 1;
+    }
+    Ok(result)
+}
+
+impl Element {
+    fn relative_points(&self) -> Vec<Pos2> {
+        self.points.iter().map(|&[x, y]| pos2(x, y)).collect()
+    }
+    fn rectangle_points(&self) -> Vec<Pos2> {
+        let (w, h) = (self.width, self.height);
+        vec![
+            pos2(0.0, 0.0),
+            pos2(w, 0.0),
+            pos2(w, h),
+            pos2(0.0, h),
+            pos2(0.0, 0.0),
+        ]
+    }
+    fn diamond_points(&self) -> Vec<Pos2> {
+        let (w, h) = (self.width, self.height);
+        vec![
+            pos2(w / 2.0, 0.0),
+            pos2(w, h / 2.0),
+            pos2(w / 2.0, h),
+            pos2(0.0, h / 2.0),
+            pos2(w / 2.0, 0.0),
+        ]
+    }
+    fn ellipse_points(&self) -> Vec<Pos2> {
+        let radius = vec2(self.width / 2.0, self.height / 2.0);
+        let mut points: Vec<Pos2> = (0..ELLIPSE_SEGMENTS)
+            .map(|i| {
+                let t = i as f32 / ELLIPSE_SEGMENTS as f32 * TAU;
+                pos2(radius.x * (1.0 + t.cos()), radius.y * (1.0 + t.sin()))
+            })
+            .collect();
+        // end exactly on the start so the outline counts as closed
+        points.push(points[0]);
+        points
+    }
+}
+
+// The two barbs of an arrow head at the last point of `points`
+fn arrow_heads(points: &[Pos2]) -> Vec<Vec<Pos2>> {
+    let [.., from, tip] = points else {
+        return Vec::new();
+    };
+    let direction = (*tip - *from).normalized();
+    if !direction.x.is_finite() || !direction.y.is_finite() {
+        return Vec::new();
+    }
+    [0.5_f32, -0.5]
+        .iter()
+        .map(|&angle| {
+            let back = -direction * ARROW_HEAD_LENGTH;
+            let barb = Vec2::angled(back.angle() + angle) * ARROW_HEAD_LENGTH;
+            vec![*tip + barb, *tip]
+        })
+        .collect()
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FreedrawElement {
+    id: String,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+    angle: f32,
+    stroke_color: String,
+    background_color: String,
+    fill_style: &'static str,
+    stroke_width: f32,
+    stroke_style: &'static str,
+    roughness: u8,
+    opacity: u8,
+    group_ids: Vec<String>,
+    frame_id: Option<String>,
+    roundness: Option<()>,
+    seed: u32,
+    version: u32,
+    version_nonce: u32,
+    is_deleted: bool,
+    bound_elements: Option<()>,
+    updated: u64,
+    link: Option<String>,
+    locked: bool,
+    points: Vec<[f32; 2]>,
+    pressures: Vec<f32>,
+    simulate_pressure: bool,
+    last_committed_point: Option<[f32; 2]>,
+}
+
+pub(crate) fn export(lines: &[Line]) -> serde_json::Result<String> {
+    let updated = now_ms();
+    let mut rng = XorShift::new(updated);
+    let elements: Vec<FreedrawElement> = lines
+        .iter()
+        // pictures would need Excalidraw's separate file store
+        .filter(|line| !line.points.is_empty() && line.picture.is_none())
+        .map(|line| {
+            let mut bbox = Rect::NOTHING;
+            for p in &line.points {
+                bbox.extend_with(*p);
+            }
+            let [r, g, b, a] = line.color.to_srgba_unmultiplied();
+            FreedrawElement {
+                id: rng.next_id(),
+                kind: "freedraw",
+                x: bbox.min.x,
+                y: bbox.min.y,
+                width: bbox.width(),
+                height: bbox.height(),
+                angle: 0.0,
+                stroke_color: format!("#{r:02x}{g:02x}{b:02x}"),
+                background_color: line.fill.map_or_else(
+                    || "transparent".to_owned(),
+                    |fill| {
+                        let [r, g, b, _] = fill.to_srgba_unmultiplied();
+                        format!("#{r:02x}{g:02x}{b:02x}")
+                    },
+                ),
+                fill_style: "solid",
+                stroke_width: excalidraw_stroke_width(line.width),
+                stroke_style: match line.style {
+                    LineStyle::Solid => "solid",
+                    LineStyle::Dashed => "dashed",
+                    LineStyle::Dotted => "dotted",
+                },
+                roughness: 0,
+                opacity: (a as f32 / 255.0 * 100.0).round() as u8,
+                group_ids: Vec::new(),
+                frame_id: None,
+                roundness: None,
+                seed: rng.next_u32(),
+                version: 1,
+                version_nonce: rng.next_u32(),
+                is_deleted: false,
+                bound_elements: None,
+                updated: if line.created_ms > 0 {
+                    line.created_ms
+                } else {
+                    updated
+                },
+                link: None,
+                locked: false,
+                points: line
+                    .points
+                    .iter()
+                    .map(|p| [p.x - bbox.min.x, p.y - bbox.min.y])
+                    .collect(),
+                pressures: line.widths.iter().map(|w| w / line.width).collect(),
+                simulate_pressure: line.widths.is_empty(),
+                last_committed_point: None,
+            }
+        })
+        .collect();
+    serde_json::to_string_pretty(&json!({
+        "type": "excalidraw",
+        "version": 2,
+        "source": "simple-whiteboard",
+        "elements": elements,
+        "appState": {
+            "viewBackgroundColor": "#ffffff",
+            "gridSize": null,
+        },
+        "files": {},
+    }))
+}
+
+// Excalidraw only offers thin, bold and extra bold strokes
+fn excalidraw_stroke_width(width: f32) -> f32 {
+    if width <= 2.0 {
+        1.0
+    } else if width <= 5.0 {
+        2.0
+    } else {
+        4.0
+    }
+}
+
+// Small PRNG for element ids and seeds, which only need to be unique
+struct XorShift(u64);
+impl XorShift {
+    fn new(seed: u64) -> Self {
+        Self(seed | 1)
+    }
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 33) as u32
+    }
+    fn next_id(&mut self) -> String {
+        const ALPHABET: &[u8] =
+            b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+        (0..21)
+            .map(|_| {
+                ALPHABET[(self.next_u64() % ALPHABET.len() as u64) as usize]
+                    as char
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+
+    // A scene saved by Excalidraw itself, with every field its file format
+    // documents for a freedraw element
+    const FIXTURE: &str = include_str!("../tests/fixtures/freedraw.excalidraw");
+
+    fn line(points: &[(f32, f32)], width: f32, color: Color32) -> Line {
+        Line {
+            points: points.iter().map(|&(x, y)| pos2(x, y)).collect(),
+            color,
+            width,
+            widths: Vec::new(),
+            style: LineStyle::Solid,
+            created_ms: 0,
+            fill: None,
+            fill_only: false,
+            picture: None,
+            locked: false,
+            id: new_line_id(),
+        }
+    }
+
+    // Same keys as `expected`, each holding the same kind of JSON value
+    fn assert_same_shape(actual: &Value, expected: &Value, path: &str) {
+        let (Value::Object(actual), Value::Object(expected)) =
+            (actual, expected)
+        else {
+            panic!("{path} is not an object");
+        };
+        for (key, expected) in expected {
+            let actual = actual
+                .get(key)
+                .unwrap_or_else(|| panic!("{path}.{key} is missing"));
+            assert_eq!(
+                std::mem::discriminant(actual),
+                std::mem::discriminant(expected),
+                "{path}.{key} has the wrong type"
+            );
+        }
+    }
+
+    #[test]
+    fn export_matches_the_excalidraw_format() {
+        let fixture: Value = serde_json::from_str(FIXTURE).unwrap();
+        let lines = [
+            line(&[(10.0, 20.0), (30.0, 60.0)], 2.0, Color32::RED),
+            line(&[(5.0, 5.0), (0.0, 9.0)], 8.0, Color32::BLUE),
+        ];
+        let scene: Value =
+            serde_json::from_str(&export(&lines).unwrap()).unwrap();
+        assert_same_shape(&scene, &fixture, "scene");
+        assert_same_shape(&scene["appState"], &fixture["appState"], "app");
+        let elements = scene["elements"].as_array().unwrap();
+        assert_eq!(elements.len(), 2);
+        for element in elements {
+            assert_same_shape(element, &fixture["elements"][0], "element");
+            assert_eq!(element["type"], "freedraw");
+        }
+        // points are relative to the element's top left corner
+        assert_eq!(elements[1]["x"], 0.0);
+        assert_eq!(elements[1]["points"], json!([[5.0, 0.0], [0.0, 4.0]]));
+        assert_eq!(elements[0]["strokeColor"], "#ff0000");
+        assert_eq!(elements[0]["strokeWidth"], 1.0);
+        assert_eq!(elements[1]["strokeWidth"], 4.0);
+        assert_ne!(elements[0]["id"], elements[1]["id"]);
+    }
+
+    #[test]
+    fn exported_scenes_import_again() {
+        let lines = [line(&[(10.0, 20.0), (30.0, 60.0)], 4.0, Color32::RED)];
+        let imported = import(&export(&lines).unwrap()).unwrap();
+        assert_eq!(imported.imported, 1);
+        assert_eq!(imported.lines[0].points, lines[0].points);
+        assert_eq!(imported.lines[0].color, Color32::RED);
+    }
+}
@@ -0,0 +1,141 @@
+use std::{
+    io::{self, Read, Write},
+    path::Path,
+};
+
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+
+use crate::state::{CURRENT_VERSION, LoadError, WhiteboardState};
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+// binary files start with this magic followed by the format version as a
+// little-endian u32, then the MessagePack encoded state
+const BINARY_MAGIC: &[u8; 4] = b"WBMP";
+const BINARY_HEADER_LEN: usize = BINARY_MAGIC.len() + 4;
+pub(crate) const COMPRESSED_EXTENSION: &str = "wbz";
+pub(crate) const BINARY_EXTENSION: &str = "wbb";
+pub(crate) const EXTENSIONS: [&str; 3] =
+    ["wb", COMPRESSED_EXTENSION, BINARY_EXTENSION];
+
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+pub(crate) enum Encoding {
+    #[default]
+    Json,
+    Gzip,
+    Binary,
+}
+impl Encoding {
+    // `.wbz` files hold gzip-compressed JSON, `.wbb` files MessagePack and
+    // everything else plain JSON
+    pub fn from_extension(path: &Path) -> Self {
+        let extension = path.extension().unwrap_or_default();
+        if extension == BINARY_EXTENSION {
+            Encoding::Binary
+        } else if extension == COMPRESSED_EXTENSION {
+            Encoding::Gzip
+        } else {
+            Encoding::Json
+        }
+    }
+    // The format is sniffed from the content so that renamed files still
+    // load
+    pub fn sniff(bytes: &[u8]) -> Self {
+        if bytes.starts_with(&GZIP_MAGIC) {
+            Encoding::Gzip
+        } else if bytes.starts_with(BINARY_MAGIC)
+            && bytes.len() >= BINARY_HEADER_LEN
+        {
+            Encoding::Binary
+        } else {
+            Encoding::Json
+        }
+    }
+}
+
+// `pretty` indents plain JSON by two spaces, one value per line, and ends
+// it with a newline; the compressed and binary encodings ignore it
+pub(crate) fn encode(
+    encoding: Encoding,
+    state: &WhiteboardState,
+    pretty: bool,
+) -> io::Result<Vec<u8>> {
+    match encoding {
+        Encoding::Json if pretty => {
+            let mut json = serde_json::to_vec_pretty(state)?;
+            json.push(b'\n');
+            Ok(json)
+        }
+        Encoding::Json => Ok(serde_json::to_vec(state)?),
+        Encoding::Gzip => {
+            let json = serde_json::to_vec(state)?;
+            let mut encoder =
+                GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&json)?;
+            encoder.finish()
+        }
+        Encoding::Binary => {
+            // named fields keep the data self-describing, so older binary
+            // files can still go through the JSON migrations
+            let mut bytes = BINARY_MAGIC.to_vec();
+            bytes.extend_from_slice(&state.version.to_le_bytes());
+            rmp_serde::encode::write_named(&mut bytes, state)
+                .map_err(io::Error::other)?;
+            Ok(bytes)
+        }
+    }
+}
+
+pub(crate) fn decode(bytes: &[u8]) -> Result<WhiteboardState, LoadError> {
+    match Encoding::sniff(bytes) {
+        Encoding::Json => WhiteboardState::from_json(bytes),
+        Encoding::Gzip => {
+            let mut json = Vec::new();
+            GzDecoder::new(bytes)
+                .read_to_end(&mut json)
+                .map_err(LoadError::Decompress)?;
+            WhiteboardState::from_json(&json)
+        }
+        Encoding::Binary => decode_binary(bytes),
+    }
+}
+
+// Like `decode`, but skips unreadable line entries instead of failing.
+// Returns the state and the number of skipped lines.
+pub(crate) fn decode_lenient(
+    bytes: &[u8],
+) -> Result<(WhiteboardState, usize), LoadError> {
+    let value = match Encoding::sniff(bytes) {
+        Encoding::Json => {
+            serde_json::from_slice(bytes).map_err(LoadError::Syntax)?
+        }
+        Encoding::Gzip => {
+            let mut json = Vec::new();
+            GzDecoder::new(bytes)
+                .read_to_end(&mut json)
+                .map_err(LoadError::Decompress)?;
+            serde_json::from_slice(&json).map_err(LoadError::Syntax)?
+        }
+        Encoding::Binary => rmp_serde::from_slice(&bytes[BINARY_HEADER_LEN..])
+            .map_err(LoadError::MessagePack)?,
+    };
+    WhiteboardState::from_value_lenient(value)
+}
+
+fn decode_binary(bytes: &[u8]) -> Result<WhiteboardState, LoadError> {
+    let version_bytes = bytes[BINARY_MAGIC.len()..BINARY_HEADER_LEN]
+        .try_into()
+        .expect("header length is checked by the caller");
+    let version = u32::from_le_bytes(version_bytes);
+    let payload = &bytes[BINARY_HEADER_LEN..];
+    if version == CURRENT_VERSION {
+        // fast path: no migration needed
+        let state: WhiteboardState =
+            rmp_serde::from_slice(payload).map_err(LoadError::MessagePack)?;
+        state.validate()?;
+        Ok(state)
+    } else {
+        let value: serde_json::Value =
+            rmp_serde::from_slice(payload).map_err(LoadError::MessagePack)?;
+        WhiteboardState::from_value(value)
+    }
+}
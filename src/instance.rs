@@ -0,0 +1,135 @@
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::Duration,
+};
+
+use chacha20poly1305::aead::{OsRng, rand_core::RngCore};
+use directories::ProjectDirs;
+use eframe::egui;
+
+// The window that's open listens on a local port for files to open. Its
+// port and a random key are kept in this file, which only the user can
+// read, so other users of the machine can't open files in their window.
+const ADDRESS_FILE_NAME: &str = "instance";
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+fn address_path() -> io::Result<PathBuf> {
+    ProjectDirs::from("", "", "Simple Whiteboard")
+        .map(|dirs| dirs.data_local_dir().join(ADDRESS_FILE_NAME))
+        .ok_or_else(|| io::Error::other("no home directory"))
+}
+
+// Asks the window that's already open to open `file_path`. Fails if there
+// is none or it didn't answer.
+pub(crate) fn send(file_path: &Path) -> io::Result<()> {
+    let saved = fs::read_to_string(address_path()?)?;
+    let (port, key) = saved
+        .trim()
+        .split_once(' ')
+        .ok_or_else(|| io::Error::other("the instance file is damaged"))?;
+    let port: u16 = port.parse().map_err(io::Error::other)?;
+    // the other window has its own working directory
+    let file_path = std::path::absolute(file_path)?;
+    let file_path = file_path
+        .to_str()
+        .ok_or_else(|| io::Error::other("the path isn't valid UTF-8"))?;
+    let mut stream = TcpStream::connect_timeout(
+        &(Ipv4Addr::LOCALHOST, port).into(),
+        TIMEOUT,
+    )?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    writeln!(stream, "{key}\n{file_path}")?;
+    let mut answer = String::new();
+    BufReader::new(stream).read_line(&mut answer)?;
+    if answer.trim() == "ok" {
+        Ok(())
+    } else {
+        Err(io::Error::other("the open window refused the file"))
+    }
+}
+
+// Receives the files that later launches hand over instead of opening a
+// window of their own. Stops taking them once dropped.
+pub(crate) struct Listener {
+    paths: Receiver<PathBuf>,
+    // written to the address file, to tell whether it's still ours
+    address: String,
+}
+impl Listener {
+    pub fn start(ctx: &egui::Context) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let port = listener.local_addr()?.port();
+        let key = format!("{:016x}{:016x}", OsRng.next_u64(), OsRng.next_u64());
+        let address = format!("{port} {key}");
+        write_private(&address_path()?, &address)?;
+        let (sender, paths) = mpsc::channel();
+        let ctx = ctx.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                match receive(stream, &key, &sender) {
+                    Ok(true) => ctx.request_repaint(),
+                    // the listener was dropped
+                    Ok(false) => return,
+                    Err(e) => eprintln!("failed to receive a file: {e}"),
+                }
+            }
+        });
+        Ok(Self { paths, address })
+    }
+    // The files handed over since the last call
+    pub fn paths(&self) -> impl Iterator<Item = PathBuf> + '_ {
+        self.paths.try_iter()
+    }
+}
+impl Drop for Listener {
+    fn drop(&mut self) {
+        // another window may have taken over since
+        if let Ok(path) = address_path()
+            && fs::read_to_string(&path)
+                .is_ok_and(|saved| saved == self.address)
+        {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+// Reads a key and a path from `stream`, answering "ok" if the key matches
+// and the path could be passed on. Returns false once nobody is receiving.
+fn receive(
+    stream: TcpStream,
+    key: &str,
+    paths: &Sender<PathBuf>,
+) -> io::Result<bool> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let mut sent_key = String::new();
+    reader.read_line(&mut sent_key)?;
+    if sent_key.trim_end() != key {
+        return Err(io::Error::other("wrong key"));
+    }
+    let mut file_path = String::new();
+    reader.read_line(&mut file_path)?;
+    let file_path = PathBuf::from(file_path.trim_end_matches(['\r', '\n']));
+    let received = paths.send(file_path).is_ok();
+    let answer = if received { "ok" } else { "closed" };
+    writeln!(&stream, "{answer}")?;
+    Ok(received)
+}
+
+fn write_private(path: &Path, text: &str) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    // permissions only apply to new files
+    let _ = fs::remove_file(path);
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(text.as_bytes())
+}
@@ -0,0 +1,141 @@
+use std::time::Duration;
+
+use eframe::egui;
+use egui::{Color32, ImageSource, Painter, Pos2, Shape, Stroke};
+
+use crate::{
+    camera::Camera,
+    tools::{CanvasTool, Tool, ToolContext},
+};
+
+// how long a finished laser stroke stays before it's gone, fading out the
+// whole time unless motion is reduced
+const FADE_SECS: f64 = 1.5;
+const COLOR: Color32 = Color32::from_rgb(255, 40, 40);
+// screen px, independent of the zoom like a real laser dot
+const WIDTH: f32 = 4.0;
+const GLOW_WIDTH: f32 = 12.0;
+
+struct LaserStroke {
+    points: Vec<Pos2>,
+    // set once the pointer is released
+    finished_at: Option<f64>,
+}
+
+// Temporary ink for pointing at things: never part of the board, so it
+// isn't saved, selectable or undoable
+#[derive(Default)]
+pub(crate) struct Laser {
+    strokes: Vec<LaserStroke>,
+}
+impl Laser {
+    pub fn push(&mut self, pos: Pos2) {
+        match self.strokes.last_mut() {
+            Some(stroke) if stroke.finished_at.is_none() => {
+                if stroke.points.last() != Some(&pos) {
+                    stroke.points.push(pos);
+                }
+            }
+            _ => self.strokes.push(LaserStroke {
+                points: vec![pos],
+                finished_at: None,
+            }),
+        }
+    }
+    pub fn finish(&mut self, now: f64) {
+        if let Some(stroke) = self.strokes.last_mut() {
+            stroke.finished_at.get_or_insert(now);
+        }
+    }
+    pub fn clear(&mut self) {
+        self.strokes.clear();
+    }
+    // Draws the strokes that haven't faded yet and drops the others. With
+    // `reduce_motion` strokes stay opaque and vanish at once.
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        painter: &Painter,
+        camera: &Camera,
+        reduce_motion: bool,
+    ) {
+        let now = ctx.input(|i| i.time);
+        self.strokes.retain(|stroke| {
+            stroke.finished_at.is_none_or(|t| now - t < FADE_SECS)
+        });
+        if self.strokes.is_empty() {
+            return;
+        }
+        for stroke in &self.strokes {
+            let opacity = match stroke.finished_at {
+                Some(t) if !reduce_motion => {
+                    1.0 - ((now - t) / FADE_SECS) as f32
+                }
+                _ => 1.0,
+            };
+            let points: Vec<Pos2> =
+                stroke.points.iter().map(|&p| camera.to_screen(p)).collect();
+            let glow = COLOR.gamma_multiply(0.3 * opacity);
+            let core = COLOR.gamma_multiply(opacity);
+            if let [point] = points[..] {
+                painter.circle_filled(point, GLOW_WIDTH / 2.0, glow);
+                painter.circle_filled(point, WIDTH / 2.0, core);
+                continue;
+            }
+            painter.add(Shape::line(
+                points.clone(),
+                Stroke::new(GLOW_WIDTH, glow),
+            ));
+            painter.add(Shape::line(points, Stroke::new(WIDTH, core)));
+        }
+        let next_expiry = self
+            .strokes
+            .iter()
+            .filter_map(|stroke| stroke.finished_at)
+            .reduce(f64::min);
+        if let Some(finished_at) = next_expiry {
+            if reduce_motion {
+                ctx.request_repaint_after(Duration::from_secs_f64(
+                    finished_at + FADE_SECS - now,
+                ));
+            } else {
+                ctx.request_repaint();
+            }
+        }
+    }
+}
+impl CanvasTool for Laser {
+    fn kind(&self) -> Tool {
+        Tool::LASER
+    }
+    fn icon(&self) -> ImageSource<'static> {
+        egui::include_image!("../assets/tools/laser.png")
+    }
+    fn tooltip(&self) -> &'static str {
+        "Laser pointer"
+    }
+    fn edits(&self) -> bool {
+        false
+    }
+    fn on_pointer_down(&mut self, _cx: &mut ToolContext<'_>, pos: Pos2) {
+        self.push(pos);
+    }
+    fn on_pointer_drag(&mut self, _cx: &mut ToolContext<'_>, pos: Pos2) {
+        self.push(pos);
+    }
+    fn on_pointer_up(&mut self, cx: &mut ToolContext<'_>, pos: Pos2) {
+        self.push(pos);
+        self.finish(cx.ctx.input(|i| i.time));
+    }
+    fn paint_overlay(
+        &mut self,
+        cx: &ToolContext<'_>,
+        painter: &Painter,
+        _pointer: Option<Pos2>,
+    ) {
+        self.show(cx.ctx, painter, &cx.camera, cx.preferences.reduce_motion);
+    }
+    fn cancel(&mut self) {
+        self.clear();
+    }
+}
@@ -1,212 +1,1165 @@
+mod align;
+mod association;
+mod atomic;
+mod backup;
+mod base64;
+mod brush;
+mod bucket;
+mod builder;
+mod camera;
+mod cli;
+mod collab;
 mod colors;
+mod crypto;
+mod diff;
+mod document;
+mod excalidraw;
+mod format;
+#[cfg(not(target_arch = "wasm32"))]
+mod instance;
+mod laser;
+mod meta;
+mod minimap;
+mod ops;
+mod pages;
+mod picture;
+mod points;
+mod preferences;
+mod presets;
+mod recent;
+mod recovery;
+mod render;
+mod replay;
+mod ruler;
+#[cfg(not(target_arch = "wasm32"))]
+mod screenshot;
+mod scrollbar;
+mod selection;
 mod state;
+mod stroke;
+mod svg;
+mod tabs;
+mod toast;
 mod tools;
 mod undo;
+#[cfg(target_arch = "wasm32")]
+mod web;
+#[cfg(not(target_arch = "wasm32"))]
+mod window;
+mod zorder;
 
 use std::{
-    collections::HashSet,
-    io,
+    collections::{HashMap, HashSet},
+    io, mem,
     path::{Path, PathBuf},
+    sync::{
+        Arc, LazyLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, SystemTime},
 };
 
+use chacha20poly1305::aead::{OsRng, rand_core::RngCore};
 use directories::UserDirs;
 use eframe::egui;
-use egui::{Color32, Painter, Pos2, Rect, Response, Stroke, Ui, pos2, vec2};
+use egui::{
+    Color32, Painter, Pos2, Rect, Response, Stroke, Ui, Vec2, pos2, vec2,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use rfd::FileDialog;
 
+#[cfg(target_arch = "wasm32")]
+use crate::web::{BrowserFiles, FileDialog};
+use crate::{
+    align::{ALIGNMENTS, Distribution},
+    backup::Backup,
+    camera::Camera,
+    collab::{Event, Session},
+    colors::{ColorPalette, RecentColors, color_name},
+    crypto::{DecryptError, FileKey},
+    document::Document,
+    format::Encoding,
+    meta::Metadata,
+    picture::Picture,
+    points::PointSettings,
+    preferences::{Preferences, THEMES},
+    presets::PalettePreset,
+    recent::RecentFiles,
+    recovery::SnapshotId,
+    render::ImageSettings,
+    replay::{ReplayExport, ReplaySettings},
+    ruler::Ruler,
+    state::CURRENT_VERSION,
+    stroke::{LINE_STYLES, LineStyle},
+    svg::SvgSettings,
+    tabs::Tab,
+    toast::{ToastLevel, Toasts},
+    zorder::{Z_ORDERS, ZOrder},
+};
+// The tools are for `WhiteboardApp::set_tool` and `register_tool` when the
+// board is embedded in another app, the rest for setting up and inspecting
+// a board without a window
+pub use crate::{
+    builder::WhiteboardBuilder,
+    render::render_to_image,
+    state::{LoadError, WhiteboardState},
+    tools::{CanvasTool, Tool, ToolContext, ToolMaker, ToolSettings},
+};
+#[cfg(not(target_arch = "wasm32"))]
 use crate::{
-    colors::ColorPalette,
-    state::WhiteboardState,
-    tools::{TOOLS, Tool},
-    undo::{UndoAction, UndoStack},
+    screenshot::{
+        Annotation, Capture, Picked, RegionPicker, ScreenshotSettings,
+    },
+    window::{BoardWindow, WindowAction},
 };
 
+const RECOVERY_INTERVAL_SECS: f64 = 15.0;
+const MIN_STROKE_WIDTH: f32 = 1.0;
+const MAX_STROKE_WIDTH: f32 = 20.0;
+// how much `[` and `]` change the stroke width
+const STROKE_WIDTH_STEP: f32 = 1.0;
+const STROKE_WIDTH_PRESETS: [(&str, f32); 3] =
+    [("Thin", 2.0), ("Medium", 5.0), ("Thick", 10.0)];
+// the most see-through a brush gets
+const MIN_OPACITY: f32 = 0.1;
+// eframe storage key of the recent colors, kept between sessions
+const RECENT_COLORS_KEY: &str = "recent_colors";
+const LAST_DIRECTORY_KEY: &str = "last_directory";
+const TOOL_SETTINGS_KEY: &str = "tool_settings";
+// how often the open file is checked for changes made by other programs
+const FILE_CHECK_INTERVAL_SECS: f64 = 2.0;
+
 #[derive(Debug, Clone)]
 struct Line {
     points: Vec<Pos2>,
     color: Color32,
     width: f32,
+    // per-point widths for pressure sensitive strokes, empty if uniform
+    widths: Vec<f32>,
+    style: LineStyle,
+    // when the stroke was drawn, in ms since the epoch; 0 if unknown (files
+    // and imports from before this was recorded). Strokes can be replayed
+    // in drawing order by sorting on it.
+    created_ms: u64,
+    // color of the enclosed area of a closed stroke
+    fill: Option<Color32>,
+    // an area filled with the fill tool: only `fill` is drawn, beneath
+    // every stroke
+    fill_only: bool,
+    // an image drawn into the rectangle between the two points instead of
+    // a stroke
+    picture: Option<Arc<Picture>>,
+    // can't be selected or erased until it's unlocked
+    locked: bool,
+    // tells lines apart while the board is shared, see `collab`; not saved
+    id: u64,
+}
+impl Line {
+    // ends where it starts, so there is an inside to fill
+    fn is_closed(&self) -> bool {
+        self.points.len() >= 4 && self.points.first() == self.points.last()
+    }
+    // the picture and the area it covers, for lines that are a picture
+    fn picture_area(&self) -> Option<(&Picture, Rect)> {
+        match (&self.picture, &self.points[..]) {
+            (Some(picture), &[a, b]) => {
+                Some((picture, Rect::from_two_pos(a, b)))
+            }
+            _ => None,
+        }
+    }
+}
+
+// What happens to an encrypted file once it's unlocked
+#[derive(Clone, Copy)]
+enum UnlockPurpose {
+    Open,
+    Import,
+    RestoreBackup,
+}
+struct Unlock {
+    file_path: PathBuf,
+    bytes: Vec<u8>,
+    purpose: UnlockPurpose,
+    passphrase: String,
+    // shown after a wrong passphrase
+    error: Option<&'static str>,
+}
+#[derive(Default)]
+struct PassphrasePrompt {
+    passphrase: String,
+    confirmation: String,
+    error: Option<&'static str>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum ResizeCorner {
-    TopLeft,
-    TopRight,
-    BottomLeft,
-    BottomRight,
+// Read-only, full screen view of the board
+struct Presentation {
+    // restored when the presentation ends
+    was_fullscreen: bool,
+    // canvas the board was last fitted to, so it's fitted again when the
+    // window size changes
+    fitted_rect: Option<Rect>,
 }
 
+// The whole whiteboard. As an eframe app it shows the board with its menus,
+// toolbar and windows. To embed the board in another egui app instead,
+// create it with `WhiteboardApp::default()` and call `ui` each frame; pointer
+// input over the board is read from egui, and `set_tool` picks what it does.
+// `WhiteboardBuilder` sets one up with known content instead, e.g. in tests.
+// `render_to_image` draws a loaded `WhiteboardState` without any window.
 pub struct WhiteboardApp {
-    lines: Vec<Line>,
-    current_line: Vec<Pos2>,
-    palette: ColorPalette,
-    stroke_width: f32,
+    // lines, pages, undo history and the dirty flag
+    document: Document,
+    // thin the brush while drawing fast
+    speed_sensitive_width: bool,
+    meta: Metadata,
+    // open "Document properties" window
+    show_properties: bool,
+    recent_colors: RecentColors,
+    // palettes saved by the user, shared by all documents
+    palette_presets: Vec<PalettePreset>,
+    preset_name: String,
+    // width and opacity of each tool that has them, see `settings_tool`
+    tool_settings: HashMap<Tool, ToolSettings>,
+    line_style: LineStyle,
     current_tool: Tool,
-    undo_stack: UndoStack,
+    // every tool on the toolbar, see `tools::built_in`, and what makes
+    // them again for a detached window
+    tools: Vec<Box<dyn CanvasTool>>,
+    tool_makers: Vec<ToolMaker>,
+    ruler: Ruler,
+    // fill strokes that end where they start
+    fill_closed_strokes: bool,
+    fill_color: Color32,
+    // start and end strokes exactly on nearby stroke ends
+    snap_to_endpoints: bool,
     whiteboard_file: Option<PathBuf>,
-
-    // Selection tool state
-    selection_start: Option<Pos2>,
-    selection_current: Option<Pos2>,
+    // how `whiteboard_file` is stored on disk, kept when saving over it
+    file_encoding: Encoding,
+    // modification time of `whiteboard_file` when it was last opened or
+    // saved by us
+    file_modified: Option<SystemTime>,
+    // key of a passphrase protected file, used whenever it's saved; the
+    // passphrase itself is never kept
+    encryption: Option<FileKey>,
+    // names the board's crash recovery snapshot
+    recovery_id: SnapshotId,
+    // encrypted file waiting for its passphrase
+    unlock: Option<Unlock>,
+    // open "Encrypt with passphrase" prompt
+    passphrase_prompt: Option<PassphrasePrompt>,
+    last_file_check: f64,
+    canvas_rect: Rect,
+    camera: Camera,
+    // a two-finger gesture is in progress; tools ignore the touches until
+    // every finger is lifted
+    touch_gesture: bool,
+    show_minimap: bool,
+    // editing is off while presenting
+    presentation: Option<Presentation>,
+    // guide lines through the cursor while drawing or measuring
+    show_crosshair: bool,
+    title_shows_dirty: bool,
+    preferences: Preferences,
+    // what's in the preferences file, to only write it when something changed
+    saved_preferences: Preferences,
+    // open "Settings" window
+    show_settings: bool,
+    last_autosave: f64,
+    last_recovery_snapshot: f64,
+    // open "Restore from backup" window
+    backups: Option<Vec<Backup>>,
+    recent_files: RecentFiles,
+    // folder of the last file opened or saved, where file dialogs start
+    last_directory: Option<PathBuf>,
+    // open "Export animation" window and the export it started
+    show_replay_window: bool,
+    replay_settings: ReplaySettings,
+    // open "Export image" window
+    show_image_window: bool,
+    image_settings: ImageSettings,
+    // open "Export data" window
+    show_points_window: bool,
+    point_settings: PointSettings,
+    replay_export: Option<ReplayExport>,
+    toasts: Toasts,
+    // lines picked with the selection tool, by index into the page
     selected_lines: HashSet<usize>,
-    is_moving_selection: bool,
-    last_mouse_pos: Option<Pos2>,
-    resizing_corner: Option<ResizeCorner>,
-    resize_original_bbox: Option<Rect>,
-    resize_original_lines: Vec<(usize, Line)>,
+    // every open board; the one shown is `tabs[active_tab]`, see `Tab`
+    tabs: Vec<Tab>,
+    active_tab: usize,
+    // boards detached into windows of their own
+    #[cfg(not(target_arch = "wasm32"))]
+    windows: Vec<BoardWindow>,
+    // set while one of `windows` is drawn, with its board in the app's
+    // fields
+    in_board_window: bool,
+
+    // the board shared over the network, as its host or a guest
+    session: Option<Session>,
+    // open "Share session" window and its fields
+    show_share_window: bool,
+    share_port: u16,
+    share_address: String,
+    share_key: String,
+
+    #[cfg(target_arch = "wasm32")]
+    browser_files: BrowserFiles,
+    // takes the files later launches hand over, in single instance mode
+    #[cfg(not(target_arch = "wasm32"))]
+    instance: Option<instance::Listener>,
+    // open "Annotate screenshot" window with the displays to pick from
+    #[cfg(not(target_arch = "wasm32"))]
+    screenshot_displays: Option<Vec<screenshot::Display>>,
+    #[cfg(not(target_arch = "wasm32"))]
+    screenshot_settings: ScreenshotSettings,
+    #[cfg(not(target_arch = "wasm32"))]
+    capture: Option<Capture>,
+    #[cfg(not(target_arch = "wasm32"))]
+    region_picker: Option<RegionPicker>,
+    #[cfg(not(target_arch = "wasm32"))]
+    annotation: Option<Annotation>,
 }
 
 impl WhiteboardApp {
+    fn document_name(&self) -> String {
+        if let Some(title) = self.meta.title() {
+            return title.to_owned();
+        }
+        self.whiteboard_file
+            .as_ref()
+            .map_or("Untitled.wb".to_owned(), |s| s.display().to_string())
+    }
+    // Non-blocking feedback; errors that need acknowledging still get a
+    // dialog
+    fn push_toast(&mut self, text: impl Into<String>, level: ToastLevel) {
+        self.toasts.push(text, level);
+    }
+    fn window_title(&self) -> String {
+        format!(
+            "{}Simple Whiteboard - {}",
+            if self.document.dirty { "* " } else { "" },
+            self.document_name()
+        )
+    }
     fn set_window_title(&mut self, ctx: &egui::Context) {
-        ctx.send_viewport_cmd(egui::ViewportCommand::Title(format!(
-            "Simple Whiteboard - {}",
-            self.whiteboard_file
-                .as_ref()
-                .map_or("Untitled.wb".to_owned(), |s| s.display().to_string())
-        )));
+        ctx.send_viewport_cmd(egui::ViewportCommand::Title(
+            self.window_title(),
+        ));
+        self.title_shows_dirty = self.document.dirty;
+    }
+    // Asks what to do with unsaved changes before they would be lost.
+    // Returns false if the user cancelled or saving failed.
+    fn confirm_discard_changes(&mut self, ctx: &egui::Context) -> bool {
+        if !self.document.dirty {
+            return true;
+        }
+        let choice = rfd::MessageDialog::new()
+            .set_level(rfd::MessageLevel::Warning)
+            .set_title("Unsaved changes")
+            .set_description(format!(
+                "Save changes to {}?",
+                self.document_name()
+            ))
+            .set_buttons(rfd::MessageButtons::YesNoCancelCustom(
+                "Save".to_owned(),
+                "Discard".to_owned(),
+                "Cancel".to_owned(),
+            ))
+            .show();
+        match choice {
+            rfd::MessageDialogResult::Custom(choice) if choice == "Save" => {
+                self.save_whiteboard(ctx);
+                !self.document.dirty
+            }
+            rfd::MessageDialogResult::Custom(choice) if choice == "Discard" => {
+                true
+            }
+            _ => false,
+        }
+    }
+    // Asks about the unsaved changes of every tab, showing each in turn
+    fn confirm_discard_all(&mut self, ctx: &egui::Context) -> bool {
+        let shown = self.active_tab;
+        for index in 0..self.tabs.len() {
+            if index != self.active_tab && !self.tabs[index].is_dirty() {
+                continue;
+            }
+            self.show_tab(ctx, index);
+            if !self.confirm_discard_changes(ctx) {
+                // the shared board has to stay in front
+                if self.session.is_some() {
+                    self.show_tab(ctx, shown);
+                }
+                return false;
+            }
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let mut windows = mem::take(&mut self.windows);
+            let confirmed = windows.iter_mut().all(|window| {
+                if !window.tab.is_dirty() {
+                    return true;
+                }
+                window.swap(self);
+                let confirmed = self.confirm_discard_changes(ctx);
+                window.swap(self);
+                confirmed
+            });
+            self.windows = windows;
+            confirmed
+        }
+        #[cfg(target_arch = "wasm32")]
+        true
+    }
+    // Other tabs can't be shown while presenting, sharing the board or
+    // waiting for the passphrase of a file opened in this one, and a
+    // detached window has no tabs
+    fn can_switch_tabs(&self) -> bool {
+        self.presentation.is_none()
+            && self.session.is_none()
+            && self.unlock.is_none()
+            && !self.in_board_window
+    }
+    // Shows the tab at `index`, keeping the board shown so far in its own
+    fn show_tab(&mut self, ctx: &egui::Context, index: usize) {
+        if index == self.active_tab || index >= self.tabs.len() {
+            return;
+        }
+        self.cancel_tools();
+        let mut tab = mem::take(&mut self.tabs[index]);
+        tab.swap(self);
+        self.tabs[self.active_tab] = tab;
+        self.active_tab = index;
+        self.set_window_title(ctx);
+    }
+    // Shows the next tab, or the previous one with `backwards`, wrapping
+    // around at the ends
+    fn cycle_tabs(&mut self, ctx: &egui::Context, backwards: bool) {
+        let count = self.tabs.len();
+        let step = if backwards { count - 1 } else { 1 };
+        self.show_tab(ctx, (self.active_tab + step) % count);
+    }
+    // Opens a blank board in a new tab right after the shown one
+    fn new_tab(&mut self, ctx: &egui::Context) {
+        let tab = Tab::new(self.preferences.palette());
+        self.tabs.insert(self.active_tab + 1, tab);
+        self.show_tab(ctx, self.active_tab + 1);
+    }
+    // Closes the shown tab after asking about unsaved changes. The last tab
+    // is cleared instead.
+    fn close_tab(&mut self, ctx: &egui::Context) {
+        if !self.confirm_discard_changes(ctx) {
+            return;
+        }
+        if self.tabs.len() == 1 {
+            self.blank_document(ctx);
+            return;
+        }
+        let closed = self.active_tab;
+        let next = if closed + 1 < self.tabs.len() {
+            closed + 1
+        } else {
+            closed - 1
+        };
+        self.show_tab(ctx, next);
+        let tab = self.tabs.remove(closed);
+        if self.active_tab > closed {
+            self.active_tab -= 1;
+        }
+        recovery::remove_snapshot(tab.recovery_id());
+    }
+    // A board nobody touched yet, like the one the app starts with
+    fn is_blank(&self) -> bool {
+        self.whiteboard_file.is_none()
+            && !self.document.dirty
+            && self.document.lines.is_empty()
+            && self.document.page_count() == 1
+    }
+    // Opens `file_path` in a tab of its own, or shows the tab it's already
+    // open in. A blank board gives up its tab for it.
+    fn open_in_tab(
+        &mut self,
+        ctx: &egui::Context,
+        file_path: PathBuf,
+    ) -> io::Result<()> {
+        if !self.can_switch_tabs() {
+            if self.confirm_discard_changes(ctx) {
+                self.load_whiteboard_file(file_path)?;
+            }
+            return Ok(());
+        }
+        if let Some(index) = self.tab_with_file(&file_path) {
+            self.show_tab(ctx, index);
+            return Ok(());
+        }
+        let opened_tab = !self.is_blank();
+        if opened_tab {
+            self.new_tab(ctx);
+        }
+        let result = self.load_whiteboard_file(file_path);
+        // nothing was loaded after all, e.g. the file couldn't be read
+        if opened_tab && self.is_blank() && self.unlock.is_none() {
+            self.close_tab(ctx);
+        }
+        result
+    }
+    fn tab_with_file(&self, file_path: &Path) -> Option<usize> {
+        if self.whiteboard_file.as_deref() == Some(file_path) {
+            return Some(self.active_tab);
+        }
+        self.tabs
+            .iter()
+            .position(|tab| tab.whiteboard_file() == Some(file_path))
+    }
+    // Opens a blank board in a window of its own
+    #[cfg(not(target_arch = "wasm32"))]
+    fn new_window(&mut self) {
+        let tab = Tab::new(self.preferences.palette());
+        self.windows.push(BoardWindow::new(
+            tab,
+            self.current_tool,
+            &self.tool_makers,
+        ));
+    }
+    // Moves the shown board out of the tabs into a window of its own
+    #[cfg(not(target_arch = "wasm32"))]
+    fn move_to_window(&mut self, ctx: &egui::Context) {
+        self.cancel_tools();
+        let mut tab = Tab::new(self.preferences.palette());
+        tab.swap(self);
+        self.windows.push(BoardWindow::new(
+            tab,
+            self.current_tool,
+            &self.tool_makers,
+        ));
+        // the tab is left blank, and closed unless it's the last one
+        self.close_tab(ctx);
+        self.set_window_title(ctx);
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    fn show_board_windows(&mut self, ctx: &egui::Context) {
+        let mut windows = mem::take(&mut self.windows);
+        let mut attached = Vec::new();
+        windows.retain_mut(|window| {
+            window.swap(self);
+            self.in_board_window = true;
+            let action = self.show_board_window(ctx, window.id);
+            self.in_board_window = false;
+            window.swap(self);
+            match action {
+                WindowAction::Keep => true,
+                WindowAction::Close => {
+                    recovery::remove_snapshot(window.tab.recovery_id());
+                    false
+                }
+                WindowAction::Attach => {
+                    attached.push(mem::take(&mut window.tab));
+                    false
+                }
+            }
+        });
+        self.windows = windows;
+        for tab in attached {
+            self.tabs.insert(self.active_tab + 1, tab);
+            self.show_tab(ctx, self.active_tab + 1);
+        }
+    }
+    // Draws the board of a detached window, which is in the app's fields
+    // for now, with its own toolbar. The window's shortcuts reach it
+    // through the viewport's input.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn show_board_window(
+        &mut self,
+        ctx: &egui::Context,
+        id: egui::ViewportId,
+    ) -> WindowAction {
+        let builder = egui::ViewportBuilder::default()
+            .with_title(self.window_title())
+            .with_inner_size([1024.0, 720.0]);
+        ctx.show_viewport_immediate(id, builder, |ctx, _class| {
+            self.handle_keyboard_event(ctx);
+            let mut action = WindowAction::Keep;
+            if ctx.input(|i| i.viewport().close_requested()) {
+                if self.confirm_discard_changes(ctx) {
+                    action = WindowAction::Close;
+                } else {
+                    ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+                }
+            }
+            egui::TopBottomPanel::top(egui::Id::new("menu_bar").with(id)).show(
+                ctx,
+                |ui| {
+                    egui::MenuBar::new().ui(ui, |ui| {
+                        if ui.button("Save").clicked() {
+                            self.save_whiteboard(ctx);
+                        }
+                        if ui.button("Save As…").clicked() {
+                            self.save_whiteboard_as();
+                        }
+                        if ui.button("Back to Main Window").clicked() {
+                            self.cancel_tools();
+                            action = WindowAction::Attach;
+                        }
+                    });
+                },
+            );
+            egui::SidePanel::left(egui::Id::new("control_panel").with(id))
+                .show(ctx, |ui| self.show_toolbar(ui));
+            egui::CentralPanel::default().show(ctx, |ui| self.ui(ui));
+            action
+        })
+    }
+    // The tools and their settings on the left of the canvas
+    fn show_toolbar(&mut self, ui: &mut Ui) {
+        ui.heading("toolbar");
+        ui.add_space(5.0);
+
+        // Tool selection
+        self.draw_tool_bar(ui);
+
+        ui.add_space(15.0);
+        ui.separator();
+        ui.add_space(15.0);
+
+        // color selection (only when brush is selected)
+        let drawing =
+            matches!(self.current_tool, Tool::BRUSH | Tool::HIGHLIGHTER);
+        ui.add_enabled_ui(drawing, |ui| {
+            if self.document.palette.draw(ui) {
+                self.document.dirty = true;
+            }
+            if let Some(color) = self.recent_colors.draw(ui)
+                && self.document.palette.select_color(color)
+            {
+                self.document.dirty = true;
+            }
+            self.draw_palette_presets(ui);
+        });
+
+        ui.add_space(10.0);
+
+        let mut width = self.tool_settings().width;
+        if ui
+            .add(
+                egui::Slider::new(
+                    &mut width,
+                    MIN_STROKE_WIDTH..=MAX_STROKE_WIDTH,
+                )
+                .text("Stroke Width"),
+            )
+            .changed()
+        {
+            self.set_stroke_width(width);
+        }
+        ui.horizontal(|ui| {
+            for (label, width) in STROKE_WIDTH_PRESETS {
+                if ui
+                    .selectable_label(
+                        self.tool_settings().width == width,
+                        label,
+                    )
+                    .clicked()
+                {
+                    self.set_stroke_width(width);
+                }
+            }
+        });
+        if let Some(opacity) = &mut self.tool_settings_mut().opacity {
+            ui.add(
+                egui::Slider::new(opacity, MIN_OPACITY..=1.0).text("Opacity"),
+            );
+        }
+        ui.checkbox(
+            &mut self.speed_sensitive_width,
+            "Thinner when drawing fast",
+        );
+        ui.horizontal(|ui| {
+            for (style, label) in LINE_STYLES {
+                ui.selectable_value(&mut self.line_style, style, label);
+            }
+        });
+
+        ui.add_space(10.0);
+
+        let ruler_text = match self.ruler {
+            Ruler::Off => "Ruler",
+            Ruler::Placing(_) => "Ruler: place two anchors",
+            Ruler::Active(..) => "Ruler: on (Esc to dismiss)",
+        };
+        if ui
+            .selectable_label(self.ruler != Ruler::Off, ruler_text)
+            .clicked()
+        {
+            self.toggle_ruler();
+        }
+        ui.checkbox(&mut self.snap_to_endpoints, "Snap to stroke ends");
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.fill_closed_strokes, "Fill closed strokes")
+                .on_hover_text("Strokes that end where they start");
+            ui.add_enabled_ui(self.fill_closed_strokes, |ui| {
+                ui.color_edit_button_srgba(&mut self.fill_color);
+            });
+        });
+
+        if self.current_tool == Tool::SELECTION
+            && self.selected_lines.len() >= 2
+        {
+            ui.add_space(10.0);
+            self.draw_arrange_buttons(ui);
+        }
+
+        ui.add_space(20.0);
+
+        if ui.button("Clear").clicked() {
+            self.document.clear();
+        }
+    }
+    fn show_tab_strip(&mut self, ui: &mut Ui) {
+        let ctx = &ui.ctx().clone();
+        let mut show = None;
+        let mut close = None;
+        ui.add_enabled_ui(self.can_switch_tabs(), |ui| {
+            ui.horizontal_wrapped(|ui| {
+                for index in 0..self.tabs.len() {
+                    let label = if index == self.active_tab {
+                        tabs::label(
+                            &self.meta,
+                            self.whiteboard_file.as_deref(),
+                            self.document.dirty,
+                        )
+                    } else {
+                        self.tabs[index].label()
+                    };
+                    let response =
+                        ui.selectable_label(index == self.active_tab, label);
+                    if response.clicked() {
+                        show = Some(index);
+                    }
+                    if ui
+                        .small_button("×")
+                        .on_hover_text("Close tab (Ctrl+W)")
+                        .clicked()
+                    {
+                        close = Some(index);
+                    }
+                    ui.separator();
+                }
+            });
+        });
+        if let Some(index) = show {
+            self.show_tab(ctx, index);
+        }
+        // unsaved changes are asked about with the tab in front
+        if let Some(index) = close {
+            self.show_tab(ctx, index);
+            self.close_tab(ctx);
+        }
     }
     fn handle_keyboard_event(&mut self, ctx: &egui::Context) {
         let mut should_save = false;
+        let mut should_save_as = false;
         let mut should_open = false;
+        let mut should_create_new = false;
+        let mut should_close_tab = false;
+        // with Shift to go backwards
+        let mut cycle_tabs = None;
+        let mut toggle_presentation = false;
+        let mut should_copy_svg = false;
+        #[cfg(not(target_arch = "wasm32"))]
+        let mut should_paste = false;
+        // only view navigation keys work while presenting
+        let mut presenting_keys = Vec::new();
+        // single-key shortcuts would fire while typing in a text field
+        let typing = ctx.wants_keyboard_input();
         ctx.input(|i| {
             for event in &i.events {
+                // egui swallows the Ctrl+V press to read text from the
+                // clipboard, but the release still comes through
+                #[cfg(not(target_arch = "wasm32"))]
+                if let egui::Event::Key {
+                    key: egui::Key::V,
+                    pressed: false,
+                    modifiers,
+                    ..
+                } = event
+                    && modifiers.command
+                    && !typing
+                    && self.presentation.is_none()
+                {
+                    should_paste = true;
+                }
+                // Ctrl+C is turned into a copy event the same way
+                if let egui::Event::Copy = event
+                    && i.modifiers.command
+                    && i.modifiers.shift
+                    && !typing
+                    && self.presentation.is_none()
+                {
+                    should_copy_svg = true;
+                }
                 if let egui::Event::Key {
                     key,
                     pressed: true,
                     modifiers,
                     ..
                 } = event
+                    && (!typing || modifiers.command)
                 {
+                    if self.presentation.is_some() {
+                        presenting_keys.push((*key, *modifiers));
+                        continue;
+                    }
                     match key {
                         egui::Key::Z if modifiers.command => {
                             self.undo();
                         }
                         egui::Key::C if !modifiers.command => {
-                            self.lines.clear();
+                            self.document.clear();
                             self.selected_lines.clear();
                         }
                         egui::Key::B if !modifiers.command => {
-                            self.current_tool = Tool::Brush;
+                            self.current_tool = Tool::BRUSH;
+                        }
+                        egui::Key::H if !modifiers.command => {
+                            self.current_tool = Tool::HIGHLIGHTER;
                         }
                         egui::Key::E if !modifiers.command => {
-                            self.current_tool = Tool::Eraser;
+                            self.current_tool = Tool::ERASER;
                         }
                         egui::Key::S if !modifiers.command => {
-                            if self.current_tool != Tool::Selection {
-                                self.selected_lines.clear();
-                                self.selection_start = None;
-                                self.selection_current = None;
-                                self.is_moving_selection = false;
-                                self.resizing_corner = None;
-                                self.resize_original_bbox = None;
-                                self.resize_original_lines.clear();
-                                self.current_tool = Tool::Selection;
+                            if self.current_tool != Tool::SELECTION {
+                                self.clear_selection_state();
+                                self.current_tool = Tool::SELECTION;
                             }
                         }
+                        egui::Key::S
+                            if modifiers.command && modifiers.shift =>
+                        {
+                            should_save_as = true;
+                        }
                         egui::Key::S if modifiers.command => {
                             should_save = true;
                         }
-                        egui::Key::O if modifiers.command => {
+                        // boards are created and opened in the main window
+                        egui::Key::N
+                            if modifiers.command && !self.in_board_window =>
+                        {
+                            should_create_new = true;
+                        }
+                        egui::Key::O
+                            if modifiers.command && !self.in_board_window =>
+                        {
                             should_open = true;
                         }
+                        egui::Key::W if modifiers.command => {
+                            should_close_tab = true;
+                        }
+                        egui::Key::Tab if modifiers.command => {
+                            cycle_tabs = Some(modifiers.shift);
+                        }
                         egui::Key::Num1 => {
-                            self.palette.set_active_color_index(0);
+                            self.document.palette.set_active_color_index(0);
                         }
                         egui::Key::Num2 => {
-                            self.palette.set_active_color_index(1);
+                            self.document.palette.set_active_color_index(1);
                         }
                         egui::Key::Num3 => {
-                            self.palette.set_active_color_index(2);
+                            self.document.palette.set_active_color_index(2);
                         }
                         egui::Key::Num4 => {
-                            self.palette.set_active_color_index(3);
+                            self.document.palette.set_active_color_index(3);
                         }
                         egui::Key::Num5 => {
-                            self.palette.set_active_color_index(4);
+                            self.document.palette.set_active_color_index(4);
                         }
                         egui::Key::Num6 => {
-                            self.palette.set_active_color_index(5);
+                            self.document.palette.set_active_color_index(5);
                         }
                         egui::Key::Num7 => {
-                            self.palette.set_active_color_index(6);
+                            self.document.palette.set_active_color_index(6);
                         }
                         egui::Key::Num8 => {
-                            self.palette.set_active_color_index(7);
+                            self.document.palette.set_active_color_index(7);
                         }
                         egui::Key::Num9 => {
-                            self.palette.set_active_color_index(8);
+                            self.document.palette.set_active_color_index(8);
                         }
                         egui::Key::Delete => {
-                            if !self.selected_lines.is_empty() {
-                                let mut indices: Vec<_> = self
-                                    .selected_lines
-                                    .iter()
-                                    .copied()
-                                    .collect();
-                                indices.sort_unstable_by(|a, b| b.cmp(a)); // sort descending
-
-                                let mut deleted_lines = Vec::new();
-                                for index in indices {
-                                    if index < self.lines.len() {
-                                        deleted_lines
-                                            .push(self.lines.remove(index));
-                                    }
-                                }
-                                // Reverse to maintain original order for undo if needed,
-                                // though simple push is fine.
-                                // For undo, we need to add them back.
-                                // Since we remove by index descending, the last removed was the first in original list.
-                                // We can just add them to undo stack as Erase action.
-                                self.undo_stack.extend_erase(deleted_lines);
+                            if self.document.erase_lines(&self.selected_lines) {
                                 self.selected_lines.clear();
                             }
                         }
+                        egui::Key::M if !modifiers.command => {
+                            self.current_tool = Tool::MEASURE;
+                        }
+                        egui::Key::G if !modifiers.command => {
+                            self.current_tool = Tool::FILL;
+                        }
+                        egui::Key::L if !modifiers.command => {
+                            self.current_tool = Tool::LASER;
+                        }
+                        egui::Key::L if modifiers.command => {
+                            self.lock_selection();
+                        }
+                        egui::Key::R if !modifiers.command => {
+                            self.toggle_ruler();
+                        }
+                        egui::Key::F
+                            if modifiers.shift && !modifiers.command =>
+                        {
+                            self.zoom_to_selection();
+                        }
+                        egui::Key::F if !modifiers.command => {
+                            self.fit_to_content();
+                        }
+                        egui::Key::Home if !modifiers.command => {
+                            self.reset_view();
+                        }
+                        egui::Key::Num0 if modifiers.command => {
+                            self.reset_zoom();
+                        }
+                        egui::Key::CloseBracket if !modifiers.command => {
+                            self.set_stroke_width(
+                                self.tool_settings().width + STROKE_WIDTH_STEP,
+                            );
+                        }
+                        egui::Key::OpenBracket if !modifiers.command => {
+                            self.set_stroke_width(
+                                self.tool_settings().width - STROKE_WIDTH_STEP,
+                            );
+                        }
+                        egui::Key::CloseBracket
+                        | egui::Key::CloseCurlyBracket
+                            if modifiers.command =>
+                        {
+                            self.reorder_selection(if modifiers.shift {
+                                ZOrder::Front
+                            } else {
+                                ZOrder::Forward
+                            });
+                        }
+                        egui::Key::OpenBracket
+                        | egui::Key::OpenCurlyBracket
+                            if modifiers.command =>
+                        {
+                            self.reorder_selection(if modifiers.shift {
+                                ZOrder::Back
+                            } else {
+                                ZOrder::Backward
+                            });
+                        }
+                        egui::Key::PageUp if modifiers.command => {
+                            self.go_to_page(
+                                self.document.current_page.wrapping_sub(1),
+                            );
+                        }
+                        egui::Key::PageDown if modifiers.command => {
+                            self.go_to_page(self.document.current_page + 1);
+                        }
                         egui::Key::Escape => {
-                            self.selected_lines.clear();
-                            self.selection_start = None;
-                            self.selection_current = None;
-                            self.is_moving_selection = false;
-                            self.resizing_corner = None;
-                            self.resize_original_bbox = None;
-                            self.resize_original_lines.clear();
+                            self.clear_selection_state();
+                            self.ruler = Ruler::Off;
+                        }
+                        egui::Key::F5 if !self.in_board_window => {
+                            toggle_presentation = true;
                         }
                         _ => {}
                     }
                 }
             }
         });
-        if should_open {
-            if let Err(e) = self.open_whiteboard_file() {
-                rfd::MessageDialog::new()
-                    .set_level(rfd::MessageLevel::Error)
-                    .set_title("Failed to read")
-                    .set_description(format!("Failed to read: {e}",))
-                    .set_buttons(rfd::MessageButtons::Ok)
-                    .show();
-            } else {
-                self.set_window_title(ctx);
+        for (key, modifiers) in presenting_keys {
+            match key {
+                egui::Key::F5 | egui::Key::Escape => toggle_presentation = true,
+                egui::Key::F if !modifiers.command => self.fit_to_content(),
+                egui::Key::Home if !modifiers.command => self.reset_view(),
+                egui::Key::Num0 if modifiers.command => self.reset_zoom(),
+                // the laser pointer edits nothing, so it works here too
+                egui::Key::L if !modifiers.command => {
+                    self.current_tool = if self.current_tool == Tool::LASER {
+                        Tool::BRUSH
+                    } else {
+                        Tool::LASER
+                    };
+                }
+                egui::Key::PageUp | egui::Key::ArrowLeft => {
+                    self.go_to_page(self.document.current_page.wrapping_sub(1));
+                }
+                egui::Key::PageDown | egui::Key::ArrowRight => {
+                    self.go_to_page(self.document.current_page + 1);
+                }
+                _ => {}
+            }
+        }
+        if toggle_presentation {
+            self.toggle_presentation(ctx);
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if should_paste {
+            self.paste_image();
+        }
+        if should_copy_svg {
+            self.copy_selection_as_svg(ctx);
+        }
+        if should_create_new {
+            self.new_document(ctx);
+        }
+        if self.can_switch_tabs() {
+            if should_close_tab {
+                self.close_tab(ctx);
+            }
+            if let Some(backwards) = cycle_tabs {
+                self.cycle_tabs(ctx, backwards);
             }
         }
+        if should_open {
+            self.open(ctx);
+        }
         if should_save {
-            self.save_whiteboard();
+            self.save(ctx);
+        }
+        if should_save_as {
+            self.save_as(ctx);
+        }
+    }
+    fn toggle_presentation(&mut self, ctx: &egui::Context) {
+        if let Some(presentation) = self.presentation.take() {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Fullscreen(
+                presentation.was_fullscreen,
+            ));
+            return;
+        }
+        self.cancel_tools();
+        self.clear_selection_state();
+        self.presentation = Some(Presentation {
+            was_fullscreen: ctx
+                .input(|i| i.viewport().fullscreen.unwrap_or(false)),
+            fitted_rect: None,
+        });
+        ctx.send_viewport_cmd(egui::ViewportCommand::Fullscreen(true));
+        self.push_toast(
+            "Presenting, press L for the laser pointer and Esc or F5 to go \
+             back to editing",
+            ToastLevel::Info,
+        );
+    }
+    // Starts a blank board in a new tab, or in place of the shown one while
+    // it has to stay in front
+    fn new_document(&mut self, ctx: &egui::Context) {
+        if self.can_switch_tabs() {
+            self.new_tab(ctx);
+        } else if self.confirm_discard_changes(ctx) {
+            self.blank_document(ctx);
+        }
+    }
+    fn blank_document(&mut self, ctx: &egui::Context) {
+        self.document = Document::default();
+        self.cancel_tools();
+        self.clear_selection_state();
+        recovery::remove_snapshot(self.recovery_id);
+        self.whiteboard_file = None;
+        self.file_encoding = Encoding::default();
+        self.file_modified = None;
+        self.encryption = None;
+        self.document.palette = self.preferences.palette();
+        self.meta = Metadata::default();
+        self.set_window_title(ctx);
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    fn open(&mut self, ctx: &egui::Context) {
+        if let Err(e) = self.open_whiteboard_file(ctx) {
+            show_read_error(&e);
+        }
+        self.set_window_title(ctx);
+    }
+    // In the browser the file arrives later, see `open_picked_file`
+    #[cfg(target_arch = "wasm32")]
+    fn open(&mut self, ctx: &egui::Context) {
+        if self.can_switch_tabs() || self.confirm_discard_changes(ctx) {
+            self.browser_files.pick(ctx);
+        }
+    }
+    // Loads the file picked in the browser, if any. It replaces the board
+    // kept in the browser once saved.
+    #[cfg(target_arch = "wasm32")]
+    fn open_picked_file(&mut self, ctx: &egui::Context) {
+        let Some((name, bytes)) = self.browser_files.take_picked() else {
+            return;
+        };
+        if crypto::is_encrypted(&bytes) {
+            show_read_error(&io::Error::other(
+                "encrypted boards can only be opened in the desktop app",
+            ));
+            return;
+        }
+        if let ReadResult::Loaded(loaded) =
+            decode_whiteboard(Path::new(&name), &bytes, None)
+        {
+            if self.can_switch_tabs() && !self.is_blank() {
+                self.new_tab(ctx);
+            }
+            self.load_state(loaded.state);
+            self.document.dirty = true;
             self.set_window_title(ctx);
         }
     }
+    fn open_recent(&mut self, ctx: &egui::Context, file_path: PathBuf) {
+        if let Err(e) = self.open_in_tab(ctx, file_path.clone()) {
+            show_read_error(&e);
+            // most likely moved or deleted
+            if e.kind() == io::ErrorKind::NotFound {
+                self.recent_files.remove(&file_path);
+            }
+        }
+        self.set_window_title(ctx);
+    }
+    fn save(&mut self, ctx: &egui::Context) {
+        self.save_whiteboard(ctx);
+        self.set_window_title(ctx);
+    }
+    fn save_as(&mut self, ctx: &egui::Context) {
+        self.save_whiteboard_as();
+        self.set_window_title(ctx);
+    }
     fn undo(&mut self) {
         self.selected_lines.clear();
-        match self.undo_stack.pop() {
-            None => {}
-            Some(action) => match action {
-                UndoAction::Erase(line) => {
-                    self.lines.push(line);
-                }
-                UndoAction::Draw(_line) => {
-                    self.lines.pop();
-                }
-            },
-        }
+        self.document.undo();
     }
-    fn write_whiteboard(&mut self, file_path: PathBuf, json: String) {
-        if let Err(e) = std::fs::write(&file_path, json) {
+    fn write_whiteboard(
+        &mut self,
+        mut file_path: PathBuf,
+        whiteboard_state: &WhiteboardState,
+    ) {
+        let encoding = if self.whiteboard_file.as_ref() == Some(&file_path) {
+            self.file_encoding
+        } else {
+            // a new destination, usually typed into the save dialog
+            let chosen = file_path;
+            file_path = normalize_extension(&chosen);
+            // the dialog only asked about replacing the name it returned
+            if file_path != chosen
+                && self.whiteboard_file.as_ref() != Some(&file_path)
+                && file_path.exists()
+                && !confirm_overwrite(&file_path)
+            {
+                return;
+            }
+            Encoding::from_extension(&file_path)
+        };
+        if let Err(e) =
+            backup::rotate(&file_path, self.preferences.backup_count)
+        {
+            eprintln!("backup of {} failed: {e}", file_path.display());
+            self.push_toast(
+                format!("Could not back up the old file: {e}"),
+                ToastLevel::Warning,
+            );
+        }
+        if let Err(e) = self
+            .encode(whiteboard_state, encoding)
+            .and_then(|bytes| atomic::write(&file_path, &bytes))
+        {
             rfd::MessageDialog::new()
                 .set_level(rfd::MessageLevel::Error)
                 .set_title("Failed to save whiteboard")
@@ -215,346 +1168,2418 @@ impl WhiteboardApp {
                 .show();
             return;
         }
-        if self.whiteboard_file.is_none() {
-            self.whiteboard_file = Some(file_path);
+        self.recent_files
+            .add(&file_path, whiteboard_state.thumbnail_png());
+        self.remember_directory(&file_path);
+        self.push_toast(
+            format!("Saved to {}", file_path.display()),
+            ToastLevel::Success,
+        );
+        self.whiteboard_file = Some(file_path);
+        self.file_encoding = encoding;
+        self.file_modified = self.whiteboard_file.as_deref().and_then(modified);
+        self.document.dirty = false;
+        recovery::remove_snapshot(self.recovery_id);
+    }
+    fn host_session(&mut self, ctx: &egui::Context) {
+        // sessions aren't encrypted, so the board would go out in the clear
+        if self.encryption.is_some()
+            && rfd::MessageDialog::new()
+                .set_level(rfd::MessageLevel::Warning)
+                .set_title("Share an encrypted board?")
+                .set_description(
+                    "This board is encrypted, but everyone who joins gets \
+                     its content unencrypted over the network. Share it \
+                     anyway?",
+                )
+                .set_buttons(rfd::MessageButtons::OkCancelCustom(
+                    "Share".to_owned(),
+                    "Cancel".to_owned(),
+                ))
+                .show()
+                != rfd::MessageDialogResult::Custom("Share".to_owned())
+        {
+            return;
+        }
+        match Session::host(self.share_port, ctx) {
+            Ok(session) => {
+                self.push_toast(
+                    format!("Sharing the board on port {}", session.port()),
+                    ToastLevel::Success,
+                );
+                self.session = Some(session);
+                self.document.outbox = Some(Vec::new());
+                self.show_share_window = false;
+            }
+            Err(e) => {
+                rfd::MessageDialog::new()
+                    .set_level(rfd::MessageLevel::Error)
+                    .set_title("Failed to share the board")
+                    .set_description(format!(
+                        "Failed to share the board on port {}: {e}",
+                        self.share_port
+                    ))
+                    .set_buttons(rfd::MessageButtons::Ok)
+                    .show();
+            }
         }
     }
-    fn save_whiteboard(&mut self) {
-        let default_path = UserDirs::new()
-            .and_then(|user_dirs| {
-                user_dirs.download_dir().map(Path::to_path_buf)
-            })
-            .unwrap_or(std::env::current_dir().unwrap_or_default());
-        let whiteboard_state = WhiteboardState::new(self);
-        let json = serde_json::to_string(&whiteboard_state).unwrap();
-        if let Some(file_path) = self.whiteboard_file.clone() {
-            self.write_whiteboard(file_path, json);
-        } else {
-            let files = rfd::FileDialog::new()
-                .add_filter("Whiteboard file", &["wb"])
-                .add_filter("All files", &["*"])
-                .set_directory(default_path)
-                .set_file_name("Untitled.wb")
-                .save_file();
-            if let Some(file_path) = files {
-                self.write_whiteboard(file_path, json);
+    // The host's board takes the place of the one shown
+    fn join_session(&mut self, ctx: &egui::Context) {
+        let session = match Session::join(
+            self.share_address.trim(),
+            &self.share_key,
+            ctx,
+        ) {
+            Ok(session) => session,
+            Err(e) => {
+                rfd::MessageDialog::new()
+                    .set_level(rfd::MessageLevel::Error)
+                    .set_title("Failed to join")
+                    .set_description(format!(
+                        "Failed to join {}: {e}",
+                        self.share_address.trim()
+                    ))
+                    .set_buttons(rfd::MessageButtons::Ok)
+                    .show();
+                return;
             }
+        };
+        // dropping the session hangs up again
+        if !self.confirm_discard_changes(ctx) {
+            return;
         }
+        self.blank_document(ctx);
+        self.session = Some(session);
+        self.document.outbox = Some(Vec::new());
+        self.show_share_window = false;
     }
-    fn open_whiteboard_file(&mut self) -> io::Result<()> {
-        let files = rfd::FileDialog::new()
-            .add_filter("Whiteboard file", &["wb"])
-            .set_title("Select whiteboard file")
-            .pick_file();
-        if let Some(file_path) = files {
-            let json = std::fs::read_to_string(&file_path)?;
-            let state = serde_json::from_str::<WhiteboardState>(&json);
-            match state {
-                Ok(state) => {
-                    self.whiteboard_file = Some(file_path);
-                    self.palette = state
-                        .palette
-                        .iter()
-                        .map(|&color| color.into())
-                        .collect::<Vec<_>>()
-                        .into();
-                    self.lines = state.lines.iter().map(Into::into).collect();
+    fn stop_sharing(&mut self) {
+        self.session = None;
+        self.document.outbox = None;
+    }
+    // Applies what the others sent and sends them what changed here
+    fn exchange_changes(&mut self) {
+        let Some(session) = &self.session else {
+            return;
+        };
+        let events: Vec<Event> = session.events().collect();
+        // a board opened while sharing replaces the shared one, unless
+        // it's encrypted: that one isn't sent without asking first
+        if self.document.outbox.is_none() {
+            if self.encryption.is_some() {
+                self.stop_sharing();
+                self.push_toast(
+                    "Stopped sharing: the board opened is encrypted",
+                    ToastLevel::Warning,
+                );
+                return;
+            }
+            session.send(&self.document.to_board());
+            self.document.outbox = Some(Vec::new());
+        }
+        let mut disconnected = None;
+        for event in events {
+            match event {
+                Event::Joined(guest) => {
+                    if let Some(session) = &self.session {
+                        session.send_to(guest, &self.document.to_board());
+                    }
+                    self.push_toast(
+                        "Someone joined the board",
+                        ToastLevel::Info,
+                    );
                 }
-                Err(_) => {
-                    rfd::MessageDialog::new()
-                        .set_level(rfd::MessageLevel::Error)
-                        .set_title("Invalid whiteboard file")
-                        .set_description(format!(
-                            "{} is not a whiteboard file",
-                            &file_path.to_string_lossy()
-                        ))
-                        .set_buttons(rfd::MessageButtons::Ok)
-                        .show();
+                Event::Left => {
+                    self.push_toast("Someone left the board", ToastLevel::Info);
+                }
+                Event::Received(message) => {
+                    if self.document.apply(message) {
+                        // the selection refers to positions in `lines`
+                        self.clear_selection_state();
+                    }
                 }
+                Event::Disconnected(reason) => disconnected = Some(reason),
             }
         }
-        Ok(())
-    }
-    fn handle_selection(&mut self, response: &Response, pointer_pos: Pos2) {
-        {
-            // Check if we are interacting with existing selection
-            let selection_info = self.get_selection_info();
-            let (bounding_box, expanded_bbox, corners) = match selection_info {
-                Some(info) => info,
-                None => (Rect::NOTHING, Rect::NOTHING, [Pos2::ZERO; 4]),
-            };
+        if let Some(reason) = disconnected {
+            self.stop_sharing();
+            self.push_toast(
+                format!("Disconnected from the shared board: {reason}"),
+                ToastLevel::Warning,
+            );
+            return;
+        }
+        if let (Some(session), Some(outbox)) =
+            (&self.session, &mut self.document.outbox)
+        {
+            for message in outbox.drain(..) {
+                session.send(&message);
+            }
+        }
+    }
+    fn show_share_window(&mut self, ctx: &egui::Context) {
+        if !self.show_share_window {
+            return;
+        }
+        let mut open = true;
+        let mut host = false;
+        let mut join = false;
+        let mut stop = false;
+        egui::Window::new("Share session")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                if let Some(session) = &self.session {
+                    ui.label(session.describe());
+                    if let Some(key) = session.key() {
+                        ui.horizontal(|ui| {
+                            ui.label("Key");
+                            ui.monospace(key);
+                            if ui.small_button("Copy").clicked() {
+                                ui.ctx().copy_text(key.to_owned());
+                            }
+                        });
+                        ui.label("Guests need this key to join.");
+                    }
+                    stop = ui.button("Stop sharing").clicked();
+                    return;
+                }
+                ui.label(
+                    "Host this board so others on the network can draw on \
+                     it with you.",
+                );
+                ui.horizontal(|ui| {
+                    ui.label("Port");
+                    ui.add(
+                        egui::DragValue::new(&mut self.share_port)
+                            .range(1024..=65535),
+                    );
+                    host = ui.button("Host").clicked();
+                });
+                ui.separator();
+                ui.label(
+                    "Join a board someone else hosts. It replaces the board \
+                     shown here.",
+                );
+                egui::Grid::new("join").num_columns(2).show(ui, |ui| {
+                    ui.label("Address");
+                    ui.text_edit_singleline(&mut self.share_address)
+                        .on_hover_text("For example 192.168.1.20:9053");
+                    ui.end_row();
+                    ui.label("Key");
+                    ui.text_edit_singleline(&mut self.share_key)
+                        .on_hover_text("The key shown to the host");
+                    ui.end_row();
+                });
+                join = ui
+                    .add_enabled(
+                        !self.share_address.trim().is_empty()
+                            && !self.share_key.trim().is_empty(),
+                        egui::Button::new("Join"),
+                    )
+                    .clicked();
+            });
+        if !open {
+            self.show_share_window = false;
+        }
+        if host {
+            self.host_session(ctx);
+        } else if join {
+            self.join_session(ctx);
+        } else if stop {
+            self.stop_sharing();
+        }
+    }
+    fn show_properties_window(&mut self, ctx: &egui::Context) {
+        if !self.show_properties {
+            return;
+        }
+        let mut open = true;
+        let mut changed = false;
+        egui::Window::new("Document properties")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("document_properties").num_columns(2).show(
+                    ui,
+                    |ui| {
+                        for (label, field) in [
+                            ("Title", &mut self.meta.title),
+                            ("Author", &mut self.meta.author),
+                        ] {
+                            ui.label(label);
+                            let mut text = field.clone().unwrap_or_default();
+                            if ui.text_edit_singleline(&mut text).changed() {
+                                *field = Some(text).filter(|t| !t.is_empty());
+                                changed = true;
+                            }
+                            ui.end_row();
+                        }
+                        for (label, time) in [
+                            ("Created", &self.meta.created),
+                            ("Modified", &self.meta.modified),
+                        ] {
+                            ui.label(label);
+                            ui.label(
+                                time.as_deref().unwrap_or("not saved yet"),
+                            );
+                            ui.end_row();
+                        }
+                    },
+                );
+            });
+        if changed {
+            self.document.dirty = true;
+            self.set_window_title(ctx);
+        }
+        self.show_properties = open;
+    }
+    fn store_preferences(&mut self) {
+        if self.preferences == self.saved_preferences {
+            return;
+        }
+        if let Err(e) = self.preferences.store() {
+            eprintln!("failed to save the preferences: {e}");
+        }
+        self.saved_preferences = self.preferences.clone();
+    }
+    // Changes apply right away and are written to the preferences file
+    // when the window is closed
+    fn show_settings_window(&mut self, ctx: &egui::Context) {
+        if !self.show_settings {
+            return;
+        }
+        let mut open = true;
+        let mut single_instance_changed = false;
+        egui::Window::new("Settings")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let preferences = &mut self.preferences;
+                egui::Grid::new("settings").num_columns(2).show(ui, |ui| {
+                    ui.label("Theme");
+                    ui.horizontal(|ui| {
+                        for (theme, name) in THEMES {
+                            if ui
+                                .selectable_value(
+                                    &mut preferences.theme,
+                                    theme,
+                                    name,
+                                )
+                                .changed()
+                            {
+                                ctx.set_theme(theme.to_egui());
+                            }
+                        }
+                    });
+                    ui.end_row();
 
-            let corner_size = vec2(10.0, 10.0);
-            let tl_rect = Rect::from_center_size(corners[0], corner_size);
-            let tr_rect = Rect::from_center_size(corners[1], corner_size);
-            let bl_rect = Rect::from_center_size(corners[2], corner_size);
-            let br_rect = Rect::from_center_size(corners[3], corner_size);
+                    ui.label("Default brush width");
+                    ui.add(egui::Slider::new(
+                        &mut preferences.stroke_width,
+                        MIN_STROKE_WIDTH..=MAX_STROKE_WIDTH,
+                    ));
+                    ui.end_row();
 
-            if response.drag_started() {
-                if !self.selected_lines.is_empty()
-                    && tl_rect.contains(pointer_pos)
-                {
-                    self.start_resizing(ResizeCorner::TopLeft, bounding_box);
-                } else if !self.selected_lines.is_empty()
-                    && tr_rect.contains(pointer_pos)
-                {
-                    self.start_resizing(ResizeCorner::TopRight, bounding_box);
-                } else if !self.selected_lines.is_empty()
-                    && bl_rect.contains(pointer_pos)
-                {
-                    self.start_resizing(ResizeCorner::BottomLeft, bounding_box);
-                } else if !self.selected_lines.is_empty()
-                    && br_rect.contains(pointer_pos)
-                {
-                    self.start_resizing(
-                        ResizeCorner::BottomRight,
-                        bounding_box,
+                    ui.label("Default palette");
+                    ui.horizontal(|ui| {
+                        for color in preferences.palette().get_palette_vec() {
+                            let (rect, _) = ui.allocate_exact_size(
+                                vec2(16.0, 16.0),
+                                egui::Sense::hover(),
+                            );
+                            ui.painter().rect_filled(rect, 2.0, *color);
+                        }
+                        if ui.button("Use current").clicked() {
+                            preferences.set_palette(&self.document.palette);
+                        }
+                        if ui.button("Reset").clicked() {
+                            preferences.set_palette(&ColorPalette::default());
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label("Eraser size");
+                    ui.add(
+                        egui::Slider::new(
+                            &mut preferences.eraser_size,
+                            0.0..=50.0,
+                        )
+                        .suffix(" px"),
+                    )
+                    .on_hover_text("How far past its width the eraser reaches");
+                    ui.end_row();
+
+                    ui.label("Autosave");
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut preferences.autosave_enabled, "");
+                        ui.add_enabled(
+                            preferences.autosave_enabled,
+                            egui::DragValue::new(
+                                &mut preferences.autosave_interval_secs,
+                            )
+                            .range(5..=3600)
+                            .suffix(" s")
+                            .prefix("every "),
+                        );
+                    });
+                    ui.end_row();
+
+                    ui.label("Backups to keep");
+                    ui.add(
+                        egui::DragValue::new(&mut preferences.backup_count)
+                            .range(0..=20),
                     );
-                } else if expanded_bbox.contains(pointer_pos)
-                    && !self.selected_lines.is_empty()
-                {
-                    self.is_moving_selection = true;
-                    self.last_mouse_pos = Some(pointer_pos);
-                } else {
-                    self.selected_lines.clear();
-                    self.selection_start = Some(pointer_pos);
-                    self.selection_current = Some(pointer_pos);
-                }
-            } else if response.dragged() {
-                if let Some(corner) = self.resizing_corner {
-                    self.update_resizing(pointer_pos, corner);
-                } else if self.is_moving_selection {
-                    if let Some(last_pos) = self.last_mouse_pos {
-                        let delta = pointer_pos - last_pos;
-                        for i in &self.selected_lines {
-                            if let Some(line) = self.lines.get_mut(*i) {
-                                for p in &mut line.points {
-                                    *p += delta;
-                                }
-                            }
+                    ui.end_row();
+
+                    ui.label("Saved files");
+                    ui.checkbox(
+                        &mut preferences.stable_saves,
+                        "Pretty, stable JSON",
+                    )
+                    .on_hover_text(
+                        "Indent .wb files and round coordinates to 0.1 px, \
+                         so an unchanged board saves to the same file and \
+                         edits make small diffs, e.g. in git",
+                    );
+                    ui.end_row();
+
+                    ui.label("Previews");
+                    ui.checkbox(
+                        &mut preferences.embed_thumbnails,
+                        "Embed a thumbnail in saved files",
+                    )
+                    .on_hover_text(
+                        "A small picture of the first page for Open Recent \
+                         and other file browsers, which adds a few KB",
+                    );
+                    ui.end_row();
+
+                    ui.label("Window");
+                    ui.checkbox(
+                        &mut preferences.remember_window,
+                        "Remember size and position",
+                    )
+                    .on_hover_text(
+                        "When off, the window always opens maximized",
+                    );
+                    ui.end_row();
+
+                    ui.label("Motion");
+                    if ui
+                        .checkbox(
+                            &mut preferences.reduce_motion,
+                            "Reduce motion",
+                        )
+                        .on_hover_text(
+                            "No fades or animations: panels appear at once, \
+                             and notifications and laser pointer strokes \
+                             disappear without fading out",
+                        )
+                        .changed()
+                    {
+                        set_reduce_motion(ctx, preferences.reduce_motion);
+                    }
+                    ui.end_row();
+
+                    #[cfg(not(target_arch = "wasm32"))]
+                    {
+                        ui.label("Opening files");
+                        single_instance_changed = ui
+                            .checkbox(
+                                &mut preferences.single_instance,
+                                "Use the open window",
+                            )
+                            .on_hover_text(
+                                "When off, every file opened from outside \
+                                 the app gets a window of its own",
+                            )
+                            .changed();
+                        ui.end_row();
+                    }
+
+                    ui.label("Presentation");
+                    ui.checkbox(
+                        &mut preferences.present_fit,
+                        "Fit the board to the screen",
+                    );
+                    ui.end_row();
+
+                    ui.label("File types");
+                    if ui
+                        .add_enabled(
+                            association::SUPPORTED,
+                            egui::Button::new("Open .wb files with this app…"),
+                        )
+                        .on_disabled_hover_text(
+                            "Not supported on this platform",
+                        )
+                        .clicked()
+                    {
+                        register_file_association();
+                    }
+                    ui.end_row();
+                });
+                ui.separator();
+                if ui.button("Restore defaults").clicked() {
+                    *preferences = Preferences::default();
+                    ctx.set_theme(preferences.theme.to_egui());
+                    set_reduce_motion(ctx, preferences.reduce_motion);
+                    single_instance_changed = true;
+                }
+            });
+        #[cfg(not(target_arch = "wasm32"))]
+        if single_instance_changed {
+            self.set_single_instance(ctx);
+        }
+        if !open {
+            self.store_preferences();
+        }
+        self.show_settings = open;
+    }
+    // Exports the current page as an SVG drawing on the canvas background
+    fn export_svg(&mut self, ctx: &egui::Context) {
+        let Some(file_path) = self
+            .file_dialog()
+            .add_filter("SVG image", &["svg"])
+            .set_file_name("Untitled.svg")
+            .save_file()
+        else {
+            return;
+        };
+        let settings = SvgSettings {
+            background: Some(ctx.style().visuals.panel_fill),
+            ..Default::default()
+        };
+        match svg::export(&self.document.lines, &settings, &file_path) {
+            Ok(_) => self.push_toast(
+                format!("Exported SVG to {}", file_path.display()),
+                ToastLevel::Success,
+            ),
+            Err(e) => {
+                rfd::MessageDialog::new()
+                    .set_level(rfd::MessageLevel::Error)
+                    .set_title("Failed to export")
+                    .set_description(format!("Failed to export: {e}"))
+                    .set_buttons(rfd::MessageButtons::Ok)
+                    .show();
+            }
+        }
+    }
+    fn show_image_window(&mut self, ctx: &egui::Context) {
+        if !self.show_image_window {
+            return;
+        }
+        let mut open = true;
+        let mut export = false;
+        egui::Window::new("Export image")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("image_settings").num_columns(2).show(
+                    ui,
+                    |ui| {
+                        ui.label("Scale");
+                        ui.add(
+                            egui::DragValue::new(
+                                &mut self.image_settings.scale,
+                            )
+                            .range(0.25..=8.0)
+                            .speed(0.05)
+                            .suffix("×"),
+                        );
+                        ui.end_row();
+                        ui.label("JPEG quality");
+                        ui.add(egui::Slider::new(
+                            &mut self.image_settings.jpeg_quality,
+                            1..=100,
+                        ));
+                        ui.end_row();
+                    },
+                );
+                if self.document.page_count() > 1 {
+                    ui.checkbox(
+                        &mut self.image_settings.all_pages,
+                        "All pages, side by side",
+                    );
+                }
+                ui.weak(
+                    "The canvas background is always included, JPEG has no \
+                     transparency.",
+                );
+                export = ui
+                    .add_enabled(
+                        !self.document.lines.is_empty(),
+                        egui::Button::new("Export…"),
+                    )
+                    .clicked();
+            });
+        self.show_image_window = open;
+        if export
+            && let Some(file_path) = self
+                .file_dialog()
+                .add_filter("PNG image", &["png"])
+                .add_filter("JPEG image", &["jpg", "jpeg"])
+                .set_file_name("Untitled.png")
+                .save_file()
+        {
+            let result = render::export_image(
+                &self.document.export_lines(self.image_settings.all_pages),
+                &self.image_settings,
+                Some(ctx.style().visuals.panel_fill),
+                &file_path,
+            );
+            match result {
+                Ok(()) => self.push_toast(
+                    format!("Exported image to {}", file_path.display()),
+                    ToastLevel::Success,
+                ),
+                Err(e) => {
+                    rfd::MessageDialog::new()
+                        .set_level(rfd::MessageLevel::Error)
+                        .set_title("Failed to export")
+                        .set_description(format!("Failed to export: {e}"))
+                        .set_buttons(rfd::MessageButtons::Ok)
+                        .show();
+                }
+            }
+        }
+    }
+    fn show_points_window(&mut self, ctx: &egui::Context) {
+        if !self.show_points_window {
+            return;
+        }
+        let mut open = true;
+        let mut export = false;
+        egui::Window::new("Export data")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label(
+                    "The points of every stroke on this page, one row each: \
+                     stroke, point, x, y, color and width.",
+                );
+                ui.add_enabled(
+                    !self.selected_lines.is_empty(),
+                    egui::Checkbox::new(
+                        &mut self.point_settings.selection_only,
+                        "Selected strokes only",
+                    ),
+                );
+                ui.checkbox(
+                    &mut self.point_settings.normalize,
+                    "Normalize to the strokes' bounds",
+                )
+                .on_hover_text(
+                    "Coordinates from 0 to 1 across the longer side of the \
+                     strokes' bounding box",
+                );
+                export = ui
+                    .add_enabled(
+                        !self.document.lines.is_empty(),
+                        egui::Button::new("Export…"),
+                    )
+                    .clicked();
+            });
+        self.show_points_window = open;
+        if export
+            && let Some(file_path) = self
+                .file_dialog()
+                .add_filter("CSV file", &["csv"])
+                .add_filter("JSON file", &["json"])
+                .set_file_name("Untitled.csv")
+                .save_file()
+        {
+            let selection_only = self.point_settings.selection_only
+                && !self.selected_lines.is_empty();
+            let strokes: Vec<(usize, &Line)> = self
+                .document
+                .lines
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| {
+                    !selection_only || self.selected_lines.contains(i)
+                })
+                .collect();
+            match points::export(
+                &strokes,
+                self.point_settings.normalize,
+                &file_path,
+            ) {
+                Ok(count) => self.push_toast(
+                    format!(
+                        "Exported {count} points to {}",
+                        file_path.display()
+                    ),
+                    ToastLevel::Success,
+                ),
+                Err(e) => {
+                    rfd::MessageDialog::new()
+                        .set_level(rfd::MessageLevel::Error)
+                        .set_title("Failed to export")
+                        .set_description(format!("Failed to export: {e}"))
+                        .set_buttons(rfd::MessageButtons::Ok)
+                        .show();
+                }
+            }
+        }
+    }
+    fn show_replay_window(&mut self, ctx: &egui::Context) {
+        if let Some(export) = &mut self.replay_export {
+            ctx.request_repaint();
+            if let Some(result) = export.poll() {
+                let path = export.path.clone();
+                self.replay_export = None;
+                match result {
+                    Ok(()) => self.push_toast(
+                        format!("Exported animation to {}", path.display()),
+                        ToastLevel::Success,
+                    ),
+                    Err(e) => {
+                        rfd::MessageDialog::new()
+                            .set_level(rfd::MessageLevel::Error)
+                            .set_title("Failed to export")
+                            .set_description(format!("Failed to export: {e}"))
+                            .set_buttons(rfd::MessageButtons::Ok)
+                            .show();
+                    }
+                }
+            }
+        }
+        if !self.show_replay_window {
+            return;
+        }
+        let mut open = true;
+        let mut export = false;
+        egui::Window::new("Export animation")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label(
+                    "Replays the drawing stroke by stroke as an animated PNG.",
+                );
+                egui::Grid::new("replay_settings").num_columns(2).show(
+                    ui,
+                    |ui| {
+                        ui.label("Frame duration");
+                        ui.add(
+                            egui::DragValue::new(
+                                &mut self.replay_settings.frame_ms,
+                            )
+                            .range(10..=2000)
+                            .suffix(" ms"),
+                        );
+                        ui.end_row();
+                        ui.label("Width");
+                        ui.add(
+                            egui::DragValue::new(
+                                &mut self.replay_settings.width,
+                            )
+                            .range(64..=4096)
+                            .suffix(" px"),
+                        );
+                        ui.end_row();
+                    },
+                );
+                if self.document.page_count() > 1 {
+                    ui.checkbox(
+                        &mut self.replay_settings.all_pages,
+                        "All pages, side by side",
+                    );
+                }
+                ui.weak(format!(
+                    "At most {} frames, long drawings skip steps.",
+                    replay::MAX_FRAMES
+                ));
+                match &self.replay_export {
+                    Some(running) => {
+                        ui.add(
+                            egui::ProgressBar::new(running.progress())
+                                .show_percentage(),
+                        );
+                    }
+                    None => {
+                        export = ui
+                            .add_enabled(
+                                !self.document.lines.is_empty(),
+                                egui::Button::new("Export…"),
+                            )
+                            .clicked();
+                    }
+                }
+            });
+        self.show_replay_window = open;
+        if export
+            && let Some(file_path) = self
+                .file_dialog()
+                .add_filter("Animated PNG", &["png", "apng"])
+                .set_file_name("Untitled.png")
+                .save_file()
+        {
+            self.replay_export = Some(ReplayExport::start(
+                self.document.export_lines(self.replay_settings.all_pages),
+                &self.replay_settings,
+                file_path,
+            ));
+        }
+    }
+    fn show_backups_window(&mut self, ctx: &egui::Context) {
+        let Some(backups) = &self.backups else {
+            return;
+        };
+        let mut open = true;
+        let mut restore = None;
+        egui::Window::new("Restore from backup")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                if backups.is_empty() {
+                    ui.label("There are no backups of this file yet.");
+                }
+                for backup in backups {
+                    ui.horizontal(|ui| {
+                        ui.label(
+                            backup
+                                .path
+                                .file_name()
+                                .unwrap_or_default()
+                                .to_string_lossy(),
+                        );
+                        ui.weak(format_age(backup.modified));
+                        if ui.button("Restore").clicked() {
+                            restore = Some(backup.path.clone());
                         }
-                        self.last_mouse_pos = Some(pointer_pos);
-                    }
-                } else if self.selection_start.is_some() {
-                    self.selection_current = Some(pointer_pos);
-                }
-            } else if response.drag_stopped() {
-                if self.resizing_corner.is_some() {
-                    self.resizing_corner = None;
-                    self.resize_original_bbox = None;
-                    self.resize_original_lines.clear();
-                } else if self.is_moving_selection {
-                    self.is_moving_selection = false;
-                    self.last_mouse_pos = None;
-                } else if let (Some(start), Some(current)) =
-                    (self.selection_start, self.selection_current)
+                    });
+                }
+            });
+        if let Some(path) = restore {
+            open = false;
+            self.restore_backup(ctx, &path);
+        }
+        if !open {
+            self.backups = None;
+        }
+    }
+    // Loads a backup in place of the current content. The board stays
+    // associated with the original file and is marked as modified.
+    fn restore_backup(&mut self, ctx: &egui::Context, path: &Path) {
+        if !self.confirm_discard_changes(ctx) {
+            return;
+        }
+        match read_whiteboard_state(path, self.encryption.as_ref()) {
+            Ok(ReadResult::Loaded(loaded)) => self.restore_state(loaded.state),
+            Ok(ReadResult::Locked(bytes)) => self.request_unlock(
+                path.to_path_buf(),
+                bytes,
+                UnlockPurpose::RestoreBackup,
+            ),
+            Ok(ReadResult::Failed) => {}
+            Err(e) => show_read_error(&e),
+        }
+    }
+    fn restore_state(&mut self, state: WhiteboardState) {
+        self.load_state(state);
+        self.document.dirty = true;
+        self.push_toast(
+            "Backup restored, save to keep it as the current version",
+            ToastLevel::Info,
+        );
+    }
+    // Runs `f` on every open board in turn: the one shown, then the other
+    // tabs and the detached windows, each in the app's fields for the time
+    // being
+    fn for_each_board(&mut self, mut f: impl FnMut(&mut Self)) {
+        f(self);
+        for index in 0..self.tabs.len() {
+            if index == self.active_tab {
+                continue;
+            }
+            let mut tab = mem::take(&mut self.tabs[index]);
+            tab.swap(self);
+            f(self);
+            tab.swap(self);
+            self.tabs[index] = tab;
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let mut windows = mem::take(&mut self.windows);
+            for window in &mut windows {
+                window.swap(self);
+                f(self);
+                window.swap(self);
+            }
+            self.windows = windows;
+        }
+    }
+    // Whether `f` holds for any open board, like `for_each_board`
+    fn any_board(&mut self, mut f: impl FnMut(&Self) -> bool) -> bool {
+        let mut any = false;
+        self.for_each_board(|app| any = any || f(app));
+        any
+    }
+    // Keeps a crash recovery snapshot of the unsaved changes of every open
+    // board, including boards that were never saved to a file
+    fn update_recovery_snapshot(&mut self, ctx: &egui::Context) {
+        let now = ctx.input(|i| i.time);
+        if !self.any_board(Self::needs_recovery_snapshot) {
+            self.last_recovery_snapshot = now;
+            return;
+        }
+        let elapsed = now - self.last_recovery_snapshot;
+        if elapsed < RECOVERY_INTERVAL_SECS {
+            ctx.request_repaint_after(Duration::from_secs_f64(
+                RECOVERY_INTERVAL_SECS - elapsed,
+            ));
+            return;
+        }
+        if self.tools.iter().any(|tool| tool.is_busy()) {
+            return;
+        }
+        self.last_recovery_snapshot = now;
+        self.for_each_board(Self::write_recovery_snapshot);
+    }
+    // the snapshot isn't encrypted, so protected boards don't get one
+    fn needs_recovery_snapshot(&self) -> bool {
+        self.document.dirty && self.encryption.is_none()
+    }
+    fn write_recovery_snapshot(&mut self) {
+        if !self.needs_recovery_snapshot() {
+            return;
+        }
+        if let Err(e) = recovery::write_snapshot(
+            self.recovery_id,
+            self.whiteboard_file.as_deref(),
+            &WhiteboardState::new(self),
+        ) {
+            eprintln!("failed to write recovery snapshot: {e}");
+        }
+    }
+    // Saves every open board with unsaved changes to its file every
+    // `preferences.autosave_interval_secs`, waiting until no stroke or drag
+    // is in progress
+    fn autosave(&mut self, ctx: &egui::Context) {
+        let now = ctx.input(|i| i.time);
+        if !self.preferences.autosave_enabled
+            || !self.any_board(Self::needs_autosave)
+        {
+            self.last_autosave = now;
+            return;
+        }
+        let interval = f64::from(self.preferences.autosave_interval_secs);
+        let elapsed = now - self.last_autosave;
+        if elapsed < interval {
+            ctx.request_repaint_after(Duration::from_secs_f64(
+                interval - elapsed,
+            ));
+            return;
+        }
+        if self.is_busy(ctx) {
+            return;
+        }
+        self.last_autosave = now;
+        self.for_each_board(Self::autosave_board);
+    }
+    fn needs_autosave(&self) -> bool {
+        self.document.dirty && self.whiteboard_file.is_some()
+    }
+    fn autosave_board(&mut self) {
+        let Some(file_path) = self.whiteboard_file.clone() else {
+            return;
+        };
+        // a detached window's stroke in progress, or a board that is saved
+        // already
+        if !self.document.dirty || self.tools.iter().any(|tool| tool.is_busy())
+        {
+            return;
+        }
+        // never clobber a newer version, the periodic check asks what to do
+        if self.changed_on_disk() {
+            return;
+        }
+        let whiteboard_state = self.state_for_saving();
+        match self
+            .encode(&whiteboard_state, self.file_encoding)
+            .and_then(|bytes| atomic::write(&file_path, &bytes))
+        {
+            Ok(()) => {
+                self.document.dirty = false;
+                self.file_modified = modified(&file_path);
+                recovery::remove_snapshot(self.recovery_id);
+            }
+            Err(e) => {
+                eprintln!("autosave to {} failed: {e}", file_path.display());
+                self.push_toast(
+                    format!("Autosave to {} failed: {e}", file_path.display()),
+                    ToastLevel::Warning,
+                );
+            }
+        }
+    }
+    // Whether a stroke or drag is in progress
+    fn is_busy(&self, ctx: &egui::Context) -> bool {
+        self.tools.iter().any(|tool| tool.is_busy())
+            || ctx.input(|i| i.pointer.any_down())
+    }
+    // Whether another program changed the open file since we last opened or
+    // saved it. A file that is missing for the moment (e.g. while a sync
+    // client replaces it) doesn't count.
+    fn changed_on_disk(&self) -> bool {
+        match (self.whiteboard_file.as_deref(), self.file_modified) {
+            (Some(path), Some(known)) => {
+                modified(path).is_some_and(|current| current != known)
+            }
+            _ => false,
+        }
+    }
+    fn check_external_modification(&mut self, ctx: &egui::Context) {
+        if self.whiteboard_file.is_none() {
+            return;
+        }
+        let now = ctx.input(|i| i.time);
+        let elapsed = now - self.last_file_check;
+        if elapsed < FILE_CHECK_INTERVAL_SECS {
+            ctx.request_repaint_after(Duration::from_secs_f64(
+                FILE_CHECK_INTERVAL_SECS - elapsed,
+            ));
+            return;
+        }
+        self.last_file_check = now;
+        ctx.request_repaint_after(Duration::from_secs_f64(
+            FILE_CHECK_INTERVAL_SECS,
+        ));
+        if !self.is_busy(ctx) && self.changed_on_disk() {
+            self.resolve_external_modification(ctx);
+        }
+    }
+    // Asks whether to reload the file changed by another program, overwrite
+    // it, or save our version elsewhere. Returns true if the caller should
+    // go ahead and overwrite it.
+    fn resolve_external_modification(&mut self, ctx: &egui::Context) -> bool {
+        let Some(file_path) = self.whiteboard_file.clone() else {
+            return true;
+        };
+        let choice = rfd::MessageDialog::new()
+            .set_level(rfd::MessageLevel::Warning)
+            .set_title("File changed on disk")
+            .set_description(format!(
+                "{} was changed by another program. Reload it, overwrite it \
+                 with this version, or save this version as a new file?",
+                file_path.display()
+            ))
+            .set_buttons(rfd::MessageButtons::YesNoCancelCustom(
+                "Reload".to_owned(),
+                "Overwrite".to_owned(),
+                "Save As…".to_owned(),
+            ))
+            .show();
+        let choice = match choice {
+            rfd::MessageDialogResult::Custom(choice) => choice,
+            _ => String::new(),
+        };
+        match choice.as_str() {
+            "Reload" => {
+                self.reload(ctx, file_path);
+                false
+            }
+            "Overwrite" => true,
+            "Save As…" => {
+                self.save_whiteboard_as();
+                false
+            }
+            _ => {
+                // don't ask again about this version
+                self.file_modified = modified(&file_path);
+                false
+            }
+        }
+    }
+    fn reload(&mut self, ctx: &egui::Context, file_path: PathBuf) {
+        if self.document.dirty {
+            let choice = rfd::MessageDialog::new()
+                .set_level(rfd::MessageLevel::Warning)
+                .set_title("Reload")
+                .set_description("Reloading discards your unsaved changes.")
+                .set_buttons(rfd::MessageButtons::OkCancelCustom(
+                    "Discard and reload".to_owned(),
+                    "Cancel".to_owned(),
+                ))
+                .show();
+            if !matches!(
+                choice,
+                rfd::MessageDialogResult::Custom(choice)
+                    if choice == "Discard and reload"
+            ) {
+                self.file_modified = modified(&file_path);
+                return;
+            }
+        }
+        if let Err(e) = self.load_whiteboard_file(file_path.clone()) {
+            show_read_error(&e);
+        }
+        // a file that failed to load shouldn't be reported again and again
+        if self.changed_on_disk() {
+            self.file_modified = modified(&file_path);
+        }
+        self.set_window_title(ctx);
+    }
+    // Records the save time in the metadata if the document changed.
+    // Saving an unchanged document again keeps the old time, so stable
+    // saves write the same bytes.
+    fn touch_modified(&mut self) {
+        if self.document.dirty || self.meta.modified.is_none() {
+            self.meta.touch(now());
+        }
+    }
+    // The document as it's written to its file, see `touch_modified`
+    fn state_for_saving(&mut self) -> WhiteboardState {
+        self.touch_modified();
+        WhiteboardState::new(self)
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    fn save_whiteboard(&mut self, ctx: &egui::Context) {
+        if self.changed_on_disk() && !self.resolve_external_modification(ctx) {
+            return;
+        }
+        if let Some(file_path) = self.whiteboard_file.clone() {
+            let whiteboard_state = self.state_for_saving();
+            self.write_whiteboard(file_path, &whiteboard_state);
+        } else {
+            self.save_whiteboard_as();
+        }
+    }
+    // Where file dialogs start: the folder of the last file opened or saved,
+    // or Downloads before there is one
+    fn dialog_directory(&self) -> PathBuf {
+        self.last_directory.clone().unwrap_or_else(|| {
+            UserDirs::new()
+                .and_then(|user_dirs| {
+                    user_dirs.download_dir().map(Path::to_path_buf)
+                })
+                .unwrap_or(std::env::current_dir().unwrap_or_default())
+        })
+    }
+    fn file_dialog(&self) -> FileDialog {
+        FileDialog::new().set_directory(self.dialog_directory())
+    }
+    fn remember_directory(&mut self, file_path: &Path) {
+        if let Some(dir) = file_path.parent()
+            && !dir.as_os_str().is_empty()
+        {
+            self.last_directory = Some(dir.to_path_buf());
+        }
+    }
+    // Always asks for a destination, starting from the current file if any
+    #[cfg(not(target_arch = "wasm32"))]
+    fn save_whiteboard_as(&mut self) {
+        let (directory, file_name) = match &self.whiteboard_file {
+            Some(file_path) => (
+                file_path.parent().map(Path::to_path_buf),
+                file_path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned()),
+            ),
+            None => (None, None),
+        };
+        let default_path = directory.unwrap_or_else(|| self.dialog_directory());
+        let files = FileDialog::new()
+            .add_filter("Whiteboard file", &["wb"])
+            .add_filter(
+                "Compressed whiteboard file",
+                &[format::COMPRESSED_EXTENSION],
+            )
+            .add_filter("Binary whiteboard file", &[format::BINARY_EXTENSION])
+            .add_filter("All files", &["*"])
+            .set_directory(default_path)
+            .set_file_name(file_name.as_deref().unwrap_or("Untitled.wb"))
+            .save_file();
+        if let Some(file_path) = files {
+            let whiteboard_state = self.state_for_saving();
+            self.write_whiteboard(file_path, &whiteboard_state);
+        }
+    }
+    // The browser keeps a single board in its storage
+    #[cfg(target_arch = "wasm32")]
+    fn save_whiteboard(&mut self, _ctx: &egui::Context) {
+        let whiteboard_state = self.state_for_saving();
+        if let Err(e) = web::store_board(&whiteboard_state) {
+            rfd::MessageDialog::new()
+                .set_level(rfd::MessageLevel::Error)
+                .set_title("Failed to save whiteboard")
+                .set_description(format!("Failed to save whiteboard: {e}",))
+                .set_buttons(rfd::MessageButtons::Ok)
+                .show();
+            return;
+        }
+        self.push_toast("Saved in this browser", ToastLevel::Success);
+        self.document.dirty = false;
+    }
+    // Downloads the board as a file
+    #[cfg(target_arch = "wasm32")]
+    fn save_whiteboard_as(&mut self) {
+        self.touch_modified();
+        match self.to_bytes() {
+            Ok(bytes) => web::download("Untitled.wb".to_owned(), bytes),
+            Err(e) => {
+                rfd::MessageDialog::new()
+                    .set_level(rfd::MessageLevel::Error)
+                    .set_title("Failed to save whiteboard")
+                    .set_description(format!("Failed to save whiteboard: {e}"))
+                    .set_buttons(rfd::MessageButtons::Ok)
+                    .show();
+            }
+        }
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    fn open_whiteboard_file(&mut self, ctx: &egui::Context) -> io::Result<()> {
+        let files = self
+            .file_dialog()
+            .add_filter("Whiteboard file", &format::EXTENSIONS)
+            .set_title("Select whiteboard file")
+            .pick_file();
+        if let Some(file_path) = files {
+            self.open_in_tab(ctx, file_path)?;
+        }
+        Ok(())
+    }
+    // Encrypted files are opened once their passphrase is entered
+    fn load_whiteboard_file(&mut self, file_path: PathBuf) -> io::Result<()> {
+        match read_whiteboard_state(&file_path, self.encryption.as_ref())? {
+            ReadResult::Loaded(loaded) => self.open_loaded(file_path, *loaded),
+            ReadResult::Locked(bytes) => {
+                self.request_unlock(file_path, bytes, UnlockPurpose::Open);
+            }
+            ReadResult::Failed => {}
+        }
+        Ok(())
+    }
+    fn open_loaded(&mut self, file_path: PathBuf, loaded: LoadedWhiteboard) {
+        self.recent_files
+            .add(&file_path, loaded.state.thumbnail_png());
+        self.remember_directory(&file_path);
+        self.load_state(loaded.state);
+        self.whiteboard_file = Some(file_path);
+        self.file_encoding = loaded.encoding;
+        self.encryption = loaded.key;
+        self.file_modified = self.whiteboard_file.as_deref().and_then(modified);
+        self.document.dirty = loaded.partial;
+        recovery::remove_snapshot(self.recovery_id);
+    }
+    fn request_unlock(
+        &mut self,
+        file_path: PathBuf,
+        bytes: Vec<u8>,
+        purpose: UnlockPurpose,
+    ) {
+        self.unlock = Some(Unlock {
+            file_path,
+            bytes,
+            purpose,
+            passphrase: String::new(),
+            error: None,
+        });
+    }
+    // Asks for the passphrase of an encrypted file. Nothing changes until
+    // the right one is entered, cancelling leaves the board as it was.
+    fn show_unlock_modal(&mut self, ctx: &egui::Context) {
+        let Some(unlock) = &mut self.unlock else {
+            return;
+        };
+        let mut submitted = false;
+        let mut cancelled = false;
+        let response =
+            egui::Modal::new(egui::Id::new("unlock")).show(ctx, |ui| {
+                ui.set_width(320.0);
+                ui.heading("Encrypted whiteboard");
+                ui.label(format!(
+                    "{} is protected with a passphrase.",
+                    unlock.file_path.display()
+                ));
+                let edit = ui.add(
+                    egui::TextEdit::singleline(&mut unlock.passphrase)
+                        .password(true)
+                        .hint_text("Passphrase")
+                        .desired_width(f32::INFINITY),
+                );
+                edit.request_focus();
+                if edit.lost_focus()
+                    && ui.input(|i| i.key_pressed(egui::Key::Enter))
                 {
-                    let rect = Rect::from_two_pos(start, current);
-                    self.selected_lines.clear();
-                    for (i, line) in self.lines.iter().enumerate() {
-                        // Check if line is inside rect
-                        // Simple check: if bounding box intersects
-                        let mut line_bbox = Rect::NOTHING;
-                        for p in &line.points {
-                            line_bbox.extend_with(*p);
-                        }
-                        if rect.intersects(line_bbox) {
-                            // More precise check: at least one point inside?
-                            // Or just keep intersection. Intersection is usually good enough for "Select Area".
-                            self.selected_lines.insert(i);
+                    submitted = true;
+                }
+                if let Some(error) = unlock.error {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+                ui.horizontal(|ui| {
+                    submitted |= ui
+                        .add_enabled(
+                            !unlock.passphrase.is_empty(),
+                            egui::Button::new("Unlock"),
+                        )
+                        .clicked();
+                    cancelled |= ui.button("Cancel").clicked();
+                });
+            });
+        if cancelled || response.should_close() {
+            self.unlock = None;
+            return;
+        }
+        if !submitted || unlock.passphrase.is_empty() {
+            return;
+        }
+        match crypto::decrypt(&unlock.bytes, &unlock.passphrase) {
+            Err(DecryptError::WrongPassphrase) => {
+                unlock.passphrase.clear();
+                unlock.error = Some("Wrong passphrase, try again.");
+            }
+            Err(e) => {
+                let file_path = mem::take(&mut unlock.file_path);
+                self.unlock = None;
+                show_decrypt_error(&file_path, &e);
+            }
+            Ok((plaintext, key)) => {
+                let Some(Unlock {
+                    file_path, purpose, ..
+                }) = self.unlock.take()
+                else {
+                    return;
+                };
+                let ReadResult::Loaded(loaded) =
+                    decode_whiteboard(&file_path, &plaintext, Some(key))
+                else {
+                    return;
+                };
+                match purpose {
+                    UnlockPurpose::Open => {
+                        self.open_loaded(file_path, *loaded);
+                        self.set_window_title(ctx);
+                    }
+                    UnlockPurpose::Import => self.import_state(&loaded.state),
+                    UnlockPurpose::RestoreBackup => {
+                        self.restore_state(loaded.state);
+                    }
+                }
+            }
+        }
+    }
+    // Sets the passphrase the document is encrypted with from its next save
+    fn show_passphrase_prompt(&mut self, ctx: &egui::Context) {
+        let encrypted = self.encryption.is_some();
+        let Some(prompt) = &mut self.passphrase_prompt else {
+            return;
+        };
+        let mut submitted = false;
+        let mut cancelled = false;
+        let response =
+            egui::Modal::new(egui::Id::new("passphrase")).show(ctx, |ui| {
+                ui.set_width(320.0);
+                ui.heading(if encrypted {
+                    "Change passphrase"
+                } else {
+                    "Encrypt with passphrase"
+                });
+                ui.label(
+                    "The file is encrypted when it's saved. A forgotten \
+                     passphrase can't be recovered, and backups made before \
+                     stay unencrypted.",
+                );
+                egui::Grid::new("passphrase_grid").num_columns(2).show(
+                    ui,
+                    |ui| {
+                        ui.label("Passphrase");
+                        ui.add(
+                            egui::TextEdit::singleline(&mut prompt.passphrase)
+                                .password(true),
+                        );
+                        ui.end_row();
+                        ui.label("Confirm");
+                        let edit = ui.add(
+                            egui::TextEdit::singleline(
+                                &mut prompt.confirmation,
+                            )
+                            .password(true),
+                        );
+                        if edit.lost_focus()
+                            && ui.input(|i| i.key_pressed(egui::Key::Enter))
+                        {
+                            submitted = true;
                         }
+                        ui.end_row();
+                    },
+                );
+                if let Some(error) = prompt.error {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+                ui.horizontal(|ui| {
+                    submitted |= ui.button("OK").clicked();
+                    cancelled |= ui.button("Cancel").clicked();
+                });
+            });
+        if cancelled || response.should_close() {
+            self.passphrase_prompt = None;
+            return;
+        }
+        if !submitted {
+            return;
+        }
+        if prompt.passphrase.is_empty() {
+            prompt.error = Some("Enter a passphrase.");
+            return;
+        }
+        if prompt.passphrase != prompt.confirmation {
+            prompt.error = Some("The passphrases don't match.");
+            prompt.confirmation.clear();
+            return;
+        }
+        match FileKey::derive(&prompt.passphrase) {
+            Ok(key) => {
+                self.passphrase_prompt = None;
+                self.encryption = Some(key);
+                self.document.dirty = true;
+                // a snapshot from before would keep the content readable
+                recovery::remove_snapshot(self.recovery_id);
+                self.push_toast(
+                    "Save to write the file encrypted",
+                    ToastLevel::Info,
+                );
+            }
+            Err(e) => {
+                self.passphrase_prompt = None;
+                rfd::MessageDialog::new()
+                    .set_level(rfd::MessageLevel::Error)
+                    .set_title("Encryption failed")
+                    .set_description(format!(
+                        "The key could not be derived: {e}"
+                    ))
+                    .set_buttons(rfd::MessageButtons::Ok)
+                    .show();
+            }
+        }
+    }
+    fn remove_encryption(&mut self) {
+        self.encryption = None;
+        self.document.dirty = true;
+        self.push_toast(
+            "Save to write the file without encryption",
+            ToastLevel::Info,
+        );
+    }
+    // Starts or stops taking files from later launches, following the
+    // preference
+    #[cfg(not(target_arch = "wasm32"))]
+    fn set_single_instance(&mut self, ctx: &egui::Context) {
+        if !self.preferences.single_instance {
+            self.instance = None;
+        } else if self.instance.is_none() {
+            match instance::Listener::start(ctx) {
+                Ok(listener) => self.instance = Some(listener),
+                Err(e) => eprintln!("failed to listen for files to open: {e}"),
+            }
+        }
+    }
+    // Opens the files later launches handed over, in front of other windows
+    #[cfg(not(target_arch = "wasm32"))]
+    fn open_handed_over_files(&mut self, ctx: &egui::Context) {
+        let Some(instance) = &self.instance else {
+            return;
+        };
+        let paths: Vec<PathBuf> = instance.paths().collect();
+        for file_path in paths {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Minimized(false));
+            ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+            self.open_recent(ctx, file_path);
+        }
+    }
+    // Opens a whiteboard file dropped onto the window
+    fn handle_dropped_files(&mut self, ctx: &egui::Context) {
+        let dropped = ctx.input(|i| i.raw.dropped_files.clone());
+        let [file] = dropped.as_slice() else {
+            if dropped.len() > 1 {
+                self.push_toast(
+                    "Drop a single whiteboard file to open",
+                    ToastLevel::Warning,
+                );
+            }
+            return;
+        };
+        let Some(file_path) = file.path.clone() else {
+            return;
+        };
+        let is_whiteboard = file_path
+            .extension()
+            .is_some_and(|ext| format::EXTENSIONS.iter().any(|e| ext == *e));
+        if !is_whiteboard {
+            self.push_toast(
+                format!(
+                    "Can't open {}: not a whiteboard file",
+                    file_path.display()
+                ),
+                ToastLevel::Warning,
+            );
+            return;
+        }
+        if let Err(e) = self.open_in_tab(ctx, file_path) {
+            show_read_error(&e);
+        }
+        self.set_window_title(ctx);
+    }
+    // Replaces the board content with a loaded document
+    fn load_state(&mut self, state: WhiteboardState) {
+        self.document = Document::from_state(&state);
+        self.meta = state.meta;
+        if let Some(width) = state.stroke_width
+            && width.is_finite()
+        {
+            self.tool_settings.entry(Tool::BRUSH).or_default().width =
+                width.clamp(MIN_STROKE_WIDTH, MAX_STROKE_WIDTH);
+        }
+        // a tool the file was saved with but this board doesn't have would
+        // leave the canvas without one
+        if let Some(tool) = state.current_tool
+            && self.tools.iter().any(|t| t.kind() == tool)
+        {
+            self.current_tool = tool;
+        }
+        self.cancel_tools();
+        self.clear_selection_state();
+    }
+    // Appends the lines of another whiteboard file to the current one
+    fn import_whiteboard_file(&mut self) -> io::Result<()> {
+        let files = self
+            .file_dialog()
+            .add_filter("Whiteboard file", &format::EXTENSIONS)
+            .set_title("Import whiteboard file")
+            .pick_file();
+        let Some(file_path) = files else {
+            return Ok(());
+        };
+        match read_whiteboard_state(&file_path, self.encryption.as_ref())? {
+            ReadResult::Loaded(loaded) => self.import_state(&loaded.state),
+            ReadResult::Locked(bytes) => {
+                self.request_unlock(file_path, bytes, UnlockPurpose::Import);
+            }
+            ReadResult::Failed => {}
+        }
+        Ok(())
+    }
+    fn import_state(&mut self, state: &WhiteboardState) {
+        for &color in &state.palette {
+            self.document.palette.add_color(color.into());
+        }
+        // every page of the other file lands on the current page
+        self.append_imported_lines(
+            state
+                .pages
+                .iter()
+                .flat_map(|page| &page.lines)
+                .map(Into::into)
+                .collect(),
+        );
+    }
+    fn import_excalidraw_file(&mut self) -> io::Result<()> {
+        let files = self
+            .file_dialog()
+            .add_filter("Excalidraw file", &["excalidraw", "json"])
+            .set_title("Import Excalidraw file")
+            .pick_file();
+        let Some(file_path) = files else {
+            return Ok(());
+        };
+        let json = std::fs::read_to_string(&file_path)?;
+        let scene = match excalidraw::import(&json) {
+            Ok(scene) => scene,
+            Err(e) => {
+                rfd::MessageDialog::new()
+                    .set_level(rfd::MessageLevel::Error)
+                    .set_title("Invalid Excalidraw file")
+                    .set_description(format!(
+                        "{} is not a valid Excalidraw file:\n\n{e}",
+                        file_path.to_string_lossy()
+                    ))
+                    .set_buttons(rfd::MessageButtons::Ok)
+                    .show();
+                return Ok(());
+            }
+        };
+
+        let mut summary = format!("Imported {} elements", scene.imported);
+        let skipped: usize = scene.skipped.values().sum();
+        if skipped > 0 {
+            let kinds = scene
+                .skipped
+                .iter()
+                .map(|(kind, count)| format!("{kind}: {count}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            summary +=
+                &format!("\nSkipped {skipped} unsupported elements ({kinds})");
+        }
+        self.append_imported_lines(scene.lines);
+        let level = if skipped > 0 {
+            ToastLevel::Warning
+        } else {
+            ToastLevel::Success
+        };
+        self.push_toast(summary, level);
+        Ok(())
+    }
+    fn export_excalidraw_file(&mut self, all_pages: bool) {
+        let Some(file_path) = self
+            .file_dialog()
+            .add_filter("Excalidraw file", &["excalidraw"])
+            .set_file_name("Untitled.excalidraw")
+            .save_file()
+        else {
+            return;
+        };
+        let result = excalidraw::export(&self.document.export_lines(all_pages))
+            .map_err(io::Error::other)
+            .and_then(|json| std::fs::write(&file_path, json));
+        match result {
+            Ok(()) => self.push_toast(
+                format!("Exported to {}", file_path.display()),
+                ToastLevel::Success,
+            ),
+            Err(e) => {
+                rfd::MessageDialog::new()
+                    .set_level(rfd::MessageLevel::Error)
+                    .set_title("Failed to export")
+                    .set_description(format!("Failed to export: {e}"))
+                    .set_buttons(rfd::MessageButtons::Ok)
+                    .show();
+            }
+        }
+    }
+    fn apply_palette_preset(&mut self, preset: &PalettePreset) -> bool {
+        let Some(palette) = preset.to_palette() else {
+            return false;
+        };
+        self.document.palette = palette;
+        self.document.dirty = true;
+        true
+    }
+    // Saves the current palette under `name`, replacing a preset of the same
+    // name
+    fn save_palette_preset(&mut self, name: &str) {
+        let preset = PalettePreset::new(name, &self.document.palette);
+        match self.palette_presets.iter_mut().find(|p| p.name == name) {
+            Some(existing) => *existing = preset,
+            None => self.palette_presets.push(preset),
+        }
+        if let Err(e) = presets::store(&self.palette_presets) {
+            rfd::MessageDialog::new()
+                .set_level(rfd::MessageLevel::Error)
+                .set_title("Failed to save palette")
+                .set_description(format!("Failed to save palette: {e}"))
+                .set_buttons(rfd::MessageButtons::Ok)
+                .show();
+        }
+    }
+    fn draw_palette_presets(&mut self, ui: &mut Ui) {
+        let mut chosen = None;
+        let mut deleted = None;
+        egui::ComboBox::from_id_salt("palette_presets")
+            .selected_text("Load palette…")
+            .show_ui(ui, |ui| {
+                for preset in presets::builtin() {
+                    if ui.selectable_label(false, &preset.name).clicked() {
+                        chosen = Some(preset);
                     }
-                    self.selection_start = None;
-                    self.selection_current = None;
                 }
-            } else if response.clicked() {
-                // Click outside selection to clear
-                if !expanded_bbox.contains(pointer_pos) {
-                    self.selected_lines.clear();
+                if !self.palette_presets.is_empty() {
+                    ui.separator();
                 }
+                for (i, preset) in self.palette_presets.iter().enumerate() {
+                    let response = ui.selectable_label(false, &preset.name);
+                    if response.clicked() {
+                        chosen = Some(preset.clone());
+                    }
+                    response.context_menu(|ui| {
+                        if ui.button("Delete").clicked() {
+                            deleted = Some(i);
+                        }
+                    });
+                }
+            });
+        if let Some(preset) = chosen {
+            self.apply_palette_preset(&preset);
+        }
+        if let Some(i) = deleted {
+            self.palette_presets.remove(i);
+            if let Err(e) = presets::store(&self.palette_presets) {
+                eprintln!("failed to save palette presets: {e}");
+            }
+        }
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.preset_name)
+                    .hint_text("Preset name")
+                    .desired_width(110.0),
+            );
+            let name = self.preset_name.trim().to_owned();
+            if ui
+                .add_enabled(!name.is_empty(), egui::Button::new("Save"))
+                .clicked()
+            {
+                self.save_palette_preset(&name);
+                self.preset_name.clear();
+            }
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Import…").clicked() {
+                self.import_palette_file();
+            }
+            if ui.button("Export…").clicked() {
+                self.export_palette_file();
+            }
+        });
+    }
+    // Loads a palette file and keeps it as a preset
+    fn import_palette_file(&mut self) {
+        let Some(file_path) = self
+            .file_dialog()
+            .add_filter("Palette file", &["json"])
+            .set_title("Import palette")
+            .pick_file()
+        else {
+            return;
+        };
+        let result = presets::import(&file_path).and_then(|mut preset| {
+            if preset.name.trim().is_empty() {
+                preset.name =
+                    file_path.file_stem().map_or("Imported".to_owned(), |s| {
+                        s.to_string_lossy().into_owned()
+                    });
+            }
+            if self.apply_palette_preset(&preset) {
+                Ok(preset)
+            } else {
+                Err(io::Error::other("the palette has no valid colors"))
+            }
+        });
+        match result {
+            Ok(preset) => self.save_palette_preset(&preset.name),
+            Err(e) => {
+                rfd::MessageDialog::new()
+                    .set_level(rfd::MessageLevel::Error)
+                    .set_title("Failed to import palette")
+                    .set_description(format!(
+                        "Failed to import {}: {e}",
+                        file_path.display()
+                    ))
+                    .set_buttons(rfd::MessageButtons::Ok)
+                    .show();
             }
         }
     }
-
-    fn get_selection_info(&self) -> Option<(Rect, Rect, [Pos2; 4])> {
-        if self.selected_lines.is_empty() {
-            return None;
+    fn export_palette_file(&mut self) {
+        let Some(file_path) = self
+            .file_dialog()
+            .add_filter("Palette file", &["json"])
+            .set_file_name("palette.json")
+            .save_file()
+        else {
+            return;
+        };
+        let name = file_path
+            .file_stem()
+            .map_or("Palette".to_owned(), |s| s.to_string_lossy().into_owned());
+        let preset = PalettePreset::new(&name, &self.document.palette);
+        match presets::export(&file_path, &preset) {
+            Ok(()) => self.push_toast(
+                format!("Exported palette to {}", file_path.display()),
+                ToastLevel::Success,
+            ),
+            Err(e) => {
+                rfd::MessageDialog::new()
+                    .set_level(rfd::MessageLevel::Error)
+                    .set_title("Failed to export")
+                    .set_description(format!("Failed to export: {e}"))
+                    .set_buttons(rfd::MessageButtons::Ok)
+                    .show();
+            }
+        }
+    }
+    // Adds imported lines as a single undoable action and selects them
+    fn append_imported_lines(&mut self, mut imported: Vec<Line>) {
+        if imported.is_empty() {
+            return;
         }
 
-        let mut bounding_box = Rect::NOTHING;
-        for &i in &self.selected_lines {
-            if let Some(line) = self.lines.get(i) {
-                for p in &line.points {
-                    bounding_box.extend_with(*p);
-                }
+        // place the imported content to the right of the existing content,
+        // or on screen if the board is empty
+        let existing_bbox = lines_bounding_box(&self.document.lines);
+        let imported_bbox = lines_bounding_box(&imported);
+        let offset = if existing_bbox.is_positive() {
+            vec2(
+                existing_bbox.max.x + 20.0 - imported_bbox.min.x,
+                existing_bbox.min.y - imported_bbox.min.y,
+            )
+        } else if !self.visible_rect().intersects(imported_bbox) {
+            self.visible_rect().min + vec2(20.0, 20.0) - imported_bbox.min
+        } else {
+            Vec2::ZERO
+        };
+        for line in &mut imported {
+            for p in &mut line.points {
+                *p += offset;
             }
         }
 
-        if bounding_box == Rect::NOTHING {
-            return None;
+        let added = self.document.append(imported);
+        self.clear_selection_state();
+        self.selected_lines = added.collect();
+        self.current_tool = Tool::SELECTION;
+    }
+    // Puts the selected lines on the clipboard as an SVG document, which
+    // vector editors paste as editable paths
+    fn copy_selection_as_svg(&mut self, ctx: &egui::Context) {
+        if self.current_tool != Tool::SELECTION {
+            return;
+        }
+        let mut indices: Vec<usize> =
+            self.selected_lines.iter().copied().collect();
+        // in drawing order, so overlapping lines stack the same way
+        indices.sort_unstable();
+        let selected: Vec<Line> = indices
+            .into_iter()
+            .filter_map(|i| self.document.lines.get(i).cloned())
+            .collect();
+        if selected.is_empty() {
+            return;
+        }
+        match svg::to_string(&selected, &SvgSettings::default()) {
+            Ok(markup) => {
+                ctx.copy_text(markup);
+                self.push_toast("Copied as SVG", ToastLevel::Success);
+            }
+            Err(e) => {
+                rfd::MessageDialog::new()
+                    .set_level(rfd::MessageLevel::Error)
+                    .set_title("Failed to copy")
+                    .set_description(format!("Failed to copy: {e}"))
+                    .set_buttons(rfd::MessageButtons::Ok)
+                    .show();
+            }
         }
-
-        let expanded_bbox = bounding_box.expand(5.0);
-        let corners = [
-            expanded_bbox.left_top(),
-            expanded_bbox.right_top(),
-            expanded_bbox.left_bottom(),
-            expanded_bbox.right_bottom(),
-        ];
-
-        Some((bounding_box, expanded_bbox, corners))
     }
-
-    fn update_cursor(&self, ctx: &egui::Context, response: &Response) {
-        if self.current_tool != Tool::Selection {
+    // Puts the image on the clipboard in the middle of the view, selected so
+    // it can be moved right away. Text on the clipboard is left alone.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn paste_image(&mut self) {
+        let picture = match picture::from_clipboard() {
+            Ok(Some(picture)) => picture,
+            Ok(None) => return,
+            Err(e) => {
+                rfd::MessageDialog::new()
+                    .set_level(rfd::MessageLevel::Error)
+                    .set_title("Failed to paste")
+                    .set_description(format!("Failed to paste: {e}"))
+                    .set_buttons(rfd::MessageButtons::Ok)
+                    .show();
+                return;
+            }
+        };
+        // as large as it was on screen, unless that doesn't fit the view
+        let view = self.visible_rect();
+        let size = picture.size() / self.camera.zoom;
+        let scale = (view.width() * 0.8 / size.x)
+            .min(view.height() * 0.8 / size.y)
+            .min(1.0);
+        let rect = Rect::from_center_size(view.center(), size * scale);
+        if !rect.is_finite() {
             return;
         }
-
-        // Handle cursor during interaction
-        if let Some(corner) = self.resizing_corner {
-            match corner {
-                ResizeCorner::TopLeft | ResizeCorner::BottomRight => {
-                    ctx.set_cursor_icon(egui::CursorIcon::ResizeNwSe);
-                }
-                ResizeCorner::TopRight | ResizeCorner::BottomLeft => {
-                    ctx.set_cursor_icon(egui::CursorIcon::ResizeNeSw);
-                }
+        let line = Line {
+            points: vec![rect.min, rect.max],
+            color: Color32::TRANSPARENT,
+            width: MIN_STROKE_WIDTH,
+            widths: Vec::new(),
+            style: LineStyle::Solid,
+            created_ms: now_ms(),
+            fill: None,
+            fill_only: false,
+            picture: Some(Arc::new(picture)),
+            locked: false,
+            id: new_line_id(),
+        };
+        let added = self.document.append(vec![line]);
+        self.clear_selection_state();
+        self.selected_lines = added.collect();
+        self.current_tool = Tool::SELECTION;
+    }
+    // Renders the page onto the canvas background and puts it on the
+    // clipboard: the annotated screenshot while annotating, or everything
+    // on the page
+    #[cfg(not(target_arch = "wasm32"))]
+    fn copy_image(&mut self, ctx: &egui::Context) {
+        let region = match &self.annotation {
+            Some(annotation) => annotation.rect,
+            None => {
+                lines_bounding_box(&self.document.lines).expand(render::MARGIN)
             }
+        };
+        let result = render::render(
+            &self.document.lines,
+            region,
+            1.0,
+            ctx.style().visuals.panel_fill,
+        )
+        .ok_or_else(|| "the image would be empty or too large".to_owned())
+        .and_then(|pixmap| {
+            picture::to_clipboard(
+                pixmap.width() as usize,
+                pixmap.height() as usize,
+                render::to_rgba(&pixmap),
+            )
+        });
+        match result {
+            Ok(()) => self.push_toast("Copied the image", ToastLevel::Success),
+            Err(e) => {
+                rfd::MessageDialog::new()
+                    .set_level(rfd::MessageLevel::Error)
+                    .set_title("Failed to copy")
+                    .set_description(format!("Failed to copy: {e}"))
+                    .set_buttons(rfd::MessageButtons::Ok)
+                    .show();
+            }
+        }
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    fn open_screenshot_window(&mut self) {
+        match screenshot::displays() {
+            Ok(displays) => self.screenshot_displays = Some(displays),
+            Err(e) => show_capture_error(&e),
+        }
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    fn show_screenshot_window(&mut self, ctx: &egui::Context) {
+        let Some(displays) = &self.screenshot_displays else {
             return;
+        };
+        let mut open = true;
+        let mut capture = false;
+        let settings = &mut self.screenshot_settings;
+        egui::Window::new("Annotate screenshot")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label(
+                    "Captures the screen to draw over it. The window gets out \
+                     of the way while capturing.",
+                );
+                if displays.len() > 1 {
+                    let selected = displays
+                        .iter()
+                        .find(|display| Some(display.id) == settings.display)
+                        .or_else(|| displays.iter().find(|d| d.primary))
+                        .map_or_else(String::new, screenshot::Display::label);
+                    egui::ComboBox::from_label("Display")
+                        .selected_text(selected)
+                        .show_ui(ui, |ui| {
+                            for display in displays {
+                                ui.selectable_value(
+                                    &mut settings.display,
+                                    Some(display.id),
+                                    display.label(),
+                                );
+                            }
+                        });
+                }
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut settings.region, false, "Full screen");
+                    ui.radio_value(&mut settings.region, true, "Region");
+                });
+                capture = ui.button("Capture").clicked();
+            });
+        if capture {
+            self.start_capture(ctx);
+        } else if !open {
+            self.screenshot_displays = None;
         }
-
-        if self.is_moving_selection {
-            ctx.set_cursor_icon(egui::CursorIcon::PointingHand);
+    }
+    // Captures a display with `screenshot_settings` once the window is
+    // minimized
+    #[cfg(not(target_arch = "wasm32"))]
+    fn start_capture(&mut self, ctx: &egui::Context) {
+        self.screenshot_displays = None;
+        ctx.send_viewport_cmd(egui::ViewportCommand::Minimized(true));
+        self.capture = Some(Capture::start(ctx, &self.screenshot_settings));
+    }
+    // Captures the main display to draw over, e.g. for `--annotate` on the
+    // command line. With `region` a part of it is picked first.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn annotate_screenshot(&mut self, ctx: &egui::Context, region: bool) {
+        self.screenshot_settings = ScreenshotSettings {
+            display: None,
+            region,
+        };
+        self.start_capture(ctx);
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    fn poll_capture(&mut self, ctx: &egui::Context) {
+        let Some(capture) = &self.capture else {
             return;
+        };
+        let Some(result) = capture.poll() else {
+            return;
+        };
+        let region = capture.region;
+        self.capture = None;
+        ctx.send_viewport_cmd(egui::ViewportCommand::Minimized(false));
+        ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+        let picture = match result {
+            Ok(picture) => picture,
+            Err(e) => {
+                show_capture_error(&e);
+                return;
+            }
+        };
+        // borderless over everything else, so the board lines up with the
+        // screen it shows
+        ctx.send_viewport_cmd(egui::ViewportCommand::Decorations(false));
+        ctx.send_viewport_cmd(egui::ViewportCommand::WindowLevel(
+            egui::WindowLevel::AlwaysOnTop,
+        ));
+        ctx.send_viewport_cmd(egui::ViewportCommand::Fullscreen(true));
+        if region {
+            self.region_picker = Some(RegionPicker::new(picture));
+        } else {
+            self.place_screenshot(picture);
         }
-
-        // Handle cursor during hover
-        if let Some(pointer_pos) = response.hover_pos() {
-            if let Some((_, expanded_bbox, corners)) = self.get_selection_info()
-            {
-                let hit_size = vec2(10.0, 10.0);
-                let tl_rect = Rect::from_center_size(corners[0], hit_size);
-                let tr_rect = Rect::from_center_size(corners[1], hit_size);
-                let bl_rect = Rect::from_center_size(corners[2], hit_size);
-                let br_rect = Rect::from_center_size(corners[3], hit_size);
-
-                if tl_rect.contains(pointer_pos)
-                    || br_rect.contains(pointer_pos)
-                {
-                    ctx.set_cursor_icon(egui::CursorIcon::ResizeNwSe);
-                } else if tr_rect.contains(pointer_pos)
-                    || bl_rect.contains(pointer_pos)
-                {
-                    ctx.set_cursor_icon(egui::CursorIcon::ResizeNeSw);
-                } else if expanded_bbox.contains(pointer_pos) {
-                    ctx.set_cursor_icon(egui::CursorIcon::PointingHand);
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    fn show_region_picker(&mut self, ctx: &egui::Context) {
+        let Some(picker) = &mut self.region_picker else {
+            return;
+        };
+        match picker.show(ctx) {
+            Some(Picked::Region(region)) => {
+                let Some(picker) = self.region_picker.take() else {
+                    return;
+                };
+                match screenshot::crop(&picker.picture, region) {
+                    Some(picture) => self.place_screenshot(picture),
+                    None => self.stop_annotating(ctx),
                 }
             }
+            Some(Picked::Cancelled) => {
+                self.region_picker = None;
+                self.stop_annotating(ctx);
+            }
+            None => {}
         }
     }
-
-    fn start_resizing(&mut self, corner: ResizeCorner, bbox: Rect) {
-        self.resizing_corner = Some(corner);
-        self.resize_original_bbox = Some(bbox);
-        self.resize_original_lines.clear();
-        for &i in &self.selected_lines {
-            if let Some(line) = self.lines.get(i) {
-                self.resize_original_lines.push((i, line.clone()));
-            }
+    // Puts the screenshot on the board, locked so drawing over it doesn't
+    // move it, on a page of its own unless this one is empty
+    #[cfg(not(target_arch = "wasm32"))]
+    fn place_screenshot(&mut self, picture: Picture) {
+        let rect = Rect::from_min_size(Pos2::ZERO, picture.size());
+        let line = Line {
+            points: vec![rect.min, rect.max],
+            color: Color32::TRANSPARENT,
+            width: MIN_STROKE_WIDTH,
+            widths: Vec::new(),
+            style: LineStyle::Solid,
+            created_ms: now_ms(),
+            fill: None,
+            fill_only: false,
+            picture: Some(Arc::new(picture)),
+            locked: true,
+            id: new_line_id(),
+        };
+        if self.document.lines.is_empty() {
+            self.cancel_tools();
+            self.clear_selection_state();
+            self.document.append(vec![line]);
+        } else {
+            self.add_page(vec![line]);
         }
+        self.annotation = Some(Annotation {
+            rect,
+            fitted_rect: None,
+        });
+        self.current_tool = Tool::BRUSH;
+        self.push_toast(
+            "Draw over the screenshot. File › Copy image copies the result, \
+             View › Stop annotating brings the window back.",
+            ToastLevel::Info,
+        );
     }
-
-    fn update_resizing(&mut self, pointer_pos: Pos2, corner: ResizeCorner) {
-        if let Some(orig_bbox) = self.resize_original_bbox {
-            let mut new_bbox = orig_bbox;
-            match corner {
-                ResizeCorner::TopLeft => {
-                    new_bbox.min = pointer_pos + vec2(5.0, 5.0);
-                }
-                ResizeCorner::TopRight => {
-                    new_bbox.max.x = pointer_pos.x - 5.0;
-                    new_bbox.min.y = pointer_pos.y + 5.0;
-                }
-                ResizeCorner::BottomLeft => {
-                    new_bbox.min.x = pointer_pos.x + 5.0;
-                    new_bbox.max.y = pointer_pos.y - 5.0;
-                }
-                ResizeCorner::BottomRight => {
-                    new_bbox.max = pointer_pos - vec2(5.0, 5.0);
+    #[cfg(not(target_arch = "wasm32"))]
+    fn stop_annotating(&mut self, ctx: &egui::Context) {
+        self.annotation = None;
+        ctx.send_viewport_cmd(egui::ViewportCommand::Fullscreen(false));
+        ctx.send_viewport_cmd(egui::ViewportCommand::WindowLevel(
+            egui::WindowLevel::Normal,
+        ));
+        ctx.send_viewport_cmd(egui::ViewportCommand::Decorations(true));
+    }
+    // The part of the canvas currently on screen, in canvas coordinates
+    fn visible_rect(&self) -> Rect {
+        self.camera.rect_to_world(self.canvas_rect)
+    }
+    fn fit_to_content(&mut self) {
+        self.camera
+            .fit(lines_bounding_box(&self.document.lines), self.canvas_rect);
+    }
+    fn zoom_to_selection(&mut self) {
+        let bbox = self
+            .selected_lines
+            .iter()
+            .filter_map(|&i| self.document.lines.get(i))
+            .map(|line| lines_bounding_box(std::slice::from_ref(line)))
+            .fold(Rect::NOTHING, Rect::union);
+        if bbox.is_finite() {
+            self.camera.fit(bbox, self.canvas_rect);
+        }
+    }
+    fn reset_view(&mut self) {
+        self.camera = Camera::default();
+    }
+    // Back to 100% around the middle of the canvas
+    fn reset_zoom(&mut self) {
+        if self.canvas_rect.is_positive() {
+            self.camera.zoom_to(self.canvas_rect.center(), 1.0);
+        } else {
+            self.reset_view();
+        }
+    }
+    // Moves the selected lines up or down in the drawing order
+    fn reorder_selection(&mut self, order: ZOrder) {
+        if self.selected_lines.is_empty() {
+            return;
+        }
+        let order = zorder::reorder(
+            self.document.lines.len(),
+            &self.selected_lines,
+            order,
+        );
+        if order.iter().enumerate().all(|(new, &old)| new == old) {
+            return;
+        }
+        self.selected_lines = order
+            .iter()
+            .enumerate()
+            .filter(|(_, old)| self.selected_lines.contains(old))
+            .map(|(new, _)| new)
+            .collect();
+        self.document.reorder(order);
+    }
+    fn draw_arrange_buttons(&mut self, ui: &mut Ui) {
+        ui.label("Align");
+        ui.horizontal_wrapped(|ui| {
+            for (alignment, label) in ALIGNMENTS {
+                if ui.button(label).clicked() {
+                    self.document.translate_lines(align::align(
+                        &self.document.lines,
+                        &self.selected_lines,
+                        alignment,
+                    ));
                 }
             }
-
-            let scale_x = if orig_bbox.width() > 0.0 {
-                new_bbox.width() / orig_bbox.width()
-            } else {
-                1.0
-            };
-            let scale_y = if orig_bbox.height() > 0.0 {
-                new_bbox.height() / orig_bbox.height()
-            } else {
-                1.0
-            };
-
-            for (i, orig_line) in &self.resize_original_lines {
-                if let Some(line) = self.lines.get_mut(*i) {
-                    for (p, orig_p) in
-                        line.points.iter_mut().zip(&orig_line.points)
-                    {
-                        let nx = new_bbox.min.x
-                            + (orig_p.x - orig_bbox.min.x) * scale_x;
-                        let ny = new_bbox.min.y
-                            + (orig_p.y - orig_bbox.min.y) * scale_y;
-                        *p = pos2(nx, ny);
+        });
+        ui.label("Distribute");
+        ui.add_enabled_ui(self.selected_lines.len() >= 3, |ui| {
+            ui.horizontal(|ui| {
+                for (distribution, label) in [
+                    (Distribution::Horizontal, "Horizontally"),
+                    (Distribution::Vertical, "Vertically"),
+                ] {
+                    if ui.button(label).clicked() {
+                        self.document.translate_lines(align::distribute(
+                            &self.document.lines,
+                            &self.selected_lines,
+                            distribution,
+                        ));
                     }
                 }
+            });
+        });
+    }
+    // Keeps the selected lines from being moved or erased; they drop out of
+    // the selection since locked lines can't be selected
+    fn lock_selection(&mut self) {
+        if self.current_tool != Tool::SELECTION {
+            return;
+        }
+        let selected = mem::take(&mut self.selected_lines);
+        self.document.set_locked(selected, true);
+        self.clear_selection_state();
+    }
+    // The tool the width and opacity controls apply to: the current tool
+    // if it has a width of its own, the brush otherwise
+    fn settings_tool(&self) -> Tool {
+        if self.tool_settings.contains_key(&self.current_tool) {
+            self.current_tool
+        } else {
+            Tool::BRUSH
+        }
+    }
+    fn tool_settings(&self) -> ToolSettings {
+        self.tool_settings
+            .get(&self.settings_tool())
+            .copied()
+            .unwrap_or_default()
+    }
+    fn tool_settings_mut(&mut self) -> &mut ToolSettings {
+        self.tool_settings.entry(self.settings_tool()).or_default()
+    }
+    // Sets the width of the current tool, and the width of the selected
+    // lines while the selection tool has a selection
+    fn set_stroke_width(&mut self, width: f32) {
+        let width = width.clamp(MIN_STROKE_WIDTH, MAX_STROKE_WIDTH);
+        self.tool_settings_mut().width = width;
+        if self.current_tool != Tool::SELECTION
+            || self.selected_lines.is_empty()
+        {
+            return;
+        }
+        self.document.set_width(&self.selected_lines, width);
+    }
+    // Shows another page; out of range pages are ignored
+    fn go_to_page(&mut self, page: usize) {
+        if page == self.document.current_page
+            || page >= self.document.page_count()
+        {
+            return;
+        }
+        self.cancel_tools();
+        self.document.go_to_page(page);
+    }
+    // Inserts a page with `lines` after the current one and shows it
+    fn add_page(&mut self, lines: Vec<Line>) {
+        self.cancel_tools();
+        self.clear_selection_state();
+        self.document.add_page(lines);
+    }
+    fn delete_page(&mut self) {
+        if self.document.page_count() <= 1 {
+            return;
+        }
+        if !self.document.lines.is_empty() {
+            let choice = rfd::MessageDialog::new()
+                .set_level(rfd::MessageLevel::Warning)
+                .set_title("Delete page?")
+                .set_description(format!(
+                    "Delete page {} and its {} lines? This can't be undone.",
+                    self.document.current_page + 1,
+                    self.document.lines.len()
+                ))
+                .set_buttons(rfd::MessageButtons::OkCancelCustom(
+                    "Delete".to_owned(),
+                    "Cancel".to_owned(),
+                ))
+                .show();
+            if !matches!(
+                choice,
+                rfd::MessageDialogResult::Custom(c) if c == "Delete"
+            ) {
+                return;
             }
         }
+        self.clear_selection_state();
+        self.cancel_tools();
+        self.document.delete_page();
     }
-
-    fn handle_eraser(&mut self, pointer_pos: Pos2) {
-        let erase_radius = self.stroke_width + 5.0; // 給予一點點擊容差
-
-        let (kept, deleted): (Vec<_>, Vec<_>) =
-            self.lines.drain(..).partition(|line| {
-                for window in line.points.windows(2) {
-                    if distance_point_to_segment(
-                        pointer_pos,
-                        window[0],
-                        window[1],
-                    ) < erase_radius
-                    {
-                        return false; // false 會進 deleted
-                    }
-                }
-                true
+    fn draw_page_switcher(&mut self, ui: &mut Ui) {
+        if ui
+            .add_enabled(self.document.current_page > 0, egui::Button::new("◀"))
+            .on_hover_text("Previous page (Ctrl+PgUp)")
+            .clicked()
+        {
+            self.go_to_page(self.document.current_page - 1);
+        }
+        let label = format!(
+            "page {}/{}",
+            self.document.current_page + 1,
+            self.document.page_count()
+        );
+        ui.menu_button(label, |ui| {
+            if let Some(page) = pages::show(ui, &self.document) {
+                ui.close();
+                self.go_to_page(page);
+            }
+        })
+        .response
+        .on_hover_text("All pages");
+        if ui
+            .add_enabled(
+                self.document.current_page + 1 < self.document.page_count(),
+                egui::Button::new("▶"),
+            )
+            .on_hover_text("Next page (Ctrl+PgDn)")
+            .clicked()
+        {
+            self.go_to_page(self.document.current_page + 1);
+        }
+    }
+    fn toggle_ruler(&mut self) {
+        self.ruler = if self.ruler == Ruler::Off {
+            self.current_tool = Tool::BRUSH;
+            Ruler::Placing(None)
+        } else {
+            Ruler::Off
+        };
+    }
+    fn clear_selection_state(&mut self) {
+        self.selected_lines.clear();
+        // a move or resize in progress works on the selected lines
+        if let Some(tool) = self
+            .tools
+            .iter_mut()
+            .find(|tool| tool.kind() == Tool::SELECTION)
+        {
+            tool.cancel();
+        }
+    }
+    // Drops every stroke or drag in progress
+    fn cancel_tools(&mut self) {
+        for tool in &mut self.tools {
+            tool.cancel();
+        }
+    }
+    fn active_tool(&self) -> Option<&dyn CanvasTool> {
+        self.tools
+            .iter()
+            .find(|tool| tool.kind() == self.current_tool)
+            .map(|tool| &**tool)
+    }
+    // Calls `f` with the current tool and the board and settings it works
+    // with. The tools are taken out of `self` meanwhile.
+    fn with_active_tool<R>(
+        &mut self,
+        ctx: &egui::Context,
+        f: impl FnOnce(&mut dyn CanvasTool, &mut ToolContext<'_>) -> R,
+    ) -> Option<R> {
+        let settings = self.tool_settings();
+        let color = self.document.palette.get_current_color();
+        let mut tools = mem::take(&mut self.tools);
+        let result = tools
+            .iter_mut()
+            .find(|tool| tool.kind() == self.current_tool)
+            .map(|tool| {
+                let mut cx = ToolContext {
+                    ctx,
+                    document: &mut self.document,
+                    selection: &mut self.selected_lines,
+                    camera: self.camera,
+                    canvas_rect: self.canvas_rect,
+                    color: settings
+                        .opacity
+                        .map_or(color, |opacity| color.gamma_multiply(opacity)),
+                    width: settings.width,
+                    style: self.line_style,
+                    fill: self.fill_closed_strokes.then_some(self.fill_color),
+                    snap_to_endpoints: self.snap_to_endpoints,
+                    speed_sensitive_width: self.speed_sensitive_width,
+                    show_crosshair: self.show_crosshair,
+                    ruler: &mut self.ruler,
+                    recent_colors: &mut self.recent_colors,
+                    preferences: &self.preferences,
+                };
+                f(tool.as_mut(), &mut cx)
             });
-
-        self.lines = kept;
-        let deleted_lines = deleted;
-        if !deleted_lines.is_empty() {
-            self.selected_lines.clear();
-            self.undo_stack.extend_erase(deleted_lines);
+        self.tools = tools;
+        result
+    }
+    // Tells when the stroke under the pointer was drawn
+    fn show_line_tooltip(&self, response: &Response) {
+        if response.dragged() {
+            return;
+        }
+        let Some(hover_pos) = response.hover_pos() else {
+            return;
+        };
+        let Some(i) = line_at(
+            &self.document.lines,
+            self.camera.to_world(hover_pos),
+            self.camera.zoom,
+        ) else {
+            return;
+        };
+        let line = &self.document.lines[i];
+        let mut text = Vec::new();
+        if line.created_ms != 0 {
+            let created =
+                SystemTime::UNIX_EPOCH + Duration::from_millis(line.created_ms);
+            text.push(format!(
+                "Drawn {} ({})",
+                meta::rfc3339(created),
+                format_age(created)
+            ));
+        }
+        if line.locked {
+            text.push("Locked, double-click to unlock".to_owned());
+        }
+        if !text.is_empty() {
+            response.clone().on_hover_text_at_pointer(text.join("\n"));
         }
     }
-
-    fn push_line(&mut self) {
-        self.lines.push(Line {
-            points: self.current_line.clone(),
-            color: self.palette.get_current_color(),
-            width: self.stroke_width,
-        });
-        self.undo_stack.add_draw(Line {
-            points: self.current_line.clone(),
-            color: self.palette.get_current_color(),
-            width: self.stroke_width,
-        });
-        self.current_line.clear();
+    // Outlines the locked line under the pointer, with a lock at its corner
+    fn draw_lock_indicator(&self, painter: &Painter, response: &Response) {
+        let Some(hover_pos) = response.hover_pos() else {
+            return;
+        };
+        let Some(i) = line_at(
+            &self.document.lines,
+            self.camera.to_world(hover_pos),
+            self.camera.zoom,
+        ) else {
+            return;
+        };
+        let line = &self.document.lines[i];
+        if !line.locked {
+            return;
+        }
+        let rect = self
+            .camera
+            .rect_to_screen(lines_bounding_box(std::slice::from_ref(line)))
+            .expand(4.0);
+        let color = painter.ctx().style().visuals.weak_text_color();
+        draw_dotted_rect(painter, rect, Stroke::new(1.0_f32, color));
+        painter.text(
+            rect.right_top(),
+            egui::Align2::LEFT_BOTTOM,
+            "🔒",
+            egui::FontId::proportional(12.0),
+            color,
+        );
     }
 
     fn draw_previous_lines(&self, painter: &Painter, i: &usize, line: &Line) {
+        if let Some((picture, rect)) = line.picture_area() {
+            painter.image(
+                picture.texture(painter.ctx()).id(),
+                self.camera.rect_to_screen(rect),
+                Rect::from_min_max(Pos2::ZERO, pos2(1.0, 1.0)),
+                Color32::WHITE,
+            );
+            return;
+        }
         if line.points.len() >= 2 {
-            let points = line.points.clone();
-            let color = if self.selected_lines.contains(&i) {
+            let points: Vec<Pos2> = line
+                .points
+                .iter()
+                .map(|&p| self.camera.to_screen(p))
+                .collect();
+            if let Some(fill) = line.fill
+                && line.is_closed()
+            {
+                painter.add(stroke::fill(&points, fill));
+            }
+            if line.fill_only {
+                return;
+            }
+            let color = if self.selected_lines.contains(i) {
                 // Highlight selected lines? Or just leave them as is and draw box?
                 // Maybe slight tint?
                 // For now, let's just keep original color, but maybe we can draw a highlight.
@@ -563,96 +3588,906 @@ impl WhiteboardApp {
                 line.color
             };
 
-            painter
-                .add(egui::Shape::line(points, Stroke::new(line.width, color)));
+            let zoom = self.camera.zoom;
+            let widths: Vec<f32> =
+                line.widths.iter().map(|w| w * zoom).collect();
+            painter.extend(stroke::shapes(
+                &points,
+                &widths,
+                line.width * zoom,
+                color,
+                line.style,
+            ));
+        }
+    }
+
+    fn draw_tool_bar(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            for tool in &self.tools {
+                let is_selected = self.current_tool == tool.kind();
+
+                let frame = if is_selected {
+                    egui::Frame::new()
+                        .stroke(Stroke::new(2.0_f32, ui.visuals().text_color()))
+                        .inner_margin(2.0)
+                        .corner_radius(4.0)
+                } else {
+                    egui::Frame::new().inner_margin(4.0)
+                };
+
+                frame.show(ui, |ui| {
+                    let img = egui::Image::new(tool.icon())
+                        .fit_to_exact_size(vec2(30.0, 30.0));
+                    let response = ui
+                        .add(egui::Button::image(img))
+                        .on_hover_text(tool.tooltip());
+                    // the button only shows an image, screen readers need
+                    // a name for it
+                    let mut label = format!("{} tool", tool.tooltip());
+                    if is_selected {
+                        label.push_str(", selected");
+                    }
+                    response.widget_info(|| {
+                        egui::WidgetInfo::labeled(
+                            egui::WidgetType::Button,
+                            true,
+                            &label,
+                        )
+                    });
+                    if response.clicked() {
+                        self.current_tool = tool.kind();
+                    }
+                });
+            }
+        });
+        self.announce_tool_and_color(ui);
+    }
+    // An invisible live region, which screen readers read out whenever the
+    // tool or the color changes, shortcuts included
+    fn announce_tool_and_color(&self, ui: &mut Ui) {
+        let tool = self.active_tool().map_or("No", |tool| tool.tooltip());
+        let status = format!(
+            "{tool} tool, color {}: {}",
+            self.document.palette.get_active_color_index() + 1,
+            color_name(self.document.palette.get_current_color())
+        );
+        let response = ui.allocate_response(Vec2::ZERO, egui::Sense::hover());
+        response.widget_info(|| {
+            egui::WidgetInfo::labeled(egui::WidgetType::Label, true, &status)
+        });
+        ui.ctx().accesskit_node_builder(response.id, |node| {
+            node.set_live(egui::accesskit::Live::Polite);
+        });
+    }
+}
+impl WhiteboardApp {
+    // Creates the app and opens `file_path`, e.g. a file passed on the
+    // command line, if given
+    pub fn new(
+        cc: &eframe::CreationContext<'_>,
+        file_path: Option<PathBuf>,
+    ) -> Self {
+        let mut app = Self::restore(cc);
+        #[cfg(target_arch = "wasm32")]
+        match web::load_board() {
+            Ok(Some(state)) => app.load_state(state),
+            Ok(None) => {}
+            Err(e) => show_read_error(&io::Error::other(e)),
+        }
+        if let Some(file_path) = file_path {
+            let ctx = &cc.egui_ctx;
+            // relative paths would break once the working directory changes
+            let file_path =
+                std::path::absolute(&file_path).unwrap_or(file_path);
+            if app.confirm_discard_changes(ctx) {
+                if let Err(e) = app.load_whiteboard_file(file_path) {
+                    show_read_error(&e);
+                }
+                app.set_window_title(ctx);
+            }
+        }
+        app
+    }
+    // A blank board set up with the user's default brush and palette
+    fn with_preferences(preferences: Preferences) -> Self {
+        let mut app = Self {
+            document: Document {
+                palette: preferences.palette(),
+                ..Document::default()
+            },
+            saved_preferences: preferences.clone(),
+            preferences,
+            ..Self::default()
+        };
+        app.tool_settings.entry(Tool::BRUSH).or_default().width =
+            app.preferences.stroke_width;
+        app
+    }
+    // Offers to restore the recovery snapshot left behind by a session that
+    // did not shut down cleanly
+    fn restore(cc: &eframe::CreationContext<'_>) -> Self {
+        let mut app = Self {
+            recent_files: RecentFiles::load(),
+            palette_presets: presets::load_saved(),
+            last_directory: cc
+                .storage
+                .and_then(|s| eframe::get_value(s, LAST_DIRECTORY_KEY)),
+            recent_colors: RecentColors {
+                colors: cc
+                    .storage
+                    .and_then(|s| eframe::get_value(s, RECENT_COLORS_KEY))
+                    .unwrap_or_default(),
+            },
+            ..Self::with_preferences(Preferences::load())
+        };
+        // the user's default brush width only counts until the brush has
+        // a width of its own
+        // read by name, so settings of tools that aren't registered yet
+        // don't make the others unreadable
+        let saved_settings: Option<HashMap<String, ToolSettings>> = cc
+            .storage
+            .and_then(|s| eframe::get_value(s, TOOL_SETTINGS_KEY));
+        for (name, settings) in saved_settings.unwrap_or_default() {
+            if let Some((_, current)) = app
+                .tool_settings
+                .iter_mut()
+                .find(|(tool, _)| tool.name() == name)
+            {
+                *current = settings.sanitized(*current);
+            }
+        }
+        cc.egui_ctx.set_theme(app.preferences.theme.to_egui());
+        set_reduce_motion(&cc.egui_ctx, app.preferences.reduce_motion);
+        // Ctrl+0 resets the board's zoom rather than the interface's
+        cc.egui_ctx
+            .options_mut(|options| options.zoom_with_keyboard = false);
+        #[cfg(not(target_arch = "wasm32"))]
+        if !app.preferences.remember_window {
+            cc.egui_ctx
+                .send_viewport_cmd(egui::ViewportCommand::Maximized(true));
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        app.set_single_instance(&cc.egui_ctx);
+        let snapshots = recovery::read_snapshots();
+        if snapshots.is_empty() {
+            return app;
+        }
+        let names: Vec<String> = snapshots
+            .iter()
+            .map(|(_, snapshot)| {
+                snapshot
+                    .whiteboard_file
+                    .as_ref()
+                    .map_or("Untitled.wb".to_owned(), |s| {
+                        s.display().to_string()
+                    })
+            })
+            .collect();
+        let restore = rfd::MessageDialog::new()
+            .set_level(rfd::MessageLevel::Warning)
+            .set_title("Restore unsaved work?")
+            .set_description(format!(
+                "Simple Whiteboard did not shut down cleanly. Restore the \
+                 unsaved changes to {}?",
+                names.join(", ")
+            ))
+            .set_buttons(rfd::MessageButtons::YesNo)
+            .show();
+        for (id, snapshot) in snapshots {
+            if restore != rfd::MessageDialogResult::Yes {
+                recovery::remove_snapshot(id);
+                continue;
+            }
+            let state = match WhiteboardState::from_value(snapshot.state) {
+                Ok(state) => state,
+                Err(e) => {
+                    eprintln!("failed to restore recovery snapshot: {e}");
+                    recovery::remove_snapshot(id);
+                    continue;
+                }
+            };
+            // each restored board gets a tab of its own
+            if !app.is_blank() {
+                app.new_tab(&cc.egui_ctx);
+            }
+            app.load_state(state);
+            app.file_encoding = snapshot
+                .whiteboard_file
+                .as_deref()
+                .map(Encoding::from_extension)
+                .unwrap_or_default();
+            app.whiteboard_file = snapshot.whiteboard_file;
+            app.file_modified =
+                app.whiteboard_file.as_deref().and_then(modified);
+            app.document.dirty = true;
+            // the snapshot stays until the board is saved or closed
+            app.recovery_id = id;
+        }
+        app.set_window_title(&cc.egui_ctx);
+        app
+    }
+    // Draws the board into the space left in `ui` and handles the pointer
+    // over it: drawing with the current tool, panning and zooming. Unlike
+    // `update` it leaves out the menus, side panel, windows and keyboard
+    // shortcuts, and sends no viewport commands, so the board can be shown
+    // inside another app's panel.
+    pub fn ui(&mut self, ui: &mut Ui) {
+        let ctx = &ui.ctx().clone();
+        let editing = self.presentation.is_none();
+        let (response, painter) =
+            ui.allocate_painter(ui.available_size(), egui::Sense::drag());
+        self.canvas_rect = response.rect;
+        if let Some(presentation) = &mut self.presentation
+            && self.preferences.present_fit
+            && presentation.fitted_rect != Some(response.rect)
+        {
+            presentation.fitted_rect = Some(response.rect);
+            self.fit_to_content();
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(annotation) = &mut self.annotation
+            && annotation.fitted_rect != Some(response.rect)
+        {
+            annotation.fitted_rect = Some(response.rect);
+            self.camera.fit(annotation.rect, response.rect);
+        }
+
+        // the canvas is unbounded, scrolling moves the view around it and
+        // ctrl+scroll or a touchpad pinch zooms around the pointer
+        if let Some(pos) = response.hover_pos() {
+            let (scroll, zoom, touch) = ctx.input(|i| {
+                (i.smooth_scroll_delta, i.zoom_delta(), i.multi_touch())
+            });
+            if scroll != Vec2::ZERO {
+                self.camera.pan += scroll;
+            }
+            // a touchscreen pinch is handled with the two-finger drag below
+            if zoom != 1.0 && touch.is_none() {
+                self.camera.zoom_around(pos, zoom);
+            }
+        }
+
+        if editing
+            && let Some(pos) =
+                response.hover_pos().or(response.interact_pointer_pos())
+        {
+            let pos = self.camera.to_world(pos);
+            if let Some(Some(icon)) =
+                self.with_active_tool(ctx, |tool, cx| tool.cursor(cx, pos))
+            {
+                ctx.set_cursor_icon(icon);
+            }
+        }
+
+        // right-click menu for the selection, on top of the drag area
+        if editing
+            && self.current_tool == Tool::SELECTION
+            && !self.selected_lines.is_empty()
+        {
+            ui.interact(
+                response.rect,
+                ui.id().with("selection_menu"),
+                egui::Sense::click(),
+            )
+            .context_menu(|ui| {
+                for (order, label, shortcut) in Z_ORDERS {
+                    if ui
+                        .add(egui::Button::new(label).shortcut_text(shortcut))
+                        .clicked()
+                    {
+                        self.reorder_selection(order);
+                    }
+                }
+                ui.separator();
+                if ui
+                    .button("Smooth")
+                    .on_hover_text("Round off the corners of the strokes")
+                    .clicked()
+                {
+                    self.document.smooth(&self.selected_lines);
+                }
+                if ui
+                    .add(
+                        egui::Button::new("Copy as SVG")
+                            .shortcut_text("Ctrl+Shift+C"),
+                    )
+                    .clicked()
+                {
+                    self.copy_selection_as_svg(ui.ctx());
+                }
+                if ui
+                    .add(egui::Button::new("Lock").shortcut_text("Ctrl+L"))
+                    .on_hover_text("Double-click a locked line to unlock it")
+                    .clicked()
+                {
+                    self.lock_selection();
+                }
+            });
         }
-    }
 
-    fn draw_selections(&self, painter: &Painter) {
-        // Draw selection rect (drag area)
-        if let (Some(start), Some(current)) =
-            (self.selection_start, self.selection_current)
-            && self.current_tool == Tool::Selection
-        {
-            let rect = Rect::from_two_pos(start, current);
-            draw_dotted_rect(&painter, rect, Stroke::new(1.0, Color32::GRAY));
+        // two fingers pinch to zoom and drag to pan
+        if let Some(touch) = ctx.input(|i| i.multi_touch()) {
+            self.touch_gesture = true;
+            self.camera.zoom_around(touch.center_pos, touch.zoom_delta);
+            self.camera.pan += touch.translation_delta;
+            // drop the stroke the first finger may have started
+            self.cancel_tools();
+        } else if self.touch_gesture && !ctx.input(|i| i.pointer.any_down()) {
+            self.touch_gesture = false;
         }
 
-        // Draw bounding box around selected lines
-        if self.current_tool == Tool::Selection {
-            if let Some((_, expanded, corners)) = self.get_selection_info() {
-                draw_dotted_rect(
-                    &painter,
-                    expanded,
-                    Stroke::new(1.0, Color32::BLUE),
-                );
+        // middle-drag or space + drag pans with any tool; while
+        // presenting any drag does, except with the laser pointer
+        let space_down = !ctx.wants_keyboard_input()
+            && ctx.input(|i| i.key_down(egui::Key::Space));
+        let pointing = self.active_tool().is_some_and(|tool| !tool.edits());
+        let panning = response.dragged_by(egui::PointerButton::Middle)
+            || ((space_down || !(editing || pointing))
+                && response.dragged_by(egui::PointerButton::Primary));
+        if panning {
+            self.camera.pan += response.drag_delta();
+            ctx.set_cursor_icon(egui::CursorIcon::Grabbing);
+        } else if space_down && response.hovered() {
+            ctx.set_cursor_icon(egui::CursorIcon::Grab);
+        }
 
-                let corner_size = vec2(8.0, 8.0);
-                for &corner in &corners {
-                    let rect = Rect::from_center_size(corner, corner_size);
-                    painter.rect_filled(rect, 0.0, Color32::GRAY);
-                    painter.rect_stroke(
-                        rect,
-                        0.0,
-                        Stroke::new(1.0, Color32::WHITE),
-                        egui::StrokeKind::Middle,
-                    );
+        // the canvas only senses drags, so a click is a drag that
+        // starts and stops again
+        if (editing || pointing)
+            && !self.touch_gesture
+            && let Some(pointer_pos) = response.interact_pointer_pos()
+        {
+            let pointer_pos = self.camera.to_world(pointer_pos);
+            let started = !panning && response.drag_started();
+            let dragged = !panning && response.dragged();
+            let stopped = response.drag_stopped();
+            self.with_active_tool(ctx, |tool, cx| {
+                if started {
+                    tool.on_pointer_down(cx, pointer_pos);
+                } else if dragged {
+                    tool.on_pointer_drag(cx, pointer_pos);
                 }
-            }
+                if stopped {
+                    tool.on_pointer_up(cx, pointer_pos);
+                }
+            });
         }
-    }
 
-    fn draw_tool_bar(&mut self, ui: &mut Ui) {
-        ui.horizontal(|ui| {
-            for (tool, path, tooltip) in TOOLS {
-                let is_selected = self.current_tool == tool;
+        // 繪製所有已存檔的線條
+        // fills from the fill tool go beneath every stroke
+        let (fills, strokes): (Vec<_>, Vec<_>) = self
+            .document
+            .lines
+            .iter()
+            .enumerate()
+            .partition(|(_, line)| line.fill_only);
+        for (i, line) in fills.into_iter().chain(strokes) {
+            self.draw_previous_lines(&painter, &i, line);
+        }
 
-                let frame = if is_selected {
-                    egui::Frame::new()
-                        .stroke(Stroke::new(2.0, ui.visuals().text_color()))
-                        .inner_margin(2.0)
-                        .corner_radius(4.0)
-                } else {
-                    egui::Frame::new().inner_margin(4.0)
-                };
+        if editing || pointing {
+            let pointer = response
+                .hover_pos()
+                .or(response.interact_pointer_pos())
+                .map(|pos| self.camera.to_world(pos));
+            self.with_active_tool(ctx, |tool, cx| {
+                tool.paint_overlay(cx, &painter, pointer);
+            });
+        }
+        if editing && self.current_tool == Tool::SELECTION {
+            self.show_line_tooltip(&response);
+        }
+        if editing
+            && matches!(self.current_tool, Tool::SELECTION | Tool::ERASER)
+        {
+            self.draw_lock_indicator(&painter, &response);
+        }
 
-                frame.show(ui, |ui| {
-                    let img = egui::Image::new(path)
-                        .fit_to_exact_size(vec2(30.0, 30.0));
-                    if ui
-                        .add(egui::Button::image(img))
-                        .on_hover_text(tooltip)
-                        .clicked()
-                    {
-                        self.current_tool = tool;
-                    }
-                });
-            }
-        });
+        if editing && self.show_minimap {
+            minimap::show(
+                ui,
+                &mut self.camera,
+                response.rect,
+                &self.document.lines,
+            );
+        }
+        if editing {
+            scrollbar::show(
+                ui,
+                &mut self.camera,
+                response.rect,
+                lines_bounding_box(&self.document.lines),
+            );
+        }
+    }
+    // Switches the tool the pointer draws with, like its toolbar button
+    pub fn set_tool(&mut self, tool: Tool) {
+        self.current_tool = tool;
+    }
+    // Adds a tool to the end of the toolbar, in the main window and every
+    // detached one
+    pub fn register_tool(&mut self, make: ToolMaker) {
+        let tool = make();
+        tool.kind().register();
+        if let Some(settings) = tool.default_settings() {
+            self.tool_settings.entry(tool.kind()).or_insert(settings);
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        for window in &mut self.windows {
+            window.add_tool(make());
+        }
+        self.tools.push(tool);
+        self.tool_makers.push(make);
+    }
+    // Erases what the eraser would when pressed at `pos`, returning whether
+    // anything was erased
+    pub fn erase_at(&mut self, pos: Pos2, radius: f32) -> bool {
+        let erased = self.document.erase_at(pos, radius);
+        if erased {
+            self.selected_lines.clear();
+        }
+        erased
+    }
+    // Selects the lines touching `rect`, like dragging it out with the
+    // selection tool, returning how many were selected
+    pub fn select_in_rect(&mut self, rect: Rect) -> usize {
+        self.selected_lines = self.document.select_in_rect(rect);
+        self.selected_lines.len()
+    }
+    // Lines on every page
+    pub fn line_count(&self) -> usize {
+        (0..self.document.page_count())
+            .map(|page| self.document.page_lines(page).len())
+            .sum()
+    }
+    // The board as it would be saved, without touching its modification
+    // time
+    pub fn export_state(&self) -> WhiteboardState {
+        WhiteboardState::new(self)
+    }
+    // The board as the content of a `.wb` file, in JSON unless the board is
+    // encrypted
+    pub fn to_bytes(&self) -> io::Result<Vec<u8>> {
+        self.encode(&self.export_state(), Encoding::Json)
+    }
+    // The content of the board's file: `whiteboard_state` in `encoding`,
+    // encrypted with the board's key if it has one. Saving, autosaving and
+    // `to_bytes` all go through here.
+    fn encode(
+        &self,
+        whiteboard_state: &WhiteboardState,
+        encoding: Encoding,
+    ) -> io::Result<Vec<u8>> {
+        let bytes = format::encode(
+            encoding,
+            whiteboard_state,
+            self.preferences.stable_saves,
+        )?;
+        match &self.encryption {
+            Some(key) => key.encrypt(&bytes),
+            None => Ok(bytes),
+        }
+    }
+    // A board opened from the content of a whiteboard file, in any of its
+    // encodings. Unlike opening a file in the app it doesn't ask anything:
+    // damaged and encrypted content fail.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, LoadError> {
+        let mut app = Self::default();
+        app.load_state(decode(bytes)?);
+        Ok(app)
     }
 }
 impl Default for WhiteboardApp {
     fn default() -> Self {
         Self {
-            lines: Vec::new(),
-            current_line: Vec::new(),
-            // 預設提供五種不同的顏色選項
-            palette: ColorPalette::default(),
-            stroke_width: 3.0,
-            current_tool: Tool::Brush,
-            undo_stack: UndoStack::default(),
+            document: Document::default(),
+            speed_sensitive_width: false,
+            meta: Metadata::default(),
+            show_properties: false,
+            recent_colors: RecentColors::default(),
+            palette_presets: Vec::new(),
+            preset_name: String::new(),
+            tool_settings: tools::default_settings(&tools::make_all(
+                &tools::built_in(),
+            )),
+            line_style: LineStyle::Solid,
+            current_tool: Tool::BRUSH,
+            tools: tools::make_all(&tools::built_in()),
+            tool_makers: tools::built_in(),
+            ruler: Ruler::Off,
+            fill_closed_strokes: false,
+            fill_color: Color32::from_rgb(70, 110, 170),
+            snap_to_endpoints: true,
             whiteboard_file: None,
-
-            selection_start: None,
-            selection_current: None,
+            file_encoding: Encoding::default(),
+            file_modified: None,
+            encryption: None,
+            recovery_id: SnapshotId::default(),
+            unlock: None,
+            passphrase_prompt: None,
+            last_file_check: 0.0,
+            canvas_rect: Rect::NOTHING,
+            camera: Camera::default(),
+            touch_gesture: false,
+            show_minimap: false,
+            presentation: None,
+            show_crosshair: false,
+            title_shows_dirty: false,
+            preferences: Preferences::default(),
+            saved_preferences: Preferences::default(),
+            show_settings: false,
+            last_autosave: 0.0,
+            last_recovery_snapshot: 0.0,
+            backups: None,
+            recent_files: RecentFiles::default(),
+            last_directory: None,
+            show_replay_window: false,
+            replay_settings: ReplaySettings::default(),
+            show_image_window: false,
+            image_settings: ImageSettings::default(),
+            show_points_window: false,
+            point_settings: PointSettings::default(),
+            replay_export: None,
+            toasts: Toasts::default(),
             selected_lines: HashSet::new(),
-            is_moving_selection: false,
-            last_mouse_pos: None,
-            resizing_corner: None,
-            resize_original_bbox: None,
-            resize_original_lines: Vec::new(),
+            tabs: vec![Tab::default()],
+            active_tab: 0,
+            #[cfg(not(target_arch = "wasm32"))]
+            windows: Vec::new(),
+            in_board_window: false,
+
+            session: None,
+            show_share_window: false,
+            share_port: collab::DEFAULT_PORT,
+            share_address: String::new(),
+            share_key: String::new(),
+
+            #[cfg(target_arch = "wasm32")]
+            browser_files: BrowserFiles::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            instance: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            screenshot_displays: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            screenshot_settings: ScreenshotSettings::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            capture: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            region_picker: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            annotation: None,
+        }
+    }
+}
+// Runs a command-line command such as `render` without opening a window.
+// `args` excludes the program name. Returns the exit status, or `None` if
+// the arguments don't name a command.
+pub fn run_command(args: &[std::ffi::OsString]) -> Option<i32> {
+    cli::run(args)
+}
+// Hands `file_path` to the window that's already open, unless single
+// instance mode is off. Returns whether that window took it, in which case
+// this launch has nothing left to do.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn open_in_running_instance(file_path: &Path) -> bool {
+    Preferences::load().single_instance && instance::send(file_path).is_ok()
+}
+struct LoadedWhiteboard {
+    state: WhiteboardState,
+    encoding: Encoding,
+    // damaged entries were skipped, so the content differs from the file
+    partial: bool,
+    // the file is encrypted with this key
+    key: Option<FileKey>,
+}
+enum ReadResult {
+    Loaded(Box<LoadedWhiteboard>),
+    // encrypted with a passphrase we don't know yet
+    Locked(Vec<u8>),
+    // the user was already told what's wrong, or chose not to load it
+    Failed,
+}
+// Reads and parses a whiteboard file. Encrypted files are decrypted with
+// `key` if they were saved with it. I/O errors are returned to the caller,
+// other problems are reported to the user.
+fn read_whiteboard_state(
+    file_path: &Path,
+    key: Option<&FileKey>,
+) -> io::Result<ReadResult> {
+    let bytes = std::fs::read(file_path)?;
+    if !crypto::is_encrypted(&bytes) {
+        return Ok(decode_whiteboard(file_path, &bytes, None));
+    }
+    let Some(key) = key.filter(|key| key.matches(&bytes)) else {
+        return Ok(ReadResult::Locked(bytes));
+    };
+    match key.decrypt(&bytes) {
+        Ok(plaintext) => {
+            Ok(decode_whiteboard(file_path, &plaintext, Some(key.clone())))
+        }
+        Err(e) => {
+            // the key did fit this file before, so it was changed since
+            show_decrypt_error(file_path, &e);
+            Ok(ReadResult::Failed)
+        }
+    }
+}
+// The board in the content of a whiteboard file, in any of its encodings.
+// Opening files and `WhiteboardApp::from_bytes` both go through here, the
+// former after decrypting.
+fn decode(bytes: &[u8]) -> Result<WhiteboardState, LoadError> {
+    if crypto::is_encrypted(bytes) {
+        return Err(LoadError::Encrypted);
+    }
+    format::decode(bytes)
+}
+// Parses the (decrypted) content of a whiteboard file. Parse errors are
+// reported to the user, who may choose to load the readable parts of a
+// damaged file.
+fn decode_whiteboard(
+    file_path: &Path,
+    bytes: &[u8],
+    key: Option<FileKey>,
+) -> ReadResult {
+    match decode(bytes) {
+        Ok(state) => ReadResult::Loaded(Box::new(LoadedWhiteboard {
+            state,
+            encoding: Encoding::sniff(bytes),
+            partial: false,
+            key,
+        })),
+        Err(LoadError::NewerVersion(version)) => {
+            rfd::MessageDialog::new()
+                .set_level(rfd::MessageLevel::Error)
+                .set_title("Unsupported whiteboard file")
+                .set_description(format!(
+                    "{} was created by a newer version of Simple Whiteboard \
+                     (format version {version}, this build supports up to \
+                     {CURRENT_VERSION})",
+                    file_path.to_string_lossy()
+                ))
+                .set_buttons(rfd::MessageButtons::Ok)
+                .show();
+            ReadResult::Failed
+        }
+        Err(e) if e.is_partially_readable() => {
+            let choice = rfd::MessageDialog::new()
+                .set_level(rfd::MessageLevel::Error)
+                .set_title("Invalid whiteboard file")
+                .set_description(format!(
+                    "{} is damaged:\n\n{e}\n\nLoad the parts that can \
+                     still be read?",
+                    file_path.to_string_lossy()
+                ))
+                .set_buttons(rfd::MessageButtons::OkCancelCustom(
+                    "Load readable parts".to_owned(),
+                    "Cancel".to_owned(),
+                ))
+                .show();
+            if !matches!(
+                choice,
+                rfd::MessageDialogResult::Custom(choice)
+                    if choice == "Load readable parts"
+            ) {
+                return ReadResult::Failed;
+            }
+            let (state, dropped) = match format::decode_lenient(bytes) {
+                Ok(result) => result,
+                Err(e) => {
+                    show_invalid_file_error(file_path, &e);
+                    return ReadResult::Failed;
+                }
+            };
+            rfd::MessageDialog::new()
+                .set_level(rfd::MessageLevel::Warning)
+                .set_title("Partially loaded")
+                .set_description(format!(
+                    "Loaded {} lines, {dropped} unreadable lines were \
+                     skipped. Saving will overwrite the file without them.",
+                    state.line_count()
+                ))
+                .set_buttons(rfd::MessageButtons::Ok)
+                .show();
+            ReadResult::Loaded(Box::new(LoadedWhiteboard {
+                state,
+                encoding: Encoding::sniff(bytes),
+                partial: true,
+                key,
+            }))
+        }
+        Err(e) => {
+            show_invalid_file_error(file_path, &e);
+            ReadResult::Failed
+        }
+    }
+}
+// Turns egui's own animations on or off, like panels sliding and sections
+// opening, for every theme
+fn set_reduce_motion(ctx: &egui::Context, reduce_motion: bool) {
+    let default = egui::Style::default();
+    ctx.all_styles_mut(|style| {
+        if reduce_motion {
+            style.animation_time = 0.0;
+            style.scroll_animation = egui::style::ScrollAnimation::none();
+        } else {
+            style.animation_time = default.animation_time;
+            style.scroll_animation = default.scroll_animation;
+        }
+    });
+}
+// "just now", "5 minutes ago", "3 hours ago", "2 days ago"
+fn format_age(time: SystemTime) -> String {
+    let secs = time.elapsed().map_or(0, |age| age.as_secs());
+    let (value, unit) = match secs {
+        0..60 => return "just now".to_owned(),
+        60..3600 => (secs / 60, "minute"),
+        3600..86400 => (secs / 3600, "hour"),
+        _ => (secs / 86400, "day"),
+    };
+    let plural = if value == 1 { "" } else { "s" };
+    format!("{value} {unit}{plural} ago")
+}
+// The current time; std's clock panics in the browser
+fn now() -> SystemTime {
+    let since_epoch = web_time::SystemTime::now()
+        .duration_since(web_time::UNIX_EPOCH)
+        .unwrap_or_default();
+    SystemTime::UNIX_EPOCH + since_epoch
+}
+fn now_ms() -> u64 {
+    now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+// Random to start with, so lines drawn by different people sharing a board
+// get different ids
+fn new_line_id() -> u64 {
+    static NEXT_ID: LazyLock<AtomicU64> =
+        LazyLock::new(|| AtomicU64::new(OsRng.next_u64()));
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+fn show_invalid_file_error(file_path: &Path, e: &LoadError) {
+    rfd::MessageDialog::new()
+        .set_level(rfd::MessageLevel::Error)
+        .set_title("Invalid whiteboard file")
+        .set_description(format!(
+            "{} is not a valid whiteboard file:\n\n{e}",
+            file_path.to_string_lossy()
+        ))
+        .set_buttons(rfd::MessageButtons::Ok)
+        .show();
+}
+fn show_decrypt_error(file_path: &Path, e: &DecryptError) {
+    rfd::MessageDialog::new()
+        .set_level(rfd::MessageLevel::Error)
+        .set_title("Can't decrypt whiteboard file")
+        .set_description(format!(
+            "{} can't be decrypted:\n\n{e}",
+            file_path.to_string_lossy()
+        ))
+        .set_buttons(rfd::MessageButtons::Ok)
+        .show();
+}
+#[cfg(not(target_arch = "wasm32"))]
+fn show_capture_error(e: &str) {
+    rfd::MessageDialog::new()
+        .set_level(rfd::MessageLevel::Error)
+        .set_title("Failed to capture the screen")
+        .set_description(format!("Failed to capture the screen: {e}"))
+        .set_buttons(rfd::MessageButtons::Ok)
+        .show();
+}
+// Appends `.wb` unless the name already ends in one of the whiteboard
+// extensions: `notes` -> `notes.wb`, `notes.txt` -> `notes.txt.wb`
+fn normalize_extension(path: &Path) -> PathBuf {
+    let is_whiteboard = path.extension().is_some_and(|ext| {
+        format::EXTENSIONS
+            .iter()
+            .any(|e| ext.eq_ignore_ascii_case(e))
+    });
+    let Some(name) = path.file_name() else {
+        return path.to_path_buf();
+    };
+    if is_whiteboard {
+        return path.to_path_buf();
+    }
+    let mut name = name.to_os_string();
+    if let Some(trimmed) = name.to_str().map(|n| n.trim_end_matches('.')) {
+        name = trimmed.into();
+    }
+    name.push(".wb");
+    path.with_file_name(name)
+}
+fn confirm_overwrite(file_path: &Path) -> bool {
+    let choice = rfd::MessageDialog::new()
+        .set_level(rfd::MessageLevel::Warning)
+        .set_title("Replace file?")
+        .set_description(format!(
+            "{} already exists. Do you want to replace it?",
+            file_path.display()
+        ))
+        .set_buttons(rfd::MessageButtons::OkCancelCustom(
+            "Replace".to_owned(),
+            "Cancel".to_owned(),
+        ))
+        .show();
+    matches!(choice, rfd::MessageDialogResult::Custom(c) if c == "Replace")
+}
+// Asks first, since it changes which program the system opens whiteboard
+// files with
+fn register_file_association() {
+    let confirmed = rfd::MessageDialog::new()
+        .set_level(rfd::MessageLevel::Info)
+        .set_title("Register file association")
+        .set_description(
+            "Make Simple Whiteboard the program that opens .wb, .wbz and \
+             .wbb files for your user account? Double-clicking one will \
+             then open it here. If the app is moved, register it again.",
+        )
+        .set_buttons(rfd::MessageButtons::OkCancel)
+        .show();
+    if confirmed != rfd::MessageDialogResult::Ok {
+        return;
+    }
+    let (level, description) = match association::register() {
+        Ok(summary) => (rfd::MessageLevel::Info, summary),
+        Err(e) => (
+            rfd::MessageLevel::Error,
+            format!("Failed to register the file association: {e}"),
+        ),
+    };
+    rfd::MessageDialog::new()
+        .set_level(level)
+        .set_title("Register file association")
+        .set_description(description)
+        .set_buttons(rfd::MessageButtons::Ok)
+        .show();
+}
+fn show_read_error(e: &io::Error) {
+    rfd::MessageDialog::new()
+        .set_level(rfd::MessageLevel::Error)
+        .set_title("Failed to read")
+        .set_description(format!("Failed to read: {e}",))
+        .set_buttons(rfd::MessageButtons::Ok)
+        .show();
+}
+fn lines_bounding_box(lines: &[Line]) -> Rect {
+    let mut bbox = Rect::NOTHING;
+    for line in lines {
+        for p in &line.points {
+            bbox.extend_with(*p);
         }
     }
+    bbox
+}
+// Keeps `pos` on the horizontal or vertical line through `start`, whichever
+// is closer to the direction of the drag
+fn constrain_to_axis(start: Pos2, pos: Pos2) -> Pos2 {
+    let delta = pos - start;
+    if delta.x.abs() >= delta.y.abs() {
+        pos2(pos.x, start.y)
+    } else {
+        pos2(start.x, pos.y)
+    }
+}
+fn is_finite_pos(p: Pos2) -> bool {
+    p.x.is_finite() && p.y.is_finite()
+}
+// The topmost line passing under `pos`
+fn line_at(lines: &[Line], pos: Pos2, zoom: f32) -> Option<usize> {
+    let tolerance = 4.0 / zoom;
+    lines.iter().rposition(|line| {
+        if let Some((_, rect)) = line.picture_area() {
+            return rect.contains(pos);
+        }
+        line.points.windows(2).any(|w| {
+            distance_point_to_segment(pos, w[0], w[1])
+                < line.width / 2.0 + tolerance
+        })
+    })
 }
 // helper function to calculate the distance from a point to a line
 fn distance_point_to_segment(p: Pos2, a: Pos2, b: Pos2) -> f32 {
@@ -667,95 +4502,396 @@ fn distance_point_to_segment(p: Pos2, a: Pos2, b: Pos2) -> f32 {
 }
 
 impl eframe::App for WhiteboardApp {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(
+            storage,
+            RECENT_COLORS_KEY,
+            &self.recent_colors.colors,
+        );
+        eframe::set_value(storage, LAST_DIRECTORY_KEY, &self.last_directory);
+        eframe::set_value(storage, TOOL_SETTINGS_KEY, &self.tool_settings);
+        self.store_preferences();
+    }
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        self.for_each_board(|app| recovery::remove_snapshot(app.recovery_id));
+    }
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         self.handle_keyboard_event(ctx);
-        // 設定側邊控制面板
-        egui::SidePanel::left("control_panel").show(ctx, |ui| {
-            ui.heading("toolbar");
-            ui.add_space(5.0);
-
-            // Tool selection
-            self.draw_tool_bar(ui);
-
-            ui.add_space(15.0);
-            ui.separator();
-            ui.add_space(15.0);
-
-            // color selection (only when brush is selected)
-            ui.add_enabled_ui(self.current_tool == Tool::Brush, |ui| {
-                self.palette.draw(ui);
-            });
-
-            ui.add_space(10.0);
-
-            ui.add(
-                egui::Slider::new(&mut self.stroke_width, 1.0..=20.0)
-                    .text("Stroke Width"),
-            );
-
-            ui.add_space(20.0);
-
-            if ui.button("Clear").clicked() {
-                self.lines.clear();
-            }
-        });
-
-        // 畫布區域
-        egui::CentralPanel::default().show(ctx, |ui| {
-            let (response, painter) =
-                ui.allocate_painter(ui.available_size(), egui::Sense::drag());
-
-            self.update_cursor(ctx, &response);
-
-            if let Some(pointer_pos) = response.interact_pointer_pos() {
-                match self.current_tool {
-                    Tool::Brush => {
-                        if response.dragged()
-                            && self.current_line.last() != Some(&pointer_pos)
+        if self.presentation.is_none() {
+            self.handle_dropped_files(ctx);
+            #[cfg(not(target_arch = "wasm32"))]
+            self.open_handed_over_files(ctx);
+        }
+        #[cfg(target_arch = "wasm32")]
+        self.open_picked_file(ctx);
+        if ctx.input(|i| i.viewport().close_requested())
+            && !self.confirm_discard_all(ctx)
+        {
+            ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+        }
+        self.exchange_changes();
+        self.autosave(ctx);
+        self.update_recovery_snapshot(ctx);
+        self.check_external_modification(ctx);
+        if self.document.dirty != self.title_shows_dirty {
+            self.set_window_title(ctx);
+        }
+        self.show_backups_window(ctx);
+        self.show_properties_window(ctx);
+        self.show_share_window(ctx);
+        self.show_settings_window(ctx);
+        self.show_image_window(ctx);
+        self.show_points_window(ctx);
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.show_screenshot_window(ctx);
+            self.poll_capture(ctx);
+            self.show_region_picker(ctx);
+        }
+        self.show_replay_window(ctx);
+        self.show_unlock_modal(ctx);
+        self.show_passphrase_prompt(ctx);
+        if self.presentation.is_none()
+            && ctx.input(|i| !i.raw.hovered_files.is_empty())
+        {
+            draw_drop_hint(ctx);
+        }
+        // presenting shows nothing but the board
+        let editing = self.presentation.is_none();
+        let menu_bar = egui::TopBottomPanel::top("menu_bar");
+        menu_bar.show_animated(ctx, editing, |ui| {
+            egui::MenuBar::new().ui(ui, |ui| {
+                ui.menu_button("File", |ui| {
+                    if ui.button("New").clicked() {
+                        ui.close();
+                        self.new_document(ctx);
+                    }
+                    if ui.button("Open…").clicked() {
+                        ui.close();
+                        self.open(ctx);
+                    }
+                    ui.menu_button("Open Recent", |ui| {
+                        if let Some(file_path) = self.recent_files.show(ui) {
+                            ui.close();
+                            self.open_recent(ctx, file_path);
+                        }
+                    });
+                    if ui.button("Save").clicked() {
+                        ui.close();
+                        self.save(ctx);
+                    }
+                    if ui.button("Save As…").clicked() {
+                        ui.close();
+                        self.save_as(ctx);
+                    }
+                    if ui
+                        .add_enabled(
+                            self.can_switch_tabs(),
+                            egui::Button::new("Close Tab"),
+                        )
+                        .clicked()
+                    {
+                        ui.close();
+                        self.close_tab(ctx);
+                    }
+                    #[cfg(not(target_arch = "wasm32"))]
+                    {
+                        if ui.button("New Window").clicked() {
+                            ui.close();
+                            self.new_window();
+                        }
+                        if ui
+                            .add_enabled(
+                                self.can_switch_tabs(),
+                                egui::Button::new("Move to New Window"),
+                            )
+                            .clicked()
                         {
-                            self.current_line.push(pointer_pos);
+                            ui.close();
+                            self.move_to_window(ctx);
+                        }
+                    }
+                    ui.add_enabled(
+                        !self.preferences.stable_saves,
+                        egui::Checkbox::new(
+                            &mut self.preferences.full_precision,
+                            "Save full precision coordinates",
+                        ),
+                    )
+                    .on_disabled_hover_text(
+                        "Stable saves always round coordinates (Settings)",
+                    );
+                    if self.encryption.is_some() {
+                        ui.menu_button("Encryption", |ui| {
+                            if ui.button("Change passphrase…").clicked() {
+                                ui.close();
+                                self.passphrase_prompt =
+                                    Some(PassphrasePrompt::default());
+                            }
+                            if ui.button("Remove encryption").clicked() {
+                                ui.close();
+                                self.remove_encryption();
+                            }
+                        });
+                    } else if ui.button("Encrypt with passphrase…").clicked()
+                    {
+                        ui.close();
+                        self.passphrase_prompt =
+                            Some(PassphrasePrompt::default());
+                    }
+                    if ui
+                        .add_enabled(
+                            self.whiteboard_file.is_some(),
+                            egui::Button::new("Restore from backup…"),
+                        )
+                        .clicked()
+                    {
+                        ui.close();
+                        self.backups =
+                            self.whiteboard_file.as_deref().map(backup::list);
+                    }
+                    if ui.button("Document properties…").clicked() {
+                        ui.close();
+                        self.show_properties = true;
+                    }
+                    if ui.button("Settings…").clicked() {
+                        ui.close();
+                        self.show_settings = true;
+                    }
+                    // browsers can't open network connections of their own
+                    #[cfg(not(target_arch = "wasm32"))]
+                    if ui.button("Share session…").clicked() {
+                        ui.close();
+                        self.show_share_window = true;
+                    }
+                    ui.separator();
+                    if ui.button("Import whiteboard…").clicked() {
+                        ui.close();
+                        if let Err(e) = self.import_whiteboard_file() {
+                            show_read_error(&e);
+                        }
+                    }
+                    if ui.button("Import Excalidraw…").clicked() {
+                        ui.close();
+                        if let Err(e) = self.import_excalidraw_file() {
+                            show_read_error(&e);
                         }
                     }
-                    Tool::Eraser => {
-                        // 支援點擊或拖曳時刪除線條
-                        if response.clicked() || response.dragged() {
-                            self.handle_eraser(pointer_pos);
+                    ui.separator();
+                    if self.document.page_count() > 1 {
+                        ui.menu_button("Export Excalidraw", |ui| {
+                            if ui.button("Current page…").clicked() {
+                                ui.close();
+                                self.export_excalidraw_file(false);
+                            }
+                            if ui.button("All pages…").clicked() {
+                                ui.close();
+                                self.export_excalidraw_file(true);
+                            }
+                        });
+                    } else if ui.button("Export Excalidraw…").clicked() {
+                        ui.close();
+                        self.export_excalidraw_file(false);
+                    }
+                    if ui.button("Export image…").clicked() {
+                        ui.close();
+                        self.show_image_window = true;
+                    }
+                    if ui
+                        .add_enabled(
+                            !self.document.lines.is_empty(),
+                            egui::Button::new("Export SVG…"),
+                        )
+                        .clicked()
+                    {
+                        ui.close();
+                        self.export_svg(ctx);
+                    }
+                    if ui.button("Export animation…").clicked() {
+                        ui.close();
+                        self.show_replay_window = true;
+                    }
+                    if ui.button("Export data…").clicked() {
+                        ui.close();
+                        self.show_points_window = true;
+                    }
+                    #[cfg(not(target_arch = "wasm32"))]
+                    {
+                        if ui
+                            .add_enabled(
+                                !self.document.lines.is_empty(),
+                                egui::Button::new("Copy image"),
+                            )
+                            .clicked()
+                        {
+                            ui.close();
+                            self.copy_image(ctx);
                         }
+                        ui.separator();
+                        if ui.button("Annotate screenshot…").clicked() {
+                            ui.close();
+                            self.open_screenshot_window();
+                        }
+                    }
+                });
+                ui.menu_button("View", |ui| {
+                    if ui.button("Fit to content (F)").clicked() {
+                        ui.close();
+                        self.fit_to_content();
+                    }
+                    if ui
+                        .add_enabled(
+                            !self.selected_lines.is_empty(),
+                            egui::Button::new("Zoom to selection (Shift+F)"),
+                        )
+                        .clicked()
+                    {
+                        ui.close();
+                        self.zoom_to_selection();
+                    }
+                    if ui.button("Reset view (Home)").clicked() {
+                        ui.close();
+                        self.reset_view();
                     }
-                    Tool::Selection => {
-                        self.handle_selection(&response, pointer_pos)
+                    #[cfg(not(target_arch = "wasm32"))]
+                    if self.annotation.is_some()
+                        && ui.button("Stop annotating").clicked()
+                    {
+                        ui.close();
+                        self.stop_annotating(ctx);
+                    }
+                    if ui
+                        .add(egui::Button::new("Present").shortcut_text("F5"))
+                        .clicked()
+                    {
+                        ui.close();
+                        self.toggle_presentation(ctx);
+                    }
+                    ui.checkbox(&mut self.show_minimap, "Minimap");
+                    ui.checkbox(&mut self.show_crosshair, "Crosshair");
+                    if ui
+                        .add(
+                            egui::Button::new("Reset zoom")
+                                .shortcut_text("Ctrl+0"),
+                        )
+                        .clicked()
+                    {
+                        ui.close();
+                        self.reset_zoom();
+                    }
+                });
+                ui.menu_button("Page", |ui| {
+                    if ui.button("New page").clicked() {
+                        ui.close();
+                        self.add_page(Vec::new());
+                    }
+                    if ui.button("Duplicate page").clicked() {
+                        ui.close();
+                        self.add_page(self.document.lines.clone());
+                    }
+                    if ui
+                        .add_enabled(
+                            self.document.page_count() > 1,
+                            egui::Button::new("Delete page"),
+                        )
+                        .clicked()
+                    {
+                        ui.close();
+                        self.delete_page();
                     }
+                    if ui
+                        .add_enabled(
+                            self.document.lines.iter().any(|line| line.locked),
+                            egui::Button::new("Unlock all lines"),
+                        )
+                        .clicked()
+                    {
+                        ui.close();
+                        self.document
+                            .set_locked(0..self.document.lines.len(), false);
+                    }
+                });
+                ui.separator();
+                self.draw_page_switcher(ui);
+                ui.separator();
+                let zoom = format!("{:.0}%", self.camera.zoom * 100.0);
+                if ui
+                    .add(egui::Button::new(zoom).frame(false))
+                    .on_hover_text("Zoom, click to reset (Ctrl+0)")
+                    .clicked()
+                {
+                    self.reset_zoom();
                 }
-            }
-
-            // 畫筆模式下，放開拖曳時儲存線條
-            if response.drag_stopped()
-                && self.current_tool == Tool::Brush
-                && !self.current_line.is_empty()
-            {
-                self.push_line();
-            }
+                if let Some(session) = &self.session {
+                    ui.separator();
+                    let status = egui::RichText::new(format!(
+                        "● {}",
+                        session.describe()
+                    ))
+                    .color(Color32::LIGHT_GREEN);
+                    if ui
+                        .add(egui::Button::new(status).frame(false))
+                        .on_hover_text("Share session")
+                        .clicked()
+                    {
+                        self.show_share_window = true;
+                    }
+                }
+            });
+        });
+        // 設定側邊控制面板
+        let side_panel = egui::SidePanel::left("control_panel");
+        side_panel.show_animated(ctx, editing, |ui| self.show_toolbar(ui));
 
-            // 繪製所有已存檔的線條
-            for (i, line) in self.lines.iter().enumerate() {
-                self.draw_previous_lines(&painter, &i, line);
-            }
+        // above the canvas only, next to the toolbar
+        let tab_strip = egui::TopBottomPanel::top("tabs");
+        tab_strip.show_animated(ctx, editing && self.tabs.len() > 1, |ui| {
+            self.show_tab_strip(ui);
+        });
 
-            self.draw_selections(&painter);
+        // 畫布區域
+        let mut canvas_frame = egui::Frame::central_panel(&ctx.style());
+        if self.presentation.is_some() {
+            canvas_frame = canvas_frame.inner_margin(0.0);
+        }
+        let canvas = egui::CentralPanel::default().frame(canvas_frame);
+        canvas.show(ctx, |ui| self.ui(ui));
+        #[cfg(not(target_arch = "wasm32"))]
+        self.show_board_windows(ctx);
+        // last, so toasts pushed during this frame show right away
+        self.toasts.show(ctx, self.preferences.reduce_motion);
+    }
+}
 
-            // 繪製正在畫的線條（僅限畫筆模式）
-            if self.current_tool == Tool::Brush && self.current_line.len() >= 2
-            {
-                painter.add(egui::Shape::line(
-                    self.current_line.clone(),
-                    Stroke::new(
-                        self.stroke_width,
-                        self.palette.get_current_color(),
-                    ),
-                ));
-            }
-        });
+// Horizontal and vertical lines through `pos` across the whole canvas
+fn draw_crosshair(
+    painter: &Painter,
+    canvas_rect: Rect,
+    pos: Pos2,
+    color: Color32,
+) {
+    if !canvas_rect.contains(pos) {
+        return;
     }
+    let stroke = Stroke::new(1.0_f32, color);
+    painter.hline(canvas_rect.x_range(), pos.y, stroke);
+    painter.vline(pos.x, canvas_rect.y_range(), stroke);
+}
+
+fn draw_drop_hint(ctx: &egui::Context) {
+    let painter = ctx.layer_painter(egui::LayerId::new(
+        egui::Order::Foreground,
+        egui::Id::new("drop_hint"),
+    ));
+    let rect = ctx.content_rect();
+    painter.rect_filled(rect, 0.0, Color32::from_black_alpha(160));
+    painter.text(
+        rect.center(),
+        egui::Align2::CENTER_CENTER,
+        "Drop to open",
+        egui::FontId::proportional(32.0),
+        Color32::WHITE,
+    );
 }
 
 fn draw_dotted_rect(painter: &egui::Painter, rect: Rect, stroke: Stroke) {
@@ -770,3 +4906,116 @@ fn draw_dotted_rect(painter: &egui::Painter, rect: Rect, stroke: Stroke) {
     ];
     painter.add(egui::Shape::dashed_line(&points, stroke, dash_len, gap_len));
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn whiteboard_names_get_an_extension() {
+        for (name, normalized) in [
+            ("notes", "notes.wb"),
+            ("notes.txt", "notes.txt.wb"),
+            ("notes.", "notes.wb"),
+            ("dir/notes", "dir/notes.wb"),
+            ("notes.wb", "notes.wb"),
+            ("notes.WB", "notes.WB"),
+            ("notes.wbz", "notes.wbz"),
+            ("notes.wbb", "notes.wbb"),
+        ] {
+            assert_eq!(
+                normalize_extension(Path::new(name)),
+                Path::new(normalized),
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn stable_saves_of_an_unchanged_board_are_identical() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("board.wb");
+        let mut app = WhiteboardApp::default();
+        app.preferences.stable_saves = true;
+        app.preferences.backup_count = 0;
+        app.document.add_line(Line {
+            points: vec![pos2(0.1 + 0.2, 10.000_001), pos2(413.719_97, 5.0)],
+            color: Color32::from_rgba_unmultiplied(200, 40, 40, 128),
+            width: 3.0,
+            widths: Vec::new(),
+            style: LineStyle::Solid,
+            created_ms: 1_718_042_355_123,
+            fill: None,
+            fill_only: false,
+            picture: None,
+            locked: false,
+            id: new_line_id(),
+        });
+        let mut save = || {
+            let state = app.state_for_saving();
+            app.write_whiteboard(path.clone(), &state);
+            std::fs::read(&path).unwrap()
+        };
+        let first = save();
+        let second = save();
+        assert_eq!(first, second);
+        // pretty, and rounded so float noise doesn't show up in diffs
+        let json = String::from_utf8(first).unwrap();
+        assert!(json.contains("\n  \"pages\": ["));
+        assert!(json.contains("413.7,") && json.contains("10.0\n"));
+        // pretty and compact files read the same
+        let pretty = WhiteboardState::from_json(json.as_bytes()).unwrap();
+        let compact = serde_json::to_vec(&pretty).unwrap();
+        let compact = WhiteboardState::from_json(&compact).unwrap();
+        assert_eq!(
+            serde_json::to_value(&pretty).unwrap(),
+            serde_json::to_value(&compact).unwrap()
+        );
+    }
+
+    #[test]
+    fn files_hold_what_to_bytes_returns() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("board.wb");
+        let mut app = WhiteboardBuilder::new().scribbles(3, 5, 100.0).build();
+        app.preferences.backup_count = 0;
+        let state = app.state_for_saving();
+        app.write_whiteboard(path.clone(), &state);
+        let saved = std::fs::read(&path).unwrap();
+        assert_eq!(saved, app.to_bytes().unwrap());
+        let opened = WhiteboardApp::from_bytes(&saved).unwrap();
+        assert_eq!(
+            serde_json::to_value(&opened.export_state().pages).unwrap(),
+            serde_json::to_value(&state.pages).unwrap()
+        );
+        // encrypted files only open with their passphrase
+        app.encryption = Some(FileKey::derive("passphrase").unwrap());
+        app.write_whiteboard(path.clone(), &state);
+        let saved = std::fs::read(&path).unwrap();
+        assert!(matches!(
+            WhiteboardApp::from_bytes(&saved),
+            Err(LoadError::Encrypted)
+        ));
+    }
+
+    #[test]
+    fn boards_keep_a_tool_when_files_name_one_they_lack() {
+        let mut app = WhiteboardApp::default();
+        app.set_tool(Tool::ERASER);
+        for (name, known) in [("Stamp", false), ("Pin", true)] {
+            // registered with some other board, not this one
+            if known {
+                Tool::new(name).register();
+            }
+            let json = format!(
+                r#"{{"version": 3, "pages": [{{"lines": []}}],
+                "current_tool": "{name}"}}"#
+            );
+            let state = WhiteboardState::from_json(json.as_bytes()).unwrap();
+            assert_eq!(state.current_tool.is_some(), known);
+            app.load_state(state);
+            assert_eq!(app.current_tool, Tool::ERASER);
+            assert!(app.active_tool().is_some());
+        }
+    }
+}
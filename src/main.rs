@@ -1,12 +1,46 @@
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+
+#[cfg(not(target_arch = "wasm32"))]
 use eframe::icon_data::from_png_bytes;
-use egui::{IconData, Style, Visuals};
+#[cfg(not(target_arch = "wasm32"))]
+use egui::IconData;
 use whiteboard::WhiteboardApp;
+#[cfg(not(target_arch = "wasm32"))]
 fn load_icon() -> Result<IconData, String> {
     let png_bytes = include_bytes!("../assets/icon.png");
     from_png_bytes(png_bytes).map_err(|err| err.to_string())
 }
+#[cfg(not(target_arch = "wasm32"))]
 fn main() -> eframe::Result<()> {
+    let args: Vec<_> = std::env::args_os().skip(1).collect();
+    // `whiteboard render …` and other commands exit without a window
+    if let Some(status) = whiteboard::run_command(&args) {
+        std::process::exit(status);
+    }
     let icon = load_icon().expect("Failed to load icon");
+    // `whiteboard board.wb` opens the file, which is also how file managers
+    // pass the file that was double-clicked
+    // `whiteboard --annotate` captures the screen to draw over instead,
+    // `--annotate-region` picks a part of it first
+    let annotate = match args.first().and_then(|arg| arg.to_str()) {
+        Some("--annotate") => Some(false),
+        Some("--annotate-region") => Some(true),
+        _ => None,
+    };
+    let file_path = args
+        .into_iter()
+        .next()
+        .filter(|_| annotate.is_none())
+        .map(PathBuf::from);
+    if let Some(file_path) = &file_path
+        && whiteboard::open_in_running_instance(file_path)
+    {
+        return Ok(());
+    }
+    // eframe restores the size, position and maximized state of the last
+    // session on top of these defaults, moving the window back on screen if
+    // its monitor is gone
     let native_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size([1280.0, 800.0])
@@ -19,14 +53,37 @@ fn main() -> eframe::Result<()> {
         native_options,
         Box::new(|ctx| {
             egui_extras::install_image_loaders(&ctx.egui_ctx);
-            ctx.egui_ctx
-                .send_viewport_cmd(egui::ViewportCommand::Maximized(true));
-            let style = Style {
-                visuals: Visuals::dark(),
-                ..Default::default()
-            };
-            ctx.egui_ctx.set_style(style);
-            Ok(Box::new(WhiteboardApp::default()))
+            let mut app = WhiteboardApp::new(ctx, file_path);
+            if let Some(region) = annotate {
+                app.annotate_screenshot(&ctx.egui_ctx, region);
+            }
+            Ok(Box::new(app))
         }),
     )
 }
+// Runs in the canvas of index.html, built with `trunk build`
+#[cfg(target_arch = "wasm32")]
+fn main() {
+    use eframe::wasm_bindgen::JsCast as _;
+
+    wasm_bindgen_futures::spawn_local(async {
+        let canvas = web_sys::window()
+            .and_then(|window| window.document())
+            .and_then(|document| document.get_element_by_id("whiteboard"))
+            .and_then(|element| {
+                element.dyn_into::<web_sys::HtmlCanvasElement>().ok()
+            })
+            .expect("index.html has no canvas with the id whiteboard");
+        eframe::WebRunner::new()
+            .start(
+                canvas,
+                eframe::WebOptions::default(),
+                Box::new(|ctx| {
+                    egui_extras::install_image_loaders(&ctx.egui_ctx);
+                    Ok(Box::new(WhiteboardApp::new(ctx, None)))
+                }),
+            )
+            .await
+            .expect("Failed to start the whiteboard");
+    });
+}
@@ -0,0 +1,101 @@
+use eframe::egui;
+use egui::{
+    Color32, Painter, Pos2, Rect, Sense, Shape, Stroke, StrokeKind, Ui, vec2,
+};
+
+use crate::{Line, camera::Camera, lines_bounding_box};
+
+const SIZE: egui::Vec2 = vec2(200.0, 140.0);
+// distance from the bottom-right corner, clear of the scrollbars
+const OFFSET: egui::Vec2 = vec2(16.0, 16.0);
+// long strokes are drawn with every n-th point only
+const MAX_POINTS_PER_LINE: usize = 64;
+
+// A scaled-down overview of the whole board in the bottom-right corner of
+// the canvas with the visible area outlined. Pressing or dragging in it
+// centers the view on that spot.
+pub(crate) fn show(
+    ui: &mut Ui,
+    camera: &mut Camera,
+    canvas_rect: Rect,
+    lines: &[Line],
+) {
+    let rect = Rect::from_min_size(canvas_rect.max - OFFSET - SIZE, SIZE);
+    if !canvas_rect.contains_rect(rect) {
+        return;
+    }
+    let visible = camera.rect_to_world(canvas_rect);
+    let content = lines_bounding_box(lines);
+    let extent = if content.is_finite() && content.is_positive() {
+        content.expand(content.size().max_elem() * 0.05 + 20.0)
+    } else {
+        visible
+    };
+    let inner = rect.shrink(6.0);
+    let scale =
+        (inner.width() / extent.width()).min(inner.height() / extent.height());
+    if !scale.is_finite() || scale <= 0.0 {
+        return;
+    }
+    let origin = inner.center() - extent.center().to_vec2() * scale;
+    let to_map = |p: Pos2| origin + p.to_vec2() * scale;
+
+    let response =
+        ui.interact(rect, ui.id().with("minimap"), Sense::click_and_drag());
+    if (response.is_pointer_button_down_on() || response.dragged())
+        && let Some(pointer) = response.interact_pointer_pos()
+    {
+        let target = ((pointer - origin) / scale).to_pos2();
+        camera.pan =
+            canvas_rect.center().to_vec2() - target.to_vec2() * camera.zoom;
+    }
+
+    let painter = ui.painter().with_clip_rect(rect);
+    painter.rect_filled(rect, 4.0, Color32::from_black_alpha(180));
+    paint_lines(&painter, lines, to_map);
+    let viewport = Rect::from_min_max(to_map(visible.min), to_map(visible.max));
+    painter.rect_stroke(
+        viewport,
+        0.0,
+        Stroke::new(1.0_f32, Color32::LIGHT_BLUE),
+        StrokeKind::Inside,
+    );
+    painter.rect_stroke(
+        rect,
+        4.0,
+        Stroke::new(1.0_f32, Color32::from_gray(90)),
+        StrokeKind::Inside,
+    );
+}
+
+// Draws `lines` one pixel wide at the positions `to_map` gives, for
+// overviews too small to show stroke widths
+pub(crate) fn paint_lines(
+    painter: &Painter,
+    lines: &[Line],
+    to_map: impl Fn(Pos2) -> Pos2,
+) {
+    // fills would only show as their outline, with the cuts into their holes
+    for line in lines.iter().filter(|line| !line.fill_only) {
+        // pictures are too small here to make out, a box stands in for them
+        if let Some((_, rect)) = line.picture_area() {
+            painter.rect_filled(
+                Rect::from_two_pos(to_map(rect.min), to_map(rect.max)),
+                0.0,
+                Color32::from_gray(120),
+            );
+            continue;
+        }
+        let step = line.points.len().div_ceil(MAX_POINTS_PER_LINE).max(1);
+        let mut points: Vec<Pos2> = line
+            .points
+            .iter()
+            .step_by(step)
+            .map(|&p| to_map(p))
+            .collect();
+        if let Some(&last) = line.points.last() {
+            points.push(to_map(last));
+        }
+        painter.add(Shape::line(points, Stroke::new(1.0_f32, line.color)));
+    }
+}
@@ -0,0 +1,278 @@
+use std::fmt;
+
+use egui::{Color32, Pos2, pos2};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{
+    Line, MAX_STROKE_WIDTH, MIN_STROKE_WIDTH, colors, is_finite_pos,
+    new_line_id, now_ms, stroke::LineStyle,
+};
+
+// An operation log is a JSON array of entries like
+// `{"op": "draw", "points": [[0, 0], [10, 20]], "color": "#ff0000",
+// "width": 3}` or `{"op": "erase_at", "x": 10, "y": 20}`, applied in order
+// with `Document::apply_ops`
+#[derive(Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Entry {
+    Draw {
+        points: Vec<[f32; 2]>,
+        // `#rrggbb` or `#rrggbbaa`, white if missing
+        #[serde(default)]
+        color: Option<String>,
+        #[serde(default = "default_width")]
+        width: f32,
+    },
+    // what the eraser removes when pressed at `x`, `y`
+    EraseAt {
+        x: f32,
+        y: f32,
+        #[serde(default = "default_radius")]
+        radius: f32,
+    },
+    // erases every line of the shown page
+    Clear,
+    Undo,
+}
+fn default_width() -> f32 {
+    3.0
+}
+fn default_radius() -> f32 {
+    5.0
+}
+impl Entry {
+    fn into_op(self) -> Result<Op, String> {
+        match self {
+            Entry::Draw {
+                points,
+                color,
+                width,
+            } => {
+                let points: Vec<Pos2> =
+                    points.into_iter().map(|[x, y]| pos2(x, y)).collect();
+                if points.is_empty() {
+                    return Err("a stroke needs at least one point".into());
+                }
+                if !points.iter().all(|p| is_finite_pos(*p)) {
+                    return Err("points must be finite numbers".into());
+                }
+                if !(MIN_STROKE_WIDTH..=MAX_STROKE_WIDTH).contains(&width) {
+                    return Err(format!(
+                        "width must be between {MIN_STROKE_WIDTH} and \
+                         {MAX_STROKE_WIDTH}"
+                    ));
+                }
+                let color = match color {
+                    Some(color) => {
+                        colors::parse_hex(&color).ok_or_else(|| {
+                            format!("unknown color {color}, expected #rrggbb")
+                        })?
+                    }
+                    None => Color32::WHITE,
+                };
+                Ok(Op::Draw(Line {
+                    points,
+                    color,
+                    width,
+                    widths: Vec::new(),
+                    style: LineStyle::Solid,
+                    created_ms: now_ms(),
+                    fill: None,
+                    fill_only: false,
+                    picture: None,
+                    locked: false,
+                    id: new_line_id(),
+                }))
+            }
+            Entry::EraseAt { x, y, radius } => {
+                let pos = pos2(x, y);
+                if !is_finite_pos(pos) {
+                    return Err("x and y must be finite numbers".into());
+                }
+                if !(radius.is_finite() && radius >= 0.0) {
+                    return Err("radius must be a number of at least 0".into());
+                }
+                Ok(Op::EraseAt { pos, radius })
+            }
+            Entry::Clear => Ok(Op::Clear),
+            Entry::Undo => Ok(Op::Undo),
+        }
+    }
+}
+
+// An entry of the log, checked and ready to apply
+pub(crate) enum Op {
+    Draw(Line),
+    EraseAt { pos: Pos2, radius: f32 },
+    Clear,
+    Undo,
+}
+
+#[derive(Debug)]
+pub(crate) enum OpsError {
+    // not a JSON array
+    Syntax(serde_json::Error),
+    // the entry at `index`, counting from 0, isn't a known operation or has
+    // bad values
+    Entry { index: usize, message: String },
+}
+impl fmt::Display for OpsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OpsError::Syntax(e) => {
+                write!(f, "the operations are not a JSON array: {e}")
+            }
+            OpsError::Entry { index, message } => {
+                write!(f, "operation {index}: {message}")
+            }
+        }
+    }
+}
+impl std::error::Error for OpsError {}
+
+// Reads every entry of the log before any is applied, so a bad entry
+// leaves the board as it was
+pub(crate) fn parse(json: &str) -> Result<Vec<Op>, OpsError> {
+    let entries: Vec<Value> =
+        serde_json::from_str(json).map_err(OpsError::Syntax)?;
+    entries
+        .into_iter()
+        .enumerate()
+        .map(|(index, entry)| {
+            serde_json::from_value::<Entry>(entry)
+                .map_err(|e| e.to_string())
+                .and_then(Entry::into_op)
+                .map_err(|message| OpsError::Entry { index, message })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Document;
+
+    // The index of the entry `json` is rejected at
+    fn bad_entry(json: &str) -> usize {
+        match parse(json) {
+            Err(OpsError::Entry { index, .. }) => index,
+            Err(e) => panic!("expected a bad entry, got {e}"),
+            Ok(_) => panic!("expected a bad entry, got none"),
+        }
+    }
+
+    #[test]
+    fn parses_every_operation() {
+        let ops = parse(
+            r##"[
+                {"op": "draw", "points": [[0, 0], [10, 20]],
+                 "color": "#ff000080", "width": 4},
+                {"op": "draw", "points": [[5, 5]]},
+                {"op": "erase_at", "x": 10, "y": 20, "radius": 2},
+                {"op": "erase_at", "x": 1, "y": 2},
+                {"op": "clear"},
+                {"op": "undo"}
+            ]"##,
+        )
+        .unwrap();
+        assert_eq!(ops.len(), 6);
+        let Op::Draw(line) = &ops[0] else {
+            panic!("expected a stroke");
+        };
+        assert_eq!(line.points, [pos2(0.0, 0.0), pos2(10.0, 20.0)]);
+        assert_eq!(line.color, Color32::from_rgba_unmultiplied(255, 0, 0, 128));
+        assert_eq!(line.width, 4.0);
+        // missing values take their defaults
+        let Op::Draw(line) = &ops[1] else {
+            panic!("expected a stroke");
+        };
+        assert_eq!((line.color, line.width), (Color32::WHITE, 3.0));
+        assert!(matches!(ops[3], Op::EraseAt { radius: 5.0, .. }));
+        assert!(matches!((&ops[4], &ops[5]), (Op::Clear, Op::Undo)));
+    }
+
+    #[test]
+    fn rejects_malformed_logs() {
+        assert!(matches!(
+            parse("{\"op\": \"undo\"}"),
+            Err(OpsError::Syntax(_))
+        ));
+        assert!(matches!(parse("[{\"op\": "), Err(OpsError::Syntax(_))));
+        assert_eq!(bad_entry(r#"[{"op": "undo"}, {"op": "rotate"}]"#), 1);
+        assert_eq!(bad_entry(r#"[{"op": "undo"}, 3]"#), 1);
+        assert_eq!(bad_entry(r#"[{"op": "erase_at", "x": 1}]"#), 0);
+        assert_eq!(bad_entry(r#"[{"op": "draw", "points": [[1]]}]"#), 0);
+        assert_eq!(
+            bad_entry(
+                r#"[{"op": "draw", "points": [[1, 2]], "color": "red"}]"#
+            ),
+            0
+        );
+    }
+
+    #[test]
+    fn rejects_values_out_of_range() {
+        for entry in [
+            r#"{"op": "draw", "points": []}"#,
+            r#"{"op": "draw", "points": [[0, 0]], "width": 0.5}"#,
+            r#"{"op": "draw", "points": [[0, 0]], "width": 21}"#,
+            // too big for an f32
+            r#"{"op": "draw", "points": [[1e39, 0]]}"#,
+            r#"{"op": "erase_at", "x": 0, "y": -1e39}"#,
+            r#"{"op": "erase_at", "x": 0, "y": 0, "radius": -1}"#,
+        ] {
+            assert_eq!(
+                bad_entry(&format!("[{{\"op\": \"clear\"}}, {entry}]")),
+                1
+            );
+        }
+    }
+
+    #[test]
+    fn a_bad_entry_leaves_the_document_unchanged() {
+        let mut document = Document::default();
+        document
+            .apply_ops(r#"[{"op": "draw", "points": [[0, 0], [10, 0]]}]"#)
+            .unwrap();
+        document.dirty = false;
+        let before: Vec<u64> = document.lines.iter().map(|l| l.id).collect();
+        let result = document.apply_ops(
+            r#"[
+                {"op": "draw", "points": [[0, 10], [10, 10]]},
+                {"op": "clear"},
+                {"op": "erase_at", "x": 0, "y": 0, "radius": "big"},
+                {"op": "undo"}
+            ]"#,
+        );
+        assert!(matches!(result, Err(OpsError::Entry { index: 2, .. })));
+        let after: Vec<u64> = document.lines.iter().map(|l| l.id).collect();
+        assert_eq!(after, before);
+        assert!(!document.dirty);
+        // nothing new to undo either: the first stroke goes, then nothing
+        assert!(document.undo());
+        assert!(!document.undo());
+    }
+
+    #[test]
+    fn applies_operations_in_order() {
+        let mut document = Document::default();
+        let count = document
+            .apply_ops(
+                r#"[
+                    {"op": "draw", "points": [[0, 0], [10, 0]]},
+                    {"op": "draw", "points": [[0, 10], [10, 10]]},
+                    {"op": "erase_at", "x": 5, "y": 0},
+                    {"op": "undo"},
+                    {"op": "clear"}
+                ]"#,
+            )
+            .unwrap();
+        assert_eq!(count, 5);
+        assert!(document.lines.is_empty());
+        // the clear comes back a line at a time, like an erased selection
+        assert!(document.undo());
+        assert!(document.undo());
+        assert_eq!(document.lines.len(), 2);
+    }
+}
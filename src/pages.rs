@@ -0,0 +1,57 @@
+use eframe::egui;
+use egui::{Color32, Sense, Stroke, StrokeKind, Ui, Vec2, vec2};
+
+use crate::{document::Document, lines_bounding_box, minimap};
+
+const THUMBNAIL_SIZE: Vec2 = vec2(128.0, 80.0);
+const COLUMNS: usize = 4;
+
+// Every page of the document scaled down in a grid, the shown one
+// outlined. Returns the page that was clicked.
+pub(crate) fn show(ui: &mut Ui, document: &Document) -> Option<usize> {
+    let mut clicked = None;
+    egui::ScrollArea::vertical()
+        .max_height(360.0)
+        .show(ui, |ui| {
+            egui::Grid::new("page_thumbnails").show(ui, |ui| {
+                for page in 0..document.page_count() {
+                    ui.vertical_centered(|ui| {
+                        if thumbnail(ui, document, page).clicked() {
+                            clicked = Some(page);
+                        }
+                        ui.label(format!("{}", page + 1));
+                    });
+                    if (page + 1) % COLUMNS == 0 {
+                        ui.end_row();
+                    }
+                }
+            });
+        });
+    clicked
+}
+
+fn thumbnail(ui: &mut Ui, document: &Document, page: usize) -> egui::Response {
+    let (rect, response) =
+        ui.allocate_exact_size(THUMBNAIL_SIZE, Sense::click());
+    let lines = document.page_lines(page);
+    let painter = ui.painter().with_clip_rect(rect);
+    painter.rect_filled(rect, 4.0, Color32::from_black_alpha(180));
+    let content = lines_bounding_box(lines);
+    if content.is_finite() {
+        let extent = content.expand(content.size().max_elem() * 0.05 + 20.0);
+        let inner = rect.shrink(4.0);
+        let scale = (inner.width() / extent.width())
+            .min(inner.height() / extent.height());
+        let origin = inner.center() - extent.center().to_vec2() * scale;
+        minimap::paint_lines(&painter, lines, |p| origin + p.to_vec2() * scale);
+    }
+    let outline = if page == document.current_page {
+        Stroke::new(2.0_f32, ui.visuals().selection.stroke.color)
+    } else if response.hovered() {
+        ui.visuals().widgets.hovered.bg_stroke
+    } else {
+        Stroke::new(1.0_f32, Color32::from_gray(90))
+    };
+    painter.rect_stroke(rect, 4.0, outline, StrokeKind::Inside);
+    response.on_hover_text(format!("{} lines", lines.len()))
+}
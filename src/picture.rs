@@ -0,0 +1,95 @@
+use std::{fmt, io::Cursor, sync::OnceLock};
+
+use eframe::egui;
+use egui::{ColorImage, TextureHandle, TextureOptions, Vec2, vec2};
+use image::{ImageFormat, RgbaImage};
+
+// An image on the board, like a pasted screenshot. The PNG is what gets
+// saved; the pixels are decoded once for drawing.
+pub(crate) struct Picture {
+    pub png: Vec<u8>,
+    pub image: ColorImage,
+    // uploaded the first time the picture is drawn on the canvas
+    texture: OnceLock<TextureHandle>,
+}
+impl Picture {
+    // `None` if `png` isn't a readable, non-empty PNG
+    pub fn from_png(png: Vec<u8>) -> Option<Self> {
+        let rgba = image::load_from_memory_with_format(&png, ImageFormat::Png)
+            .ok()?
+            .into_rgba8();
+        Self::new(png, &rgba)
+    }
+    // Encodes 8-bit RGBA pixels, row by row, as a PNG
+    pub fn from_rgba(width: usize, height: usize, rgba: &[u8]) -> Option<Self> {
+        let rgba = RgbaImage::from_raw(
+            u32::try_from(width).ok()?,
+            u32::try_from(height).ok()?,
+            rgba.to_vec(),
+        )?;
+        let mut png = Vec::new();
+        rgba.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .ok()?;
+        Self::new(png, &rgba)
+    }
+    fn new(png: Vec<u8>, rgba: &RgbaImage) -> Option<Self> {
+        if rgba.width() == 0 || rgba.height() == 0 {
+            return None;
+        }
+        let size = [rgba.width() as usize, rgba.height() as usize];
+        Some(Self {
+            png,
+            image: ColorImage::from_rgba_unmultiplied(size, rgba.as_raw()),
+            texture: OnceLock::new(),
+        })
+    }
+    // in pixels
+    pub fn size(&self) -> Vec2 {
+        vec2(self.image.width() as f32, self.image.height() as f32)
+    }
+    pub fn texture(&self, ctx: &egui::Context) -> &TextureHandle {
+        self.texture.get_or_init(|| {
+            ctx.load_texture(
+                "picture",
+                self.image.clone(),
+                TextureOptions::LINEAR,
+            )
+        })
+    }
+}
+// Lines are printed while debugging; the pixels would drown everything else
+impl fmt::Debug for Picture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Picture({}x{})", self.image.width(), self.image.height())
+    }
+}
+
+// The image on the system clipboard, `None` if it holds text or nothing
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn from_clipboard() -> Result<Option<Picture>, String> {
+    let mut clipboard = arboard::Clipboard::new().map_err(|e| e.to_string())?;
+    match clipboard.get_image() {
+        Ok(image) => {
+            Ok(Picture::from_rgba(image.width, image.height, &image.bytes))
+        }
+        Err(arboard::Error::ContentNotAvailable) => Ok(None),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// Puts 8-bit RGBA pixels, row by row, on the system clipboard
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn to_clipboard(
+    width: usize,
+    height: usize,
+    rgba: Vec<u8>,
+) -> Result<(), String> {
+    let mut clipboard = arboard::Clipboard::new().map_err(|e| e.to_string())?;
+    clipboard
+        .set_image(arboard::ImageData {
+            width,
+            height,
+            bytes: rgba.into(),
+        })
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,132 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use eframe::egui;
+use egui::{Pos2, Rect};
+use serde::Serialize;
+
+use crate::{Line, colors};
+
+// Raw point data of the strokes for analysis elsewhere, one row per point:
+// stroke, point, x, y, color, width. Strokes are numbered by their place in
+// the drawing order of their page.
+#[derive(Clone, Copy, PartialEq, Default)]
+pub(crate) enum PointFormat {
+    #[default]
+    Csv,
+    // a flat array of objects with the same fields as the CSV columns
+    Json,
+}
+impl PointFormat {
+    // `.json` files get JSON, everything else CSV
+    pub fn from_extension(path: &Path) -> Self {
+        if path.extension().is_some_and(|ext| ext == "json") {
+            PointFormat::Json
+        } else {
+            PointFormat::Csv
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct PointSettings {
+    // only the selected strokes instead of the whole page
+    pub selection_only: bool,
+    // coordinates relative to the top left of the strokes' bounding box,
+    // and widths too, divided by its longer side, so they run from 0 to 1
+    // without stretching the strokes
+    pub normalize: bool,
+}
+
+#[derive(Serialize)]
+struct Row<'a> {
+    stroke: usize,
+    point: usize,
+    x: f32,
+    y: f32,
+    color: &'a str,
+    width: f32,
+}
+
+// Writes the points to `path`, returning the number of rows written
+pub(crate) fn export(
+    strokes: &[(usize, &Line)],
+    normalize: bool,
+    path: &Path,
+) -> io::Result<usize> {
+    let mut out = BufWriter::new(File::create(path)?);
+    let count = write(
+        strokes,
+        normalize,
+        PointFormat::from_extension(path),
+        &mut out,
+    )?;
+    out.flush()?;
+    Ok(count)
+}
+
+// Writes one row at a time, so large boards don't need the whole text in
+// memory. `strokes` are the lines with their index on the page; pictures
+// and areas filled with the fill tool aren't strokes and are left out.
+pub(crate) fn write(
+    strokes: &[(usize, &Line)],
+    normalize: bool,
+    format: PointFormat,
+    mut out: impl Write,
+) -> io::Result<usize> {
+    let strokes: Vec<(usize, &Line)> = strokes
+        .iter()
+        .copied()
+        .filter(|(_, line)| line.picture.is_none() && !line.fill_only)
+        .collect();
+    let (origin, scale) = if normalize {
+        let mut bounds = Rect::NOTHING;
+        for (_, line) in &strokes {
+            for &p in &line.points {
+                bounds.extend_with(p);
+            }
+        }
+        let side = bounds.width().max(bounds.height());
+        (bounds.min, if side > 0.0 { 1.0 / side } else { 1.0 })
+    } else {
+        (Pos2::ZERO, 1.0)
+    };
+    match format {
+        PointFormat::Csv => writeln!(out, "stroke,point,x,y,color,width")?,
+        PointFormat::Json => write!(out, "[")?,
+    }
+    let mut count = 0;
+    for (stroke, line) in strokes {
+        let color = colors::to_hex(line.color);
+        for (point, &p) in line.points.iter().enumerate() {
+            let row = Row {
+                stroke,
+                point,
+                x: (p.x - origin.x) * scale,
+                y: (p.y - origin.y) * scale,
+                color: &color,
+                width: line.widths.get(point).copied().unwrap_or(line.width)
+                    * scale,
+            };
+            match format {
+                PointFormat::Csv => writeln!(
+                    out,
+                    "{},{},{},{},{},{}",
+                    row.stroke, row.point, row.x, row.y, row.color, row.width
+                )?,
+                PointFormat::Json => {
+                    out.write_all(if count == 0 { b"\n" } else { b",\n" })?;
+                    serde_json::to_writer(&mut out, &row)?;
+                }
+            }
+            count += 1;
+        }
+    }
+    if format == PointFormat::Json {
+        writeln!(out, "\n]")?;
+    }
+    Ok(count)
+}
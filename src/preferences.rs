@@ -0,0 +1,212 @@
+use std::{fs, io, path::PathBuf};
+
+use directories::ProjectDirs;
+use eframe::egui;
+use egui::ThemePreference;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    MAX_STROKE_WIDTH, MIN_STROKE_WIDTH, atomic,
+    colors::{ColorPalette, parse_hex, to_hex},
+};
+
+const PREFERENCES_FILE_NAME: &str = "preferences.json";
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Theme {
+    // follow the operating system
+    System,
+    #[default]
+    Dark,
+    Light,
+}
+pub(crate) const THEMES: [(Theme, &str); 3] = [
+    (Theme::System, "System"),
+    (Theme::Dark, "Dark"),
+    (Theme::Light, "Light"),
+];
+impl Theme {
+    pub fn to_egui(self) -> ThemePreference {
+        match self {
+            Theme::System => ThemePreference::System,
+            Theme::Dark => ThemePreference::Dark,
+            Theme::Light => ThemePreference::Light,
+        }
+    }
+}
+
+// User-level settings shared by every document
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct Preferences {
+    pub theme: Theme,
+    // brush width of new windows
+    pub stroke_width: f32,
+    // palette of new documents, as hex colors
+    pub palette: Vec<String>,
+    // how far past its width the eraser reaches, in screen px
+    pub eraser_size: f32,
+    pub autosave_enabled: bool,
+    pub autosave_interval_secs: u32,
+    // copies of the previous file content kept on every save
+    pub backup_count: usize,
+    // indented JSON with rounded coordinates, so unchanged documents save
+    // to the same bytes and edits make small diffs
+    pub stable_saves: bool,
+    // save coordinates without rounding them to 0.1px, unless saves are
+    // stable
+    pub full_precision: bool,
+    // a small preview of the first page in saved files, for Open Recent
+    // and file browsers
+    pub embed_thumbnails: bool,
+    // restore the last session's window instead of always maximizing
+    pub remember_window: bool,
+    // zoom the board to fit the screen when presenting
+    pub present_fit: bool,
+    // no fades or animations; temporary things just disappear
+    pub reduce_motion: bool,
+    // files opened while the app is running open in its window instead of
+    // a new one
+    pub single_instance: bool,
+}
+impl Default for Preferences {
+    fn default() -> Self {
+        Self {
+            theme: Theme::default(),
+            stroke_width: 3.0,
+            palette: palette_hex(&ColorPalette::default()),
+            eraser_size: 5.0,
+            autosave_enabled: true,
+            autosave_interval_secs: 60,
+            backup_count: 3,
+            stable_saves: false,
+            full_precision: false,
+            embed_thumbnails: true,
+            remember_window: true,
+            present_fit: true,
+            reduce_motion: false,
+            single_instance: true,
+        }
+    }
+}
+
+fn preferences_path() -> Option<PathBuf> {
+    ProjectDirs::from("", "", "Simple Whiteboard")
+        .map(|dirs| dirs.config_dir().join(PREFERENCES_FILE_NAME))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn read_saved() -> Option<Vec<u8>> {
+    fs::read(preferences_path()?).ok()
+}
+#[cfg(target_arch = "wasm32")]
+fn read_saved() -> Option<Vec<u8>> {
+    crate::web::read(PREFERENCES_FILE_NAME).map(String::into_bytes)
+}
+
+fn palette_hex(palette: &ColorPalette) -> Vec<String> {
+    palette
+        .get_palette_vec()
+        .iter()
+        .map(|&color| to_hex(color))
+        .collect()
+}
+
+impl Preferences {
+    // The saved preferences. Missing, unreadable or out of range values
+    // fall back to their defaults one by one, so a typo in a hand-edited
+    // file doesn't reset everything else.
+    pub fn load() -> Self {
+        let Some(value) =
+            read_saved().and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        else {
+            return Self::default();
+        };
+        let mut preferences = Self::from_value(value);
+        preferences.sanitize();
+        preferences
+    }
+    fn from_value(value: Value) -> Self {
+        let (Value::Object(saved), Ok(Value::Object(mut merged))) =
+            (value, serde_json::to_value(Self::default()))
+        else {
+            return Self::default();
+        };
+        // take the saved values one at a time, keeping each only if the
+        // result still parses
+        for (key, value) in saved {
+            let Some(old) = merged.get(&key).cloned() else {
+                continue;
+            };
+            merged.insert(key.clone(), value);
+            if serde_json::from_value::<Self>(Value::Object(merged.clone()))
+                .is_err()
+            {
+                merged.insert(key, old);
+            }
+        }
+        serde_json::from_value(Value::Object(merged)).unwrap_or_default()
+    }
+    fn sanitize(&mut self) {
+        let default = Self::default();
+        if !(MIN_STROKE_WIDTH..=MAX_STROKE_WIDTH).contains(&self.stroke_width) {
+            self.stroke_width = default.stroke_width;
+        }
+        if !(0.0..=50.0).contains(&self.eraser_size) {
+            self.eraser_size = default.eraser_size;
+        }
+        self.autosave_interval_secs =
+            self.autosave_interval_secs.clamp(5, 3600);
+        self.backup_count = self.backup_count.min(20);
+        if self.palette.iter().all(|hex| parse_hex(hex).is_none()) {
+            self.palette = default.palette;
+        }
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn store(&self) -> io::Result<()> {
+        let path = preferences_path()
+            .ok_or_else(|| io::Error::other("no config directory available"))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        atomic::write(&path, &serde_json::to_vec_pretty(self)?)
+    }
+    // The browser keeps them in its storage
+    #[cfg(target_arch = "wasm32")]
+    pub fn store(&self) -> io::Result<()> {
+        crate::web::write(PREFERENCES_FILE_NAME, &serde_json::to_string(self)?)
+    }
+    // Invalid colors are skipped
+    pub fn palette(&self) -> ColorPalette {
+        let colors: Vec<_> = self
+            .palette
+            .iter()
+            .filter_map(|hex| parse_hex(hex))
+            .collect();
+        if colors.is_empty() {
+            ColorPalette::default()
+        } else {
+            colors.into()
+        }
+    }
+    pub fn set_palette(&mut self, palette: &ColorPalette) {
+        self.palette = palette_hex(palette);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_precision_is_remembered() {
+        // written before the setting existed
+        let old = serde_json::json!({"theme": "light", "stable_saves": true});
+        let preferences = Preferences::from_value(old);
+        assert!(!preferences.full_precision && preferences.stable_saves);
+        let saved = serde_json::json!({"full_precision": true});
+        assert!(Preferences::from_value(saved).full_precision);
+    }
+}
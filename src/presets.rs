@@ -0,0 +1,93 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use directories::ProjectDirs;
+use egui::Color32;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    atomic,
+    colors::{ColorPalette, parse_hex, to_hex},
+};
+
+const PRESETS_FILE_NAME: &str = "palettes.json";
+
+// A named palette, stored with hex colors so exported files are easy to
+// read and edit by hand
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct PalettePreset {
+    pub name: String,
+    pub colors: Vec<String>,
+}
+impl PalettePreset {
+    pub fn new(name: &str, palette: &ColorPalette) -> Self {
+        Self {
+            name: name.to_owned(),
+            colors: palette
+                .get_palette_vec()
+                .iter()
+                .map(|&color| to_hex(color))
+                .collect(),
+        }
+    }
+    // Invalid entries are skipped; `None` if no color is left
+    pub fn to_palette(&self) -> Option<ColorPalette> {
+        let colors: Vec<Color32> = self
+            .colors
+            .iter()
+            .filter_map(|hex| parse_hex(hex))
+            .collect();
+        (!colors.is_empty()).then(|| colors.into())
+    }
+}
+
+pub(crate) fn builtin() -> Vec<PalettePreset> {
+    let preset = |name: &str, colors: &[&str]| PalettePreset {
+        name: name.to_owned(),
+        colors: colors.iter().map(|&hex| hex.to_owned()).collect(),
+    };
+    vec![
+        preset(
+            "Grayscale",
+            &["#ffffff", "#c0c0c0", "#808080", "#404040", "#000000"],
+        ),
+        preset(
+            "Pastel",
+            &["#ffb3ba", "#ffdfba", "#ffffba", "#baffc9", "#bae1ff"],
+        ),
+    ]
+}
+
+fn presets_path() -> Option<PathBuf> {
+    ProjectDirs::from("", "", "Simple Whiteboard")
+        .map(|dirs| dirs.config_dir().join(PRESETS_FILE_NAME))
+}
+
+// The presets saved by the user, empty if there are none or the file is
+// unreadable
+pub(crate) fn load_saved() -> Vec<PalettePreset> {
+    presets_path()
+        .and_then(|path| fs::read(path).ok())
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+pub(crate) fn store(presets: &[PalettePreset]) -> io::Result<()> {
+    let path = presets_path()
+        .ok_or_else(|| io::Error::other("no config directory available"))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    atomic::write(&path, &serde_json::to_vec_pretty(presets)?)
+}
+
+pub(crate) fn import(path: &Path) -> io::Result<PalettePreset> {
+    let bytes = fs::read(path)?;
+    Ok(serde_json::from_slice(&bytes)?)
+}
+
+pub(crate) fn export(path: &Path, preset: &PalettePreset) -> io::Result<()> {
+    fs::write(path, serde_json::to_vec_pretty(preset)?)
+}
@@ -0,0 +1,128 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use directories::ProjectDirs;
+use eframe::egui;
+use egui::{Ui, vec2};
+
+use crate::{atomic, format};
+
+const RECENT_FILE_NAME: &str = "recent.json";
+const MAX_RECENT_FILES: usize = 10;
+
+// A decoded thumbnail and the URI it's cached under in egui. The URI changes
+// whenever the thumbnail does, so egui doesn't keep showing the old one.
+type Thumbnail = (String, Arc<[u8]>);
+
+// Recently opened or saved whiteboard files, newest first, kept across
+// sessions
+#[derive(Default)]
+pub(crate) struct RecentFiles {
+    paths: Vec<PathBuf>,
+    // read from the files the first time the list is shown
+    thumbnails: HashMap<PathBuf, Option<Thumbnail>>,
+    revision: u64,
+}
+
+fn recent_path() -> Option<PathBuf> {
+    ProjectDirs::from("", "", "Simple Whiteboard")
+        .map(|dirs| dirs.data_dir().join(RECENT_FILE_NAME))
+}
+
+impl RecentFiles {
+    pub fn load() -> Self {
+        let paths = recent_path()
+            .and_then(|path| fs::read(path).ok())
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+        Self {
+            paths,
+            ..Default::default()
+        }
+    }
+    fn store(&self) -> io::Result<()> {
+        let path = recent_path()
+            .ok_or_else(|| io::Error::other("no data directory available"))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        atomic::write(&path, &serde_json::to_vec(&self.paths)?)
+    }
+    fn save(&self) {
+        if let Err(e) = self.store() {
+            eprintln!("failed to save the recent files: {e}");
+        }
+    }
+    // Moves `path` to the top of the list with its current thumbnail
+    pub fn add(&mut self, path: &Path, thumbnail: Option<Vec<u8>>) {
+        self.paths.retain(|p| p != path);
+        self.paths.insert(0, path.to_path_buf());
+        self.paths.truncate(MAX_RECENT_FILES);
+        self.revision += 1;
+        let thumbnail = thumbnail.map(|png| self.thumbnail(path, png));
+        self.thumbnails.insert(path.to_path_buf(), thumbnail);
+        self.save();
+    }
+    pub fn remove(&mut self, path: &Path) {
+        self.paths.retain(|p| p != path);
+        self.thumbnails.remove(path);
+        self.save();
+    }
+    fn thumbnail(&self, path: &Path, png: Vec<u8>) -> Thumbnail {
+        let uri =
+            format!("bytes://recent/{}/{}.png", self.revision, path.display());
+        (uri, png.into())
+    }
+    // The menu entries; returns the file that was clicked
+    pub fn show(&mut self, ui: &mut Ui) -> Option<PathBuf> {
+        if self.paths.is_empty() {
+            ui.label("No recent files");
+            return None;
+        }
+        let mut clicked = None;
+        for path in &self.paths {
+            if !self.thumbnails.contains_key(path) {
+                self.revision += 1;
+                let thumbnail =
+                    read_thumbnail(path).map(|png| self.thumbnail(path, png));
+                self.thumbnails.insert(path.clone(), thumbnail);
+            }
+            let name = path.file_name().map_or_else(
+                || path.display().to_string(),
+                |name| name.to_string_lossy().into_owned(),
+            );
+            let button = match &self.thumbnails[path] {
+                Some((uri, png)) => egui::Button::image_and_text(
+                    egui::Image::from_bytes(uri.clone(), png.clone())
+                        .max_size(vec2(64.0, 48.0)),
+                    name,
+                ),
+                None => egui::Button::new(name),
+            };
+            if ui
+                .add(button)
+                .on_hover_text(path.display().to_string())
+                .clicked()
+            {
+                clicked = Some(path.clone());
+            }
+        }
+        ui.separator();
+        if ui.button("Clear recent files").clicked() {
+            self.paths.clear();
+            self.thumbnails.clear();
+            self.save();
+        }
+        clicked
+    }
+}
+
+// Files without a thumbnail or that fail to load just show their name
+fn read_thumbnail(path: &Path) -> Option<Vec<u8>> {
+    let bytes = fs::read(path).ok()?;
+    format::decode(&bytes).ok()?.thumbnail_png()
+}
@@ -0,0 +1,140 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        LazyLock,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use chacha20poly1305::aead::{OsRng, rand_core::RngCore};
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{atomic, state::WhiteboardState};
+
+const RECOVERY_DIR_NAME: &str = "recovery";
+
+// A snapshot of a board written periodically while the app runs and
+// removed on a clean exit, so finding one on startup means the previous
+// session did not shut down cleanly. Every open board has its own.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct RecoverySnapshot {
+    pub whiteboard_file: Option<PathBuf>,
+    // kept as a raw value so it goes through the normal migrations on load
+    pub state: Value,
+}
+
+// Which board a snapshot belongs to. Random to start with, so boards of
+// an earlier session or another instance don't take each other's file.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) struct SnapshotId(u64);
+impl Default for SnapshotId {
+    fn default() -> Self {
+        static NEXT_ID: LazyLock<AtomicU64> =
+            LazyLock::new(|| AtomicU64::new(OsRng.next_u64()));
+        Self(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+fn recovery_dir() -> Option<PathBuf> {
+    ProjectDirs::from("", "", "Simple Whiteboard")
+        .map(|dirs| dirs.data_dir().join(RECOVERY_DIR_NAME))
+}
+
+fn snapshot_path(dir: &Path, id: SnapshotId) -> PathBuf {
+    dir.join(format!("{:016x}.json", id.0))
+}
+
+pub(crate) fn write_snapshot(
+    id: SnapshotId,
+    whiteboard_file: Option<&Path>,
+    state: &WhiteboardState,
+) -> io::Result<()> {
+    let dir = recovery_dir()
+        .ok_or_else(|| io::Error::other("no data directory available"))?;
+    write_snapshot_in(&dir, id, whiteboard_file, state)
+}
+
+fn write_snapshot_in(
+    dir: &Path,
+    id: SnapshotId,
+    whiteboard_file: Option<&Path>,
+    state: &WhiteboardState,
+) -> io::Result<()> {
+    let snapshot = RecoverySnapshot {
+        whiteboard_file: whiteboard_file.map(Path::to_path_buf),
+        state: serde_json::to_value(state)?,
+    };
+    let json = serde_json::to_vec(&snapshot)?;
+    fs::create_dir_all(dir)?;
+    atomic::write(&snapshot_path(dir, id), &json)
+}
+
+// Every snapshot left behind, with the board each belongs to
+pub(crate) fn read_snapshots() -> Vec<(SnapshotId, RecoverySnapshot)> {
+    recovery_dir().map_or_else(Vec::new, |dir| read_snapshots_in(&dir))
+}
+
+fn read_snapshots_in(dir: &Path) -> Vec<(SnapshotId, RecoverySnapshot)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut snapshots: Vec<_> = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != "json" {
+                return None;
+            }
+            let stem = path.file_stem()?.to_str()?;
+            let id = SnapshotId(u64::from_str_radix(stem, 16).ok()?);
+            let bytes = fs::read(&path).ok()?;
+            Some((id, serde_json::from_slice(&bytes).ok()?))
+        })
+        .collect();
+    snapshots.sort_by_key(|(id, _)| id.0);
+    snapshots
+}
+
+// Removes the snapshot of the board `id`, once it was saved or closed
+pub(crate) fn remove_snapshot(id: SnapshotId) {
+    if let Some(dir) = recovery_dir() {
+        remove_snapshot_in(&dir, id);
+    }
+}
+
+fn remove_snapshot_in(dir: &Path, id: SnapshotId) {
+    let path = snapshot_path(dir, id);
+    if let Err(e) = fs::remove_file(&path)
+        && e.kind() != io::ErrorKind::NotFound
+    {
+        eprintln!("failed to remove {}: {e}", path.display());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Document;
+
+    #[test]
+    fn each_board_keeps_its_own_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b) = (SnapshotId::default(), SnapshotId::default());
+        let state =
+            WhiteboardState::from_document(&Document::default(), true, false);
+        write_snapshot_in(dir.path(), a, None, &state).unwrap();
+        write_snapshot_in(dir.path(), b, Some(Path::new("b.wb")), &state)
+            .unwrap();
+        assert_eq!(read_snapshots_in(dir.path()).len(), 2);
+        remove_snapshot_in(dir.path(), a);
+        let left = read_snapshots_in(dir.path());
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0].0, b);
+        assert_eq!(
+            left[0].1.whiteboard_file.as_deref(),
+            Some(Path::new("b.wb"))
+        );
+    }
+}
@@ -0,0 +1,329 @@
+use std::{fs, io, io::Cursor, path::Path};
+
+use eframe::egui;
+use egui::{Color32, Pos2, Rect, vec2};
+use image::RgbaImage;
+use tiny_skia::{
+    FillRule, FilterQuality, IntSize, LineCap, LineJoin, Paint, PathBuilder,
+    Pixmap, PixmapPaint, Stroke, StrokeDash, Transform,
+};
+
+use crate::{
+    Line, document::Document, lines_bounding_box, picture::Picture,
+    state::WhiteboardState, stroke::LineStyle,
+};
+
+// the dark theme's panel color, so thumbnails and replays look like the
+// default canvas
+pub(crate) const BACKGROUND: Color32 = Color32::from_gray(27);
+const THUMBNAIL_SIZE: f32 = 256.0;
+// room around the content so strokes at the edge aren't cut in half
+pub(crate) const MARGIN: f32 = 10.0;
+// space between pages when all pages are exported together
+const PAGE_GAP: f32 = 100.0;
+
+// Rasterizes the `region` of the board (in board coordinates) at `scale`
+// pixels per unit onto an opaque `background`. Returns `None` if the image
+// would be empty or too large.
+pub(crate) fn render(
+    lines: &[Line],
+    region: Rect,
+    scale: f32,
+    background: Color32,
+) -> Option<Pixmap> {
+    let mut pixmap = blank(region, scale)?;
+    pixmap.fill(to_skia(background.to_opaque()));
+    draw_lines(&mut pixmap, lines, region, scale);
+    Some(pixmap)
+}
+
+// Like `render`, on a transparent background
+pub(crate) fn render_strokes(
+    lines: &[Line],
+    region: Rect,
+    scale: f32,
+) -> Option<Pixmap> {
+    let mut pixmap = blank(region, scale)?;
+    draw_lines(&mut pixmap, lines, region, scale);
+    Some(pixmap)
+}
+
+fn blank(region: Rect, scale: f32) -> Option<Pixmap> {
+    let size = region.size() * scale;
+    if !size.x.is_finite() || !size.y.is_finite() {
+        return None;
+    }
+    Pixmap::new(size.x.ceil() as u32, size.y.ceil() as u32)
+}
+
+fn draw_lines(pixmap: &mut Pixmap, lines: &[Line], region: Rect, scale: f32) {
+    let transform = Transform::from_row(
+        scale,
+        0.0,
+        0.0,
+        scale,
+        -region.min.x * scale,
+        -region.min.y * scale,
+    );
+    // bucket fills lie beneath every stroke, like on the canvas
+    let (fills, strokes): (Vec<&Line>, Vec<&Line>) =
+        lines.iter().partition(|line| line.fill_only);
+    for line in fills.into_iter().chain(strokes) {
+        draw_line(pixmap, line, transform);
+    }
+}
+
+// A PNG preview of the whole board fitting in 256x256, `None` for an empty
+// board
+pub(crate) fn thumbnail(lines: &[Line]) -> Option<Vec<u8>> {
+    let content = lines_bounding_box(lines);
+    if !content.is_finite() {
+        return None;
+    }
+    let region = content.expand(MARGIN);
+    let scale =
+        (THUMBNAIL_SIZE / region.width()).min(THUMBNAIL_SIZE / region.height());
+    let pixmap = render(lines, region, scale, BACKGROUND)?;
+    encode_png(&pixmap).ok()
+}
+
+// Rasterizes the page `state` opens on without a window, e.g. for thumbnails
+// on a server: the content with a margin around it, at `scale` pixels per
+// board unit, on the default canvas background. An empty board, or one too
+// large to rasterize, gives a 0x0 image.
+pub fn render_to_image(state: &WhiteboardState, scale: f32) -> RgbaImage {
+    let lines = Document::from_state(state).export_lines(false);
+    let content = lines_bounding_box(&lines);
+    if !content.is_finite() {
+        return RgbaImage::new(0, 0);
+    }
+    render(&lines, content.expand(MARGIN), scale, BACKGROUND)
+        .map_or_else(|| RgbaImage::new(0, 0), |pixmap| to_image(&pixmap))
+}
+
+pub(crate) struct ImageSettings {
+    // pixels per board unit
+    pub scale: f32,
+    pub jpeg_quality: u8,
+    // export every page instead of just the current one
+    pub all_pages: bool,
+}
+impl Default for ImageSettings {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            jpeg_quality: 90,
+            all_pages: false,
+        }
+    }
+}
+
+// Lays pages out side by side from left to right, `PAGE_GAP` apart; empty
+// pages take no room
+pub(crate) fn side_by_side<'a>(
+    pages: impl IntoIterator<Item = &'a [Line]>,
+) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut next_x: Option<f32> = None;
+    for page_lines in pages {
+        let bbox = lines_bounding_box(page_lines);
+        if !bbox.is_finite() {
+            continue;
+        }
+        let offset = vec2(next_x.map_or(0.0, |x| x - bbox.min.x), 0.0);
+        next_x = Some(bbox.max.x + offset.x + PAGE_GAP);
+        lines.extend(page_lines.iter().map(|line| {
+            let mut line = line.clone();
+            line.points.iter_mut().for_each(|p| *p += offset);
+            line
+        }));
+    }
+    lines
+}
+
+pub(crate) fn is_jpeg(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| {
+        ext.eq_ignore_ascii_case("jpg") || ext.eq_ignore_ascii_case("jpeg")
+    })
+}
+
+// Writes the whole board as a PNG, or a JPEG if `path` ends in .jpg or
+// .jpeg. Without a `background` the PNG is transparent around the strokes;
+// JPEGs always need one.
+pub(crate) fn export_image(
+    lines: &[Line],
+    settings: &ImageSettings,
+    background: Option<Color32>,
+    path: &Path,
+) -> io::Result<()> {
+    let content = lines_bounding_box(lines);
+    if !content.is_finite() {
+        return Err(io::Error::other("the board is empty"));
+    }
+    let is_jpeg = is_jpeg(path);
+    if is_jpeg && background.is_none() {
+        return Err(io::Error::other("JPEG images can't be transparent"));
+    }
+    let region = content.expand(MARGIN);
+    let pixmap = match background {
+        Some(background) => render(lines, region, settings.scale, background),
+        None => render_strokes(lines, region, settings.scale),
+    }
+    .ok_or_else(|| io::Error::other("the image would be too large"))?;
+    let bytes = if is_jpeg {
+        encode_jpeg(&pixmap, settings.jpeg_quality)
+    } else {
+        encode_png(&pixmap)
+    }
+    .map_err(io::Error::other)?;
+    fs::write(path, bytes)
+}
+
+// The pixels as straight (not premultiplied) RGBA
+pub(crate) fn to_rgba(pixmap: &Pixmap) -> Vec<u8> {
+    pixmap
+        .pixels()
+        .iter()
+        .flat_map(|p| {
+            let c = p.demultiply();
+            [c.red(), c.green(), c.blue(), c.alpha()]
+        })
+        .collect()
+}
+
+fn to_image(pixmap: &Pixmap) -> RgbaImage {
+    RgbaImage::from_raw(pixmap.width(), pixmap.height(), to_rgba(pixmap))
+        .expect("buffer matches the pixmap size")
+}
+
+pub(crate) fn encode_png(pixmap: &Pixmap) -> image::ImageResult<Vec<u8>> {
+    let image = to_image(pixmap);
+    let mut png = Vec::new();
+    image.write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)?;
+    Ok(png)
+}
+
+// JPEG has no alpha channel; the pixmap is opaque since `render` fills it
+// with the background first
+pub(crate) fn encode_jpeg(
+    pixmap: &Pixmap,
+    quality: u8,
+) -> image::ImageResult<Vec<u8>> {
+    let rgb = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|p| {
+            let c = p.demultiply();
+            [c.red(), c.green(), c.blue()]
+        })
+        .collect();
+    let image = image::RgbImage::from_raw(pixmap.width(), pixmap.height(), rgb)
+        .expect("buffer matches the pixmap size");
+    let mut jpeg = Vec::new();
+    image::codecs::jpeg::JpegEncoder::new_with_quality(
+        &mut jpeg,
+        quality.clamp(1, 100),
+    )
+    .encode_image(&image)?;
+    Ok(jpeg)
+}
+
+fn draw_picture(
+    pixmap: &mut Pixmap,
+    picture: &Picture,
+    rect: Rect,
+    transform: Transform,
+) {
+    let size = picture.size();
+    // egui's colors are premultiplied like tiny-skia's pixels
+    let pixels = picture
+        .image
+        .pixels
+        .iter()
+        .flat_map(|color| color.to_array())
+        .collect();
+    let Some(source) = IntSize::from_wh(size.x as u32, size.y as u32)
+        .and_then(|int_size| Pixmap::from_vec(pixels, int_size))
+    else {
+        return;
+    };
+    let paint = PixmapPaint {
+        quality: FilterQuality::Bilinear,
+        ..Default::default()
+    };
+    let transform = transform
+        .pre_translate(rect.min.x, rect.min.y)
+        .pre_scale(rect.width() / size.x, rect.height() / size.y);
+    pixmap.draw_pixmap(0, 0, source.as_ref(), &paint, transform, None);
+}
+
+fn draw_line(pixmap: &mut Pixmap, line: &Line, transform: Transform) {
+    if let Some((picture, rect)) = line.picture_area() {
+        draw_picture(pixmap, picture, rect, transform);
+        return;
+    }
+    if let Some(fill) = line.fill
+        && line.is_closed()
+        && let Some(path) = polyline(&line.points)
+    {
+        let mut paint = Paint::default();
+        paint.set_color(to_skia(fill));
+        pixmap.fill_path(&path, &paint, FillRule::Winding, transform, None);
+    }
+    if line.fill_only {
+        return;
+    }
+    let mut paint = Paint::default();
+    paint.set_color(to_skia(line.color));
+    let w = line.width;
+    // same dash and dot spacing as `stroke::shapes`
+    let dash = match line.style {
+        LineStyle::Solid => None,
+        LineStyle::Dashed => {
+            StrokeDash::new(vec![w * 3.0 + 6.0, w * 2.0 + 4.0], 0.0)
+        }
+        // zero-length dashes with round caps are dots
+        LineStyle::Dotted => StrokeDash::new(vec![0.0, w * 2.0 + 2.0], 0.0),
+    };
+    let stroke = Stroke {
+        width: w,
+        line_cap: LineCap::Round,
+        line_join: LineJoin::Round,
+        dash,
+        ..Default::default()
+    };
+    let uniform =
+        line.widths.len() != line.points.len() || !line.style.is_solid();
+    if uniform {
+        if let Some(path) = polyline(&line.points) {
+            pixmap.stroke_path(&path, &paint, &stroke, transform, None);
+        }
+        return;
+    }
+    // pressure sensitive strokes go segment by segment, each as wide as
+    // the average of its ends
+    for (points, widths) in line.points.windows(2).zip(line.widths.windows(2)) {
+        let stroke = Stroke {
+            width: (widths[0] + widths[1]) / 2.0,
+            ..stroke.clone()
+        };
+        if let Some(path) = polyline(points) {
+            pixmap.stroke_path(&path, &paint, &stroke, transform, None);
+        }
+    }
+}
+
+fn polyline(points: &[Pos2]) -> Option<tiny_skia::Path> {
+    let (first, rest) = points.split_first()?;
+    let mut builder = PathBuilder::new();
+    builder.move_to(first.x, first.y);
+    for p in rest {
+        builder.line_to(p.x, p.y);
+    }
+    builder.finish()
+}
+
+fn to_skia(color: Color32) -> tiny_skia::Color {
+    let [r, g, b, a] = color.to_srgba_unmultiplied();
+    tiny_skia::Color::from_rgba8(r, g, b, a)
+}
@@ -0,0 +1,166 @@
+use std::{
+    fs, io,
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread::{self, JoinHandle},
+};
+
+use crate::{Line, lines_bounding_box, render};
+
+// longer replays drop intermediate steps rather than grow without bound
+pub(crate) const MAX_FRAMES: usize = 300;
+// long strokes are revealed this many points per frame at most
+const POINTS_PER_FRAME: usize = 32;
+// the finished drawing stays on screen this long before the replay loops
+const FINAL_FRAME_MS: u16 = 2000;
+const MAX_HEIGHT: u32 = 4096;
+
+pub(crate) struct ReplaySettings {
+    pub frame_ms: u16,
+    pub width: u32,
+    // replay every page instead of just the current one
+    pub all_pages: bool,
+}
+impl Default for ReplaySettings {
+    fn default() -> Self {
+        Self {
+            frame_ms: 50,
+            width: 800,
+            all_pages: false,
+        }
+    }
+}
+
+// One frame of the replay: every stroke before `line` in full plus the
+// first `points` points of `line`
+#[derive(Clone, Copy)]
+struct Step {
+    line: usize,
+    points: usize,
+}
+
+// An animated PNG being rendered on a background thread
+pub(crate) struct ReplayExport {
+    pub path: PathBuf,
+    rendered: Arc<AtomicUsize>,
+    total: usize,
+    handle: Option<JoinHandle<io::Result<()>>>,
+}
+impl ReplayExport {
+    pub fn start(
+        mut lines: Vec<Line>,
+        settings: &ReplaySettings,
+        path: PathBuf,
+    ) -> Self {
+        // the board order is the drawing order unless strokes were moved in
+        // z-order, which the timestamps undo when every stroke has one
+        if lines.iter().all(|line| line.created_ms > 0) {
+            lines.sort_by_key(|line| line.created_ms);
+        }
+        let steps = plan(&lines);
+        let rendered = Arc::new(AtomicUsize::new(0));
+        let total = steps.len();
+        let frame_ms = settings.frame_ms.max(1);
+        let width = settings.width.max(1);
+        let handle = {
+            let rendered = Arc::clone(&rendered);
+            let path = path.clone();
+            thread::spawn(move || {
+                let apng = encode(&lines, &steps, frame_ms, width, &rendered)?;
+                fs::write(path, apng)
+            })
+        };
+        Self {
+            path,
+            rendered,
+            total,
+            handle: Some(handle),
+        }
+    }
+    pub fn progress(&self) -> f32 {
+        self.rendered.load(Ordering::Relaxed) as f32 / self.total.max(1) as f32
+    }
+    // The result once the export has finished
+    pub fn poll(&mut self) -> Option<io::Result<()>> {
+        if !self.handle.as_ref()?.is_finished() {
+            return None;
+        }
+        let handle = self.handle.take()?;
+        Some(handle.join().unwrap_or_else(|_| {
+            Err(io::Error::other("the export thread panicked"))
+        }))
+    }
+}
+
+fn plan(lines: &[Line]) -> Vec<Step> {
+    let total_points: usize = lines.iter().map(|line| line.points.len()).sum();
+    let chunk = POINTS_PER_FRAME.max(total_points.div_ceil(MAX_FRAMES));
+    let mut steps = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        let mut points = 0;
+        while points < line.points.len() {
+            points = (points + chunk).min(line.points.len());
+            steps.push(Step { line: i, points });
+        }
+    }
+    // many short strokes can still make too many frames; keep every n-th
+    // one and always the finished drawing
+    let stride = steps.len().div_ceil(MAX_FRAMES).max(1);
+    if stride > 1 {
+        let last = steps.last().copied();
+        steps = steps.into_iter().step_by(stride).collect();
+        if let Some(last) = last {
+            steps.pop();
+            steps.push(last);
+        }
+    }
+    steps
+}
+
+fn encode(
+    lines: &[Line],
+    steps: &[Step],
+    frame_ms: u16,
+    width: u32,
+    rendered: &AtomicUsize,
+) -> io::Result<Vec<u8>> {
+    let content = lines_bounding_box(lines);
+    if steps.is_empty() || !content.is_finite() {
+        return Err(io::Error::other("the board is empty"));
+    }
+    let region = content.expand(render::MARGIN);
+    let scale = (width as f32 / region.width())
+        .min(MAX_HEIGHT as f32 / region.height());
+    let size = region.size() * scale;
+    let mut apng = Vec::new();
+    let mut encoder = png::Encoder::new(
+        &mut apng,
+        size.x.ceil() as u32,
+        size.y.ceil() as u32,
+    );
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(steps.len() as u32, 0)?;
+    encoder.set_frame_delay(frame_ms, 1000)?;
+    let mut writer = encoder.write_header()?;
+    for (i, step) in steps.iter().enumerate() {
+        let mut visible = lines[..step.line].to_vec();
+        let mut line = lines[step.line].clone();
+        line.points.truncate(step.points);
+        line.widths.truncate(step.points);
+        visible.push(line);
+        let pixmap =
+            render::render(&visible, region, scale, render::BACKGROUND)
+                .ok_or_else(|| io::Error::other("the image would be empty"))?;
+        if i + 1 == steps.len() {
+            writer.set_frame_delay(FINAL_FRAME_MS.max(frame_ms), 1000)?;
+        }
+        writer.write_image_data(&render::to_rgba(&pixmap))?;
+        rendered.fetch_add(1, Ordering::Relaxed);
+    }
+    writer.finish()?;
+    Ok(apng)
+}
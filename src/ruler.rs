@@ -0,0 +1,59 @@
+use eframe::egui;
+use egui::{Color32, Painter, Pos2, Rect, Stroke};
+
+use crate::camera::Camera;
+
+// A straight edge the brush snaps to. Two anchors are placed by pressing on
+// the canvas, after which every brush point is projected onto the infinite
+// line through them.
+#[derive(Default, Clone, Copy, PartialEq)]
+pub(crate) enum Ruler {
+    #[default]
+    Off,
+    Placing(Option<Pos2>),
+    Active(Pos2, Pos2),
+}
+impl Ruler {
+    pub fn is_placing(&self) -> bool {
+        matches!(self, Ruler::Placing(_))
+    }
+    pub fn place_anchor(&mut self, pos: Pos2) {
+        *self = match *self {
+            Ruler::Placing(None) => Ruler::Placing(Some(pos)),
+            Ruler::Placing(Some(first)) if first != pos => {
+                Ruler::Active(first, pos)
+            }
+            ruler => ruler,
+        };
+    }
+    pub fn project(&self, pos: Pos2) -> Pos2 {
+        let Ruler::Active(a, b) = *self else {
+            return pos;
+        };
+        let direction = (b - a).normalized();
+        a + direction * (pos - a).dot(direction)
+    }
+    pub fn draw(&self, painter: &Painter, camera: &Camera, canvas_rect: Rect) {
+        let stroke = Stroke::new(1.0_f32, Color32::from_gray(140));
+        let to_screen = |pos| camera.to_screen(pos);
+        match *self {
+            Ruler::Off | Ruler::Placing(None) => {}
+            Ruler::Placing(Some(anchor)) => {
+                painter.circle_stroke(to_screen(anchor), 4.0, stroke);
+            }
+            Ruler::Active(a, b) => {
+                let (a, b) = (to_screen(a), to_screen(b));
+                // extend the guide past both edges of the canvas
+                let direction = (b - a).normalized();
+                let reach = canvas_rect.size().length()
+                    + canvas_rect.center().distance(a);
+                painter.line_segment(
+                    [a - direction * reach, a + direction * reach],
+                    stroke,
+                );
+                painter.circle_stroke(a, 4.0, stroke);
+                painter.circle_stroke(b, 4.0, stroke);
+            }
+        }
+    }
+}
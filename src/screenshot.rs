@@ -0,0 +1,219 @@
+use std::{
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::Duration,
+};
+
+use eframe::egui;
+use egui::{Color32, Pos2, Rect, Stroke};
+use xcap::Monitor;
+
+use crate::{draw_dotted_rect, picture::Picture};
+
+// how long the window gets to minimize before the screen is captured
+const HIDE_DELAY: Duration = Duration::from_millis(500);
+
+// A display that can be captured
+pub(crate) struct Display {
+    pub id: u32,
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub primary: bool,
+}
+impl Display {
+    pub fn label(&self) -> String {
+        let primary = if self.primary { ", main display" } else { "" };
+        format!("{} ({}×{}{primary})", self.name, self.width, self.height)
+    }
+}
+
+pub(crate) fn displays() -> Result<Vec<Display>, String> {
+    let monitors = Monitor::all().map_err(|e| e.to_string())?;
+    Ok(monitors
+        .iter()
+        .map(|monitor| Display {
+            id: monitor.id(),
+            name: monitor.name().to_owned(),
+            width: monitor.width(),
+            height: monitor.height(),
+            primary: monitor.is_primary(),
+        })
+        .collect())
+}
+
+#[derive(Default)]
+pub(crate) struct ScreenshotSettings {
+    // `Display::id` of the display to capture, the main one if `None`
+    pub display: Option<u32>,
+    // pick a part of the display after capturing it
+    pub region: bool,
+}
+
+// A screenshot on the board being drawn over, while the window is
+// borderless on top of everything else
+pub(crate) struct Annotation {
+    // where the screenshot is on the board
+    pub rect: Rect,
+    // canvas the screenshot was last fitted to, so it's fitted again once
+    // the window is full screen
+    pub fitted_rect: Option<Rect>,
+}
+
+// A screenshot being taken on a background thread, after waiting for the
+// window to get out of the way
+pub(crate) struct Capture {
+    pub region: bool,
+    result: Receiver<Result<Picture, String>>,
+}
+impl Capture {
+    pub fn start(ctx: &egui::Context, settings: &ScreenshotSettings) -> Self {
+        let (sender, result) = mpsc::channel();
+        let ctx = ctx.clone();
+        let display = settings.display;
+        thread::spawn(move || {
+            thread::sleep(HIDE_DELAY);
+            let _ = sender.send(capture(display));
+            ctx.request_repaint();
+        });
+        Self {
+            region: settings.region,
+            result,
+        }
+    }
+    // The screenshot once it's taken
+    pub fn poll(&self) -> Option<Result<Picture, String>> {
+        self.result.try_recv().ok()
+    }
+}
+
+fn capture(display: Option<u32>) -> Result<Picture, String> {
+    let monitors = Monitor::all().map_err(|e| e.to_string())?;
+    let monitor = monitors
+        .iter()
+        .find(|monitor| match display {
+            Some(id) => monitor.id() == id,
+            None => monitor.is_primary(),
+        })
+        .or(monitors.first())
+        .ok_or("no display found")?;
+    let image = monitor.capture_image().map_err(|e| e.to_string())?;
+    Picture::from_rgba(
+        image.width() as usize,
+        image.height() as usize,
+        image.as_raw(),
+    )
+    .ok_or_else(|| "the screenshot is empty".to_owned())
+}
+
+// Shows a screenshot over the whole window to drag out the part of it to
+// keep
+pub(crate) struct RegionPicker {
+    pub picture: Picture,
+    start: Option<Pos2>,
+    current: Option<Pos2>,
+}
+pub(crate) enum Picked {
+    // the part of the screenshot to keep, in pixels
+    Region(Rect),
+    Cancelled,
+}
+impl RegionPicker {
+    pub fn new(picture: Picture) -> Self {
+        Self {
+            picture,
+            start: None,
+            current: None,
+        }
+    }
+    pub fn show(&mut self, ctx: &egui::Context) -> Option<Picked> {
+        let screen = ctx.content_rect();
+        let size = self.picture.size();
+        // the screenshot stretched over the window, which fills the display
+        // it was taken on
+        let to_pixels = size / screen.size();
+        let area = egui::Area::new(egui::Id::new("region_picker"))
+            .order(egui::Order::Foreground)
+            .fixed_pos(screen.min);
+        let response = area
+            .show(ctx, |ui| {
+                let (response, painter) =
+                    ui.allocate_painter(screen.size(), egui::Sense::drag());
+                painter.image(
+                    self.picture.texture(ctx).id(),
+                    screen,
+                    Rect::from_min_max(Pos2::ZERO, egui::pos2(1.0, 1.0)),
+                    Color32::WHITE,
+                );
+                painter.rect_filled(screen, 0.0, Color32::from_black_alpha(96));
+                if let (Some(start), Some(current)) = (self.start, self.current)
+                {
+                    let rect = Rect::from_two_pos(start, current);
+                    painter.image(
+                        self.picture.texture(ctx).id(),
+                        rect,
+                        Rect::from_min_max(
+                            ((rect.min - screen.min) / screen.size()).to_pos2(),
+                            ((rect.max - screen.min) / screen.size()).to_pos2(),
+                        ),
+                        Color32::WHITE,
+                    );
+                    draw_dotted_rect(
+                        &painter,
+                        rect,
+                        Stroke::new(1.0_f32, Color32::WHITE),
+                    );
+                }
+                painter.text(
+                    screen.center_top() + egui::vec2(0.0, 24.0),
+                    egui::Align2::CENTER_TOP,
+                    "Drag over the part to annotate, Esc to cancel",
+                    egui::FontId::proportional(18.0),
+                    Color32::WHITE,
+                );
+                ctx.set_cursor_icon(egui::CursorIcon::Crosshair);
+                response
+            })
+            .inner;
+        if ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+            return Some(Picked::Cancelled);
+        }
+        if response.drag_started() {
+            self.start = response.interact_pointer_pos();
+        }
+        if response.dragged() {
+            self.current = response.interact_pointer_pos();
+        }
+        if response.drag_stopped()
+            && let (Some(start), Some(current)) = (self.start, self.current)
+        {
+            self.start = None;
+            self.current = None;
+            let rect = Rect::from_two_pos(
+                ((start - screen.min) * to_pixels).to_pos2(),
+                ((current - screen.min) * to_pixels).to_pos2(),
+            )
+            .intersect(Rect::from_min_size(Pos2::ZERO, size));
+            let rect = Rect::from_min_max(rect.min.round(), rect.max.round());
+            // a click without a drag picks nothing
+            if rect.width() >= 1.0 && rect.height() >= 1.0 {
+                return Some(Picked::Region(rect));
+            }
+        }
+        None
+    }
+}
+
+// The `region` (in pixels) of `picture`
+pub(crate) fn crop(picture: &Picture, region: Rect) -> Option<Picture> {
+    let image = &picture.image;
+    let (x, y) = (region.min.x as usize, region.min.y as usize);
+    let width = (region.width() as usize).min(image.width().saturating_sub(x));
+    let height =
+        (region.height() as usize).min(image.height().saturating_sub(y));
+    let rgba: Vec<u8> = (y..y + height)
+        .flat_map(|row| &image.pixels[row * image.width() + x..][..width])
+        .flat_map(|pixel| pixel.to_srgba_unmultiplied())
+        .collect();
+    Picture::from_rgba(width, height, &rgba)
+}
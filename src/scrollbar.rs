@@ -0,0 +1,82 @@
+use eframe::egui;
+use egui::{Color32, Rect, Sense, Ui, Vec2, pos2, vec2};
+
+use crate::camera::Camera;
+
+const THICKNESS: f32 = 8.0;
+const MIN_THUMB_LENGTH: f32 = 24.0;
+
+// Scrollbars along the bottom and right edge of the canvas. They span the
+// content plus the visible area, so they show how far the drawing extends
+// past the window; dragging a thumb pans the camera.
+pub(crate) fn show(
+    ui: &mut Ui,
+    camera: &mut Camera,
+    canvas_rect: Rect,
+    content: Rect,
+) {
+    let visible = camera.rect_to_world(canvas_rect);
+    let extent = if content.is_finite() && content.is_positive() {
+        // leave room to scroll a bit past the content
+        content.expand2(visible.size() * 0.5).union(visible)
+    } else {
+        visible
+    };
+    for horizontal in [true, false] {
+        let axis = |v: Vec2| if horizontal { v.x } else { v.y };
+        let track = if horizontal {
+            Rect::from_min_max(
+                pos2(canvas_rect.min.x, canvas_rect.max.y - THICKNESS),
+                pos2(canvas_rect.max.x - THICKNESS, canvas_rect.max.y),
+            )
+        } else {
+            Rect::from_min_max(
+                pos2(canvas_rect.max.x - THICKNESS, canvas_rect.min.y),
+                pos2(canvas_rect.max.x, canvas_rect.max.y - THICKNESS),
+            )
+        };
+        let track_length = axis(track.size());
+        let extent_length = axis(extent.size());
+        if track_length <= 0.0 || extent_length <= axis(visible.size()) {
+            continue;
+        }
+        let world_to_track = track_length / extent_length;
+        let thumb_length = (axis(visible.size()) * world_to_track)
+            .max(MIN_THUMB_LENGTH)
+            .min(track_length);
+        let thumb_start = (axis(visible.min.to_vec2())
+            - axis(extent.min.to_vec2()))
+            * world_to_track;
+        let thumb = if horizontal {
+            Rect::from_min_size(
+                pos2(track.min.x + thumb_start, track.min.y),
+                vec2(thumb_length, THICKNESS),
+            )
+        } else {
+            Rect::from_min_size(
+                pos2(track.min.x, track.min.y + thumb_start),
+                vec2(THICKNESS, thumb_length),
+            )
+        };
+
+        let id = ui.id().with(("canvas_scrollbar", horizontal));
+        let response = ui.interact(thumb, id, Sense::drag());
+        if response.dragged() {
+            let delta = axis(response.drag_delta()) / world_to_track;
+            let pan = delta * camera.zoom;
+            if horizontal {
+                camera.pan.x -= pan;
+            } else {
+                camera.pan.y -= pan;
+            }
+        }
+        let color = if response.hovered() || response.dragged() {
+            Color32::from_gray(170)
+        } else {
+            Color32::from_gray(110)
+        };
+        let painter = ui.painter();
+        painter.rect_filled(track, 0.0, Color32::from_black_alpha(60));
+        painter.rect_filled(thumb, THICKNESS / 2.0, color);
+    }
+}
@@ -0,0 +1,304 @@
+use std::collections::HashSet;
+
+use eframe::egui;
+use egui::{
+    Color32, CursorIcon, ImageSource, Painter, Pos2, Rect, Stroke, pos2, vec2,
+};
+
+use crate::{
+    Line, draw_dotted_rect, line_at,
+    tools::{CanvasTool, Tool, ToolContext},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ResizeCorner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+// in the order of the corners `bounds` returns
+const CORNERS: [ResizeCorner; 4] = [
+    ResizeCorner::TopLeft,
+    ResizeCorner::TopRight,
+    ResizeCorner::BottomLeft,
+    ResizeCorner::BottomRight,
+];
+
+// Drags out a rectangle to select the lines in it, then moves the selected
+// lines or resizes them by their corners. The selection itself belongs to
+// the app, which other actions work on.
+#[derive(Default)]
+pub(crate) struct Selection {
+    selection_start: Option<Pos2>,
+    selection_current: Option<Pos2>,
+    is_moving_selection: bool,
+    last_mouse_pos: Option<Pos2>,
+    resizing_corner: Option<ResizeCorner>,
+    resize_original_bbox: Option<Rect>,
+    resize_original_lines: Vec<(usize, Line)>,
+}
+impl Selection {
+    fn start_resizing(
+        &mut self,
+        cx: &ToolContext<'_>,
+        corner: ResizeCorner,
+        bbox: Rect,
+    ) {
+        self.resizing_corner = Some(corner);
+        self.resize_original_bbox = Some(bbox);
+        self.resize_original_lines.clear();
+        for &i in cx.selection.iter() {
+            if let Some(line) = cx.document.lines.get(i) {
+                self.resize_original_lines.push((i, line.clone()));
+            }
+        }
+    }
+    fn update_resizing(
+        &self,
+        cx: &mut ToolContext<'_>,
+        pointer_pos: Pos2,
+        corner: ResizeCorner,
+    ) {
+        if let Some(orig_bbox) = self.resize_original_bbox {
+            // the handles sit on the expanded box, 5 screen px outside
+            let inset = 5.0 / cx.camera.zoom;
+            let mut new_bbox = orig_bbox;
+            match corner {
+                ResizeCorner::TopLeft => {
+                    new_bbox.min = pointer_pos + vec2(inset, inset);
+                }
+                ResizeCorner::TopRight => {
+                    new_bbox.max.x = pointer_pos.x - inset;
+                    new_bbox.min.y = pointer_pos.y + inset;
+                }
+                ResizeCorner::BottomLeft => {
+                    new_bbox.min.x = pointer_pos.x + inset;
+                    new_bbox.max.y = pointer_pos.y - inset;
+                }
+                ResizeCorner::BottomRight => {
+                    new_bbox.max = pointer_pos - vec2(inset, inset);
+                }
+            }
+
+            let scale_x = if orig_bbox.width() > 0.0 {
+                new_bbox.width() / orig_bbox.width()
+            } else {
+                1.0
+            };
+            let scale_y = if orig_bbox.height() > 0.0 {
+                new_bbox.height() / orig_bbox.height()
+            } else {
+                1.0
+            };
+
+            cx.document.dirty = true;
+            for (i, orig_line) in &self.resize_original_lines {
+                if let Some(line) = cx.document.lines.get_mut(*i) {
+                    for (p, orig_p) in
+                        line.points.iter_mut().zip(&orig_line.points)
+                    {
+                        let nx = new_bbox.min.x
+                            + (orig_p.x - orig_bbox.min.x) * scale_x;
+                        let ny = new_bbox.min.y
+                            + (orig_p.y - orig_bbox.min.y) * scale_y;
+                        *p = pos2(nx, ny);
+                    }
+                }
+            }
+            cx.document
+                .share(self.resize_original_lines.iter().map(|(i, _)| *i));
+        }
+    }
+}
+impl CanvasTool for Selection {
+    fn kind(&self) -> Tool {
+        Tool::SELECTION
+    }
+    fn icon(&self) -> ImageSource<'static> {
+        egui::include_image!("../assets/tools/select.png")
+    }
+    fn tooltip(&self) -> &'static str {
+        "Selection"
+    }
+    fn on_pointer_down(&mut self, cx: &mut ToolContext<'_>, pos: Pos2) {
+        // Check if we are interacting with existing selection
+        if let Some((bounding_box, expanded_bbox, corners)) =
+            bounds(&cx.document.lines, cx.selection, cx.camera.zoom)
+        {
+            let corner_size = vec2(10.0, 10.0) / cx.camera.zoom;
+            if let Some((corner, _)) =
+                CORNERS.into_iter().zip(corners).find(|&(_, c)| {
+                    Rect::from_center_size(c, corner_size).contains(pos)
+                })
+            {
+                self.start_resizing(cx, corner, bounding_box);
+                return;
+            }
+            if expanded_bbox.contains(pos) {
+                self.is_moving_selection = true;
+                self.last_mouse_pos = Some(pos);
+                return;
+            }
+        }
+        cx.selection.clear();
+        self.selection_start = Some(pos);
+        self.selection_current = Some(pos);
+    }
+    fn on_pointer_drag(&mut self, cx: &mut ToolContext<'_>, pos: Pos2) {
+        if let Some(corner) = self.resizing_corner {
+            self.update_resizing(cx, pos, corner);
+        } else if self.is_moving_selection {
+            if let Some(last_pos) = self.last_mouse_pos {
+                cx.document.move_selection(cx.selection, pos - last_pos);
+                self.last_mouse_pos = Some(pos);
+            }
+        } else if self.selection_start.is_some() {
+            self.selection_current = Some(pos);
+        }
+    }
+    fn on_pointer_up(&mut self, cx: &mut ToolContext<'_>, pos: Pos2) {
+        if let (Some(start), Some(current)) =
+            (self.selection_start, self.selection_current)
+        {
+            *cx.selection = cx
+                .document
+                .select_in_rect(Rect::from_two_pos(start, current));
+            // locked lines can't be selected, a double-click unlocks them
+            if cx.ctx.input(|i| {
+                i.pointer
+                    .button_double_clicked(egui::PointerButton::Primary)
+            }) && let Some(i) =
+                line_at(&cx.document.lines, pos, cx.camera.zoom)
+                && cx.document.lines[i].locked
+            {
+                cx.document.set_locked([i], false);
+                *cx.selection = HashSet::from([i]);
+            }
+        }
+        self.cancel();
+    }
+    fn paint_overlay(
+        &mut self,
+        cx: &ToolContext<'_>,
+        painter: &Painter,
+        _pointer: Option<Pos2>,
+    ) {
+        // Draw selection rect (drag area)
+        if let (Some(start), Some(current)) =
+            (self.selection_start, self.selection_current)
+        {
+            let rect =
+                cx.camera.rect_to_screen(Rect::from_two_pos(start, current));
+            draw_dotted_rect(
+                painter,
+                rect,
+                Stroke::new(1.0_f32, Color32::GRAY),
+            );
+        }
+
+        // Draw bounding box around selected lines
+        if let Some((_, expanded, corners)) =
+            bounds(&cx.document.lines, cx.selection, cx.camera.zoom)
+        {
+            draw_dotted_rect(
+                painter,
+                cx.camera.rect_to_screen(expanded),
+                Stroke::new(1.0_f32, Color32::BLUE),
+            );
+
+            let corner_size = vec2(8.0, 8.0);
+            for &corner in &corners {
+                let rect = Rect::from_center_size(
+                    cx.camera.to_screen(corner),
+                    corner_size,
+                );
+                painter.rect_filled(rect, 0.0, Color32::GRAY);
+                painter.rect_stroke(
+                    rect,
+                    0.0,
+                    Stroke::new(1.0_f32, Color32::WHITE),
+                    egui::StrokeKind::Middle,
+                );
+            }
+        }
+    }
+    fn cursor(
+        &self,
+        cx: &ToolContext<'_>,
+        pointer: Pos2,
+    ) -> Option<CursorIcon> {
+        // Handle cursor during interaction
+        if let Some(corner) = self.resizing_corner {
+            return Some(resize_cursor(corner));
+        }
+        if self.is_moving_selection {
+            return Some(CursorIcon::PointingHand);
+        }
+
+        // Handle cursor during hover
+        let (_, expanded_bbox, corners) =
+            bounds(&cx.document.lines, cx.selection, cx.camera.zoom)?;
+        let hit_size = vec2(10.0, 10.0) / cx.camera.zoom;
+        if let Some((corner, _)) =
+            CORNERS.into_iter().zip(corners).find(|&(_, c)| {
+                Rect::from_center_size(c, hit_size).contains(pointer)
+            })
+        {
+            Some(resize_cursor(corner))
+        } else if expanded_bbox.contains(pointer) {
+            Some(CursorIcon::PointingHand)
+        } else {
+            None
+        }
+    }
+    fn is_busy(&self) -> bool {
+        self.is_moving_selection || self.resizing_corner.is_some()
+    }
+    fn cancel(&mut self) {
+        *self = Self::default();
+    }
+}
+
+fn resize_cursor(corner: ResizeCorner) -> CursorIcon {
+    match corner {
+        ResizeCorner::TopLeft | ResizeCorner::BottomRight => {
+            CursorIcon::ResizeNwSe
+        }
+        ResizeCorner::TopRight | ResizeCorner::BottomLeft => {
+            CursorIcon::ResizeNeSw
+        }
+    }
+}
+
+// The bounding box of the selected lines, the box drawn around them 5 screen
+// px further out, and that box's corners (top left, top right, bottom left,
+// bottom right)
+fn bounds(
+    lines: &[Line],
+    selection: &HashSet<usize>,
+    zoom: f32,
+) -> Option<(Rect, Rect, [Pos2; 4])> {
+    let mut bounding_box = Rect::NOTHING;
+    for &i in selection {
+        if let Some(line) = lines.get(i) {
+            for p in &line.points {
+                bounding_box.extend_with(*p);
+            }
+        }
+    }
+
+    if bounding_box == Rect::NOTHING {
+        return None;
+    }
+
+    let expanded_bbox = bounding_box.expand(5.0 / zoom);
+    let corners = [
+        expanded_bbox.left_top(),
+        expanded_bbox.right_top(),
+        expanded_bbox.left_bottom(),
+        expanded_bbox.right_bottom(),
+    ];
+
+    Some((bounding_box, expanded_bbox, corners))
+}
@@ -1,8 +1,30 @@
+use std::{fmt, sync::Arc};
+
 use eframe::{emath::Pos2, epaint::Color32};
-use serde::{Deserialize, Serialize};
+use image::{ImageFormat, RgbaImage};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
 
-use crate::{Line, WhiteboardApp};
+use crate::{
+    Line, MIN_STROKE_WIDTH, WhiteboardApp, base64, colors::ColorPalette,
+    document::Document, meta::Metadata, new_line_id, picture::Picture, render,
+    stroke::LineStyle, tools::Tool,
+};
 
+// Compatibility policy for the saved structs below:
+// - Fields that existed in the first file format (points, color, width)
+//   and `pages`, which replaced `lines` in version 2, stay required. Every
+//   field added after that must load when it's missing: `#[serde(default)]`,
+//   an `Option`, or a `Default` impl for its type, plus
+//   `skip_serializing_if` when leaving it out saves space.
+// - Unknown fields are ignored (no `deny_unknown_fields`), so files written
+//   by newer builds still open in older ones as long as the version allows.
+// - Values whose set of variants may grow (like `LineStyle`) are read with
+//   `ignore_invalid` or `ignore_invalid_or_default`, so an unknown variant
+//   falls back to the default instead of failing the whole file. `Tool`
+//   is saved by name, and a name this build has no tool for reads as none.
+// - Renaming or changing the meaning of a field needs a `CURRENT_VERSION`
+//   bump and a step in `migrate`.
 #[derive(Serialize, Deserialize)]
 struct Pos {
     x: f32,
@@ -23,17 +45,20 @@ impl From<&Pos> for Pos2 {
         Pos2::new(pos.x, pos.y)
     }
 }
+// Saved unmultiplied, like a color picker shows it. `Color32` keeps its
+// channels premultiplied, and multiplying them by the alpha again when
+// loading gives back the same bytes.
 #[derive(Serialize, Deserialize, Copy, Clone)]
 pub(crate) struct Color(pub(crate) [u8; 4]);
 impl From<&Color32> for Color {
     fn from(c: &Color32) -> Self {
-        Self([c[0], c[1], c[2], c[3]])
+        Self(c.to_srgba_unmultiplied())
     }
 }
 
 impl From<Color32> for Color {
     fn from(c: Color32) -> Self {
-        Self([c[0], c[1], c[2], c[3]])
+        Self::from(&c)
     }
 }
 impl From<Color> for Color32 {
@@ -48,40 +73,810 @@ pub struct LineState {
     points: Vec<Pos>,
     color: Color,
     width: f32,
+    // per-point widths of pressure sensitive strokes, absent if uniform
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    widths: Vec<f32>,
+    #[serde(
+        default,
+        skip_serializing_if = "LineStyle::is_solid",
+        deserialize_with = "ignore_invalid_or_default"
+    )]
+    style: LineStyle,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    created_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fill: Option<Color>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    fill_only: bool,
+    // base64 PNG of a picture, see `Line::picture`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    image: Option<String>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    locked: bool,
 }
+// Non-finite numbers are left out when saving: serde_json writes them as
+// `null`, which would make the whole file fail to load again
 impl From<&Line> for LineState {
     fn from(line: &Line) -> Self {
+        let width = if line.width.is_finite() {
+            line.width
+        } else {
+            MIN_STROKE_WIDTH
+        };
+        let points = line.points.iter().filter(|p| p.is_finite());
+        let widths = if line.widths.len() == line.points.len() {
+            line.points
+                .iter()
+                .zip(&line.widths)
+                .filter(|(p, _)| p.is_finite())
+                .map(|(_, &w)| if w.is_finite() { w } else { width })
+                .collect()
+        } else {
+            Vec::new()
+        };
         Self {
-            points: line.points.iter().map(Into::into).collect(),
+            points: points.map(Into::into).collect(),
             color: line.color.into(),
-            width: line.width,
+            width,
+            widths,
+            style: line.style,
+            created_ms: line.created_ms,
+            fill: line.fill.map(Into::into),
+            fill_only: line.fill_only,
+            image: line.picture.as_ref().map(|p| base64::encode(&p.png)),
+            locked: line.locked,
+        }
+    }
+}
+// Coordinates are rounded to 1/COORDINATE_SCALE px when saving without full
+// precision, which keeps the JSON short ("413.7" instead of "413.71997")
+const COORDINATE_SCALE: f32 = 10.0;
+impl LineState {
+    fn quantize(&mut self) {
+        for p in &mut self.points {
+            p.x = (p.x * COORDINATE_SCALE).round() / COORDINATE_SCALE;
+            p.y = (p.y * COORDINATE_SCALE).round() / COORDINATE_SCALE;
+        }
+        for w in &mut self.widths {
+            *w = (*w * COORDINATE_SCALE).round() / COORDINATE_SCALE;
         }
     }
 }
+impl Pos {
+    fn is_finite(&self) -> bool {
+        self.x.is_finite() && self.y.is_finite()
+    }
+}
 impl From<&LineState> for Line {
     fn from(state: &LineState) -> Self {
+        let picture = state
+            .image
+            .as_deref()
+            .and_then(base64::decode)
+            .and_then(Picture::from_png)
+            .map(Arc::new);
+        // non-finite points are dropped rather than poisoning the canvas;
+        // an unreadable picture leaves nothing to draw instead of a stroke
+        // between its corners
+        let points: Vec<Pos2> = if state.image.is_some() && picture.is_none() {
+            Vec::new()
+        } else {
+            state
+                .points
+                .iter()
+                .filter(|p| p.is_finite())
+                .map(Into::into)
+                .collect()
+        };
+        // widths that don't match the points fall back to the uniform width
+        let widths = if state.widths.len() == state.points.len() {
+            state
+                .points
+                .iter()
+                .zip(&state.widths)
+                .filter(|(p, _)| p.is_finite())
+                .map(|(_, &w)| if w.is_finite() { w } else { state.width })
+                .collect()
+        } else {
+            Vec::new()
+        };
         Line {
-            points: state.points.iter().map(Into::into).collect(),
+            points,
             color: state.color.into(),
             width: state.width,
+            widths,
+            style: state.style,
+            created_ms: state.created_ms,
+            fill: state.fill.map(Into::into),
+            fill_only: state.fill_only,
+            picture,
+            locked: state.locked,
+            id: new_line_id(),
+        }
+    }
+}
+// Files written before the format was versioned have no `version` field and
+// deserialize as version 0.
+pub(crate) const CURRENT_VERSION: u32 = 3;
+
+#[derive(Debug)]
+pub enum LoadError {
+    // not JSON at all, e.g. truncated or a different kind of file
+    Syntax(serde_json::Error),
+    // valid JSON that doesn't match the whiteboard format, with the page
+    // and index of the first unreadable line entry if that is where it
+    // failed
+    Schema {
+        error: serde_json::Error,
+        line: Option<(usize, usize)>,
+    },
+    Decompress(std::io::Error),
+    MessagePack(rmp_serde::decode::Error),
+    NewerVersion(u32),
+    NonFinitePoint {
+        page: usize,
+        line: usize,
+        point: usize,
+    },
+    // protected with a passphrase, which only the app asks for
+    Encrypted,
+    // the file couldn't be read at all
+    Io(std::io::Error),
+}
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Syntax(e) => {
+                write!(f, "the file is not valid JSON: {e}")
+            }
+            LoadError::Schema {
+                error,
+                line: Some((page, line)),
+            } => write!(
+                f,
+                "line entry {line} on page {} doesn't match the whiteboard \
+                 format: {error}",
+                page + 1
+            ),
+            LoadError::Schema { error, line: None } => write!(
+                f,
+                "the file is JSON but doesn't match the whiteboard format: \
+                 {error}"
+            ),
+            LoadError::Decompress(e) => {
+                write!(f, "failed to decompress file: {e}")
+            }
+            LoadError::MessagePack(e) => {
+                write!(f, "invalid binary whiteboard data: {e}")
+            }
+            LoadError::NonFinitePoint { page, line, point } => write!(
+                f,
+                "point {point} of line {line} on page {} has a non-finite \
+                 coordinate",
+                page + 1
+            ),
+            LoadError::NewerVersion(version) => write!(
+                f,
+                "file was created by a newer version (format version \
+                 {version}, this build supports up to {CURRENT_VERSION})"
+            ),
+            LoadError::Encrypted => {
+                write!(f, "the file is encrypted with a passphrase")
+            }
+            LoadError::Io(e) => write!(f, "failed to read the file: {e}"),
         }
     }
 }
+impl std::error::Error for LoadError {}
+impl LoadError {
+    // Whether `WhiteboardState::from_value_lenient` may get something out of
+    // the file by skipping the broken parts
+    pub fn is_partially_readable(&self) -> bool {
+        matches!(
+            self,
+            LoadError::Schema { line: Some(_), .. }
+                | LoadError::NonFinitePoint { .. }
+        )
+    }
+}
+
 #[derive(Serialize, Deserialize)]
-pub struct WhiteboardState {
+pub struct PageState {
     pub lines: Vec<LineState>,
+}
+impl PageState {
+    // Non-finite points are left out, and coordinates are rounded to 0.1px
+    // if `quantize` is set
+    pub(crate) fn from_lines(lines: &[Line], quantize: bool) -> Self {
+        let mut lines: Vec<LineState> = lines
+            .iter()
+            .map(LineState::from)
+            // nothing left to draw once non-finite points are gone
+            .filter(|line| !line.points.is_empty())
+            .collect();
+        if quantize {
+            lines.iter_mut().for_each(LineState::quantize);
+        }
+        Self { lines }
+    }
+    pub(crate) fn to_lines(&self) -> Vec<Line> {
+        self.lines.iter().map(Into::into).collect()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct WhiteboardState {
+    #[serde(default)]
+    pub version: u32,
+    pub pages: Vec<PageState>,
+    // the page that was shown when saving
+    #[serde(default)]
+    pub(crate) current_page: Option<usize>,
+    // an empty or missing palette loads as the default one
+    #[serde(default)]
     pub(crate) palette: Vec<Color>,
+    // brush settings at the time of saving, restored on open
+    #[serde(default)]
+    pub(crate) stroke_width: Option<f32>,
+    #[serde(default, deserialize_with = "ignore_invalid")]
+    pub(crate) current_tool: Option<Tool>,
+    #[serde(default)]
+    pub(crate) active_color_index: Option<usize>,
+    #[serde(default, skip_serializing_if = "Metadata::is_empty")]
+    pub(crate) meta: Metadata,
+    // base64 PNG preview of the board for file browsers, never rendered
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    thumbnail: Option<String>,
 }
 impl WhiteboardState {
     pub fn new(app: &WhiteboardApp) -> Self {
         Self {
-            lines: app.lines.iter().map(Into::into).collect(),
-            palette: app
+            stroke_width: app
+                .tool_settings
+                .get(&Tool::BRUSH)
+                .map(|settings| settings.width)
+                .filter(|w| w.is_finite()),
+            current_tool: Some(app.current_tool),
+            meta: app.meta.clone(),
+            // stable saves need the rounding, so float noise from moving
+            // lines back and forth doesn't change the file
+            ..Self::from_document(
+                &app.document,
+                !app.preferences.full_precision || app.preferences.stable_saves,
+                app.preferences.embed_thumbnails,
+            )
+        }
+    }
+    // The pages and palette of `document`, rounded to 0.1px if `quantize`
+    // is set, without the brush settings and properties the app adds
+    pub(crate) fn from_document(
+        document: &Document,
+        quantize: bool,
+        thumbnail: bool,
+    ) -> Self {
+        Self {
+            version: CURRENT_VERSION,
+            pages: document.to_pages(quantize),
+            current_page: Some(document.current_page),
+            palette: document
                 .palette
                 .get_palette_vec()
                 .iter()
                 .map(Color::from)
                 .collect(),
+            stroke_width: None,
+            current_tool: None,
+            active_color_index: Some(document.palette.get_active_color_index()),
+            meta: Metadata::default(),
+            // the first page, like the cover of a slide deck
+            thumbnail: thumbnail
+                .then(|| render::thumbnail(document.page_lines(0)))
+                .flatten()
+                .map(|png| base64::encode(&png)),
+        }
+    }
+    // Takes the pages of `document`, e.g. after editing a loaded file
+    // without the app
+    pub(crate) fn set_pages(&mut self, document: &Document) {
+        self.version = CURRENT_VERSION;
+        self.pages = document.to_pages(true);
+        self.current_page = Some(document.current_page);
+        // files saved without a preview stay without one
+        if self.thumbnail.is_some() {
+            self.thumbnail = render::thumbnail(document.page_lines(0))
+                .map(|png| base64::encode(&png));
+        }
+    }
+    pub fn line_count(&self) -> usize {
+        self.pages.iter().map(|page| page.lines.len()).sum()
+    }
+    // The embedded PNG thumbnail, if the file has a readable one
+    pub fn thumbnail_png(&self) -> Option<Vec<u8>> {
+        base64::decode(self.thumbnail.as_deref()?)
+    }
+    // The embedded thumbnail's pixels, at most 256x256, without reading
+    // the lines
+    pub fn thumbnail(&self) -> Option<RgbaImage> {
+        let png = self.thumbnail_png()?;
+        image::load_from_memory_with_format(&png, ImageFormat::Png)
+            .ok()
+            .map(|image| image.into_rgba8())
+    }
+    pub fn from_json(json: &[u8]) -> Result<Self, LoadError> {
+        let value: Value =
+            serde_json::from_slice(json).map_err(LoadError::Syntax)?;
+        // older files only match the structs after migrating
+        let is_current = value.get("version").and_then(Value::as_u64)
+            == Some(CURRENT_VERSION.into());
+        match Self::from_value(value) {
+            // errors from a `Value` have no position; parsing the text again
+            // gives the same error with its line and column
+            Err(LoadError::Schema { error, line })
+                if error.line() == 0 && is_current =>
+            {
+                let error = serde_json::from_slice::<Self>(json)
+                    .err()
+                    .filter(serde_json::Error::is_data)
+                    .unwrap_or(error);
+                Err(LoadError::Schema { error, line })
+            }
+            result => result,
+        }
+    }
+    pub fn from_value(value: Value) -> Result<Self, LoadError> {
+        let value = upgrade(value)?;
+        let state =
+            Self::deserialize(&value).map_err(|error| LoadError::Schema {
+                error,
+                line: first_unreadable_line(&value),
+            })?;
+        state.validate()?;
+        Ok(state)
+    }
+    // Loads whatever can be read, skipping line entries that don't parse or
+    // have non-finite points. Returns the state and the number of skipped
+    // lines.
+    pub fn from_value_lenient(
+        value: Value,
+    ) -> Result<(Self, usize), LoadError> {
+        let mut value = upgrade(value)?;
+        let pages = match value.get_mut("pages").map(Value::take) {
+            Some(Value::Array(pages)) => pages,
+            _ => Vec::new(),
+        };
+        let mut total = 0;
+        let pages: Vec<PageState> = pages
+            .into_iter()
+            .map(|mut page| {
+                let entries = match page.get_mut("lines").map(Value::take) {
+                    Some(Value::Array(entries)) => entries,
+                    _ => Vec::new(),
+                };
+                total += entries.len();
+                let lines = entries
+                    .into_iter()
+                    .filter_map(|entry| {
+                        serde_json::from_value::<LineState>(entry).ok()
+                    })
+                    .filter(|line| line.points.iter().all(Pos::is_finite))
+                    .collect();
+                PageState { lines }
+            })
+            .collect();
+        let palette = value
+            .get_mut("palette")
+            .map(Value::take)
+            .and_then(|palette| serde_json::from_value(palette).ok())
+            .filter(|palette: &Vec<Color>| !palette.is_empty())
+            .unwrap_or_else(|| {
+                ColorPalette::default()
+                    .get_palette_vec()
+                    .iter()
+                    .map(Color::from)
+                    .collect()
+            });
+        let meta = value
+            .get_mut("meta")
+            .map(Value::take)
+            .and_then(|meta| serde_json::from_value(meta).ok())
+            .unwrap_or_default();
+        let current_page = value
+            .get("current_page")
+            .and_then(Value::as_u64)
+            .and_then(|page| usize::try_from(page).ok());
+        let dropped =
+            total - pages.iter().map(|page| page.lines.len()).sum::<usize>();
+        Ok((
+            Self {
+                version: CURRENT_VERSION,
+                pages,
+                current_page,
+                palette,
+                stroke_width: None,
+                current_tool: None,
+                active_color_index: None,
+                meta,
+                thumbnail: None,
+            },
+            dropped,
+        ))
+    }
+    // NaN or infinite coordinates would break bounding boxes and hit-testing
+    pub(crate) fn validate(&self) -> Result<(), LoadError> {
+        for (page, page_state) in self.pages.iter().enumerate() {
+            for (line, state) in page_state.lines.iter().enumerate() {
+                if let Some(point) =
+                    state.points.iter().position(|p| !p.is_finite())
+                {
+                    return Err(LoadError::NonFinitePoint {
+                        page,
+                        line,
+                        point,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_zero(value: &u64) -> bool {
+    *value == 0
+}
+fn is_false(value: &bool) -> bool {
+    !value
+}
+// A tool added by a newer version shouldn't make the whole file unreadable
+fn ignore_invalid<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: serde::de::DeserializeOwned,
+{
+    let value = Value::deserialize(deserializer)?;
+    Ok(serde_json::from_value(value).ok())
+}
+
+fn ignore_invalid_or_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: serde::de::DeserializeOwned + Default,
+{
+    ignore_invalid(deserializer).map(Option::unwrap_or_default)
+}
+
+// Rejects documents from newer versions and migrates older ones
+fn upgrade(value: Value) -> Result<Value, LoadError> {
+    let version = value
+        .get("version")
+        .and_then(Value::as_u64)
+        .map_or(0, |v| u32::try_from(v).unwrap_or(u32::MAX));
+    if version > CURRENT_VERSION {
+        return Err(LoadError::NewerVersion(version));
+    }
+    Ok(migrate(value, version))
+}
+
+fn first_unreadable_line(value: &Value) -> Option<(usize, usize)> {
+    value.get("pages")?.as_array()?.iter().enumerate().find_map(
+        |(page, page_value)| {
+            let line = page_value
+                .get("lines")?
+                .as_array()?
+                .iter()
+                .position(|entry| LineState::deserialize(entry).is_err())?;
+            Some((page, line))
+        },
+    )
+}
+
+// Upgrades a document one version at a time until it matches
+// `CURRENT_VERSION`.
+fn migrate(mut value: Value, mut version: u32) -> Value {
+    while version < CURRENT_VERSION {
+        match version {
+            // v0 -> v1: only the `version` field was introduced
+            0 => {}
+            // v1 -> v2: the lines became the first and only page
+            1 => {
+                if let Some(object) = value.as_object_mut()
+                    && let Some(lines) = object.remove("lines")
+                {
+                    let page = serde_json::json!({ "lines": lines });
+                    object.insert("pages".to_owned(), Value::Array(vec![page]));
+                }
+            }
+            // v2 -> v3: colors were saved premultiplied by their alpha
+            2 => {
+                let pages =
+                    value.get_mut("pages").and_then(Value::as_array_mut);
+                for line in pages
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|page| page.get_mut("lines")?.as_array_mut())
+                    .flatten()
+                {
+                    unmultiply(line.get_mut("color"));
+                    unmultiply(line.get_mut("fill"));
+                }
+                if let Some(palette) =
+                    value.get_mut("palette").and_then(Value::as_array_mut)
+                {
+                    palette
+                        .iter_mut()
+                        .for_each(|color| unmultiply(Some(color)));
+                }
+            }
+            _ => unreachable!("no migration from version {version}"),
+        }
+        version += 1;
+    }
+    if let Some(object) = value.as_object_mut() {
+        object.insert("version".to_owned(), CURRENT_VERSION.into());
+    }
+    value
+}
+
+// Leaves anything that isn't a color alone for loading to deal with
+fn unmultiply(color: Option<&mut Value>) {
+    let Some(color) = color else { return };
+    if let Ok(Color([r, g, b, a])) = Color::deserialize(&*color) {
+        let color_32 = Color32::from_rgba_premultiplied(r, g, b, a);
+        *color = serde_json::json!(Color::from(color_32));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::MAX_STROKE_WIDTH;
+
+    #[test]
+    fn non_finite_points_are_rejected_on_load() {
+        // 1e39 is too big for an f32 and reads as infinity
+        let json = br#"{"version": 3, "pages": [{"lines": [
+            {"points": [{"x": 0, "y": 0}, {"x": 1e39, "y": 5}],
+             "color": [0, 0, 0, 255], "width": 2},
+            {"points": [{"x": 1, "y": 1}, {"x": 2, "y": 2}],
+             "color": [0, 0, 0, 255], "width": 2}
+        ]}]}"#;
+        assert!(matches!(
+            WhiteboardState::from_json(json),
+            Err(LoadError::NonFinitePoint {
+                page: 0,
+                line: 0,
+                point: 1
+            })
+        ));
+        let value = serde_json::from_slice(json).unwrap();
+        let (state, dropped) =
+            WhiteboardState::from_value_lenient(value).unwrap();
+        assert_eq!((state.line_count(), dropped), (1, 1));
+        // JSON itself has no NaN
+        let json = br#"{"pages": [{"lines": [{"points": [{"x": NaN,
+            "y": 0}], "color": [0, 0, 0, 255], "width": 2}]}]}"#;
+        assert!(matches!(
+            WhiteboardState::from_json(json),
+            Err(LoadError::Syntax(_))
+        ));
+    }
+
+    #[test]
+    fn lines_never_get_non_finite_points() {
+        let mut state: LineState = serde_json::from_value(serde_json::json!({
+            "points": [{"x": 0, "y": 0}, {"x": 1, "y": 1}],
+            "color": [0, 0, 0, 255],
+            "width": 2,
+            "widths": [1, 2],
+        }))
+        .unwrap();
+        state.points.insert(
+            1,
+            Pos {
+                x: f32::NAN,
+                y: 0.0,
+            },
+        );
+        state.widths.insert(1, 3.0);
+        let line = Line::from(&state);
+        assert_eq!(line.points, [Pos2::ZERO, Pos2::new(1.0, 1.0)]);
+        assert_eq!(line.widths, [1.0, 2.0]);
+    }
+
+    // Files as older builds wrote them, which must keep loading
+    const V0: &[u8] = include_bytes!("../tests/fixtures/v0.wb");
+    const V1: &[u8] = include_bytes!("../tests/fixtures/v1.wb");
+    const V2: &[u8] = include_bytes!("../tests/fixtures/v2.wb");
+
+    #[test]
+    fn old_versions_still_load() {
+        for (fixture, pages, lines) in [(V0, 1, 2), (V1, 1, 1), (V2, 2, 1)] {
+            let state = WhiteboardState::from_json(fixture).unwrap();
+            assert_eq!(state.version, CURRENT_VERSION);
+            assert_eq!(state.pages.len(), pages);
+            assert_eq!(state.line_count(), lines);
+        }
+        let state = WhiteboardState::from_json(V1).unwrap();
+        assert_eq!(state.current_tool, Some(Tool::BRUSH));
+        assert_eq!(state.active_color_index, Some(1));
+        assert_eq!(state.pages[0].lines[0].created_ms, 1_700_000_000_000);
+        // version 2 saved colors premultiplied
+        let state = WhiteboardState::from_json(V2).unwrap();
+        let line = &state.pages[0].lines[0];
+        assert_eq!(
+            Color32::from(line.color),
+            Color32::from_rgba_premultiplied(64, 0, 0, 128)
+        );
+        assert_eq!(
+            line.fill.map(Color32::from),
+            Some(Color32::from_rgba_premultiplied(0, 32, 0, 64))
+        );
+        assert_eq!(
+            Color32::from(state.palette[1]),
+            Color32::from_rgba_premultiplied(0, 0, 100, 200)
+        );
+    }
+
+    #[test]
+    fn newer_versions_are_refused() {
+        let json =
+            format!(r#"{{"version": {}, "pages": []}}"#, CURRENT_VERSION + 1);
+        let Err(error) = WhiteboardState::from_json(json.as_bytes()) else {
+            panic!("a newer file loaded");
+        };
+        assert!(matches!(error, LoadError::NewerVersion(v)
+            if v == CURRENT_VERSION + 1));
+        assert!(error.to_string().contains("newer version"));
+    }
+
+    // The current format with every field filled in. Frozen: when the
+    // structs grow, this file has to keep loading as it is.
+    const V3: &[u8] = include_bytes!("../tests/fixtures/v3.wb");
+    // Written as if by a newer build that added fields and values this one
+    // doesn't know about
+    const V3_NEWER: &[u8] =
+        include_bytes!("../tests/fixtures/v3-newer-fields.wb");
+
+    #[test]
+    fn the_current_format_keeps_loading() {
+        let state = WhiteboardState::from_json(V3).unwrap();
+        assert_eq!(state.pages.len(), 2);
+        assert_eq!(state.current_page, Some(1));
+        assert_eq!(state.palette.len(), 3);
+        assert_eq!(state.stroke_width, Some(5.0));
+        assert_eq!(state.current_tool, Some(Tool::HIGHLIGHTER));
+        assert_eq!(state.active_color_index, Some(2));
+        assert_eq!(state.meta.title(), Some("Sprint planning"));
+        let [stroke, fill] = &state.pages[0].lines[..] else {
+            panic!("expected two lines");
+        };
+        assert_eq!(stroke.widths, [2.0, 4.0, 3.5]);
+        assert_eq!(stroke.style, LineStyle::Dashed);
+        assert_eq!(stroke.created_ms, 1_718_042_355_123);
+        assert!(stroke.locked && stroke.fill.is_some());
+        assert!(fill.fill_only);
+    }
+
+    #[test]
+    fn unknown_fields_and_values_are_skipped() {
+        let state = WhiteboardState::from_json(V3_NEWER).unwrap();
+        assert_eq!(state.line_count(), 1);
+        let line = &state.pages[0].lines[0];
+        // a style this build doesn't know is drawn solid
+        assert_eq!(line.style, LineStyle::Solid);
+        assert_eq!(line.points.len(), 2);
+        assert_eq!(state.current_tool, None);
+        assert_eq!(state.meta.title(), Some("From a newer build"));
+    }
+
+    #[test]
+    fn fields_added_later_may_be_missing() {
+        let json = br#"{"version": 3, "pages": [{"lines": [{"points":
+            [{"x": 0, "y": 0}], "color": [0, 0, 0, 255], "width": 2}]}]}"#;
+        let state = WhiteboardState::from_json(json).unwrap();
+        let line = &state.pages[0].lines[0];
+        assert!(line.widths.is_empty() && line.fill.is_none());
+        assert!(!line.fill_only && !line.locked && line.image.is_none());
+        assert_eq!(line.created_ms, 0);
+        assert!(state.palette.is_empty() && state.meta.is_empty());
+        assert_eq!(state.current_page, None);
+    }
+
+    #[test]
+    fn every_color_survives_a_round_trip() {
+        // Every color a `Color32` can hold: premultiplied, so no channel
+        // above the alpha
+        for a in 0..=255u8 {
+            for c in 0..=a {
+                let color = Color32::from_rgba_premultiplied(c, a - c, c, a);
+                assert_eq!(Color32::from(Color::from(color)), color);
+            }
+        }
+    }
+
+    #[test]
+    fn transparent_colors_keep_their_alpha() {
+        let opaque = Color32::from_rgb(12, 34, 56);
+        assert_eq!(Color::from(opaque).0, [12, 34, 56, 255]);
+        assert_eq!(Color32::from(Color::from(opaque)), opaque);
+        // saved as picked, not darkened by the alpha, give or take the
+        // rounding of the premultiplied channels
+        let translucent = Color32::from_rgba_unmultiplied(200, 100, 0, 128);
+        let saved = Color::from(translucent).0;
+        assert!(
+            saved
+                .iter()
+                .zip([200, 100, 0, 128])
+                .all(|(&c, p)| c.abs_diff(p) <= 1),
+            "{saved:?}"
+        );
+        assert_eq!(Color32::from(Color::from(translucent)), translucent);
+        assert_eq!(Color::from(Color32::TRANSPARENT).0, [0, 0, 0, 0]);
+        // nothing to see, whatever the color was
+        assert_eq!(
+            Color32::from(Color([200, 100, 0, 0])),
+            Color32::TRANSPARENT
+        );
+    }
+
+    // A stroke: its points, each with a width in case it's pressure
+    // sensitive, its color, its width and whether it's pressure sensitive
+    type RandomLine = (Vec<(Pos2, f32)>, Color32, f32, bool);
+
+    fn random_line() -> impl Strategy<Value = RandomLine> {
+        let point = (-5e3f32..5e3, -5e3f32..5e3, 0f32..50.0)
+            .prop_map(|(x, y, width)| (Pos2::new(x, y), width));
+        let color = any::<[u8; 4]>().prop_map(|[r, g, b, a]| {
+            Color32::from_rgba_unmultiplied(r, g, b, a)
+        });
+        (
+            prop::collection::vec(point, 1..30),
+            color,
+            MIN_STROKE_WIDTH..MAX_STROKE_WIDTH,
+            any::<bool>(),
+        )
+    }
+
+    proptest! {
+        #[test]
+        fn random_boards_survive_a_round_trip(
+            random_lines in prop::collection::vec(random_line(), 0..20),
+            quantize: bool,
+        ) {
+            let mut builder = crate::WhiteboardBuilder::new();
+            for (points, color, width, _) in &random_lines {
+                let points: Vec<Pos2> = points.iter().map(|(p, _)| *p).collect();
+                builder = builder.line(points, *color, *width);
+            }
+            let mut document = builder.build().document;
+            for (line, (points, .., pressure)) in
+                document.lines.iter_mut().zip(&random_lines)
+            {
+                if *pressure {
+                    line.widths = points.iter().map(|(_, w)| *w).collect();
+                }
+            }
+            let state =
+                WhiteboardState::from_document(&document, quantize, false);
+            let json = serde_json::to_vec(&state).unwrap();
+            let loaded = WhiteboardState::from_json(&json).unwrap();
+            let lines = loaded.pages[0].to_lines();
+            let tolerance = if quantize { 0.051 } else { 1e-3 };
+            prop_assert_eq!(lines.len(), document.lines.len());
+            for (loaded, line) in lines.iter().zip(&document.lines) {
+                prop_assert_eq!(loaded.color, line.color);
+                prop_assert_eq!(loaded.width, line.width);
+                prop_assert_eq!(loaded.points.len(), line.points.len());
+                for (p, q) in loaded.points.iter().zip(&line.points) {
+                    let d = (*p - *q).abs();
+                    prop_assert!(d.max_elem() <= tolerance, "{} {}", p, q);
+                }
+                prop_assert_eq!(loaded.widths.len(), line.widths.len());
+                for (w, v) in loaded.widths.iter().zip(&line.widths) {
+                    prop_assert!((w - v).abs() <= tolerance, "{} {}", w, v);
+                }
+            }
         }
     }
 }
@@ -0,0 +1,203 @@
+use eframe::egui;
+use egui::{Color32, Mesh, Pos2, Shape, Stroke};
+use serde::{Deserialize, Serialize};
+
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum LineStyle {
+    #[default]
+    Solid,
+    Dashed,
+    Dotted,
+}
+pub(crate) const LINE_STYLES: [(LineStyle, &str); 3] = [
+    (LineStyle::Solid, "Solid"),
+    (LineStyle::Dashed, "Dashed"),
+    (LineStyle::Dotted, "Dotted"),
+];
+impl LineStyle {
+    pub fn is_solid(&self) -> bool {
+        *self == LineStyle::Solid
+    }
+}
+
+// The shapes for a polyline drawn in `style`. An empty `widths` means the
+// whole line is `width` wide; per-point widths only apply to solid lines.
+pub(crate) fn shapes(
+    points: &[Pos2],
+    widths: &[f32],
+    width: f32,
+    color: Color32,
+    style: LineStyle,
+) -> Vec<Shape> {
+    match style {
+        LineStyle::Solid => vec![solid(points, widths, width, color)],
+        // dash and dot spacing grow with the width so thick lines don't
+        // turn into a solid blur
+        LineStyle::Dashed => Shape::dashed_line(
+            points,
+            Stroke::new(width, color),
+            width * 3.0 + 6.0,
+            width * 2.0 + 4.0,
+        ),
+        LineStyle::Dotted => {
+            Shape::dotted_line(points, color, width * 2.0 + 2.0, width / 2.0)
+        }
+    }
+}
+
+// Rounds off the corners of a polyline by cutting each one at a quarter of
+// the way along its segments (Chaikin's algorithm), `iterations` times.
+// The ends stay put and closed lines stay closed. Per-point `widths` are
+// cut the same way.
+pub(crate) fn smooth(
+    points: &[Pos2],
+    widths: &[f32],
+    iterations: usize,
+) -> (Vec<Pos2>, Vec<f32>) {
+    let closed = points.len() >= 4 && points.first() == points.last();
+    let mut points = points.to_vec();
+    let mut widths = if widths.len() == points.len() {
+        widths.to_vec()
+    } else {
+        Vec::new()
+    };
+    for _ in 0..iterations {
+        if points.len() < 3 {
+            break;
+        }
+        points = cut_corners(&points, closed, |a, b, t| a.lerp(b, t));
+        if !widths.is_empty() {
+            widths = cut_corners(&widths, closed, |a, b, t| a + (b - a) * t);
+        }
+    }
+    (points, widths)
+}
+
+fn cut_corners<T: Copy>(
+    values: &[T],
+    closed: bool,
+    lerp: impl Fn(T, T, f32) -> T,
+) -> Vec<T> {
+    let mut cut = Vec::with_capacity(values.len() * 2);
+    if !closed {
+        cut.push(values[0]);
+    }
+    for segment in values.windows(2) {
+        cut.push(lerp(segment[0], segment[1], 0.25));
+        cut.push(lerp(segment[0], segment[1], 0.75));
+    }
+    // the closing segment is cut too, so the line ends where it now starts
+    cut.push(if closed {
+        cut[0]
+    } else {
+        values[values.len() - 1]
+    });
+    cut
+}
+
+fn solid(points: &[Pos2], widths: &[f32], width: f32, color: Color32) -> Shape {
+    if widths.len() != points.len() || points.len() < 2 {
+        return Shape::line(points.to_vec(), Stroke::new(width, color));
+    }
+    // a triangle strip with two vertices per point, offset along the normal
+    // of the line at that point
+    let mut mesh = Mesh::default();
+    for (i, (&p, &w)) in points.iter().zip(widths).enumerate() {
+        let prev = points[i.saturating_sub(1)];
+        let next = points[(i + 1).min(points.len() - 1)];
+        let normal = (next - prev).normalized().rot90() * (w / 2.0);
+        if !normal.x.is_finite() || !normal.y.is_finite() {
+            continue;
+        }
+        let idx = mesh.vertices.len() as u32;
+        mesh.colored_vertex(p + normal, color);
+        mesh.colored_vertex(p - normal, color);
+        if idx >= 2 {
+            mesh.add_triangle(idx - 2, idx - 1, idx);
+            mesh.add_triangle(idx - 1, idx, idx + 1);
+        }
+    }
+    Shape::mesh(mesh)
+}
+
+// outlines with more points are thinned before triangulating, freehand
+// strokes are dense enough that it doesn't show
+pub(crate) const MAX_FILL_VERTICES: usize = 256;
+
+// The area enclosed by a closed outline. Concave outlines are fine;
+// self-intersecting ones are filled as far as ear clipping gets.
+pub(crate) fn fill(points: &[Pos2], color: Color32) -> Shape {
+    let stride = points.len().div_ceil(MAX_FILL_VERTICES).max(1);
+    let mut outline: Vec<Pos2> =
+        points.iter().step_by(stride).copied().collect();
+    outline.dedup();
+    if outline.len() > 1 && outline.first() == outline.last() {
+        outline.pop();
+    }
+    let mut mesh = Mesh::default();
+    for &p in &outline {
+        mesh.colored_vertex(p, color);
+    }
+    for [a, b, c] in triangulate(&outline) {
+        mesh.add_triangle(a, b, c);
+    }
+    Shape::mesh(mesh)
+}
+
+// Ear clipping: repeatedly cuts off a corner that turns the same way as the
+// whole outline and has no other vertex inside it
+fn triangulate(points: &[Pos2]) -> Vec<[u32; 3]> {
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
+    let mut triangles = Vec::new();
+    let winding: f32 = points
+        .iter()
+        .zip(points.iter().cycle().skip(1))
+        .map(|(a, b)| a.x * b.y - b.x * a.y)
+        .sum();
+    let mut i = 0;
+    let mut misses = 0;
+    while remaining.len() >= 3 && misses < remaining.len() {
+        let n = remaining.len();
+        let (prev, cur, next) = (
+            remaining[(i + n - 1) % n],
+            remaining[i],
+            remaining[(i + 1) % n],
+        );
+        let (a, b, c) = (points[prev], points[cur], points[next]);
+        let turn = (b - a).x * (c - b).y - (b - a).y * (c - b).x;
+        if turn == 0.0 {
+            // a straight run encloses nothing
+            remaining.remove(i);
+        } else if (turn > 0.0) == (winding > 0.0)
+            && !remaining.iter().any(|&j| {
+                // the cuts into holes of bucket fills visit some points
+                // twice
+                ![a, b, c].contains(&points[j])
+                    && in_triangle(points[j], a, b, c)
+            })
+        {
+            triangles.push([prev as u32, cur as u32, next as u32]);
+            remaining.remove(i);
+            misses = 0;
+        } else {
+            i += 1;
+            misses += 1;
+        }
+        if i >= remaining.len() {
+            i = 0;
+        }
+    }
+    triangles
+}
+
+fn in_triangle(p: Pos2, a: Pos2, b: Pos2, c: Pos2) -> bool {
+    let side = |from: Pos2, to: Pos2| {
+        (to - from).x * (p - from).y - (to - from).y * (p - from).x
+    };
+    let (ab, bc, ca) = (side(a, b), side(b, c), side(c, a));
+    (ab >= 0.0 && bc >= 0.0 && ca >= 0.0)
+        || (ab <= 0.0 && bc <= 0.0 && ca <= 0.0)
+}
@@ -0,0 +1,226 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use eframe::egui;
+use egui::{Color32, Pos2, Rect};
+
+use crate::{Line, base64, lines_bounding_box, render, stroke::LineStyle};
+
+pub(crate) struct SvgSettings {
+    // crop the view to the content; otherwise it also takes in the board
+    // origin, so exports of the same board line up
+    pub fit: bool,
+    // room around the content, in board units
+    pub margin: f32,
+    pub background: Option<Color32>,
+}
+impl Default for SvgSettings {
+    fn default() -> Self {
+        Self {
+            fit: true,
+            margin: render::MARGIN,
+            background: None,
+        }
+    }
+}
+
+// Writes the lines as an SVG file, returning the number of lines written
+pub(crate) fn export(
+    lines: &[Line],
+    settings: &SvgSettings,
+    path: &Path,
+) -> io::Result<usize> {
+    // checked before creating the file, which would empty an older export
+    if !lines_bounding_box(lines).is_finite() {
+        return Err(empty_board());
+    }
+    let mut out = BufWriter::new(File::create(path)?);
+    let count = write(lines, settings, &mut out)?;
+    out.flush()?;
+    Ok(count)
+}
+
+fn empty_board() -> io::Error {
+    io::Error::other("the board is empty")
+}
+
+// The SVG document as text, for the clipboard
+pub(crate) fn to_string(
+    lines: &[Line],
+    settings: &SvgSettings,
+) -> io::Result<String> {
+    let mut out = Vec::new();
+    write(lines, settings, &mut out)?;
+    String::from_utf8(out).map_err(io::Error::other)
+}
+
+// Writes the SVG document one element at a time, so large boards don't
+// need the whole text in memory. Lines are drawn like `render` draws them,
+// with board units as user units.
+pub(crate) fn write(
+    lines: &[Line],
+    settings: &SvgSettings,
+    mut out: impl Write,
+) -> io::Result<usize> {
+    let content = lines_bounding_box(lines);
+    if !content.is_finite() {
+        return Err(empty_board());
+    }
+    let content = if settings.fit {
+        content
+    } else {
+        content.union(Rect::ZERO)
+    };
+    let view = content.expand(settings.margin.max(0.0));
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        out,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" \
+         height=\"{}\" viewBox=\"{} {} {} {}\">",
+        Num(view.width()),
+        Num(view.height()),
+        Num(view.min.x),
+        Num(view.min.y),
+        Num(view.width()),
+        Num(view.height()),
+    )?;
+    if let Some(background) = settings.background {
+        writeln!(
+            out,
+            r#"<rect x="{}" y="{}" width="{}" height="{}" {}/>"#,
+            Num(view.min.x),
+            Num(view.min.y),
+            Num(view.width()),
+            Num(view.height()),
+            Paint("fill", background.to_opaque()),
+        )?;
+    }
+    // bucket fills lie beneath every stroke, like on the canvas
+    let (fills, strokes): (Vec<&Line>, Vec<&Line>) =
+        lines.iter().partition(|line| line.fill_only);
+    let mut count = 0;
+    for line in fills.into_iter().chain(strokes) {
+        if write_line(&mut out, line)? {
+            count += 1;
+        }
+    }
+    writeln!(out, "</svg>")?;
+    Ok(count)
+}
+
+// Returns whether anything was written; lines without two finite points
+// have nothing to draw
+fn write_line(out: &mut impl Write, line: &Line) -> io::Result<bool> {
+    if let Some((picture, rect)) = line.picture_area() {
+        writeln!(
+            out,
+            "<image x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" \
+             preserveAspectRatio=\"none\" href=\"data:image/png;base64,{}\"/>",
+            Num(rect.min.x),
+            Num(rect.min.y),
+            Num(rect.width()),
+            Num(rect.height()),
+            base64::encode(&picture.png),
+        )?;
+        return Ok(true);
+    }
+    let points: Vec<(Pos2, Option<f32>)> = line
+        .points
+        .iter()
+        .enumerate()
+        .map(|(i, &p)| (p, line.widths.get(i).copied()))
+        .filter(|(p, _)| p.x.is_finite() && p.y.is_finite())
+        .collect();
+    if points.len() < 2 {
+        return Ok(false);
+    }
+    if let Some(fill) = line.fill
+        && line.is_closed()
+    {
+        write!(out, r#"<path d=""#)?;
+        for (i, (p, _)) in points.iter().enumerate() {
+            let command = if i == 0 { 'M' } else { 'L' };
+            write!(out, "{command}{} {}", Num(p.x), Num(p.y))?;
+        }
+        writeln!(out, r#"Z" {}/>"#, Paint("fill", fill))?;
+    }
+    if line.fill_only {
+        return Ok(true);
+    }
+    let w = line.width;
+    // same dash and dot spacing as `render`
+    let dashes = match line.style {
+        LineStyle::Solid => String::new(),
+        LineStyle::Dashed => format!(
+            r#" stroke-dasharray="{} {}""#,
+            Num(w * 3.0 + 6.0),
+            Num(w * 2.0 + 4.0)
+        ),
+        LineStyle::Dotted => {
+            format!(r#" stroke-dasharray="0 {}""#, Num(w * 2.0 + 2.0))
+        }
+    };
+    let stroke = format!(
+        "fill=\"none\" {} stroke-linecap=\"round\" \
+         stroke-linejoin=\"round\"{dashes}",
+        Paint("stroke", line.color)
+    );
+    let uniform =
+        line.widths.len() != line.points.len() || !line.style.is_solid();
+    if uniform {
+        write!(out, r#"<polyline points=""#)?;
+        for (i, (p, _)) in points.iter().enumerate() {
+            let separator = if i == 0 { "" } else { " " };
+            write!(out, "{separator}{},{}", Num(p.x), Num(p.y))?;
+        }
+        writeln!(out, r#"" {stroke} stroke-width="{}"/>"#, Num(w))?;
+        return Ok(true);
+    }
+    // pressure sensitive strokes go segment by segment, each as wide as
+    // the average of its ends
+    writeln!(out, "<g {stroke}>")?;
+    for segment in points.windows(2) {
+        let [(a, wa), (b, wb)] = [segment[0], segment[1]];
+        let width = (wa.unwrap_or(w) + wb.unwrap_or(w)) / 2.0;
+        writeln!(
+            out,
+            r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke-width="{}"/>"#,
+            Num(a.x),
+            Num(a.y),
+            Num(b.x),
+            Num(b.y),
+            Num(if width.is_finite() { width } else { w }),
+        )?;
+    }
+    writeln!(out, "</g>")?;
+    Ok(true)
+}
+
+// A coordinate rounded to 0.01, without trailing zeros
+struct Num(f32);
+impl fmt::Display for Num {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rounded = (self.0 * 100.0).round() / 100.0;
+        // no "-0"
+        write!(f, "{}", rounded + 0.0)
+    }
+}
+
+// A `fill` or `stroke` attribute with the color as `#rrggbb`, plus its
+// opacity attribute if the color is translucent
+struct Paint(&'static str, Color32);
+impl fmt::Display for Paint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let &Paint(attribute, color) = self;
+        let [r, g, b, a] = color.to_srgba_unmultiplied();
+        write!(f, r##"{attribute}="#{r:02x}{g:02x}{b:02x}""##)?;
+        if a < 255 {
+            write!(f, r#" {attribute}-opacity="{}""#, Num(a as f32 / 255.0))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,83 @@
+use std::{
+    collections::HashSet,
+    mem,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{
+    WhiteboardApp, camera::Camera, colors::ColorPalette, crypto::FileKey,
+    document::Document, format::Encoding, meta::Metadata, recovery::SnapshotId,
+};
+
+// A board open in a tab, with its own file, undo history, selection and
+// view. The shown tab's board lives in the app's own fields; its entry in
+// `WhiteboardApp::tabs` stays empty until another tab is shown and the two
+// trade places.
+#[derive(Default)]
+pub(crate) struct Tab {
+    document: Document,
+    meta: Metadata,
+    whiteboard_file: Option<PathBuf>,
+    file_encoding: Encoding,
+    file_modified: Option<SystemTime>,
+    encryption: Option<FileKey>,
+    recovery_id: SnapshotId,
+    selected_lines: HashSet<usize>,
+    camera: Camera,
+}
+impl Tab {
+    // A blank board with the user's default palette
+    pub fn new(palette: ColorPalette) -> Self {
+        Self {
+            document: Document {
+                palette,
+                ..Document::default()
+            },
+            ..Default::default()
+        }
+    }
+    // Trades the board `app` shows for this one
+    pub fn swap(&mut self, app: &mut WhiteboardApp) {
+        mem::swap(&mut self.document, &mut app.document);
+        mem::swap(&mut self.meta, &mut app.meta);
+        mem::swap(&mut self.whiteboard_file, &mut app.whiteboard_file);
+        mem::swap(&mut self.file_encoding, &mut app.file_encoding);
+        mem::swap(&mut self.file_modified, &mut app.file_modified);
+        mem::swap(&mut self.encryption, &mut app.encryption);
+        mem::swap(&mut self.recovery_id, &mut app.recovery_id);
+        mem::swap(&mut self.selected_lines, &mut app.selected_lines);
+        mem::swap(&mut self.camera, &mut app.camera);
+    }
+    pub fn is_dirty(&self) -> bool {
+        self.document.dirty
+    }
+    pub fn recovery_id(&self) -> SnapshotId {
+        self.recovery_id
+    }
+    pub fn whiteboard_file(&self) -> Option<&Path> {
+        self.whiteboard_file.as_deref()
+    }
+    pub fn label(&self) -> String {
+        label(
+            &self.meta,
+            self.whiteboard_file.as_deref(),
+            self.document.dirty,
+        )
+    }
+}
+
+// What a tab shows: the document title if it has one, otherwise the file
+// name, marked like the window title while there are unsaved changes
+pub(crate) fn label(
+    meta: &Metadata,
+    file: Option<&Path>,
+    dirty: bool,
+) -> String {
+    let name = match (meta.title(), file.and_then(Path::file_name)) {
+        (Some(title), _) => title.to_owned(),
+        (None, Some(name)) => name.to_string_lossy().into_owned(),
+        (None, None) => "Untitled".to_owned(),
+    };
+    if dirty { format!("* {name}") } else { name }
+}
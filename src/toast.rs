@@ -0,0 +1,99 @@
+use std::time::Duration;
+
+use eframe::egui;
+use egui::{Align2, Color32, Id, RichText, vec2};
+
+const TOAST_DURATION: f64 = 4.0;
+// the last part of `TOAST_DURATION`, during which a toast fades out
+const FADE_DURATION: f64 = 0.5;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ToastLevel {
+    Info,
+    Success,
+    Warning,
+}
+impl ToastLevel {
+    fn icon(self) -> (&'static str, Color32) {
+        match self {
+            ToastLevel::Info => ("ℹ", Color32::LIGHT_BLUE),
+            ToastLevel::Success => ("✔", Color32::LIGHT_GREEN),
+            ToastLevel::Warning => ("⚠", Color32::YELLOW),
+        }
+    }
+}
+
+struct Toast {
+    text: String,
+    level: ToastLevel,
+    // set when the toast is first shown, so a toast pushed while a blocking
+    // dialog is open still stays up for the whole duration
+    expires_at: Option<f64>,
+}
+
+// Short-lived, non-blocking messages shown in the bottom-right corner
+#[derive(Default)]
+pub(crate) struct Toasts {
+    toasts: Vec<Toast>,
+}
+impl Toasts {
+    pub fn push(&mut self, text: impl Into<String>, level: ToastLevel) {
+        self.toasts.push(Toast {
+            text: text.into(),
+            level,
+            expires_at: None,
+        });
+    }
+    // With `reduce_motion` toasts disappear at once instead of fading out
+    pub fn show(&mut self, ctx: &egui::Context, reduce_motion: bool) {
+        let fade_duration = if reduce_motion { 0.0 } else { FADE_DURATION };
+        let now = ctx.input(|i| i.time);
+        for toast in &mut self.toasts {
+            toast.expires_at.get_or_insert(now + TOAST_DURATION);
+        }
+        self.toasts
+            .retain(|toast| toast.expires_at.is_some_and(|t| t > now));
+        if self.toasts.is_empty() {
+            return;
+        }
+        let fading = self.toasts.iter().any(|toast| {
+            toast.expires_at.is_some_and(|t| t - now < fade_duration)
+        });
+        if fading {
+            ctx.request_repaint();
+        } else if let Some(fade_start) = self
+            .toasts
+            .iter()
+            .filter_map(|toast| toast.expires_at)
+            .reduce(f64::min)
+        {
+            ctx.request_repaint_after(Duration::from_secs_f64(
+                fade_start - fade_duration - now,
+            ));
+        }
+
+        egui::Area::new(Id::new("toasts"))
+            .anchor(Align2::RIGHT_BOTTOM, vec2(-10.0, -10.0))
+            .interactable(false)
+            .show(ctx, |ui| {
+                for toast in &self.toasts {
+                    let remaining = toast.expires_at.unwrap_or(now) - now;
+                    let opacity = if reduce_motion {
+                        1.0
+                    } else {
+                        (remaining / FADE_DURATION).min(1.0) as f32
+                    };
+                    ui.scope(|ui| {
+                        ui.set_opacity(opacity);
+                        egui::Frame::popup(ui.style()).show(ui, |ui| {
+                            ui.horizontal(|ui| {
+                                let (icon, color) = toast.level.icon();
+                                ui.label(RichText::new(icon).color(color));
+                                ui.label(&toast.text);
+                            });
+                        });
+                    });
+                }
+            });
+    }
+}
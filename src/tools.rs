@@ -1,28 +1,464 @@
-use egui::ImageSource;
-use serde::Serialize;
-
-pub(super) const TOOLS: [(Tool, ImageSource, &str); 3] = [
-    (
-        Tool::Brush,
-        egui::include_image!("../assets/tools/brush.png"),
-        "brush",
-    ),
-    (
-        Tool::Eraser,
-        egui::include_image!("../assets/tools/eraser.png"),
-        "eraser",
-    ),
-    (
-        Tool::Selection,
-        egui::include_image!("../assets/tools/select.png"),
-        "Selection Tool",
-    ),
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Mutex, PoisonError},
+};
+
+use eframe::egui;
+use egui::{Color32, CursorIcon, ImageSource, Painter, Pos2, Rect, Stroke};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    Line, MAX_STROKE_WIDTH, MIN_OPACITY, MIN_STROKE_WIDTH, brush::Brush,
+    bucket, camera::Camera, colors::RecentColors, document::Document,
+    draw_crosshair, laser::Laser, new_line_id, now_ms,
+    preferences::Preferences, ruler::Ruler, selection::Selection,
+    stroke::LineStyle,
+};
+
+// Names a tool. It's what `WhiteboardState::current_tool` saves and what
+// tool settings are remembered by, so a tool added with
+// `WhiteboardApp::register_tool` brings a name of its own.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Tool(&'static str);
+impl Tool {
+    pub const BRUSH: Self = Self("Brush");
+    pub const ERASER: Self = Self("Eraser");
+    pub const SELECTION: Self = Self("Selection");
+    pub const MEASURE: Self = Self("Measure");
+    // fills the area enclosed by strokes around a click
+    pub const FILL: Self = Self("Fill");
+    // temporary ink that fades out, for pointing at things
+    pub const LASER: Self = Self("Laser");
+    // a wide, see-through brush
+    pub const HIGHLIGHTER: Self = Self("Highlighter");
+
+    pub const fn new(name: &'static str) -> Self {
+        Self(name)
+    }
+    pub fn name(self) -> &'static str {
+        self.0
+    }
+}
+impl Default for Tool {
+    fn default() -> Self {
+        Self::BRUSH
+    }
+}
+impl Serialize for Tool {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0)
+    }
+}
+impl<'de> Deserialize<'de> for Tool {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Tool::named(&name).ok_or_else(|| {
+            serde::de::Error::custom(format!("unknown tool {name:?}"))
+        })
+    }
+}
+
+const BUILT_IN: [Tool; 7] = [
+    Tool::BRUSH,
+    Tool::ERASER,
+    Tool::SELECTION,
+    Tool::MEASURE,
+    Tool::FILL,
+    Tool::LASER,
+    Tool::HIGHLIGHTER,
 ];
+// Tools added with `WhiteboardApp::register_tool` since the start
+static REGISTERED: Mutex<Vec<Tool>> = Mutex::new(Vec::new());
+impl Tool {
+    // The built-in or registered tool called `name`. Names of tools this
+    // build doesn't have are read as no tool, which files and settings
+    // skip.
+    fn named(name: &str) -> Option<Self> {
+        let registered =
+            REGISTERED.lock().unwrap_or_else(PoisonError::into_inner);
+        BUILT_IN
+            .iter()
+            .chain(registered.iter())
+            .find(|tool| tool.0 == name)
+            .copied()
+    }
+    pub(crate) fn register(self) {
+        let mut registered =
+            REGISTERED.lock().unwrap_or_else(PoisonError::into_inner);
+        if !BUILT_IN.contains(&self) && !registered.contains(&self) {
+            registered.push(self);
+        }
+    }
+}
+
+// Width and opacity of a tool that draws or erases, remembered per tool
+// between sessions
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ToolSettings {
+    pub width: f32,
+    // how opaque the current color is drawn, `None` for tools that don't
+    // draw
+    pub opacity: Option<f32>,
+}
+impl Default for ToolSettings {
+    fn default() -> Self {
+        Self {
+            width: 3.0,
+            opacity: Some(1.0),
+        }
+    }
+}
+impl ToolSettings {
+    // Out of range values from a damaged file go back to `default`
+    pub(crate) fn sanitized(self, default: Self) -> Self {
+        let width =
+            if (MIN_STROKE_WIDTH..=MAX_STROKE_WIDTH).contains(&self.width) {
+                self.width
+            } else {
+                default.width
+            };
+        let opacity = match (self.opacity, default.opacity) {
+            (Some(opacity), Some(_))
+                if (MIN_OPACITY..=1.0).contains(&opacity) =>
+            {
+                Some(opacity)
+            }
+            _ => default.opacity,
+        };
+        Self { width, opacity }
+    }
+}
+
+// The board and the settings a tool works with, lent by the app for the
+// length of a call. Tools from outside the crate go through its methods.
+pub struct ToolContext<'a> {
+    pub(crate) ctx: &'a egui::Context,
+    pub(crate) document: &'a mut Document,
+    // indices into `document.lines`
+    pub(crate) selection: &'a mut HashSet<usize>,
+    pub(crate) camera: Camera,
+    // the canvas on screen
+    pub(crate) canvas_rect: Rect,
+    pub(crate) color: Color32,
+    pub(crate) width: f32,
+    pub(crate) style: LineStyle,
+    // fill of strokes that end where they start, if they're filled
+    pub(crate) fill: Option<Color32>,
+    pub(crate) snap_to_endpoints: bool,
+    pub(crate) speed_sensitive_width: bool,
+    pub(crate) show_crosshair: bool,
+    pub(crate) ruler: &'a mut Ruler,
+    pub(crate) recent_colors: &'a mut RecentColors,
+    pub(crate) preferences: &'a Preferences,
+}
+impl ToolContext<'_> {
+    pub fn ctx(&self) -> &egui::Context {
+        self.ctx
+    }
+    // The color and width picked on the toolbar
+    pub fn color(&self) -> Color32 {
+        self.color
+    }
+    pub fn width(&self) -> f32 {
+        self.width
+    }
+    pub fn canvas_rect(&self) -> Rect {
+        self.canvas_rect
+    }
+    // Where a point of the board is on screen, for `paint_overlay`
+    pub fn to_screen(&self, pos: Pos2) -> Pos2 {
+        self.camera.to_screen(pos)
+    }
+    pub fn zoom(&self) -> f32 {
+        self.camera.zoom
+    }
+    // The points of every stroke on the page, bottom to top
+    pub fn strokes(&self) -> impl Iterator<Item = &[Pos2]> {
+        self.document
+            .lines
+            .iter()
+            .map(|line| line.points.as_slice())
+    }
+    // Adds a stroke through `points` in the current color, width and line
+    // style, as one step to undo
+    pub fn add_stroke(&mut self, points: Vec<Pos2>) {
+        let line = Line {
+            points,
+            color: self.color,
+            width: self.width,
+            widths: Vec::new(),
+            style: self.style,
+            created_ms: now_ms(),
+            fill: None,
+            fill_only: false,
+            picture: None,
+            locked: false,
+            id: new_line_id(),
+        };
+        self.recent_colors.push(self.color);
+        self.document.add_line(line);
+    }
+    // Erases what the eraser would when pressed at `pos`, returning whether
+    // anything was erased
+    pub fn erase_at(&mut self, pos: Pos2, radius: f32) -> bool {
+        let erased = self.document.erase_at(pos, radius);
+        if erased {
+            self.selection.clear();
+        }
+        erased
+    }
+}
+
+// Something on the toolbar that handles the pointer on the canvas. Positions
+// are in canvas coordinates; a click is a press and a release without a
+// drag in between. Tools from outside the crate are added with
+// `WhiteboardApp::register_tool`.
+pub trait CanvasTool {
+    fn kind(&self) -> Tool;
+    fn icon(&self) -> ImageSource<'static>;
+    fn tooltip(&self) -> &'static str;
+    // tools that change nothing keep working while presenting
+    fn edits(&self) -> bool {
+        true
+    }
+    // what the tool starts with if it has a width of its own
+    fn default_settings(&self) -> Option<ToolSettings> {
+        None
+    }
+    fn on_pointer_down(&mut self, cx: &mut ToolContext<'_>, pos: Pos2);
+    fn on_pointer_drag(&mut self, _cx: &mut ToolContext<'_>, _pos: Pos2) {}
+    fn on_pointer_up(&mut self, _cx: &mut ToolContext<'_>, _pos: Pos2) {}
+    // Draws on top of the board; `pointer` is where the pointer hovers or
+    // drags, if it's over the canvas
+    fn paint_overlay(
+        &mut self,
+        _cx: &ToolContext<'_>,
+        _painter: &Painter,
+        _pointer: Option<Pos2>,
+    ) {
+    }
+    fn cursor(
+        &self,
+        _cx: &ToolContext<'_>,
+        _pointer: Pos2,
+    ) -> Option<CursorIcon> {
+        None
+    }
+    // whether a stroke or drag is in progress
+    fn is_busy(&self) -> bool {
+        false
+    }
+    // Drops the stroke or drag in progress, e.g. when the page changes
+    fn cancel(&mut self) {}
+}
+
+// The settings every tool with a width of its own starts with
+pub(crate) fn default_settings(
+    tools: &[Box<dyn CanvasTool>],
+) -> HashMap<Tool, ToolSettings> {
+    tools
+        .iter()
+        .filter_map(|tool| Some((tool.kind(), tool.default_settings()?)))
+        .collect()
+}
+
+// Makes a tool in its starting state. Each detached window makes its own
+// set of tools, so a stroke in one doesn't get mixed up with another.
+pub type ToolMaker = fn() -> Box<dyn CanvasTool>;
+
+// The tools on the toolbar, in order
+pub(crate) fn built_in() -> Vec<ToolMaker> {
+    vec![
+        || Box::new(Brush::new(Tool::BRUSH)),
+        || Box::new(Brush::new(Tool::HIGHLIGHTER)),
+        || Box::new(Eraser),
+        || Box::new(Selection::default()),
+        || Box::new(Fill),
+        || Box::new(Measure::default()),
+        || Box::new(Laser::default()),
+    ]
+}
+
+pub(crate) fn make_all(makers: &[ToolMaker]) -> Vec<Box<dyn CanvasTool>> {
+    makers.iter().map(|make| make()).collect()
+}
+
+struct Eraser;
+impl Eraser {
+    fn erase(cx: &mut ToolContext<'_>, pos: Pos2) {
+        // 給予一點點擊容差
+        let erase_radius =
+            cx.width + cx.preferences.eraser_size / cx.camera.zoom;
+        cx.erase_at(pos, erase_radius);
+    }
+}
+impl CanvasTool for Eraser {
+    fn kind(&self) -> Tool {
+        Tool::ERASER
+    }
+    fn icon(&self) -> ImageSource<'static> {
+        egui::include_image!("../assets/tools/eraser.png")
+    }
+    fn tooltip(&self) -> &'static str {
+        "Eraser"
+    }
+    fn default_settings(&self) -> Option<ToolSettings> {
+        Some(ToolSettings {
+            opacity: None,
+            ..ToolSettings::default()
+        })
+    }
+    // 支援點擊或拖曳時刪除線條
+    fn on_pointer_down(&mut self, cx: &mut ToolContext<'_>, pos: Pos2) {
+        Self::erase(cx, pos);
+    }
+    fn on_pointer_drag(&mut self, cx: &mut ToolContext<'_>, pos: Pos2) {
+        Self::erase(cx, pos);
+    }
+}
+
+struct Fill;
+impl CanvasTool for Fill {
+    fn kind(&self) -> Tool {
+        Tool::FILL
+    }
+    fn icon(&self) -> ImageSource<'static> {
+        egui::include_image!("../assets/tools/fill.png")
+    }
+    fn tooltip(&self) -> &'static str {
+        "Fill"
+    }
+    // Fills the area enclosed by strokes around `pos` with the current
+    // color; does nothing if `pos` isn't enclosed
+    fn on_pointer_down(&mut self, cx: &mut ToolContext<'_>, pos: Pos2) {
+        let Some(mut points) =
+            bucket::enclosed_region(&cx.document.lines, pos, cx.camera.zoom)
+        else {
+            return;
+        };
+        points.push(points[0]);
+        let line = Line {
+            points,
+            color: cx.color,
+            width: MIN_STROKE_WIDTH,
+            widths: Vec::new(),
+            style: LineStyle::Solid,
+            created_ms: now_ms(),
+            fill: Some(cx.color),
+            fill_only: true,
+            picture: None,
+            locked: false,
+            id: new_line_id(),
+        };
+        cx.recent_colors.push(cx.color);
+        cx.document.add_line(line);
+    }
+}
+
+// Only shown while dragging
+#[derive(Default)]
+struct Measure {
+    start: Option<Pos2>,
+    end: Option<Pos2>,
+}
+impl CanvasTool for Measure {
+    fn kind(&self) -> Tool {
+        Tool::MEASURE
+    }
+    fn icon(&self) -> ImageSource<'static> {
+        egui::include_image!("../assets/tools/measure.png")
+    }
+    fn tooltip(&self) -> &'static str {
+        "Measure"
+    }
+    fn on_pointer_down(&mut self, _cx: &mut ToolContext<'_>, pos: Pos2) {
+        self.start = Some(pos);
+    }
+    fn on_pointer_drag(&mut self, _cx: &mut ToolContext<'_>, pos: Pos2) {
+        self.end = Some(pos);
+    }
+    fn on_pointer_up(&mut self, _cx: &mut ToolContext<'_>, _pos: Pos2) {
+        self.cancel();
+    }
+    fn paint_overlay(
+        &mut self,
+        cx: &ToolContext<'_>,
+        painter: &Painter,
+        pointer: Option<Pos2>,
+    ) {
+        if let (Some(start), Some(end)) = (self.start, self.end) {
+            draw_measurement(painter, &cx.camera, start, end);
+        }
+        if cx.show_crosshair
+            && let Some(pos) = pointer
+        {
+            draw_crosshair(
+                painter,
+                cx.canvas_rect,
+                cx.camera.to_screen(pos),
+                cx.ctx.style().visuals.text_color().gamma_multiply(0.25),
+            );
+        }
+    }
+    fn cancel(&mut self) {
+        self.start = None;
+        self.end = None;
+    }
+}
+
+// Dimension line between `start` and `end` with ticks at both ends and a
+// label showing the length (in canvas units) and angle
+fn draw_measurement(
+    painter: &egui::Painter,
+    camera: &Camera,
+    start: Pos2,
+    end: Pos2,
+) {
+    let stroke = Stroke::new(1.5_f32, Color32::LIGHT_BLUE);
+    let length = (end - start).length();
+    let (start, end) = (camera.to_screen(start), camera.to_screen(end));
+    let delta = end - start;
+    let normal = if delta.length() > 0.0 {
+        delta.rot90() / delta.length()
+    } else {
+        egui::Vec2::ZERO
+    };
+    painter.line_segment([start, end], stroke);
+    for p in [start, end] {
+        painter.line_segment([p - normal * 6.0, p + normal * 6.0], stroke);
+    }
+
+    // screen y grows downwards, flip it so angles are counter-clockwise
+    let angle = (-delta.y).atan2(delta.x).to_degrees();
+    painter.text(
+        end + egui::vec2(12.0, 12.0),
+        egui::Align2::LEFT_TOP,
+        format!("{length:.1} px  {angle:.1}°"),
+        egui::FontId::proportional(14.0),
+        Color32::LIGHT_BLUE,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-#[derive(PartialEq, Default, Serialize)]
-pub enum Tool {
-    #[default]
-    Brush,
-    Eraser,
-    Selection,
+    #[test]
+    fn tools_are_saved_by_name() {
+        // the names the enum this replaced was saved with
+        let json = serde_json::to_string(&Tool::HIGHLIGHTER).unwrap();
+        assert_eq!(json, "\"Highlighter\"");
+        let tool: Tool = serde_json::from_str(&json).unwrap();
+        assert_eq!(tool, Tool::HIGHLIGHTER);
+        // only tools this build has
+        assert!(serde_json::from_str::<Tool>("\"Stamp\"").is_err());
+        Tool::new("Stamp").register();
+        let tool: Tool = serde_json::from_str("\"Stamp\"").unwrap();
+        assert_eq!(tool, Tool::new("Stamp"));
+    }
 }
@@ -1,12 +1,27 @@
 use std::collections::VecDeque;
 const MAX_UNDO_STACK_SIZE: usize = 100;
 
+use eframe::egui::Pos2;
+
 use crate::Line;
 
 #[derive(Debug, Clone)]
 pub enum UndoAction {
-    Erase(Line),
+    // a line that was erased and the index it had
+    Erase(usize, Line),
     Draw(Line),
+    // a batch of lines appended to the end of the board
+    Import(usize),
+    // the points of lines before they were moved, by index
+    Transform(Vec<(usize, Vec<Pos2>)>),
+    // new position -> old position of every line after a z-order change
+    Reorder(Vec<usize>),
+    // the width and per-point widths of lines before a width change
+    Width(Vec<(usize, f32, Vec<f32>)>),
+    // the points and per-point widths of lines before they were smoothed
+    Reshape(Vec<(usize, Vec<Pos2>, Vec<f32>)>),
+    // lines that were locked or unlocked, by index
+    Lock(Vec<usize>),
 }
 #[derive(Default)]
 pub struct UndoStack {
@@ -14,13 +29,42 @@ pub struct UndoStack {
 }
 impl UndoStack {
     pub fn add_draw(&mut self, line: Line) {
-        self.stack.push_back(UndoAction::Draw(line));
-        if self.stack.len() > MAX_UNDO_STACK_SIZE {
-            self.stack.pop_front();
+        self.push(UndoAction::Draw(line));
+    }
+    // `erased` is in the order the lines were removed in, highest index
+    // first, so undoing puts each one back where it was
+    pub fn extend_erase(&mut self, erased: Vec<(usize, Line)>) {
+        for (i, line) in erased {
+            self.push(UndoAction::Erase(i, line));
         }
     }
-    pub fn extend_erase(&mut self, erased: Vec<Line>) {
-        self.stack.extend(erased.into_iter().map(UndoAction::Erase));
+    pub fn add_import(&mut self, count: usize) {
+        self.push(UndoAction::Import(count));
+    }
+    pub fn add_transform(&mut self, original: Vec<(usize, Vec<Pos2>)>) {
+        self.push(UndoAction::Transform(original));
+    }
+    pub fn add_reorder(&mut self, order: Vec<usize>) {
+        self.push(UndoAction::Reorder(order));
+    }
+    // Repeated width changes of the same lines undo in one step
+    pub fn add_width(&mut self, original: Vec<(usize, f32, Vec<f32>)>) {
+        if let Some(UndoAction::Width(last)) = self.stack.back()
+            && last.iter().map(|l| l.0).eq(original.iter().map(|l| l.0))
+        {
+            return;
+        }
+        self.push(UndoAction::Width(original));
+    }
+    pub fn add_reshape(&mut self, original: Vec<(usize, Vec<Pos2>, Vec<f32>)>) {
+        self.push(UndoAction::Reshape(original));
+    }
+    pub fn add_lock(&mut self, changed: Vec<usize>) {
+        self.push(UndoAction::Lock(changed));
+    }
+    // Adds `action`, forgetting the oldest one once there are too many
+    fn push(&mut self, action: UndoAction) {
+        self.stack.push_back(action);
         if self.stack.len() > MAX_UNDO_STACK_SIZE {
             self.stack.pop_front();
         }
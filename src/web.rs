@@ -0,0 +1,139 @@
+use std::{
+    cell::RefCell,
+    io,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use eframe::egui;
+
+use crate::{format, state::WhiteboardState};
+
+// localStorage key of the board saved in the browser
+const BOARD_KEY: &str = "whiteboard.wb";
+
+fn storage() -> io::Result<web_sys::Storage> {
+    web_sys::window()
+        .and_then(|window| window.local_storage().ok().flatten())
+        .ok_or_else(|| io::Error::other("the browser's storage is disabled"))
+}
+
+// The value saved under `key`, `None` if there is none
+pub(crate) fn read(key: &str) -> Option<String> {
+    storage().ok()?.get_item(key).ok().flatten()
+}
+
+pub(crate) fn write(key: &str, value: &str) -> io::Result<()> {
+    storage()?
+        .set_item(key, value)
+        .map_err(|_| io::Error::other("the browser's storage is full"))
+}
+
+// The board saved in the browser, going through the same migrations as
+// files
+pub(crate) fn load_board() -> Result<Option<WhiteboardState>, String> {
+    let Some(json) = read(BOARD_KEY) else {
+        return Ok(None);
+    };
+    format::decode(json.as_bytes())
+        .map(Some)
+        .map_err(|e| e.to_string())
+}
+
+pub(crate) fn store_board(state: &WhiteboardState) -> io::Result<()> {
+    write(BOARD_KEY, &serde_json::to_string(state)?)
+}
+
+// Whiteboard files picked with the browser's file picker. It doesn't block
+// like the native dialogs, so the picked file shows up in a later frame.
+#[derive(Default)]
+pub(crate) struct BrowserFiles {
+    picked: Rc<RefCell<Option<(String, Vec<u8>)>>>,
+}
+impl BrowserFiles {
+    pub fn pick(&self, ctx: &egui::Context) {
+        let picked = Rc::clone(&self.picked);
+        let ctx = ctx.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            let Some(file) = rfd::AsyncFileDialog::new()
+                .add_filter("Whiteboard file", &format::EXTENSIONS)
+                .pick_file()
+                .await
+            else {
+                return;
+            };
+            let bytes = file.read().await;
+            *picked.borrow_mut() = Some((file.file_name(), bytes));
+            ctx.request_repaint();
+        });
+    }
+    // The name and content of the file picked since the last call
+    pub fn take_picked(&self) -> Option<(String, Vec<u8>)> {
+        self.picked.borrow_mut().take()
+    }
+}
+
+// Offers `bytes` as a download named `file_name`
+pub(crate) fn download(file_name: String, bytes: Vec<u8>) {
+    wasm_bindgen_futures::spawn_local(async move {
+        if let Some(file) = rfd::AsyncFileDialog::new()
+            .set_file_name(file_name)
+            .save_file()
+            .await
+            && let Err(e) = file.write(&bytes).await
+        {
+            rfd::MessageDialog::new()
+                .set_level(rfd::MessageLevel::Error)
+                .set_title("Failed to download whiteboard")
+                .set_description(format!("Failed to download whiteboard: {e}"))
+                .set_buttons(rfd::MessageButtons::Ok)
+                .show();
+        }
+    });
+}
+
+// Stands in for `rfd::FileDialog`, which needs a file system. Boards are
+// opened and saved through `BrowserFiles`, `download` and the browser's
+// storage instead; the imports and exports that ask for a path explain
+// that they need the desktop app.
+pub(crate) struct FileDialog;
+impl FileDialog {
+    pub fn new() -> Self {
+        Self
+    }
+    pub fn add_filter(
+        self,
+        _name: impl Into<String>,
+        _extensions: &[impl ToString],
+    ) -> Self {
+        self
+    }
+    pub fn set_directory(self, _path: impl AsRef<Path>) -> Self {
+        self
+    }
+    pub fn set_file_name(self, _file_name: impl Into<String>) -> Self {
+        self
+    }
+    pub fn set_title(self, _title: impl Into<String>) -> Self {
+        self
+    }
+    pub fn pick_file(self) -> Option<PathBuf> {
+        show_desktop_only();
+        None
+    }
+    pub fn save_file(self) -> Option<PathBuf> {
+        show_desktop_only();
+        None
+    }
+}
+fn show_desktop_only() {
+    rfd::MessageDialog::new()
+        .set_level(rfd::MessageLevel::Info)
+        .set_title("Not available in the browser")
+        .set_description(
+            "Importing and exporting files needs the desktop app. Use Save \
+             As to download the board.",
+        )
+        .set_buttons(rfd::MessageButtons::Ok)
+        .show();
+}
@@ -0,0 +1,67 @@
+use std::{
+    mem,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use eframe::egui;
+use egui::{Rect, ViewportId};
+
+use crate::{
+    WhiteboardApp,
+    ruler::Ruler,
+    screenshot::Annotation,
+    tabs::Tab,
+    tools::{self, CanvasTool, Tool, ToolMaker},
+};
+
+static NEXT_WINDOW: AtomicU64 = AtomicU64::new(0);
+
+// A board detached into a native window of its own. Besides the board it
+// has its own tools, so a stroke in one window doesn't get mixed up with
+// another. While the window is drawn it trades places with the main
+// window's board and tools, like a tab being shown.
+pub(crate) struct BoardWindow {
+    pub id: ViewportId,
+    pub tab: Tab,
+    tools: Vec<Box<dyn CanvasTool>>,
+    current_tool: Tool,
+    ruler: Ruler,
+    canvas_rect: Rect,
+    touch_gesture: bool,
+    annotation: Option<Annotation>,
+}
+// What the window asked for while it was drawn
+pub(crate) enum WindowAction {
+    Keep,
+    Close,
+    // go back to being a tab of the main window
+    Attach,
+}
+impl BoardWindow {
+    pub fn new(tab: Tab, current_tool: Tool, tools: &[ToolMaker]) -> Self {
+        let id = NEXT_WINDOW.fetch_add(1, Ordering::Relaxed);
+        Self {
+            id: ViewportId::from_hash_of(("board_window", id)),
+            tab,
+            tools: tools::make_all(tools),
+            current_tool,
+            ruler: Ruler::Off,
+            canvas_rect: Rect::NOTHING,
+            touch_gesture: false,
+            annotation: None,
+        }
+    }
+    pub fn add_tool(&mut self, tool: Box<dyn CanvasTool>) {
+        self.tools.push(tool);
+    }
+    // Trades the board and tools `app` shows for this window's
+    pub fn swap(&mut self, app: &mut WhiteboardApp) {
+        self.tab.swap(app);
+        mem::swap(&mut self.tools, &mut app.tools);
+        mem::swap(&mut self.current_tool, &mut app.current_tool);
+        mem::swap(&mut self.ruler, &mut app.ruler);
+        mem::swap(&mut self.canvas_rect, &mut app.canvas_rect);
+        mem::swap(&mut self.touch_gesture, &mut app.touch_gesture);
+        mem::swap(&mut self.annotation, &mut app.annotation);
+    }
+}
@@ -0,0 +1,54 @@
+use std::collections::HashSet;
+
+// Lines are drawn in order, so later lines sit on top of earlier ones
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum ZOrder {
+    Front,
+    Forward,
+    Backward,
+    Back,
+}
+pub(crate) const Z_ORDERS: [(ZOrder, &str, &str); 4] = [
+    (ZOrder::Front, "Bring to Front", "Ctrl+Shift+]"),
+    (ZOrder::Forward, "Bring Forward", "Ctrl+]"),
+    (ZOrder::Backward, "Send Backward", "Ctrl+["),
+    (ZOrder::Back, "Send to Back", "Ctrl+Shift+["),
+];
+
+// The new order of `len` lines after moving the selected ones, as a list of
+// old indices. Selected lines keep their order relative to each other.
+pub(crate) fn reorder(
+    len: usize,
+    selected: &HashSet<usize>,
+    order: ZOrder,
+) -> Vec<usize> {
+    let mut indices: Vec<usize> = (0..len).collect();
+    match order {
+        ZOrder::Front => {
+            indices.sort_by_key(|i| selected.contains(i));
+        }
+        ZOrder::Back => {
+            indices.sort_by_key(|i| !selected.contains(i));
+        }
+        ZOrder::Forward => {
+            // step each selected line over the unselected line above it
+            for i in (0..len.saturating_sub(1)).rev() {
+                if selected.contains(&indices[i])
+                    && !selected.contains(&indices[i + 1])
+                {
+                    indices.swap(i, i + 1);
+                }
+            }
+        }
+        ZOrder::Backward => {
+            for i in 1..len {
+                if selected.contains(&indices[i])
+                    && !selected.contains(&indices[i - 1])
+                {
+                    indices.swap(i, i - 1);
+                }
+            }
+        }
+    }
+    indices
+}
@@ -0,0 +1,113 @@
+// `whiteboard convert` run the way a script would, against the fixtures
+use std::{path::Path, process::Command};
+
+use quick_xml::{Reader, events::Event};
+
+// Converts `fixture` with `options`, returning what it printed and the SVG
+fn convert(fixture: &str, options: &[&str]) -> (String, String) {
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("out.svg");
+    let input = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(fixture);
+    let result = Command::new(env!("CARGO_BIN_EXE_whiteboard"))
+        .arg("convert")
+        .arg(input)
+        .arg("-o")
+        .arg(&output)
+        .args(options)
+        .output()
+        .unwrap();
+    assert!(
+        result.status.success(),
+        "{}",
+        String::from_utf8_lossy(&result.stderr)
+    );
+    let stdout = String::from_utf8(result.stdout).unwrap();
+    (stdout, std::fs::read_to_string(output).unwrap())
+}
+
+// Checks that `svg` is well-formed XML with an `svg` root element, and
+// returns its viewBox and the number of elements inside the root
+fn parse(svg: &str) -> ([f32; 4], usize) {
+    let mut reader = Reader::from_str(svg);
+    let mut view_box = None;
+    let mut depth = 0;
+    let mut elements = 0;
+    loop {
+        let event = reader.read_event().expect("not well-formed XML");
+        let (start, empty) = match &event {
+            Event::Start(start) => (start, false),
+            Event::Empty(start) => (start, true),
+            Event::End(_) => {
+                depth -= 1;
+                continue;
+            }
+            Event::Eof => break,
+            _ => continue,
+        };
+        if depth == 0 {
+            assert_eq!(start.name().as_ref(), b"svg");
+            let attribute = start
+                .try_get_attribute("viewBox")
+                .unwrap()
+                .expect("no viewBox");
+            let value = attribute.unescape_value().unwrap();
+            let numbers: Vec<f32> =
+                value.split(' ').map(|n| n.parse().unwrap()).collect();
+            view_box = Some(numbers.try_into().unwrap());
+        } else {
+            elements += 1;
+        }
+        if !empty {
+            depth += 1;
+        }
+    }
+    assert_eq!(depth, 0, "unclosed elements");
+    (view_box.expect("no svg element"), elements)
+}
+
+#[test]
+fn converts_the_strokes_of_a_page() {
+    let (stdout, svg) = convert("v3.wb", &["--page", "1"]);
+    assert!(stdout.starts_with("Converted 2 strokes to "), "{stdout}");
+    let (_, elements) = parse(&svg);
+    assert!(elements >= 2);
+}
+
+#[test]
+fn fit_and_margin_set_the_view_box() {
+    // the v1 fixture's only stroke goes from (10, 20) to (30, 40)
+    let (_, fitted) = convert("v1.wb", &["--fit", "--margin", "0"]);
+    let ([x, y, width, height], _) = parse(&fitted);
+    assert!(x > 0.0 && y > 0.0 && x <= 10.0 && y <= 20.0);
+    assert!(x + width >= 30.0 && y + height >= 40.0);
+
+    let (_, padded) = convert("v1.wb", &["--fit", "--margin", "5"]);
+    let ([padded_x, _, padded_width, _], _) = parse(&padded);
+    assert_eq!(padded_x, x - 5.0);
+    assert_eq!(padded_width, width + 10.0);
+
+    // without --fit the view also takes in the board origin
+    let (_, unfitted) = convert("v1.wb", &["--margin", "0"]);
+    let ([x, y, ..], _) = parse(&unfitted);
+    assert_eq!((x, y), (0.0, 0.0));
+}
+
+#[test]
+fn empty_pages_fail() {
+    let dir = tempfile::tempdir().unwrap();
+    let input =
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/v3.wb");
+    let output = dir.path().join("out.svg");
+    // the page shown when saving is the empty second one
+    let result = Command::new(env!("CARGO_BIN_EXE_whiteboard"))
+        .arg("convert")
+        .arg(input)
+        .arg("-o")
+        .arg(&output)
+        .output()
+        .unwrap();
+    assert_eq!(result.status.code(), Some(1));
+    assert!(!output.exists());
+}
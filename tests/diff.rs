@@ -0,0 +1,81 @@
+// `whiteboard diff` run the way a script would, against the fixtures
+use std::{
+    path::Path,
+    process::{Command, Output},
+};
+
+// Compares the fixtures `old` and `new` with `options`
+fn diff(old: &str, new: &str, options: &[&str]) -> Output {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    Command::new(env!("CARGO_BIN_EXE_whiteboard"))
+        .arg("diff")
+        .arg(fixtures.join(old))
+        .arg(fixtures.join(new))
+        .args(options)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn identical_boards_exit_with_0() {
+    let result = diff("v3.wb", "v3.wb", &[]);
+    assert_eq!(result.status.code(), Some(0));
+    let stdout = String::from_utf8(result.stdout).unwrap();
+    assert_eq!(stdout, "The boards are identical\n");
+}
+
+#[test]
+fn different_boards_exit_with_1() {
+    let result = diff("v1.wb", "v3.wb", &[]);
+    assert_eq!(result.status.code(), Some(1));
+    let stdout = String::from_utf8(result.stdout).unwrap();
+    assert_eq!(
+        stdout,
+        "page 1: 2 added, 1 removed, 0 modified\n\
+         2 strokes added, 1 removed, 0 modified\n"
+    );
+}
+
+#[test]
+fn errors_exit_with_2() {
+    let result = diff("v3.wb", "missing.wb", &[]);
+    assert_eq!(result.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&result.stderr).contains("missing.wb"));
+    let result = diff("v1.wb", "v3.wb", &["--colour"]);
+    assert_eq!(result.status.code(), Some(2));
+    assert!(result.stdout.is_empty());
+}
+
+#[test]
+fn json_lists_the_strokes_by_page() {
+    let result = diff("v1.wb", "v3.wb", &["--json"]);
+    assert_eq!(result.status.code(), Some(1));
+    let json: serde_json::Value =
+        serde_json::from_slice(&result.stdout).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "added": 2,
+            "removed": 1,
+            "modified": 0,
+            "pages": [
+                {"page": 1, "removed": [0], "added": [0, 1], "modified": []}
+            ]
+        })
+    );
+}
+
+#[test]
+fn differences_are_drawn_on_request() {
+    let dir = tempfile::tempdir().unwrap();
+    let image = dir.path().join("diff.png");
+    let image_arg = image.to_str().unwrap();
+    let result = diff("v1.wb", "v3.wb", &["-o", image_arg]);
+    assert_eq!(result.status.code(), Some(1));
+    assert!(image::open(&image).is_ok());
+    // identical boards have nothing to draw
+    let image = dir.path().join("same.png");
+    let result = diff("v3.wb", "v3.wb", &["-o", image.to_str().unwrap()]);
+    assert_eq!(result.status.code(), Some(0));
+    assert!(!image.exists());
+}
@@ -0,0 +1,63 @@
+// Rough time budgets for operations that run while the user waits, on the
+// same synthetic boards as the benchmarks. The budgets leave plenty of room
+// for a slow machine and an unoptimized build; they catch an operation
+// becoming much slower, like going quadratic, not small regressions.
+use std::time::{Duration, Instant};
+
+use egui::{Rect, pos2};
+use whiteboard::{WhiteboardApp, WhiteboardBuilder, render_to_image};
+
+const STROKES: usize = 10_000;
+const POINTS: usize = 50;
+const BOARD_SIZE: f32 = 4000.0;
+
+fn board(strokes: usize) -> WhiteboardApp {
+    WhiteboardBuilder::new()
+        .scribbles(strokes, POINTS, BOARD_SIZE)
+        .build()
+}
+
+// The fastest of a few runs of `f`, so a busy machine doesn't fail the test
+fn fastest(mut f: impl FnMut()) -> Duration {
+    (0..3)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+#[test]
+fn erasing_stays_within_budget() {
+    let mut app = board(STROKES);
+    let time = fastest(|| {
+        app.erase_at(pos2(-100.0, -100.0), 5.0);
+    });
+    assert!(time < Duration::from_millis(500), "erasing took {time:?}");
+}
+
+#[test]
+fn selecting_stays_within_budget() {
+    let mut app = board(STROKES);
+    let rect = Rect::from_min_max(
+        pos2(0.0, 0.0),
+        pos2(BOARD_SIZE / 2.0, BOARD_SIZE / 2.0),
+    );
+    let time = fastest(|| {
+        app.select_in_rect(rect);
+    });
+    assert!(time < Duration::from_millis(500), "selecting took {time:?}");
+}
+
+#[test]
+fn rendering_stays_within_budget() {
+    // Rendering is much slower than hit testing in an unoptimized build, so
+    // it gets a smaller board
+    let state = board(STROKES / 10).export_state();
+    let time = fastest(|| {
+        render_to_image(&state, 0.25);
+    });
+    assert!(time < Duration::from_secs(5), "rendering took {time:?}");
+}
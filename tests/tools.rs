@@ -0,0 +1,65 @@
+// A tool from outside the crate, added the way an app embedding the board
+// would add one
+use eframe::egui;
+use egui::{ImageSource, Pos2, pos2, vec2};
+use whiteboard::{CanvasTool, Tool, ToolContext, WhiteboardApp};
+
+const DOT: Tool = Tool::new("Dot");
+
+// Puts a short stroke where it's pressed
+struct Dot;
+impl CanvasTool for Dot {
+    fn kind(&self) -> Tool {
+        DOT
+    }
+    fn icon(&self) -> ImageSource<'static> {
+        egui::include_image!("../assets/tools/measure.png")
+    }
+    fn tooltip(&self) -> &'static str {
+        "Dot"
+    }
+    fn on_pointer_down(&mut self, cx: &mut ToolContext<'_>, pos: Pos2) {
+        cx.add_stroke(vec![pos, pos + vec2(1.0, 0.0)]);
+    }
+}
+
+// Runs a frame of `app` alone in a window with `events`
+fn frame(
+    ctx: &egui::Context,
+    app: &mut WhiteboardApp,
+    events: Vec<egui::Event>,
+) {
+    let input = egui::RawInput {
+        screen_rect: Some(egui::Rect::from_min_size(
+            Pos2::ZERO,
+            vec2(800.0, 600.0),
+        )),
+        events,
+        ..Default::default()
+    };
+    let _ = ctx.run(input, |ctx| {
+        egui::CentralPanel::default().show(ctx, |ui| app.ui(ui));
+    });
+}
+
+#[test]
+fn registered_tools_draw_on_the_board() {
+    let ctx = egui::Context::default();
+    let mut app = WhiteboardApp::default();
+    app.register_tool(|| Box::new(Dot));
+    app.set_tool(DOT);
+    let pos = pos2(400.0, 300.0);
+    let button = |pressed| egui::Event::PointerButton {
+        pos,
+        button: egui::PointerButton::Primary,
+        pressed,
+        modifiers: egui::Modifiers::default(),
+    };
+    frame(&ctx, &mut app, vec![egui::Event::PointerMoved(pos)]);
+    frame(&ctx, &mut app, vec![button(true)]);
+    frame(&ctx, &mut app, vec![button(false)]);
+    assert_eq!(app.line_count(), 1);
+    // the tool is saved by its name, like the built-in ones
+    let bytes = app.to_bytes().unwrap();
+    assert!(String::from_utf8_lossy(&bytes).contains("\"Dot\""));
+}